@@ -0,0 +1,161 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::Context;
+use namada_sdk::{address::Address, key::common::SecretKey};
+use serde::Deserialize;
+
+use crate::{
+    config::AppConfig,
+    key::{self, KeyScheme},
+    namada::KEY_ALIAS,
+};
+
+/// A delegator compounded by this process, with its own key and schedule.
+#[derive(Debug, Clone)]
+pub struct Account {
+    pub alias: String,
+    pub secret_key: SecretKey,
+    pub address: Address,
+    pub state_file: Option<PathBuf>,
+}
+
+impl Account {
+    pub fn new(alias: String, secret_key: SecretKey, state_file: Option<PathBuf>) -> Self {
+        let address = Address::from(&secret_key.to_public());
+        Self {
+            alias,
+            secret_key,
+            address,
+            state_file,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AccountsFile {
+    #[serde(rename = "account")]
+    accounts: Vec<AccountEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct AccountEntry {
+    alias: String,
+    secret_key: Option<String>,
+    secret_key_file: Option<PathBuf>,
+    state_file: Option<PathBuf>,
+}
+
+/// The accounts listed in `--accounts-file`, or the single one given by the
+/// key options and `--state-file`.
+pub fn load_accounts(config: &AppConfig) -> anyhow::Result<Vec<Account>> {
+    let Some(path) = &config.accounts_file else {
+        let secret_key = key::resolve_secret_key(config)?;
+        return Ok(vec![Account::new(
+            KEY_ALIAS.to_string(),
+            secret_key,
+            config.state_file.clone(),
+        )]);
+    };
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Can't read accounts file {}", path.display()))?;
+    parse_accounts(&content, config.key_scheme)
+        .with_context(|| format!("Invalid accounts file {}", path.display()))
+}
+
+/// Parse a TOML list of `[[account]]` tables, each with an `alias`, exactly
+/// one of `secret_key` or `secret_key_file`, and an optional `state_file`.
+/// Every key must be of `scheme`.
+fn parse_accounts(content: &str, scheme: KeyScheme) -> anyhow::Result<Vec<Account>> {
+    let file = toml::from_str::<AccountsFile>(content)?;
+    anyhow::ensure!(!file.accounts.is_empty(), "No account listed");
+
+    let mut aliases = HashSet::new();
+    let mut addresses = HashSet::new();
+    let mut accounts = Vec::with_capacity(file.accounts.len());
+
+    for entry in file.accounts {
+        let secret_key = match (&entry.secret_key, &entry.secret_key_file) {
+            (Some(raw), None) => key::parse_secret_key(raw, scheme, "Can't parse secret key")?,
+            (None, Some(path)) => {
+                let raw = key::read_secret(path)?;
+                key::parse_secret_key(&raw, scheme, "Can't parse secret key file")?
+            }
+            _ => anyhow::bail!(
+                "Account {} needs exactly one of secret_key or secret_key_file",
+                entry.alias
+            ),
+        };
+
+        let account = Account::new(entry.alias, secret_key, entry.state_file);
+        anyhow::ensure!(
+            aliases.insert(account.alias.clone()),
+            "Duplicate account alias {}",
+            account.alias
+        );
+        anyhow::ensure!(
+            addresses.insert(account.address.clone()),
+            "Account {} duplicates the key of another account",
+            account.alias
+        );
+        accounts.push(account);
+    }
+
+    Ok(accounts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_accounts;
+    use crate::key::KeyScheme;
+
+    const FIRST: &str = "000101010101010101010101010101010101010101010101010101010101010101";
+    const SECOND: &str = "000202020202020202020202020202020202020202020202020202020202020202";
+
+    #[test]
+    fn test_parse_accounts() {
+        let content = format!(
+            r#"
+            [[account]]
+            alias = "main"
+            secret_key = "{FIRST}"
+            state_file = "main.json"
+
+            [[account]]
+            alias = "cold"
+            secret_key = "{SECOND}"
+            "#
+        );
+        let accounts = parse_accounts(&content, KeyScheme::Ed25519).unwrap();
+
+        assert_eq!(accounts.len(), 2);
+        assert_eq!(accounts[0].alias, "main");
+        assert!(accounts[0].state_file.is_some());
+        assert!(accounts[1].state_file.is_none());
+        assert_ne!(accounts[0].address, accounts[1].address);
+    }
+
+    #[test]
+    fn test_reject_duplicate_accounts() {
+        let duplicate_key = format!(
+            r#"
+            [[account]]
+            alias = "main"
+            secret_key = "{FIRST}"
+
+            [[account]]
+            alias = "again"
+            secret_key = "{FIRST}"
+            "#
+        );
+        assert!(parse_accounts(&duplicate_key, KeyScheme::Ed25519).is_err());
+
+        let missing_key = r#"
+            [[account]]
+            alias = "main"
+            "#;
+        assert!(parse_accounts(missing_key, KeyScheme::Ed25519).is_err());
+    }
+}
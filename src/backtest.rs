@@ -0,0 +1,251 @@
+use anyhow::Context;
+use namada_sdk::{address::Address, key::common::SecretKey};
+
+use crate::{
+    compound,
+    config::{AppConfig, BacktestArgs},
+    namada::NamadaRpc,
+    opt::{self, RoundFee},
+};
+
+const HEADER: &str = "epoch,bonded,net_apr,pending_rewards,compounded,fee,balance,fees_paid_total";
+
+/// The delegation at a past epoch, as the backtest replays it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EpochSnapshot {
+    pub epoch: u64,
+    /// Bonded on chain at the epoch, in NAM
+    pub bonded: f64,
+    pub net_apr: f64,
+}
+
+/// One epoch of the replay, as a row of the backtest CSV.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BacktestRow {
+    pub epoch: u64,
+    pub bonded: f64,
+    pub net_apr: f64,
+    /// Rewards accrued since the last compound of the replay, before this
+    /// epoch's
+    pub pending_rewards: f64,
+    pub compounded: bool,
+    /// Paid this epoch, zero if not compounding
+    pub fee: f64,
+    /// What the replay would have bonded by the end of the epoch
+    pub balance: f64,
+    pub fees_paid_total: f64,
+}
+
+impl BacktestRow {
+    fn to_csv_row(&self) -> String {
+        format!(
+            "{},{:.6},{:.6},{:.6},{},{:.6},{:.6},{:.6}",
+            self.epoch,
+            self.bonded,
+            self.net_apr,
+            self.pending_rewards,
+            self.compounded,
+            self.fee,
+            self.balance,
+            self.fees_paid_total
+        )
+    }
+}
+
+/// Replay `snapshots`, starting from the bond of the first one: rewards
+/// accrue on the replayed balance at each epoch's net APR, and are bonded
+/// back once the optimizer's interval has passed, they reach
+/// `--min-claim-nam` and, unless `--force`, they cover `fee`. Bond changes
+/// on chain after the first epoch are left out.
+pub fn replay(
+    snapshots: &[EpochSnapshot],
+    config: &AppConfig,
+    fee: RoundFee,
+    epochs_per_year: f64,
+) -> Vec<BacktestRow> {
+    let Some(first) = snapshots.first() else {
+        return vec![];
+    };
+
+    let mut balance = first.bonded;
+    let mut pending = 0.0;
+    let mut fees_paid_total = 0.0;
+    let mut last_compound = first.epoch;
+    let mut rows = Vec::with_capacity(snapshots.len());
+
+    for snapshot in snapshots {
+        let pending_rewards = pending;
+        let interval_epochs = config
+            .optimizer
+            .optimize(
+                balance,
+                snapshot.net_apr,
+                fee,
+                Some(epochs_per_year),
+                config.horizon_years,
+                config.max_frequency(),
+            )
+            .map(|result| (epochs_per_year / result.optimal_frequency as f64).ceil() as u64);
+
+        let compounded = interval_epochs
+            .is_some_and(|interval| snapshot.epoch - last_compound >= interval.max(1))
+            && pending > 0.0
+            && pending >= config.min_claim_nam
+            && (config.force || pending > fee.total());
+        let round_fee = if compounded { fee.total() } else { 0.0 };
+        if compounded {
+            balance += pending;
+            pending = 0.0;
+            fees_paid_total += round_fee;
+            last_compound = snapshot.epoch;
+        }
+        pending += balance * snapshot.net_apr / epochs_per_year;
+
+        rows.push(BacktestRow {
+            epoch: snapshot.epoch,
+            bonded: snapshot.bonded,
+            net_apr: snapshot.net_apr,
+            pending_rewards,
+            compounded,
+            fee: round_fee,
+            balance,
+            fees_paid_total,
+        });
+    }
+
+    rows
+}
+
+/// The delegation of `delegator` at each epoch of `args`, from the bonds
+/// and commissions at that epoch. The chain only serves the current
+/// inflation rate, which stands for every epoch.
+async fn snapshots<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    delegator: &Address,
+    args: &BacktestArgs,
+) -> anyhow::Result<Vec<EpochSnapshot>> {
+    let pos_inflation = compound::inflation_rate(rpc, config).await?;
+
+    let mut snapshots = vec![];
+    for epoch in args.from_epoch..=args.to_epoch {
+        let validators = compound::delegations(rpc, config, delegator, epoch).await?;
+        let bonds = rpc
+            .query_bonds(&validators, delegator, epoch, config.strict)
+            .await?;
+        let commissions = rpc
+            .query_validators_commissions(&validators, epoch, config.strict)
+            .await?;
+        let commission = if bonds.is_empty() {
+            0.0
+        } else {
+            config
+                .commission_aggregation
+                .aggregate(&commissions, &bonds)
+                .with_context(|| format!("Can't compute the commissions at epoch {}", epoch))?
+        };
+
+        snapshots.push(EpochSnapshot {
+            epoch,
+            bonded: bonds.values().sum(),
+            net_apr: pos_inflation * (1.0 - commission),
+        });
+    }
+
+    Ok(snapshots)
+}
+
+/// Print, as CSV, what compounding with `config` would have done over the
+/// epochs of `args`.
+pub async fn backtest<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    secret_key: &SecretKey,
+    args: &BacktestArgs,
+) -> anyhow::Result<()> {
+    let delegator_address = Address::from(&secret_key.to_public());
+    let current_epoch = rpc.get_current_epoch().await?;
+    anyhow::ensure!(
+        args.from_epoch <= args.to_epoch && args.to_epoch <= current_epoch,
+        "Epochs {} to {} are not a range of past epochs, the current one is {}",
+        args.from_epoch,
+        args.to_epoch,
+        current_epoch
+    );
+
+    let epochs_per_year = opt::epochs_per_year(Some(rpc.epoch_duration().await?.as_secs()))
+        .context("The chain has no epoch duration")?;
+    let validators = compound::delegations(rpc, config, &delegator_address, args.to_epoch).await?;
+    let native_token_address = rpc.query_native_token().await?;
+    let fee = RoundFee::new(
+        compound::txs_per_round(validators.len(), config.batch_claims),
+        compound::estimate_fee_per_tx(rpc, config, &native_token_address).await,
+    );
+
+    let snapshots = snapshots(rpc, config, &delegator_address, args).await?;
+
+    println!("{}", HEADER);
+    for row in replay(&snapshots, config, fee, epochs_per_year) {
+        println!("{}", row.to_csv_row());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::{replay, EpochSnapshot};
+    use crate::{config::test::config_with as config, opt::RoundFee};
+
+    fn snapshots(epochs: u64) -> Vec<EpochSnapshot> {
+        (0..epochs)
+            .map(|epoch| EpochSnapshot {
+                epoch: 100 + epoch,
+                bonded: 100_000.0,
+                net_apr: 0.1,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_replay() {
+        // Daily epochs
+        let rows = replay(&snapshots(60), &config(&[]), RoundFee::flat(0.5), 365.0);
+
+        assert_eq!(rows.len(), 60);
+        assert!(!rows[0].compounded);
+        let compounds = rows.iter().filter(|row| row.compounded).count();
+        assert!(compounds > 1 && compounds < 60);
+        // Every compound pays the fee and grows the balance
+        let last = rows.last().unwrap();
+        assert_eq!(last.fees_paid_total, compounds as f64 * 0.5);
+        assert!(last.balance > 100_000.0);
+        assert!(rows
+            .windows(2)
+            .all(|pair| pair[0].balance <= pair[1].balance));
+    }
+
+    #[test]
+    fn test_replay_thresholds() {
+        // Rewards never reach the minimum claim
+        let rows = replay(
+            &snapshots(30),
+            &config(&["--min-claim-nam", "10000"]),
+            RoundFee::flat(0.5),
+            365.0,
+        );
+        assert!(rows.iter().all(|row| !row.compounded));
+        assert_eq!(rows.last().unwrap().balance, 100_000.0);
+
+        // Nor cover the fee
+        let rows = replay(
+            &snapshots(30),
+            &config(&[]),
+            RoundFee::flat(10_000.0),
+            365.0,
+        );
+        assert!(rows.iter().all(|row| !row.compounded));
+
+        assert!(replay(&[], &config(&[]), RoundFee::flat(0.5), 365.0).is_empty());
+    }
+}
@@ -0,0 +1,144 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use anyhow::Context;
+use serde::Deserialize;
+
+use crate::config::AppConfig;
+
+/// A chain compounded by this process, with its own RPC, keys, wallet and
+/// thresholds.
+pub struct Chain {
+    pub name: String,
+    pub config: AppConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainsFile {
+    #[serde(rename = "chain")]
+    chains: Vec<ChainEntry>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ChainEntry {
+    name: String,
+    /// Options file of the chain, as for `--config`
+    config: PathBuf,
+}
+
+/// The chains listed in `--chains-file`, each configured from its own
+/// options file. Env vars still apply to every chain.
+pub fn load_chains(config: &AppConfig) -> anyhow::Result<Vec<Chain>> {
+    let Some(path) = &config.chains_file else {
+        return Ok(vec![]);
+    };
+
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Can't read chains file {}", path.display()))?;
+    parse_chains(&content).with_context(|| format!("Invalid chains file {}", path.display()))
+}
+
+/// Parse a TOML list of `[[chain]]` tables, each with a `name` and the
+/// `config` file of its options.
+fn parse_chains(content: &str) -> anyhow::Result<Vec<Chain>> {
+    let file = toml::from_str::<ChainsFile>(content)?;
+    anyhow::ensure!(!file.chains.is_empty(), "No chain listed");
+
+    let mut names = HashSet::new();
+    let mut state_files = HashSet::new();
+    let mut chains = Vec::with_capacity(file.chains.len());
+
+    for entry in file.chains {
+        anyhow::ensure!(
+            names.insert(entry.name.clone()),
+            "Duplicate chain name {}",
+            entry.name
+        );
+
+        let config = AppConfig::load_from([
+            "autocompound".into(),
+            "--config".into(),
+            entry.config.into_os_string(),
+        ])
+        .with_context(|| format!("Chain {}", entry.name))?;
+        // Process-wide, so only taken from the top-level options
+        anyhow::ensure!(
+            config.chains_file.is_none() && config.metrics_addr.is_none(),
+            "Chain {} can't set chains_file or metrics_addr",
+            entry.name
+        );
+        if let Some(state_file) = &config.state_file {
+            anyhow::ensure!(
+                state_files.insert(state_file.clone()),
+                "Chain {} shares the state file {} of another chain",
+                entry.name,
+                state_file.display()
+            );
+        }
+
+        chains.push(Chain {
+            name: entry.name,
+            config,
+        });
+    }
+
+    Ok(chains)
+}
+
+#[cfg(test)]
+mod test {
+    use super::parse_chains;
+
+    const SECRET_KEY: &str = "000101010101010101010101010101010101010101010101010101010101010101";
+
+    #[test]
+    fn test_parse_chains() {
+        let dir = std::env::temp_dir().join(format!("autocompound-chains-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let config = |name: &str, extra: &str| {
+            let path = dir.join(format!("{}.toml", name));
+            std::fs::write(
+                &path,
+                format!(
+                    "namada_rpc = [\"http://{name}:26657\"]\nsecret_key = \"{SECRET_KEY}\"\n{extra}"
+                ),
+            )
+            .unwrap();
+            path.display().to_string()
+        };
+        let mainnet = config("mainnet", "min_claim_nam = 10");
+        let testnet = config("testnet", "state_file = \"testnet.json\"");
+        let shared = config("shared", "state_file = \"testnet.json\"");
+        let nested = config("nested", "chains_file = \"chains.toml\"");
+
+        let chains = parse_chains(&format!(
+            "[[chain]]\nname = \"mainnet\"\nconfig = {mainnet:?}\n\n\
+             [[chain]]\nname = \"testnet\"\nconfig = {testnet:?}\n"
+        ))
+        .unwrap();
+        let duplicate = parse_chains(&format!(
+            "[[chain]]\nname = \"mainnet\"\nconfig = {mainnet:?}\n\n\
+             [[chain]]\nname = \"mainnet\"\nconfig = {testnet:?}\n"
+        ));
+        let shared = parse_chains(&format!(
+            "[[chain]]\nname = \"testnet\"\nconfig = {testnet:?}\n\n\
+             [[chain]]\nname = \"shared\"\nconfig = {shared:?}\n"
+        ));
+        let nested = parse_chains(&format!(
+            "[[chain]]\nname = \"nested\"\nconfig = {nested:?}\n"
+        ));
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(chains.len(), 2);
+        assert_eq!(chains[0].name, "mainnet");
+        assert_eq!(chains[0].config.namada_rpc, ["http://mainnet:26657"]);
+        assert_eq!(chains[0].config.min_claim_nam, 10.0);
+        assert_eq!(chains[1].config.namada_rpc, ["http://testnet:26657"]);
+        assert_eq!(chains[1].config.min_claim_nam, 0.0);
+        assert!(duplicate.is_err());
+        assert!(shared.is_err());
+        assert!(nested.is_err());
+        assert!(parse_chains("").is_err());
+    }
+}
@@ -0,0 +1,5523 @@
+use anyhow::Context;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Display},
+};
+
+use namada_sdk::{
+    address::Address, key::common::SecretKey, proof_of_stake::types::ValidatorState, token,
+};
+use serde::Serialize;
+
+use crate::{
+    config::{
+        AppConfig, BondTarget, ClaimedAmount, OutputFormat, RewardAction, RoundOrder,
+        ValidatorRanking,
+    },
+    denom,
+    error::{TaskError, TaskResult},
+    events::{self, Event},
+    history::CompoundRecord,
+    metrics::METRICS,
+    namada::{ClaimReport, NamadaRpc, TxReceipt, TX_GAS_LIMIT},
+    opt::{self, OptimizationInputs, RoundFee},
+    scheduler,
+    state::{
+        ChosenTarget, ChosenValidators, LastRound, PendingBond, PendingClaim, State,
+        MAX_CLAIM_ATTEMPTS,
+    },
+    strategy::{Clock, Strategy},
+    utils,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RoundOutcome {
+    /// Projections were logged, nothing was submitted
+    DryRun,
+    /// The delegator has no (selected) bonds, so there is nothing to compound
+    NoDelegations,
+    /// The optimal interval since the last compound hasn't elapsed yet
+    NotDue,
+    /// No pending rewards, or below `--min-claim-nam`
+    BelowMinimum,
+    /// The claim and bond fees would eat the whole reward
+    Unprofitable,
+    /// The estimated fees are above `--max-fee-nam`, or `--max-token-fee`
+    /// with a `--fee-token`
+    FeeTooHigh,
+    /// Paying the fees would take the liquid balance below
+    /// `--min-liquid-balance`, or the `--fee-token` balance can't pay them
+    LowBalance,
+    /// The claim went through but the balance didn't grow, so nothing was bonded
+    NoRewards,
+    /// The net APR is negative or above `--max-apr`, so the optimizer can't
+    /// be trusted
+    InvalidApr,
+    /// With `--observe-epochs`, the rewards a compound would have bonded,
+    /// nothing being submitted
+    Observed(token::Amount),
+    Compounded(CompoundRecord),
+}
+
+impl RoundOutcome {
+    /// For the control server's responses and the metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::DryRun => "dry_run",
+            Self::NoDelegations => "no_delegations",
+            Self::NotDue => "not_due",
+            Self::BelowMinimum => "below_minimum",
+            Self::Unprofitable => "unprofitable",
+            Self::FeeTooHigh => "fee_too_high",
+            Self::LowBalance => "low_balance",
+            Self::NoRewards => "no_rewards",
+            Self::InvalidApr => "invalid_apr",
+            Self::Observed(_) => "observed",
+            Self::Compounded(_) => "compounded",
+        }
+    }
+}
+
+/// What started a round.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Trigger {
+    /// The compounding loop, which follows the optimized schedule
+    Scheduled,
+    /// A `POST /compound` on the control server, compounding every validator
+    /// right away. The fee checks still apply.
+    Manual,
+}
+
+const RATIO_PRECISION: u64 = 1_000_000;
+
+/// Share of the pending rewards the balance change may differ from them by,
+/// e.g. because of the claim fee, before we suspect an unrelated transfer.
+const REWARDS_TOLERANCE: f64 = 0.01;
+
+/// Share of the bonded rewards the bonds may grow short of, e.g. from
+/// rounding, before we consider the bond didn't land.
+const BOND_CHECK_TOLERANCE: f64 = 0.01;
+
+/// Dry-run projections, printed to stdout with `--output json`.
+#[derive(Debug, Serialize)]
+pub struct DryRunReport {
+    pub compounding_frequency_hours: f64,
+    pub compounding_frequency_days: f64,
+    pub bonded_amount: f64,
+    pub projected_balance_1y: f64,
+    pub apr: f64,
+    /// Moving average of `apr` the frequency is optimized for
+    pub smoothed_apr: f64,
+    pub apy: f64,
+    /// The frequency was capped by `--min-hours-between-compounds`
+    pub frequency_clamped: bool,
+    pub next_round: NextRound,
+    pub validators: Vec<ValidatorProjection>,
+}
+
+/// What the next round does given the state, as [`next_round`] decides it.
+/// The pending rewards and the fees may still skip a compound that's due.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum NextRound {
+    CompoundNow,
+    /// Ahead of the schedule, with `--compound-on-epoch-change`
+    CompoundOnNewEpoch,
+    /// Bond what an unfinished round claimed, before claiming again
+    ResumeBond,
+    /// Claim what an unfinished `--round-order bond-then-claim` round bonded
+    /// ahead
+    ResumeClaim,
+    /// Only retry the claims that failed in an earlier epoch, from this many
+    /// validators
+    RetryClaims(usize),
+    /// Not due for this many seconds
+    WaitSecs(u64),
+    /// Not due before this epoch, with `--epoch-aligned`
+    WaitEpoch(u64),
+}
+
+impl Display for NextRound {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::CompoundNow => f.write_str("would compound now"),
+            Self::CompoundOnNewEpoch => f.write_str("would compound now, the epoch changed"),
+            Self::ResumeBond => f.write_str("would bond the rewards of an unfinished round"),
+            Self::ResumeClaim => {
+                f.write_str("would claim the rewards an unfinished round bonded ahead")
+            }
+            Self::RetryClaims(count) => write!(
+                f,
+                "would retry the failed claims from {} validator(s)",
+                count
+            ),
+            Self::WaitSecs(secs) => write!(
+                f,
+                "would wait {:.2} hours until the next reclaim",
+                *secs as f64 / 3600.0
+            ),
+            Self::WaitEpoch(epoch) => write!(f, "would wait for epoch {}", epoch),
+        }
+    }
+}
+
+/// The scheduling decision of a round over `validators` at `current_epoch`
+/// and the unix timestamp `now`, which [`run_round`] acts on and the dry-run
+/// reports. With `--per-validator-schedule`, that of the portfolio as a
+/// whole.
+pub fn next_round(
+    state: &State,
+    config: &AppConfig,
+    validators: &BTreeSet<Address>,
+    current_epoch: u64,
+    reclaim_interval: u64,
+    trigger: Trigger,
+    now: u64,
+) -> NextRound {
+    // The first round would only start the schedule
+    let (last_claimed, last_epoch) = if config.skip_first_compound && !state.claimed_first_time {
+        (Some(now), Some(current_epoch))
+    } else {
+        (
+            state
+                .claimed_first_time
+                .then_some(state.last_claimed_timestamp),
+            state.claimed_first_time.then_some(state.last_claimed_epoch),
+        )
+    };
+
+    if let Some(last_epoch) =
+        last_epoch.filter(|_| trigger == Trigger::Scheduled && config.epoch_aligned)
+    {
+        let due_epoch = last_epoch.saturating_add(config.epochs_between_compounds);
+        if current_epoch < due_epoch {
+            return NextRound::WaitEpoch(due_epoch);
+        }
+    }
+    if state.pending_bond.is_some() {
+        return NextRound::ResumeBond;
+    }
+    // Otherwise no longer delegated to, nothing left to claim
+    if state.pending_claim.as_ref().is_some_and(|pending| {
+        validators
+            .iter()
+            .any(|validator| pending.validators.contains(&validator.to_string()))
+    }) {
+        return NextRound::ResumeClaim;
+    }
+
+    let clock = Clock { now, last_claimed };
+    if trigger == Trigger::Manual || config.strategy.is_due(clock, reclaim_interval) {
+        return NextRound::CompoundNow;
+    }
+    let new_epoch = last_epoch.map_or(true, |last_epoch| current_epoch > last_epoch);
+    if config.compound_on_epoch_change && new_epoch {
+        return NextRound::CompoundOnNewEpoch;
+    }
+    let retry = validators
+        .iter()
+        .filter(|validator| state.should_retry_claim(validator, current_epoch))
+        .count();
+    if retry > 0 {
+        return NextRound::RetryClaims(retry);
+    }
+
+    let interval = config
+        .strategy
+        .interval(reclaim_interval)
+        .unwrap_or(reclaim_interval);
+    let elapsed = now.saturating_sub(last_claimed.unwrap_or(now));
+    NextRound::WaitSecs(interval.saturating_sub(elapsed))
+}
+
+/// One validator of the dry-run, as if it were compounded on its own.
+#[derive(Debug, Serialize)]
+pub struct ValidatorProjection {
+    pub address: String,
+    pub bond: f64,
+    pub commission: f64,
+    /// Share of the liveness window signed, 1.0 unless discounted by
+    /// `--uptime-window-epochs`
+    pub uptime: f64,
+    pub net_apr: f64,
+    /// `None` if compounding this validator alone never covers its fees
+    pub compounding_frequency_hours: Option<f64>,
+}
+
+/// Rewards gained between two balance reads, or `None` if the balance went
+/// down in the meantime (fees, or an unrelated outgoing transfer).
+pub fn rewards_delta(
+    balance_pre: token::Amount,
+    balance_post: token::Amount,
+) -> Option<token::Amount> {
+    balance_post.checked_sub(balance_pre)
+}
+
+/// Rewards to bond after a claim: the `pending` rewards queried right before
+/// it, sanity checked against the balance `delta`, which also catches fees
+/// and unrelated transfers. If they disagree beyond [`REWARDS_TOLERANCE`],
+/// the smaller one.
+pub fn claimed_rewards(pending: token::Amount, delta: token::Amount) -> token::Amount {
+    let (smaller, larger) = if pending <= delta {
+        (pending, delta)
+    } else {
+        (delta, pending)
+    };
+    let tolerance = split_rewards(pending, REWARDS_TOLERANCE)
+        .map(|(tolerance, _)| tolerance)
+        .unwrap_or_default();
+
+    if larger.checked_sub(smaller).unwrap_or_default() <= tolerance {
+        return pending;
+    }
+
+    tracing::warn!(
+        "Pending rewards ({}) and balance change ({}) disagree, bonding the smaller",
+        denom::display_nam(pending),
+        denom::display_nam(delta)
+    );
+    smaller
+}
+
+/// Fee of a single claim or bond tx at the chain's current minimum gas
+/// price, or `--base-fee-nam` if that can't be queried or the fees are paid
+/// in a `--fee-token`.
+pub async fn estimate_fee_per_tx<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    native_token_address: &Address,
+) -> f64 {
+    if config.fee_token.is_some() {
+        return config.base_fee_nam;
+    }
+
+    match rpc.query_min_gas_price(native_token_address).await {
+        Ok(gas_price) => gas_price * TX_GAS_LIMIT as f64,
+        Err(err) => {
+            tracing::warn!(
+                "Can't estimate fees from the chain ({:#}), using --base-fee-nam",
+                err
+            );
+            config.base_fee_nam
+        }
+    }
+}
+
+/// Whether bonding `to_bond` in `bond_txs` txs is worth more than their fees.
+pub fn covers_bond_fee(to_bond: token::Amount, bond_txs: usize, fee_per_tx: f64) -> bool {
+    to_bond > denom::amount_from_nam(bond_txs as f64 * fee_per_tx)
+}
+
+/// Bonds to build for `--validate-txs` and the `preflight` subcommand: the
+/// rewards aren't liquid before the claim, so they're built for a micro-NAM
+/// to each validator `--bond-target` may bond to.
+pub fn validation_distribution(
+    config: &AppConfig,
+    validators: &BTreeSet<Address>,
+) -> Vec<(Address, token::Amount)> {
+    let mut distribution = match &config.bond_target {
+        BondTarget::Validator(target) => vec![target.clone()],
+        BondTarget::Even | BondTarget::Proportional | BondTarget::BestApr => {
+            validators.iter().cloned().collect()
+        }
+    }
+    .into_iter()
+    .map(|validator| (validator, token::Amount::from_u64(1)))
+    .collect::<Vec<_>>();
+    distribution.sort();
+    distribution
+}
+
+/// Txs of a round compounding `validators` validators: one bond each, and
+/// one claim each or a single one with `batch_claims`.
+pub fn txs_per_round(validators: usize, batch_claims: bool) -> u64 {
+    let claims = if batch_claims {
+        validators.min(1)
+    } else {
+        validators
+    };
+    (claims + validators) as u64
+}
+
+/// Fee of compounding a single validator on its own: its claim and its bond.
+fn validator_fee(fee_per_tx: f64) -> RoundFee {
+    RoundFee::new(txs_per_round(1, false), fee_per_tx)
+}
+
+/// Split claimed rewards into the part to bond and the part left liquid.
+///
+/// The bonded part is rounded down to the token's native precision, so the
+/// two always add up to `rewards` exactly.
+pub fn split_rewards(
+    rewards: token::Amount,
+    compound_ratio: f64,
+) -> Option<(token::Amount, token::Amount)> {
+    let ratio_ppm = (compound_ratio * RATIO_PRECISION as f64).round() as u64;
+    let to_bond = rewards
+        .checked_mul(token::Amount::from_u64(ratio_ppm))?
+        .checked_div_u64(RATIO_PRECISION)?;
+
+    Some((to_bond, rewards.checked_sub(to_bond)?))
+}
+
+/// Amount each of `actions` takes of the `rewards` to compound: its share
+/// of them, capped at what the earlier ones left, or all of it without a
+/// share.
+pub fn action_amounts(
+    actions: &[RewardAction],
+    rewards: token::Amount,
+) -> Option<Vec<token::Amount>> {
+    let mut left = rewards;
+    actions
+        .iter()
+        .map(|action| {
+            let amount = match action.share() {
+                Some(share) => split_rewards(rewards, share)?.0.min(left),
+                None => left,
+            };
+            left = left.checked_sub(amount)?;
+            Some(amount)
+        })
+        .collect()
+}
+
+/// What `actions` bond of the `rewards` to compound.
+fn bonded_share(actions: &[RewardAction], rewards: token::Amount) -> anyhow::Result<token::Amount> {
+    let amounts = action_amounts(actions, rewards)
+        .context("Can't split the rewards between the --reward-actions")?;
+    Ok(actions
+        .iter()
+        .zip(amounts)
+        .filter(|(action, _)| matches!(action, RewardAction::Bond { .. }))
+        .fold(token::Amount::zero(), |total, (_, amount)| {
+            total.checked_add(amount).unwrap_or(total)
+        }))
+}
+
+/// How `amount` is spread over `validators` for `target`: the rounding
+/// remainder goes to the last one so the amounts add up to `amount`.
+/// `bonds` and `commissions` are by validator. Proportional bonds are even
+/// if the bond of any validator couldn't be queried.
+pub fn bond_distribution(
+    target: &BondTarget,
+    amount: token::Amount,
+    validators: &BTreeSet<Address>,
+    bonds: &HashMap<Address, f64>,
+    commissions: &HashMap<Address, f64>,
+) -> Option<Vec<(Address, token::Amount)>> {
+    let validators = validators.iter().cloned().collect::<Vec<_>>();
+    let bonds_known = validators
+        .iter()
+        .all(|validator| bonds.contains_key(validator));
+    if *target == BondTarget::Proportional && !bonds_known {
+        tracing::warn!(
+            "Bonding evenly, the bonds of some validators couldn't be queried to bond in proportion"
+        );
+    }
+
+    let weights = match target {
+        BondTarget::Validator(validator) => vec![(validator.clone(), 1.0)],
+        BondTarget::BestApr => vec![(lowest_commission(&validators, commissions)?, 1.0)],
+        BondTarget::Proportional
+            if bonds_known
+                && validators
+                    .iter()
+                    .any(|validator| bonds.get(validator).is_some_and(|bond| *bond > 0.0)) =>
+        {
+            validators
+                .into_iter()
+                .map(|validator| {
+                    let bond = bonds.get(&validator).copied().unwrap_or_default();
+                    (validator, bond.max(0.0))
+                })
+                .collect()
+        }
+        // Nothing bonded yet, or known, to be proportional to
+        BondTarget::Proportional | BondTarget::Even => validators
+            .into_iter()
+            .map(|validator| (validator, 1.0))
+            .collect(),
+    };
+
+    let total_weight = weights.iter().map(|(_, weight)| weight).sum::<f64>();
+    let last = weights.len().checked_sub(1)?;
+    let mut remaining = amount;
+
+    weights
+        .into_iter()
+        .enumerate()
+        .map(|(index, (validator, weight))| {
+            let share = if index == last {
+                remaining
+            } else {
+                split_rewards(amount, weight / total_weight)?.0
+            };
+            remaining = remaining.checked_sub(share)?;
+            Some((validator, share))
+        })
+        .collect()
+}
+
+/// The validator of `validators` paying the most rewards, net of its
+/// commission. An unknown commission counts as the whole rewards.
+fn lowest_commission<'a>(
+    validators: impl IntoIterator<Item = &'a Address>,
+    commissions: &HashMap<Address, f64>,
+) -> Option<Address> {
+    let commission = |validator: &Address| commissions.get(validator).copied().unwrap_or(1.0);
+    validators
+        .into_iter()
+        .min_by(|a, b| commission(a).total_cmp(&commission(b)))
+        .cloned()
+}
+
+/// The validator `--bond-target best-apr` keeps bonding to rather than
+/// `best`: the one `chosen` before, if still among `validators` and within
+/// `cooldown_epochs` of being chosen, unless `best` pays more than `margin`
+/// more rewards net of commission. `None` to go with `best`.
+pub fn sticky_target(
+    chosen: Option<&ChosenTarget>,
+    best: &Address,
+    validators: &BTreeSet<Address>,
+    commissions: &HashMap<Address, f64>,
+    epoch: u64,
+    cooldown_epochs: u64,
+    margin: f64,
+) -> Option<Address> {
+    let chosen = chosen.filter(|chosen| epoch < chosen.epoch.saturating_add(cooldown_epochs))?;
+    let current = validators
+        .iter()
+        .find(|validator| validator.to_string() == chosen.validator && *validator != best)?;
+
+    let net = |validator: &Address| 1.0 - commissions.get(validator).copied().unwrap_or(1.0);
+    let advantage = if net(current) > 0.0 {
+        net(best) / net(current) - 1.0
+    } else {
+        f64::INFINITY
+    };
+    (advantage <= margin).then(|| current.clone())
+}
+
+/// A consensus validator ranked by `--auto-select-validators`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RankedValidator {
+    pub address: Address,
+    pub stake: f64,
+    pub commission: Option<f64>,
+    pub uptime: Option<f64>,
+}
+
+/// The `top` of `validators` by stake, most first, less those holding more
+/// than `max_voting_power` of the whole stake.
+pub fn top_by_stake(
+    mut validators: Vec<(Address, f64)>,
+    top: usize,
+    max_voting_power: Option<f64>,
+) -> Vec<(Address, f64)> {
+    let total_stake = validators.iter().map(|(_, stake)| stake).sum::<f64>();
+    validators.sort_by(|(a, a_stake), (b, b_stake)| b_stake.total_cmp(a_stake).then(a.cmp(b)));
+    validators.truncate(top);
+
+    validators
+        .into_iter()
+        .filter(|(_, stake)| {
+            max_voting_power.map_or(true, |max| total_stake <= 0.0 || stake / total_stake <= max)
+        })
+        .collect()
+}
+
+/// `candidates` best first by `ranking`, ties going to the most staked. A
+/// validator whose commission or uptime is unknown comes last when ranked
+/// by it.
+pub fn rank_validators(
+    candidates: Vec<(Address, f64)>,
+    ranking: ValidatorRanking,
+    commissions: &HashMap<Address, f64>,
+    uptimes: &HashMap<Address, f64>,
+) -> Vec<RankedValidator> {
+    let mut ranked = candidates
+        .into_iter()
+        .map(|(address, stake)| RankedValidator {
+            commission: commissions.get(&address).copied(),
+            uptime: uptimes.get(&address).copied(),
+            address,
+            stake,
+        })
+        .collect::<Vec<_>>();
+    ranked.sort_by(|a, b| b.stake.total_cmp(&a.stake).then(a.address.cmp(&b.address)));
+
+    // Stable, so the stake order breaks ties
+    match ranking {
+        ValidatorRanking::Commission => ranked.sort_by(|a, b| {
+            let commission = |v: &RankedValidator| v.commission.unwrap_or(f64::INFINITY);
+            commission(a).total_cmp(&commission(b))
+        }),
+        ValidatorRanking::VotingPower => {}
+        ValidatorRanking::Uptime => ranked.sort_by(|a, b| {
+            let uptime = |v: &RankedValidator| v.uptime.unwrap_or(f64::NEG_INFINITY);
+            uptime(b).total_cmp(&uptime(a))
+        }),
+    }
+
+    ranked
+}
+
+/// The first `count` of `ranked`, except that within `cooldown_epochs` of
+/// the `chosen` selection, its validators still ranked keep their place
+/// unless the newcomer ranks more than `margin` better by `ranking`.
+pub fn sticky_selection(
+    ranked: &[RankedValidator],
+    count: usize,
+    ranking: ValidatorRanking,
+    chosen: Option<&ChosenValidators>,
+    epoch: u64,
+    cooldown_epochs: u64,
+    margin: f64,
+) -> Vec<RankedValidator> {
+    let mut selected = ranked.iter().take(count).cloned().collect::<Vec<_>>();
+    let Some(chosen) = chosen.filter(|chosen| epoch < chosen.epoch.saturating_add(cooldown_epochs))
+    else {
+        return selected;
+    };
+    let is_chosen =
+        |validator: &RankedValidator| chosen.validators.contains(&validator.address.to_string());
+
+    let score = |validator: &RankedValidator| match ranking {
+        ValidatorRanking::Commission => 1.0 - validator.commission.unwrap_or(1.0),
+        ValidatorRanking::VotingPower => validator.stake,
+        ValidatorRanking::Uptime => validator.uptime.unwrap_or(0.0),
+    };
+    // Best first, as the newcomers they'd replace go worst first
+    let mut incumbents = ranked
+        .iter()
+        .skip(count)
+        .filter(|validator| is_chosen(validator))
+        .peekable();
+    for newcomer in selected.iter_mut().rev() {
+        if is_chosen(newcomer) {
+            continue;
+        }
+        let Some(&incumbent) = incumbents.peek() else {
+            break;
+        };
+        let advantage = if score(incumbent) > 0.0 {
+            score(newcomer) / score(incumbent) - 1.0
+        } else {
+            f64::INFINITY
+        };
+        if advantage > margin {
+            // The better ranked newcomers beat it too
+            break;
+        }
+        incumbents.next();
+        *newcomer = incumbent.clone();
+    }
+
+    selected
+}
+
+/// The `--auto-select-count` best validators of the consensus set, logging
+/// how they ranked. Within `--target-cooldown-epochs` of the `chosen`
+/// selection, its validators keep their place as [`sticky_selection`] says.
+/// The commissions of the candidates are added to `commissions`.
+async fn auto_select_validators<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    commissions: &mut HashMap<Address, f64>,
+    chosen: Option<&ChosenValidators>,
+    epoch: u64,
+) -> anyhow::Result<BTreeSet<Address>> {
+    let candidates = top_by_stake(
+        rpc.query_consensus_validators(epoch).await?,
+        config.auto_select_top,
+        config.auto_select_max_voting_power,
+    );
+
+    let unknown = candidates
+        .iter()
+        .map(|(address, _)| address.clone())
+        .filter(|address| !commissions.contains_key(address))
+        .collect::<BTreeSet<_>>();
+    commissions.extend(
+        rpc.query_validators_commissions(&unknown, epoch, false)
+            .await?,
+    );
+    let uptimes = if config.auto_select_by == ValidatorRanking::Uptime {
+        rpc.query_uptime().await?
+    } else {
+        HashMap::new()
+    };
+
+    let ranked = rank_validators(candidates, config.auto_select_by, commissions, &uptimes);
+    tracing::info!(
+        "Auto-selecting {} of {} validators by {:?}:",
+        config.auto_select_count.min(ranked.len()),
+        ranked.len(),
+        config.auto_select_by
+    );
+    for (rank, validator) in ranked.iter().enumerate() {
+        let line = format!(
+            "{}. {}: stake {:.2}, commission {}, uptime {}",
+            rank + 1,
+            validator.address,
+            validator.stake,
+            validator
+                .commission
+                .map_or("unknown".to_string(), |commission| format!(
+                    "{:.2}%",
+                    commission * 100.0
+                )),
+            validator
+                .uptime
+                .map_or("unknown".to_string(), |uptime| format!(
+                    "{:.2}%",
+                    uptime * 100.0
+                )),
+        );
+        if rank < config.auto_select_count {
+            tracing::info!("{}", line);
+        } else {
+            tracing::debug!("{}", line);
+        }
+    }
+
+    let selected = sticky_selection(
+        &ranked,
+        config.auto_select_count,
+        config.auto_select_by,
+        chosen,
+        epoch,
+        config.target_cooldown_epochs,
+        config.target_switch_margin,
+    );
+    for (validator, top) in selected.iter().zip(&ranked) {
+        if validator.address != top.address {
+            tracing::info!(
+                "Keeping {} selected, {} doesn't rank enough better to switch",
+                validator.address,
+                top.address
+            );
+        }
+    }
+
+    Ok(selected
+        .into_iter()
+        .map(|validator| validator.address)
+        .collect())
+}
+
+/// Drop the jailed and inactive validators: new stake would earn nothing
+/// there. Rewards are still claimed from them.
+pub async fn bondable_validators<R: NamadaRpc>(
+    rpc: &R,
+    validators: BTreeSet<Address>,
+    epoch: u64,
+) -> anyhow::Result<BTreeSet<Address>> {
+    let mut bondable = BTreeSet::new();
+    let mut skipped = vec![];
+
+    for validator in validators {
+        match rpc.query_validator_state(&validator, epoch).await? {
+            Some(ValidatorState::Jailed) => skipped.push(format!("{} (jailed)", validator)),
+            Some(ValidatorState::Inactive) => skipped.push(format!("{} (inactive)", validator)),
+            None => skipped.push(format!("{} (not a validator)", validator)),
+            Some(_) => {
+                bondable.insert(validator);
+            }
+        }
+    }
+
+    if !skipped.is_empty() {
+        tracing::warn!("Not bonding to: {}", skipped.join(", "));
+    }
+
+    Ok(bondable)
+}
+
+/// Drop the validators with slashes pending or that the delegator is
+/// unbonding from: their bonds are about to change, so their share of the
+/// rewards stays liquid.
+pub async fn settled_validators<R: NamadaRpc>(
+    rpc: &R,
+    delegator: &Address,
+    validators: BTreeSet<Address>,
+    epoch: u64,
+) -> anyhow::Result<BTreeSet<Address>> {
+    let slashed = rpc.query_pending_slashes().await?;
+    let mut settled = BTreeSet::new();
+    let mut skipped = vec![];
+
+    for validator in validators {
+        if slashed.contains(&validator) {
+            skipped.push(format!("{} (slash pending)", validator));
+        } else if rpc
+            .has_pending_unbonds(delegator, &validator, epoch)
+            .await?
+        {
+            skipped.push(format!("{} (unbonding)", validator));
+        } else {
+            settled.insert(validator);
+        }
+    }
+
+    if !skipped.is_empty() {
+        tracing::warn!(
+            "Not bonding until settled, see --bond-during-slashing: {}",
+            skipped.join(", ")
+        );
+    }
+
+    Ok(settled)
+}
+
+/// Drop the validators charging more than `max_commission`, and recommend
+/// redelegating from them to the cheapest of `candidates`.
+pub fn within_max_commission(
+    validators: BTreeSet<Address>,
+    candidates: &BTreeSet<Address>,
+    commissions: &HashMap<Address, f64>,
+    max_commission: f64,
+) -> BTreeSet<Address> {
+    let commission = |validator: &Address| commissions.get(validator).copied().unwrap_or(1.0);
+    let (within, above): (BTreeSet<_>, BTreeSet<_>) = validators
+        .into_iter()
+        .partition(|validator| commission(validator) <= max_commission);
+
+    if above.is_empty() {
+        return within;
+    }
+
+    let cheapest = candidates
+        .iter()
+        .filter(|validator| commission(validator) <= max_commission)
+        .min_by(|a, b| commission(a).total_cmp(&commission(b)));
+    for validator in &above {
+        tracing::warn!(
+            "Not bonding to {}: commission {:.2}% is above --max-commission ({:.2}%)",
+            validator,
+            commission(validator) * 100.0,
+            max_commission * 100.0
+        );
+        match cheapest {
+            Some(cheapest) => tracing::warn!(
+                "Consider redelegating from {} to {} ({:.2}% commission)",
+                validator,
+                cheapest,
+                commission(cheapest) * 100.0
+            ),
+            None => tracing::warn!(
+                "Consider redelegating from {}, none of your validators is below --max-commission",
+                validator
+            ),
+        }
+    }
+
+    within
+}
+
+/// Uptime of each of `validators` averaged over `--uptime-window-epochs`, or
+/// none if unset. Validators missing, e.g. out of the consensus set, aren't
+/// discounted.
+pub async fn uptime_discounts<R: NamadaRpc>(
+    rpc: &R,
+    state: &mut State,
+    config: &AppConfig,
+    validators: &BTreeSet<Address>,
+    epoch: u64,
+) -> anyhow::Result<HashMap<Address, f64>> {
+    let Some(window) = config.uptime_window_epochs else {
+        return Ok(HashMap::new());
+    };
+
+    let uptimes = rpc.query_uptime().await?;
+    Ok(validators
+        .iter()
+        .filter_map(|validator| {
+            let uptime = *uptimes.get(validator)?;
+            let average = state.average_uptime(validator, uptime, epoch, window);
+            Some((validator.clone(), average))
+        })
+        .collect())
+}
+
+fn uptime_of(uptimes: &HashMap<Address, f64>, validator: &Address) -> f64 {
+    uptimes.get(validator).copied().unwrap_or(1.0)
+}
+
+/// Mean of `uptimes` weighted by `bonds`, the share of the nominal APR the
+/// whole delegation earns.
+pub fn portfolio_uptime(uptimes: &HashMap<Address, f64>, bonds: &HashMap<Address, f64>) -> f64 {
+    let total = bonds.values().sum::<f64>();
+    if total <= 0.0 {
+        return 1.0;
+    }
+
+    bonds
+        .iter()
+        .map(|(validator, bond)| bond * uptime_of(uptimes, validator))
+        .sum::<f64>()
+        / total
+}
+
+/// What every validator projection shares, for [`validator_projections`].
+#[derive(Clone, Copy, Debug)]
+pub struct ChainRates {
+    pub pos_inflation: f64,
+    /// Paid for each tx of compounding a validator on its own
+    pub fee_per_tx: f64,
+    /// `None` if the epoch duration is unknown
+    pub epochs_per_year: Option<f64>,
+}
+
+/// Bond, commission, net APR and optimal frequency of each validator, sorted
+/// by address. `bonds`, `commissions` and `uptimes` are by validator. The
+/// validators whose commission couldn't be queried are left out, rather
+/// than projected at no commission.
+pub fn validator_projections(
+    config: &AppConfig,
+    validators: &BTreeSet<Address>,
+    bonds: &HashMap<Address, f64>,
+    commissions: &HashMap<Address, f64>,
+    uptimes: &HashMap<Address, f64>,
+    rates: ChainRates,
+) -> Vec<ValidatorProjection> {
+    let fee = validator_fee(rates.fee_per_tx);
+
+    validators
+        .iter()
+        .filter_map(|validator| {
+            let bond = bonds.get(validator).copied().unwrap_or_default();
+            let commission = known_commission(commissions, validator)?;
+            let uptime = uptime_of(uptimes, validator);
+            let net_apr = rates.pos_inflation * (1.0 - commission) * uptime;
+            let compounding_frequency_hours = config
+                .optimizer
+                .optimize(
+                    bond,
+                    net_apr,
+                    fee,
+                    rates.epochs_per_year,
+                    config.horizon_years,
+                    config.max_frequency(),
+                )
+                .map(|result| result.hours_between_compounding());
+
+            Some(ValidatorProjection {
+                address: validator.to_string(),
+                bond,
+                commission,
+                uptime,
+                net_apr,
+                compounding_frequency_hours,
+            })
+        })
+        .collect()
+}
+
+/// Commission of `validator`, `None` with a warning if it couldn't be
+/// queried.
+fn known_commission(commissions: &HashMap<Address, f64>, validator: &Address) -> Option<f64> {
+    let commission = commissions.get(validator).copied();
+    if commission.is_none() {
+        tracing::warn!(
+            "Skipping validator {}, its commission couldn't be queried",
+            validator
+        );
+    }
+    commission
+}
+
+/// The chain's staking rewards rate, or `--fallback-apr` if it can't be
+/// queried, e.g. on a chain version without the query.
+pub async fn inflation_rate<R: NamadaRpc>(rpc: &R, config: &AppConfig) -> anyhow::Result<f64> {
+    match rpc.get_pos_inflation_rate().await {
+        Ok(rate) => Ok(rate),
+        Err(err) => match config.fallback_apr {
+            Some(fallback_apr) => {
+                tracing::warn!(
+                    "Can't query the staking rewards rate, using --fallback-apr {}: {:#}",
+                    fallback_apr,
+                    err
+                );
+                Ok(fallback_apr)
+            }
+            None => Err(anyhow::Error::new(err)
+                .context("Can't query the staking rewards rate, set --fallback-apr to carry on")),
+        },
+    }
+}
+
+/// Validators `delegator` bonded to, narrowed down by `--only-validators` and
+/// `--exclude-validators`.
+pub async fn delegations<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    delegator: &Address,
+    epoch: u64,
+) -> anyhow::Result<BTreeSet<Address>> {
+    let validators = rpc.get_delegators_validators(delegator, epoch).await?;
+    let validators = utils::filter_validators(
+        validators,
+        &config.only_validators,
+        &config.exclude_validators,
+    )?;
+
+    Ok(validators)
+}
+
+/// One iteration of the compounding loop: refresh the chain view, optimize
+/// the frequency and, if due, claim the rewards and bond them back.
+pub async fn run_once<R: NamadaRpc>(
+    rpc: &R,
+    state: &mut State,
+    config: &AppConfig,
+    secret_key: &SecretKey,
+) -> anyhow::Result<RoundOutcome> {
+    run_round(rpc, state, config, secret_key, Trigger::Scheduled).await
+}
+
+/// Like [`run_once`], but a [`Trigger::Manual`] round skips the schedule.
+pub async fn run_round<R: NamadaRpc>(
+    rpc: &R,
+    state: &mut State,
+    config: &AppConfig,
+    secret_key: &SecretKey,
+    trigger: Trigger,
+) -> anyhow::Result<RoundOutcome> {
+    let delegator_address = Address::from(&secret_key.to_public());
+
+    if let Some(anomaly) = state.anomaly.as_ref().filter(|_| !config.dry_run) {
+        return Err(
+            TaskError::Anomaly(format!("{}, restart with --clear-anomaly", anomaly)).into(),
+        );
+    }
+
+    let current_epoch = rpc.get_current_epoch().await?;
+
+    let pos_inflation = inflation_rate(rpc, config).await?;
+
+    tracing::info!("Inflation rate is: {}", pos_inflation);
+
+    let validators = delegations(rpc, config, &delegator_address, current_epoch).await?;
+
+    // New validators join the next compound, and with
+    // `--per-validator-schedule` are due right away
+    let (added, removed) = state.reconcile_delegations(&validators);
+    if !added.is_empty() || !removed.is_empty() {
+        tracing::warn!(
+            "Delegations changed, added: [{}], removed: [{}]",
+            added.join(", "),
+            removed.join(", ")
+        );
+    }
+
+    if validators.is_empty() {
+        tracing::info!("No active delegations, sleeping...");
+        return Ok(RoundOutcome::NoDelegations);
+    }
+
+    let mut commissions = rpc
+        .query_validators_commissions(&validators, current_epoch, config.strict)
+        .await?;
+
+    let bonds = rpc
+        .query_bonds(
+            &validators,
+            &delegator_address,
+            current_epoch,
+            config.strict,
+        )
+        .await?;
+
+    let bonded_amount = bonds.values().sum::<f64>();
+    let bonded_drop = state.record_bonded(bonded_amount);
+    if let Some(drop) = bonded_drop.filter(|drop| {
+        !config.dry_run
+            && config
+                .anomaly_threshold_pct
+                .is_some_and(|threshold| *drop > threshold)
+    }) {
+        return Err(halt(
+            state,
+            format!("bonded amount dropped {:.2}% since the last round", drop),
+        ));
+    }
+    if bonded_amount > opt::MAX_EXACT_AMOUNT {
+        tracing::warn!(
+            "Bonded amount {} is beyond the exact f64 range, the projections are rounded",
+            bonded_amount
+        );
+    }
+
+    let commission = config
+        .commission_aggregation
+        .aggregate(&commissions, &bonds)
+        .context("Can't compute validator commissions")?;
+
+    let uptimes = uptime_discounts(rpc, state, config, &validators, current_epoch).await?;
+    let uptime = portfolio_uptime(&uptimes, &bonds);
+    if config.uptime_window_epochs.is_some() {
+        tracing::info!("Uptime of the delegation: {:.2}%", uptime * 100.0);
+    }
+
+    let net_apr = (pos_inflation - (pos_inflation * commission)) * uptime;
+    // Checked before it makes it into the moving average
+    let net_apr = match opt::checked_apr(net_apr, config.max_apr) {
+        Ok(net_apr) => net_apr,
+        Err(err) => {
+            tracing::warn!("{}, skipping...", err);
+            return Ok(RoundOutcome::InvalidApr);
+        }
+    };
+    let smoothed_apr = state.smooth_apr(net_apr, current_epoch, config.apr_ema_alpha);
+
+    let native_token_address = rpc.query_native_token().await?;
+
+    let estimated_fee_per_tx = estimate_fee_per_tx(rpc, config, &native_token_address).await;
+    // What the txs actually cost so far beats the worst case at the gas limit
+    let fee_per_tx = state.avg_fee_per_tx.unwrap_or(estimated_fee_per_tx);
+    if fee_per_tx.is_nan() || fee_per_tx <= 0.0 {
+        tracing::warn!(
+            "Fee estimate of {} per tx, compounding at most once per epoch",
+            denom::display_nam_f64(fee_per_tx)
+        );
+    }
+
+    let round_fee = RoundFee::new(
+        txs_per_round(validators.len(), config.batch_claims),
+        fee_per_tx,
+    );
+
+    // Rewards come once per epoch, which caps what compounding more often
+    // can earn. Waking up mid-epoch would find no new rewards either.
+    let epoch_secs = rpc
+        .epoch_duration()
+        .await
+        .inspect_err(|err| tracing::warn!("Can't query the epoch duration: {:#}", err))
+        .ok()
+        .map(|duration| duration.as_secs());
+    let epochs_per_year = opt::epochs_per_year(epoch_secs);
+
+    let optimization_result = state
+        .optimization
+        .optimize(
+            config.optimizer,
+            OptimizationInputs {
+                principal: bonded_amount,
+                apr: smoothed_apr,
+                fee: round_fee.total(),
+                epochs_per_year,
+                horizon_years: config.horizon_years,
+                max_frequency: config.max_frequency(),
+            },
+            config.reoptimize_epsilon,
+        )
+        .context("Failed optimizing frequency")?
+        .with_safety_factor(
+            config.frequency_safety_factor,
+            bonded_amount,
+            config.horizon_years,
+        );
+    let optimization_result = match config.target_apy {
+        Some(target_apy) => {
+            let (result, shortfall) = optimization_result.with_target_apy(
+                target_apy,
+                bonded_amount,
+                config.horizon_years,
+            );
+            match shortfall {
+                Some(shortfall) => tracing::warn!(
+                    "--target-apy {:.2}% is out of reach at these fees, compounding for the most ({:.2}%, {:.2}% short)",
+                    target_apy * 100.0,
+                    result.apy(bonded_amount) * 100.0,
+                    shortfall * 100.0
+                ),
+                None => tracing::info!(
+                    "Compounding every {:.2} hours for an APY of {:.2}%, per --target-apy",
+                    result.hours_between_compounding(),
+                    result.apy(bonded_amount) * 100.0
+                ),
+            }
+            result
+        }
+        None => optimization_result,
+    };
+    if optimization_result.clamped {
+        tracing::info!(
+            "Compounding frequency capped by --min-hours-between-compounds ({}h)",
+            config.min_hours_between_compounds
+        );
+    }
+
+    state.last_round = Some(LastRound {
+        epoch: current_epoch,
+        bonded_amount,
+        net_apr,
+        optimal_frequency_hours: optimization_result.hours_between_compounding(),
+    });
+    events::emit(Event::Optimized {
+        delegator: delegator_address.to_string(),
+        epoch: current_epoch,
+        bonded_amount: denom::amount_from_nam(bonded_amount).to_string_native(),
+        net_apr,
+        optimal_frequency_hours: optimization_result.hours_between_compounding(),
+    });
+
+    METRICS.bonded_amount.set(bonded_amount);
+    METRICS.net_apr.set(net_apr);
+    METRICS
+        .optimal_frequency
+        .set(optimization_result.optimal_frequency as f64);
+
+    let reclaim_interval =
+        optimization_result.interval_secs(config.interval_rounding, epoch_secs) as u64;
+
+    let decision = next_round(
+        state,
+        config,
+        &validators,
+        current_epoch,
+        reclaim_interval,
+        trigger,
+        scheduler::now(),
+    );
+
+    if config.dry_run {
+        let projections = validator_projections(
+            config,
+            &validators,
+            &bonds,
+            &commissions,
+            &uptimes,
+            ChainRates {
+                pos_inflation,
+                fee_per_tx,
+                epochs_per_year,
+            },
+        );
+
+        match config.output {
+            OutputFormat::Text => {
+                tracing::info!("Dry-run mode");
+                for projection in &projections {
+                    let frequency = projection
+                        .compounding_frequency_hours
+                        .map_or("never".to_string(), |hours| {
+                            format!("every {:.2} hours", hours)
+                        });
+                    tracing::info!(
+                        "- {}: bond {:.2}, commission {:.2}%, APR {:.2}%, compound {}",
+                        projection.address,
+                        projection.bond,
+                        projection.commission * 100.0,
+                        projection.net_apr * 100.0,
+                        frequency
+                    );
+                }
+                tracing::info!("Portfolio:");
+                tracing::info!(
+                    "- Compunding frequency: {:.2} hours / {:.2} days",
+                    optimization_result
+                        .hours_between_compounding_rounded(config.interval_rounding, epoch_secs),
+                    optimization_result
+                        .days_between_compounding_rounded(config.interval_rounding, epoch_secs)
+                );
+                tracing::info!("- Current bonded balance: {:.2}", bonded_amount);
+                tracing::info!(
+                    "- Balance in {} year(s): {:.2}",
+                    config.horizon_years,
+                    optimization_result.max_balance
+                );
+                tracing::info!("- APR: {:.2}%", net_apr * 100.0);
+                tracing::info!("- Smoothed APR: {:.2}%", smoothed_apr * 100.0);
+                tracing::info!(
+                    "- APY: {:.2}%",
+                    optimization_result.apy(bonded_amount) * 100.0
+                );
+                tracing::info!("Next round: {}", decision);
+            }
+            OutputFormat::Json => {
+                let projected_balance_1y = opt::compound_balance(
+                    bonded_amount,
+                    smoothed_apr,
+                    round_fee.total(),
+                    optimization_result.optimal_frequency,
+                    epochs_per_year,
+                    1.0,
+                );
+                let report = DryRunReport {
+                    compounding_frequency_hours: optimization_result.hours_between_compounding(),
+                    compounding_frequency_days: optimization_result.days_between_compounding(),
+                    bonded_amount,
+                    projected_balance_1y,
+                    apr: net_apr,
+                    smoothed_apr,
+                    apy: optimization_result.apy(bonded_amount),
+                    frequency_clamped: optimization_result.clamped,
+                    next_round: decision,
+                    validators: projections,
+                };
+                println!("{}", serde_json::to_string(&report)?);
+            }
+        }
+
+        if config.validate_txs {
+            let distribution = validation_distribution(config, &validators);
+            rpc.validate_txs(&delegator_address, &validators, &distribution, secret_key)
+                .await?;
+            tracing::info!("The claim and bond txs build and sign fine");
+        }
+
+        return Ok(RoundOutcome::DryRun);
+    }
+
+    // The first round starts the schedule instead of being due right away
+    if config.skip_first_compound && !state.claimed_first_time {
+        tracing::info!("Skipping the first compound, scheduling it from now");
+        state.update_validators(&validators, current_epoch);
+    }
+
+    if let NextRound::WaitEpoch(due_epoch) = decision {
+        tracing::info!("Waiting for epoch {} to compound...", due_epoch);
+        return Ok(RoundOutcome::NotDue);
+    }
+
+    let ctx = RoundContext {
+        rpc,
+        config,
+        secret_key,
+        delegator: &delegator_address,
+        native_token: &native_token_address,
+        epoch: current_epoch,
+        bonds: &bonds,
+        fee_per_tx,
+        estimated_fee_per_tx,
+        reclaim_interval,
+    };
+
+    if let (NextRound::ResumeBond, Some(claim)) = (decision, state.pending_bond.clone()) {
+        return resume_bond(&ctx, state, &validators, &mut commissions, claim).await;
+    }
+    if let (NextRound::ResumeClaim, Some(pending)) = (decision, state.pending_claim.clone()) {
+        return resume_claim(&ctx, state, &validators, pending).await;
+    }
+    // Anything left is from validators no longer delegated to, nothing
+    // left to claim
+    state.pending_claim = None;
+
+    // With `--per-validator-schedule`, each validator's own interval
+    // Only retrying the failed claims of the last round leaves the schedule
+    let (validators, validator_intervals, retrying) = if trigger == Trigger::Manual {
+        tracing::info!("Compounding on demand, ignoring the schedule");
+        (validators, None, false)
+    } else if config.per_validator_schedule && config.strategy == Strategy::Optimizer {
+        let schedule = opt::compute_frequency_per_validator(
+            config.optimizer,
+            validators.iter().filter_map(|validator| {
+                let bond = bonds.get(validator).copied().unwrap_or_default();
+                let commission = known_commission(&commissions, validator)?;
+                let apr = pos_inflation * (1.0 - commission) * uptime_of(&uptimes, validator);
+                Some((validator, bond, apr))
+            }),
+            // The same claim and bond txs for each
+            |_| Some(validator_fee(fee_per_tx)),
+            epochs_per_year,
+            config.horizon_years,
+            config.max_frequency(),
+        );
+
+        let intervals = schedule.iter().map(|(validator, result)| {
+            let result = result.clone().with_safety_factor(
+                config.frequency_safety_factor,
+                bonds.get(validator).copied().unwrap_or_default(),
+                config.horizon_years,
+            );
+            let interval = result.interval_secs(config.interval_rounding, epoch_secs);
+            (validator, interval as u64)
+        });
+        let due = intervals
+            .clone()
+            .filter(|(validator, interval)| state.should_reclaim_validator(validator, *interval))
+            .map(|(validator, _)| validator.clone())
+            .collect::<BTreeSet<_>>();
+
+        if due.is_empty() {
+            state.schedule_validators(intervals.clone());
+            let next_reclaim_in = intervals
+                .map(|(validator, interval)| state.next_validator_reclaim_in(validator, interval))
+                .min()
+                .unwrap_or_default();
+            tracing::info!("Next reclaim in {} hours...", next_reclaim_in / 60 / 60);
+            return Ok(RoundOutcome::NotDue);
+        }
+
+        // Left out of the schedule without a bond, their rewards go with
+        // the due ones
+        let due = due
+            .into_iter()
+            .chain(unbonded_validators(&validators, &bonds))
+            .collect::<BTreeSet<_>>();
+        tracing::info!("Due validators: {}/{}", due.len(), validators.len());
+        let intervals = intervals
+            .map(|(validator, interval)| (validator.clone(), interval))
+            .collect::<HashMap<_, _>>();
+        (due, Some(intervals), false)
+    } else {
+        // Whether the pending rewards are worth it is checked below
+        match decision {
+            NextRound::CompoundOnNewEpoch => {
+                tracing::info!(
+                    "Epoch {} is past the last compound's, compounding early",
+                    current_epoch
+                );
+                (validators, None, false)
+            }
+            NextRound::RetryClaims(count) => {
+                tracing::info!(
+                    "Retrying the claims from {} validator(s) that failed in an earlier epoch",
+                    count
+                );
+                let retry = validators
+                    .iter()
+                    .filter(|validator| state.should_retry_claim(validator, current_epoch))
+                    .cloned()
+                    .collect::<BTreeSet<_>>();
+                (retry, None, true)
+            }
+            NextRound::WaitSecs(_) => {
+                // Only a threshold has no interval, and it's always due
+                let interval = config
+                    .strategy
+                    .interval(reclaim_interval)
+                    .unwrap_or(reclaim_interval);
+                state.schedule(interval);
+                tracing::info!(
+                    "Next reclaim in {} hours...",
+                    state.next_reclaim_in(interval) / 60 / 60
+                );
+                return Ok(RoundOutcome::NotDue);
+            }
+            _ => (validators, None, false),
+        }
+    };
+
+    // Only the validators compounded this round
+    let estimated_fee = RoundFee::new(
+        txs_per_round(validators.len(), config.batch_claims),
+        fee_per_tx,
+    )
+    .total();
+
+    // Fees paid in a `--fee-token` are capped in it below
+    if let Some(max_fee) = config.max_fee_nam.filter(|_| config.fee_token.is_none()) {
+        if estimated_fee > max_fee {
+            tracing::warn!(
+                "Estimated fee ({}) exceeds --max-fee-nam ({}), skipping...",
+                denom::display_nam_f64(estimated_fee),
+                denom::display_nam_f64(max_fee)
+            );
+            return Ok(RoundOutcome::FeeTooHigh);
+        }
+    }
+
+    let pending = rpc
+        .query_pos_rewards(&validators, &delegator_address)
+        .await?;
+    // Compared exactly from here on, the f64 amounts only feed the optimizer
+    if let Some(threshold) = config.anomaly_threshold_pct.filter(|_| !config.dry_run) {
+        if pending > denom::amount_from_nam(bonded_amount * threshold / 100.0) {
+            return Err(halt(
+                state,
+                format!(
+                    "pending rewards of {} are more than {}% of the bonded amount",
+                    denom::display_nam(pending),
+                    threshold
+                ),
+            ));
+        }
+    }
+
+    // Even with `--force`, claiming nothing would only pay fees
+    if pending.is_zero() {
+        tracing::info!("No pending rewards, skipping...");
+        return Ok(RoundOutcome::BelowMinimum);
+    }
+
+    if pending < denom::amount_from_nam(config.min_claim_nam) {
+        tracing::info!(
+            "Pending rewards ({}) are below the minimum claim ({}), skipping...",
+            denom::display_nam(pending),
+            denom::display_nam_f64(config.min_claim_nam)
+        );
+        return Ok(RoundOutcome::BelowMinimum);
+    }
+
+    if trigger == Trigger::Scheduled && !config.strategy.enough_rewards(pending) {
+        tracing::info!(
+            "Pending rewards ({}) are below the {} strategy, skipping...",
+            denom::display_nam(pending),
+            config.strategy
+        );
+        return Ok(RoundOutcome::BelowMinimum);
+    }
+
+    if !config.force && denom::amount_from_nam(estimated_fee) >= pending {
+        tracing::info!(
+            "Skipping: fee exceeds reward ({} >= {})",
+            denom::display_nam_f64(estimated_fee),
+            denom::display_nam(pending)
+        );
+        return Ok(RoundOutcome::Unprofitable);
+    }
+
+    // Check before claiming, so the rewards don't end up stuck liquid
+    if let BondTarget::Validator(target) = &config.bond_target {
+        anyhow::ensure!(
+            rpc.query_validator_state(target, current_epoch)
+                .await?
+                .is_some(),
+            "--bond-target {} is not a validator",
+            target
+        );
+    }
+
+    let needs_balance = match config.round_order {
+        RoundOrder::ClaimThenBond => config.claimed_amount == ClaimedAmount::BalanceDiff,
+        // To check it can take the bond
+        RoundOrder::BondThenClaim => true,
+    };
+    let balance_pre = if needs_balance || config.min_liquid_balance.is_some() {
+        let balance_pre = rpc
+            .query_balance(&delegator_address, &native_token_address)
+            .await?;
+        tracing::info!("Pre balance: {}", denom::display_nam(balance_pre));
+        Some(balance_pre)
+    } else {
+        None
+    };
+
+    if let Some(fee_token) = &config.fee_token {
+        let txs = txs_per_round(validators.len(), config.batch_claims);
+        // In whole tokens of the fee token, not NAM
+        let fee = rpc.query_min_gas_price(fee_token).await? * (txs * TX_GAS_LIMIT) as f64;
+        if let Some(max_fee) = config.max_token_fee {
+            if fee > max_fee {
+                tracing::warn!(
+                    "Estimated fee ({} of fee token {}) exceeds --max-token-fee ({}), skipping...",
+                    fee,
+                    fee_token,
+                    max_fee
+                );
+                return Ok(RoundOutcome::FeeTooHigh);
+            }
+        }
+
+        let decimals = rpc.query_denomination(fee_token).await?;
+        let fee = denom::amount_from_whole(fee, decimals);
+        let balance = rpc.query_balance(&delegator_address, fee_token).await?;
+        if balance < fee {
+            let denominated =
+                |amount| token::DenominatedAmount::new(amount, token::Denomination(decimals));
+            tracing::warn!(
+                "Balance of fee token {} ({}) can't pay the estimated fee ({}), skipping...",
+                fee_token,
+                denominated(balance),
+                denominated(fee)
+            );
+            return Ok(RoundOutcome::LowBalance);
+        }
+    }
+
+    // The fees come out of the liquid balance, not the rewards, unless paid
+    // in another token
+    if let (Some(min_liquid_balance), Some(balance_pre)) = (config.min_liquid_balance, balance_pre)
+    {
+        let native_fee = if config.fee_token.is_some() {
+            token::Amount::zero()
+        } else {
+            denom::amount_from_nam(estimated_fee)
+        };
+        let liquid = balance_pre.checked_sub(native_fee).unwrap_or_default();
+        if liquid < denom::amount_from_nam(min_liquid_balance) {
+            tracing::warn!(
+                "Balance ({}) less the estimated fee ({}) is below --min-liquid-balance ({}), skipping...",
+                denom::display_nam(balance_pre),
+                denom::display_nam_f64(estimated_fee),
+                denom::display_nam_f64(min_liquid_balance)
+            );
+            return Ok(RoundOutcome::LowBalance);
+        }
+    }
+
+    if config.observe_epochs.is_some() {
+        tracing::info!(
+            "Would compound {} from {} validator(s)",
+            denom::display_nam(pending),
+            validators.len()
+        );
+        // As if it went through, so the next rounds decide as they would
+        state.update_validators(&validators, current_epoch);
+        schedule_next(
+            state,
+            config,
+            validator_intervals.as_ref(),
+            reclaim_interval,
+        );
+        return Ok(RoundOutcome::Observed(pending));
+    }
+
+    let projected_gain = state
+        .projected_rewards(
+            validators.iter().map(|validator| {
+                let bond = bonds.get(validator).copied().unwrap_or_default();
+                (validator, bond)
+            }),
+            smoothed_apr,
+        )
+        .map(|rewards| rewards - estimated_fee);
+    let mut compound = Compound {
+        claimed_from: validators.clone(),
+        validators,
+        retrying,
+        pending,
+        balance_pre,
+        projected_gain,
+        claim: None,
+        receipts: vec![],
+        bonded: token::Amount::zero(),
+    };
+
+    // Known before the claim when bonding first
+    if config.round_order == RoundOrder::BondThenClaim {
+        let (bond_first, retained) = compound.split(&ctx, pending, None)?;
+        let native_fee = if config.fee_token.is_some() {
+            token::Amount::zero()
+        } else {
+            denom::amount_from_nam(estimated_fee)
+        };
+        let needed = bond_first
+            .amount
+            .checked_add(native_fee)
+            .unwrap_or_default();
+        if balance_pre.is_some_and(|balance_pre| balance_pre < needed) {
+            tracing::warn!(
+                "Balance ({}) can't bond {} and pay the fees before claiming, skipping...",
+                denom::display_nam(balance_pre.unwrap_or_default()),
+                denom::display_nam(bond_first.amount)
+            );
+            return Ok(RoundOutcome::LowBalance);
+        }
+        compound.claim = Some((bond_first, retained));
+    }
+
+    let steps = round_steps(config.round_order);
+    for (i, step) in steps.iter().enumerate() {
+        let later = &steps[i + 1..];
+        match step {
+            RoundStep::Claim => {
+                let bond_after = later.contains(&RoundStep::Actions);
+                if let Some(outcome) = claim_step(&ctx, state, &mut compound, bond_after).await? {
+                    return Ok(outcome);
+                }
+            }
+            RoundStep::Actions => {
+                let claim_after = later.contains(&RoundStep::Claim);
+                actions_step(&ctx, state, &mut commissions, &mut compound, claim_after).await?;
+            }
+            RoundStep::SendRetained => send_retained_step(&ctx, &mut compound).await?,
+        }
+    }
+    let (claim, _) = compound.claim.context("Round ended without claiming")?;
+
+    if compound.retrying {
+        state.update_retried(&compound.claimed_from);
+    } else {
+        state.update_validators(&compound.claimed_from, current_epoch);
+        schedule_next(
+            state,
+            config,
+            validator_intervals.as_ref(),
+            reclaim_interval,
+        );
+    }
+
+    let fees = FeeEstimate {
+        total: estimated_fee,
+        per_tx: estimated_fee_per_tx,
+    };
+    Ok(compounded::<R>(
+        state,
+        config,
+        &claim,
+        compound.bonded,
+        compound.receipts,
+        compound.claimed_from.len(),
+        fees,
+    ))
+}
+
+/// The chain as a round found it, for the functions carrying the round out.
+struct RoundContext<'a, R> {
+    rpc: &'a R,
+    config: &'a AppConfig,
+    secret_key: &'a SecretKey,
+    delegator: &'a Address,
+    native_token: &'a Address,
+    epoch: u64,
+    bonds: &'a HashMap<Address, f64>,
+    fee_per_tx: f64,
+    /// From the gas price, where `fee_per_tx` may be the average paid
+    estimated_fee_per_tx: f64,
+    reclaim_interval: u64,
+}
+
+/// A compound in progress: what it claims, and what its [`RoundStep`]s did
+/// so far.
+struct Compound {
+    /// Validators compounded this round
+    validators: BTreeSet<Address>,
+    /// Only retrying the failed claims of an earlier round, which leaves the
+    /// schedule
+    retrying: bool,
+    pending: token::Amount,
+    balance_pre: Option<token::Amount>,
+    projected_gain: Option<f64>,
+    /// The rewards to compound and those retained, known before the claim
+    /// when bonding first
+    claim: Option<(PendingBond, token::Amount)>,
+    receipts: Vec<TxReceipt>,
+    bonded: token::Amount,
+    /// Those whose claim failed are due again next round
+    claimed_from: BTreeSet<Address>,
+}
+
+impl Compound {
+    /// Split `rewards` per `--compound-ratio` into the claim to compound and
+    /// the rewards it retains.
+    fn split<R>(
+        &self,
+        ctx: &RoundContext<'_, R>,
+        rewards: token::Amount,
+        balance_post: Option<token::Amount>,
+    ) -> anyhow::Result<(PendingBond, token::Amount)> {
+        let (to_bond, retained) = split_rewards(rewards, ctx.config.compound_ratio(ctx.delegator))
+            .context("Can't split the rewards")?;
+        tracing::info!(
+            "Compounding {}, retaining {}",
+            denom::display_nam(to_bond),
+            denom::display_nam(retained)
+        );
+
+        let claim = PendingBond {
+            epoch: ctx.epoch,
+            rewards,
+            amount: to_bond,
+            balance_pre: self.balance_pre,
+            balance_post,
+            projected_gain: self.projected_gain,
+        };
+        Ok((claim, retained))
+    }
+}
+
+/// Bond the rewards `claim`ed by a round that crashed or failed bonding,
+/// before claiming anything new.
+async fn resume_bond<R: NamadaRpc>(
+    ctx: &RoundContext<'_, R>,
+    state: &mut State,
+    validators: &BTreeSet<Address>,
+    commissions: &mut HashMap<Address, f64>,
+    claim: PendingBond,
+) -> anyhow::Result<RoundOutcome> {
+    if ctx.config.observe_epochs.is_some() {
+        tracing::info!(
+            "Would bond {} claimed at epoch {} by an unfinished round",
+            denom::display_nam(claim.amount),
+            claim.epoch
+        );
+        state.pending_bond = None;
+        return Ok(RoundOutcome::Observed(claim.amount));
+    }
+    tracing::info!(
+        "Bonding {} claimed at epoch {} by an unfinished round",
+        denom::display_nam(claim.amount),
+        claim.epoch
+    );
+    let (bonded, receipts) = bond_rewards(
+        ctx.rpc,
+        ctx.config,
+        ctx.secret_key,
+        claim.amount,
+        BondContext {
+            validators,
+            bonds: ctx.bonds,
+            commissions,
+            chosen_target: &mut state.chosen_target,
+            auto_selected: &mut state.auto_selected,
+            fee_per_tx: ctx.fee_per_tx,
+            epoch: ctx.epoch,
+        },
+    )
+    .await
+    .map_err(|err| bond_anomaly(state, err, false))?;
+    state.pending_bond = None;
+
+    state.update_validators(validators, claim.epoch);
+    state.schedule(ctx.reclaim_interval);
+
+    let fees = FeeEstimate {
+        total: RoundFee::new(receipts.len() as u64, ctx.fee_per_tx).total(),
+        per_tx: ctx.estimated_fee_per_tx,
+    };
+    Ok(compounded::<R>(
+        state,
+        ctx.config,
+        &claim,
+        bonded,
+        receipts,
+        validators.len(),
+        fees,
+    ))
+}
+
+/// Claim what a `--round-order bond-then-claim` round that failed claiming
+/// left `pending`, without bonding again: the rewards are bonded already.
+async fn resume_claim<R: NamadaRpc>(
+    ctx: &RoundContext<'_, R>,
+    state: &mut State,
+    validators: &BTreeSet<Address>,
+    pending: PendingClaim,
+) -> anyhow::Result<RoundOutcome> {
+    let to_claim = validators
+        .iter()
+        .filter(|validator| pending.validators.contains(&validator.to_string()))
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    if ctx.config.observe_epochs.is_some() {
+        tracing::info!(
+            "Would claim from {} validator(s) the rewards bonded ahead at epoch {}",
+            to_claim.len(),
+            pending.bond.epoch
+        );
+        state.pending_claim = None;
+        return Ok(RoundOutcome::Observed(pending.bond.amount));
+    }
+    tracing::info!(
+        "Claiming from {} validator(s) the rewards bonded ahead at epoch {} by an unfinished round",
+        to_claim.len(),
+        pending.bond.epoch
+    );
+    let report = ctx
+        .rpc
+        .claim_rewards(
+            ctx.delegator,
+            &to_claim,
+            ctx.secret_key,
+            ctx.config.batch_claims,
+        )
+        .await
+        .inspect_err(|_| METRICS.failed_compounds.inc())?;
+    let failed = report
+        .failed()
+        .into_iter()
+        .map(|result| result.validator.to_string())
+        .collect::<BTreeSet<_>>();
+    if failed.is_empty() {
+        tracing::info!("Claims: {}", report);
+    } else {
+        tracing::warn!("Claims: {}", report);
+        METRICS.failed_claims.inc_by(failed.len() as u64);
+    }
+    state.pending_claim = (!failed.is_empty()).then(|| PendingClaim {
+        validators: failed,
+        bond: PendingBond {
+            rewards: token::Amount::zero(),
+            amount: token::Amount::zero(),
+            ..pending.bond.clone()
+        },
+    });
+
+    let claimed_from = report.claimed();
+    if pending.bond.amount.is_zero() {
+        state.update_retried(&claimed_from);
+    } else {
+        state.update_validators(&claimed_from, pending.bond.epoch);
+        state.schedule(ctx.reclaim_interval);
+    }
+    let receipts = report.receipts();
+    let fees = FeeEstimate {
+        total: RoundFee::new(receipts.len() as u64, ctx.fee_per_tx).total(),
+        per_tx: ctx.estimated_fee_per_tx,
+    };
+    Ok(compounded::<R>(
+        state,
+        ctx.config,
+        &pending.bond,
+        pending.bond.amount,
+        receipts,
+        claimed_from.len(),
+        fees,
+    ))
+}
+
+/// [`RoundStep::Claim`]: claim the pending rewards, then find out how much
+/// came in unless they were bonded ahead. With `bond_after`, the rewards to
+/// bond are checkpointed until they are. Returns the outcome of a round
+/// that ends here, nothing having come in.
+async fn claim_step<R: NamadaRpc>(
+    ctx: &RoundContext<'_, R>,
+    state: &mut State,
+    compound: &mut Compound,
+    bond_after: bool,
+) -> anyhow::Result<Option<RoundOutcome>> {
+    let config = ctx.config;
+    let report = match ctx
+        .rpc
+        .claim_rewards(
+            ctx.delegator,
+            &compound.validators,
+            ctx.secret_key,
+            config.batch_claims,
+        )
+        .await
+    {
+        Ok(report) => report,
+        Err(err) => {
+            METRICS.failed_compounds.inc();
+            // Counted as an attempt, not retried again this epoch
+            if compound.retrying {
+                let failed = compound
+                    .validators
+                    .iter()
+                    .map(|validator| (validator.to_string(), err.to_string()))
+                    .collect();
+                give_up_claims(state.record_claims(&compound.validators, &failed, ctx.epoch));
+                if let Err(err) = state.checkpoint() {
+                    tracing::warn!("{:#}", err);
+                }
+            }
+            return Err(err.into());
+        }
+    };
+    let failed = report.failed().len();
+    let unconfirmed = report.unconfirmed();
+    if failed > 0 || !unconfirmed.is_empty() {
+        tracing::warn!("Claims: {}", report);
+        METRICS.failed_claims.inc_by(failed as u64);
+    } else {
+        tracing::info!("Claims: {}", report);
+    }
+    for result in &report.results {
+        events::emit(Event::Claim {
+            delegator: ctx.delegator.to_string(),
+            validator: result.validator.to_string(),
+            rewards: result.rewards.map(|rewards| rewards.to_string_native()),
+            tx_hash: result.outcome.as_ref().ok().map(|tx| tx.hash.clone()),
+            error: result.outcome.clone().err(),
+        });
+    }
+    compound.receipts.extend(report.receipts());
+    // A claim that timed out may still land, so it isn't retried before its
+    // validator is due again
+    compound.claimed_from = report.claimed().union(&unconfirmed).cloned().collect();
+    let failed: BTreeMap<_, _> = report
+        .failed()
+        .into_iter()
+        .map(|result| {
+            let reason = result.outcome.clone().err().unwrap_or_default();
+            (result.validator.to_string(), reason)
+        })
+        .collect();
+    if let Some((claimed, _)) = &compound.claim {
+        state.pending_claim = None;
+        if compound.bonded.is_zero() {
+            give_up_claims(state.record_claims(&compound.validators, &failed, ctx.epoch));
+            if config.accumulate_until.is_some() {
+                state.accumulate(bonded_share(&config.reward_actions, claimed.amount)?);
+            }
+            return Ok(None);
+        }
+
+        // Bonded ahead: the failed claims are claimed again on their own,
+        // never bonded twice
+        state.record_claims(&compound.validators, &BTreeMap::new(), ctx.epoch);
+        if !failed.is_empty() {
+            state.pending_claim = Some(PendingClaim {
+                validators: failed.into_keys().collect(),
+                // Recorded by this round
+                bond: PendingBond {
+                    rewards: token::Amount::zero(),
+                    amount: token::Amount::zero(),
+                    ..claimed.clone()
+                },
+            });
+        }
+        return Ok(None);
+    }
+    give_up_claims(state.record_claims(&compound.validators, &failed, ctx.epoch));
+
+    let Some((rewards, balance_post)) = measure_claim(
+        ctx.rpc,
+        config,
+        ctx.delegator,
+        ctx.native_token,
+        compound.pending,
+        &report,
+        compound.balance_pre,
+    )
+    .await?
+    else {
+        state.update_validators(&compound.claimed_from, ctx.epoch);
+        return Ok(Some(RoundOutcome::NoRewards));
+    };
+    let (claimed, retained) = compound.split(ctx, rewards, balance_post)?;
+    // Until bonded, so a crash from here on doesn't claim again on restart.
+    // Only the bonds are resumed, the transfers' shares stay liquid. With
+    // `--accumulate-until` they are bonded from the accumulated rewards
+    // instead, once enough.
+    let to_bond = bonded_share(&config.reward_actions, claimed.amount)?;
+    if !to_bond.is_zero() && bond_after {
+        match config.accumulate_until {
+            Some(_) => state.accumulate(to_bond),
+            None => {
+                state.pending_bond = Some(PendingBond {
+                    amount: to_bond,
+                    ..claimed.clone()
+                })
+            }
+        }
+        if let Err(err) = state.checkpoint() {
+            tracing::warn!("{:#}", err);
+        }
+    }
+    compound.claim = Some((claimed, retained));
+
+    Ok(None)
+}
+
+/// [`RoundStep::Actions`]: run the `--reward-actions` on the rewards to
+/// compound. With `claim_after` they are bonded ahead, and the claim is left
+/// pending until it goes through.
+async fn actions_step<R: NamadaRpc>(
+    ctx: &RoundContext<'_, R>,
+    state: &mut State,
+    commissions: &mut HashMap<Address, f64>,
+    compound: &mut Compound,
+    claim_after: bool,
+) -> anyhow::Result<()> {
+    let config = ctx.config;
+    let (claimed, _) = compound
+        .claim
+        .as_ref()
+        .context("Nothing to bond before the rewards are known")?;
+    let amounts = action_amounts(&config.reward_actions, claimed.amount)
+        .context("Can't split the rewards between the --reward-actions")?;
+    let unclaimed = match claim_after {
+        true => bonded_share(&config.reward_actions, claimed.amount)?,
+        false => token::Amount::zero(),
+    };
+    let mut accumulated_bonded = false;
+    for (action, amount) in config.reward_actions.iter().zip(amounts) {
+        match action {
+            RewardAction::Bond { .. } => {
+                let amount = match config.accumulate_until {
+                    // Every bond action's share is bonded at once
+                    Some(_) if accumulated_bonded => continue,
+                    Some(threshold) => {
+                        accumulated_bonded = true;
+                        let threshold = denom::amount_from_nam(threshold);
+                        let total = state
+                            .accumulated
+                            .checked_add(unclaimed)
+                            .unwrap_or(state.accumulated);
+                        if total < threshold {
+                            tracing::info!(
+                                "Accumulated {} of the {} to bond at once, rewards stay liquid",
+                                denom::display_nam(total),
+                                denom::display_nam(threshold)
+                            );
+                            continue;
+                        }
+                        tracing::info!(
+                            "Accumulated {}, bonding it at once",
+                            denom::display_nam(total)
+                        );
+                        total
+                    }
+                    None => amount,
+                };
+                let (bonded_now, bond_receipts) = bond_rewards(
+                    ctx.rpc,
+                    config,
+                    ctx.secret_key,
+                    amount,
+                    BondContext {
+                        validators: &compound.validators,
+                        bonds: ctx.bonds,
+                        commissions: &mut *commissions,
+                        chosen_target: &mut state.chosen_target,
+                        auto_selected: &mut state.auto_selected,
+                        fee_per_tx: ctx.fee_per_tx,
+                        epoch: ctx.epoch,
+                    },
+                )
+                .await
+                .map_err(|err| bond_anomaly(state, err, config.accumulate_until.is_some()))?;
+                compound.receipts.extend(bond_receipts);
+                compound.bonded = compound
+                    .bonded
+                    .checked_add(bonded_now)
+                    .unwrap_or(compound.bonded);
+                // Otherwise below, once sure to be claimed
+                if config.accumulate_until.is_some() && !claim_after {
+                    state.bonded_accumulated(bonded_now);
+                    if let Err(err) = state.checkpoint() {
+                        tracing::warn!("{:#}", err);
+                    }
+                }
+            }
+            RewardAction::Transfer { receiver, .. } => {
+                compound
+                    .receipts
+                    .extend(send_rewards(ctx.rpc, ctx.secret_key, receiver, amount).await);
+            }
+        }
+    }
+    state.pending_bond = None;
+
+    // Until claimed, so a failed claim is retried on its own rather than
+    // bonding the rewards again
+    if claim_after && !compound.bonded.is_zero() {
+        if config.accumulate_until.is_some() {
+            state.accumulate(unclaimed);
+            state.bonded_accumulated(compound.bonded);
+        }
+        state.pending_claim = Some(PendingClaim {
+            validators: compound.validators.iter().map(Address::to_string).collect(),
+            bond: PendingBond {
+                amount: compound.bonded,
+                ..claimed.clone()
+            },
+        });
+        if let Err(err) = state.checkpoint() {
+            tracing::warn!("{:#}", err);
+        }
+    }
+
+    Ok(())
+}
+
+/// [`RoundStep::SendRetained`]: send the rewards not compounded to
+/// `--reward-receiver`, if any.
+async fn send_retained_step<R: NamadaRpc>(
+    ctx: &RoundContext<'_, R>,
+    compound: &mut Compound,
+) -> anyhow::Result<()> {
+    let (_, retained) = compound
+        .claim
+        .as_ref()
+        .context("Nothing to send before the rewards are known")?;
+    if let Some(receiver) = ctx.config.reward_receiver(ctx.delegator) {
+        compound
+            .receipts
+            .extend(send_rewards(ctx.rpc, ctx.secret_key, receiver, *retained).await);
+    }
+
+    Ok(())
+}
+
+/// A step of a compound that submits txs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RoundStep {
+    /// Claim the pending rewards, and find out how much came in unless
+    /// known already
+    Claim,
+    /// Run the `--reward-actions` on the share of the rewards to compound
+    Actions,
+    /// Send the rest to `--reward-receiver`, if any
+    SendRetained,
+}
+
+/// The steps of a compound, in the order of `--round-order`.
+pub fn round_steps(order: RoundOrder) -> &'static [RoundStep] {
+    match order {
+        RoundOrder::ClaimThenBond => &[
+            RoundStep::Claim,
+            RoundStep::Actions,
+            RoundStep::SendRetained,
+        ],
+        RoundOrder::BondThenClaim => &[
+            RoundStep::Actions,
+            RoundStep::Claim,
+            RoundStep::SendRetained,
+        ],
+    }
+}
+
+/// Rewards the claims of `report` just paid out, per `--claimed-amount`,
+/// and the balance after them if queried. `None` if the balance went down
+/// instead. `pending` stands in for the rewards the report doesn't know.
+async fn measure_claim<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    delegator_address: &Address,
+    native_token_address: &Address,
+    pending: token::Amount,
+    report: &ClaimReport,
+    balance_pre: Option<token::Amount>,
+) -> anyhow::Result<Option<(token::Amount, Option<token::Amount>)>> {
+    match (config.claimed_amount, balance_pre) {
+        (ClaimedAmount::BalanceDiff, Some(balance_pre)) => {
+            let balance_post = rpc
+                .query_balance(delegator_address, native_token_address)
+                .await?;
+
+            tracing::info!("Post balance: {}", denom::display_nam(balance_post));
+
+            let Some(delta) = rewards_delta(balance_pre, balance_post) else {
+                tracing::warn!(
+                    "Balance decreased while claiming ({} -> {}), skipping bond",
+                    denom::display_nam(balance_pre),
+                    denom::display_nam(balance_post)
+                );
+                return Ok(None);
+            };
+            // Only what the claims known to have gone through paid out
+            let pending = match report.claimed_rewards() {
+                Some(claimed) if report.claimed().len() < report.results.len() => claimed,
+                _ => pending,
+            };
+            Ok(Some((claimed_rewards(pending, delta), Some(balance_post))))
+        }
+        _ => match report.claimed_rewards() {
+            Some(claimed) => {
+                tracing::info!(
+                    "Claimed {} of rewards, as the claims report them",
+                    denom::display_nam(claimed)
+                );
+                Ok(Some((claimed, None)))
+            }
+            None => {
+                tracing::info!("Claimed {} of pending rewards", denom::display_nam(pending));
+                Ok(Some((pending, None)))
+            }
+        },
+    }
+}
+
+/// Schedule the compound after the one just made, by validator with
+/// `--per-validator-schedule`.
+fn schedule_next(
+    state: &mut State,
+    config: &AppConfig,
+    validator_intervals: Option<&HashMap<Address, u64>>,
+    reclaim_interval: u64,
+) {
+    match validator_intervals {
+        Some(intervals) => state.schedule_validators(
+            intervals
+                .iter()
+                .map(|(validator, interval)| (validator, *interval)),
+        ),
+        None => match config.strategy.interval(reclaim_interval) {
+            Some(interval) => state.schedule(interval),
+            // Due whenever the rewards are
+            None => state.next_due_timestamp = None,
+        },
+    }
+}
+
+/// What a round's txs were estimated to cost, for [`compounded`].
+struct FeeEstimate {
+    total: f64,
+    per_tx: f64,
+}
+
+/// Where a round bonds and at what cost, for [`bond_rewards`].
+struct BondContext<'a> {
+    /// Validators compounded this round
+    validators: &'a BTreeSet<Address>,
+    bonds: &'a HashMap<Address, f64>,
+    commissions: &'a mut HashMap<Address, f64>,
+    /// See [`sticky_target`]
+    chosen_target: &'a mut Option<ChosenTarget>,
+    /// See [`sticky_selection`]
+    auto_selected: &'a mut Option<ChosenValidators>,
+    fee_per_tx: f64,
+    epoch: u64,
+}
+
+/// Bond `to_bond` as `--bond-target` says. Nothing is bonded, and the
+/// rewards stay liquid, if no validator qualifies or the fees would eat it.
+/// Returns the bonded amount and the bond txs.
+async fn bond_rewards<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    secret_key: &SecretKey,
+    to_bond: token::Amount,
+    context: BondContext<'_>,
+) -> anyhow::Result<(token::Amount, Vec<TxReceipt>)> {
+    let BondContext {
+        validators,
+        bonds,
+        commissions,
+        chosen_target,
+        auto_selected,
+        fee_per_tx,
+        epoch: current_epoch,
+    } = context;
+    let delegator_address = Address::from(&secret_key.to_public());
+
+    // Recorded once bonded to, for `--target-cooldown-epochs`
+    let (mut new_selection, mut new_target) = (None, None);
+
+    // Auto-selected validators share the rewards evenly
+    let target = if config.auto_select_validators {
+        &BondTarget::Even
+    } else {
+        &config.bond_target
+    };
+    let bond_validators = if config.auto_select_validators {
+        let selected = auto_select_validators(
+            rpc,
+            config,
+            commissions,
+            auto_selected.as_ref(),
+            current_epoch,
+        )
+        .await?;
+        if auto_selected.as_ref().map_or(true, |chosen| {
+            !selected
+                .iter()
+                .map(ToString::to_string)
+                .eq(chosen.validators.iter().cloned())
+        }) {
+            new_selection = Some(ChosenValidators {
+                validators: selected.iter().map(ToString::to_string).collect(),
+                epoch: current_epoch,
+            });
+        }
+        selected
+    } else {
+        bond_candidates(config, validators, bonds)
+    };
+    let bond_validators = if config.bond_to_jailed {
+        bond_validators
+    } else {
+        bondable_validators(rpc, bond_validators, current_epoch).await?
+    };
+    let bond_validators = if config.bond_during_slashing {
+        bond_validators
+    } else {
+        settled_validators(rpc, &delegator_address, bond_validators, current_epoch).await?
+    };
+    // Those outside the delegations weren't queried with them. A delegation
+    // whose commission couldn't be fetched was skipped already
+    for validator in &bond_validators {
+        if !validators.contains(validator) && !commissions.contains_key(validator) {
+            let commission = rpc
+                .query_validator_commissions(validator, current_epoch)
+                .await?;
+            commissions.insert(validator.clone(), commission);
+        }
+    }
+    let bond_validators = match config.max_commission {
+        Some(max_commission) => within_max_commission(
+            bond_validators,
+            &bonds.keys().cloned().collect(),
+            commissions,
+            max_commission,
+        ),
+        None => bond_validators,
+    };
+
+    let bonded = if bond_validators.is_empty() {
+        tracing::warn!("No validator to bond to, rewards stay liquid");
+        (token::Amount::zero(), vec![])
+    } else if to_bond.is_zero() {
+        tracing::info!("Nothing left to bond after applying the compound ratio");
+        (token::Amount::zero(), vec![])
+    } else {
+        // Pinned to one validator for `--target-cooldown-epochs`
+        let sticky;
+        let target = match lowest_commission(&bond_validators, commissions) {
+            Some(best)
+                if matches!(target, BondTarget::BestApr) && config.target_cooldown_epochs > 0 =>
+            {
+                let validator = match sticky_target(
+                    chosen_target.as_ref(),
+                    &best,
+                    &bond_validators,
+                    commissions,
+                    current_epoch,
+                    config.target_cooldown_epochs,
+                    config.target_switch_margin,
+                ) {
+                    Some(current) => {
+                        tracing::info!(
+                            "Keeping {} as the bond target, {} doesn't pay enough more to switch",
+                            current,
+                            best
+                        );
+                        current
+                    }
+                    None => best,
+                };
+                if chosen_target
+                    .as_ref()
+                    .map_or(true, |chosen| chosen.validator != validator.to_string())
+                {
+                    new_target = Some(ChosenTarget {
+                        validator: validator.to_string(),
+                        epoch: current_epoch,
+                    });
+                }
+                sticky = BondTarget::Validator(validator);
+                &sticky
+            }
+            _ => target,
+        };
+        let distribution = bond_distribution(target, to_bond, &bond_validators, bonds, commissions)
+            .context("Can't split the bond between validators")?;
+        let (distribution, to_bond) = match config.max_bond_per_validator {
+            Some(cap) => {
+                capped_distribution(rpc, distribution, &bond_validators, cap, current_epoch).await?
+            }
+            None => (distribution, to_bond),
+        };
+        let bond_txs = distribution
+            .iter()
+            .filter(|(_, amount)| !amount.is_zero())
+            .count();
+
+        if to_bond.is_zero() {
+            // Every validator is at the cap, as logged above
+            (token::Amount::zero(), vec![])
+        } else if !covers_bond_fee(to_bond, bond_txs, fee_per_tx) {
+            tracing::warn!(
+                "{} doesn't cover the fee of {} bond tx(s), rewards stay liquid",
+                denom::display_nam(to_bond),
+                bond_txs
+            );
+            (token::Amount::zero(), vec![])
+        } else {
+            tracing::info!(
+                "Bonding {} ({}): {}",
+                denom::display_nam(to_bond),
+                target,
+                distribution
+                    .iter()
+                    .map(|(validator, amount)| format!(
+                        "{} to {}",
+                        denom::display_nam(*amount),
+                        validator
+                    ))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            );
+
+            let bond_receipts =
+                bond_checked(rpc, secret_key, &distribution, to_bond, current_epoch)
+                    .await
+                    .inspect_err(|_| METRICS.failed_compounds.inc())?;
+            if new_target.is_some() {
+                *chosen_target = new_target;
+            }
+            if new_selection.is_some() {
+                *auto_selected = new_selection;
+            }
+            events::emit(Event::Bond {
+                delegator: delegator_address.to_string(),
+                amount: to_bond.to_string_native(),
+                distribution: distribution
+                    .iter()
+                    .filter(|(_, amount)| !amount.is_zero())
+                    .map(|(validator, amount)| (validator.to_string(), amount.to_string_native()))
+                    .collect(),
+                tx_hashes: bond_receipts
+                    .iter()
+                    .map(|receipt| receipt.hash.clone())
+                    .collect(),
+            });
+            (to_bond, bond_receipts)
+        }
+    };
+
+    Ok(bonded)
+}
+
+/// Bond `distribution`, then check the bonds to its validators grew by about
+/// `to_bond` at the pipeline epoch, where a bond takes effect. Both sides of
+/// the check come from the same endpoint, so one lagging behind another
+/// can't pass for a bond that didn't land.
+///
+/// Failing before anything was submitted leaves the bond to be retried.
+/// Once a tx went out, a bond that didn't land as expected, or that can't be
+/// checked, is a [`TaskError::Anomaly`]: bonding again could take the
+/// rewards out of the principal.
+async fn bond_checked<R: NamadaRpc>(
+    rpc: &R,
+    secret_key: &SecretKey,
+    distribution: &[(Address, token::Amount)],
+    to_bond: token::Amount,
+    epoch: u64,
+) -> anyhow::Result<Vec<TxReceipt>> {
+    let pipeline_len = rpc.pipeline_len().await?;
+    rpc.pin_endpoint(true);
+    let res = bond_and_compare(rpc, secret_key, distribution, to_bond, epoch, pipeline_len).await;
+    rpc.pin_endpoint(false);
+    res
+}
+
+async fn bond_and_compare<R: NamadaRpc>(
+    rpc: &R,
+    secret_key: &SecretKey,
+    distribution: &[(Address, token::Amount)],
+    to_bond: token::Amount,
+    epoch: u64,
+    pipeline_len: u64,
+) -> anyhow::Result<Vec<TxReceipt>> {
+    let delegator_address = Address::from(&secret_key.to_public());
+    let bonded_to = distribution
+        .iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .map(|(validator, _)| validator.clone())
+        .collect::<BTreeSet<_>>();
+    let bonds_at_pipeline = || async {
+        // The epoch may have changed since, moving where the bond lands
+        let pipeline_epoch = rpc.get_current_epoch().await? + pipeline_len;
+        let bonds = rpc
+            .query_bonds(&bonded_to, &delegator_address, pipeline_epoch, true)
+            .await?
+            .values()
+            .sum::<f64>();
+        TaskResult::Ok((pipeline_epoch, bonds))
+    };
+    let before = rpc
+        .query_bonds(&bonded_to, &delegator_address, epoch + pipeline_len, true)
+        .await?
+        .values()
+        .sum::<f64>();
+
+    let receipts = match rpc.bond(&delegator_address, distribution, secret_key).await {
+        Ok(receipts) => receipts,
+        Err(err) => {
+            // A timed out tx may still land, and a distribution's first
+            // bonds may have landed before a later one failed
+            let landed = match (&err, bonds_at_pipeline().await) {
+                (TaskError::Timeout(_), _) | (_, Err(_)) => true,
+                (_, Ok((_, after))) => after > before,
+            };
+            if landed {
+                return Err(TaskError::Anomaly(format!(
+                    "Bonding {} failed ({}), but it may have landed",
+                    denom::display_nam(to_bond),
+                    err
+                ))
+                .into());
+            }
+            return Err(err.into());
+        }
+    };
+
+    let (pipeline_epoch, after) = bonds_at_pipeline().await.map_err(|err| {
+        TaskError::Anomaly(format!(
+            "Bonded {} but can't check it landed: {}",
+            denom::display_nam(to_bond),
+            err
+        ))
+    })?;
+    tracing::info!(
+        "Bonded to these validators at epoch {}: {} before, {} after",
+        pipeline_epoch,
+        denom::display_nam_f64(before),
+        denom::display_nam_f64(after)
+    );
+
+    let expected = R::amount_to_f64(to_bond)?;
+    if !bond_landed(before, after, expected) {
+        return Err(TaskError::Anomaly(format!(
+            "Bonded {} but the bonds went from {} to {}",
+            denom::display_nam(to_bond),
+            denom::display_nam_f64(before),
+            denom::display_nam_f64(after)
+        ))
+        .into());
+    }
+    Ok(receipts)
+}
+
+/// Whether bonds that went from `before` to `after` took a bond of
+/// `expected`, within [`BOND_CHECK_TOLERANCE`].
+pub fn bond_landed(before: f64, after: f64, expected: f64) -> bool {
+    after - before >= expected * (1.0 - BOND_CHECK_TOLERANCE)
+}
+
+/// `distribution` within `--max-bond-per-validator` of `cap` NAM, its
+/// overflow redirected to the other `bond_validators`, and what it bonds in
+/// total.
+async fn capped_distribution<R: NamadaRpc>(
+    rpc: &R,
+    mut distribution: Vec<(Address, token::Amount)>,
+    bond_validators: &BTreeSet<Address>,
+    cap: f64,
+    epoch: u64,
+) -> anyhow::Result<(Vec<(Address, token::Amount)>, token::Amount)> {
+    // Those left out by the bond target can still take the overflow
+    for validator in bond_validators {
+        if !distribution.iter().any(|(bonded, _)| bonded == validator) {
+            distribution.push((validator.clone(), token::Amount::zero()));
+        }
+    }
+    let mut stakes = HashMap::new();
+    for (validator, _) in &distribution {
+        let stake = rpc.query_validator_stake(validator, epoch).await?;
+        stakes.insert(validator.clone(), stake);
+    }
+
+    let capped = cap_bonds(distribution, &stakes, cap);
+    for (src, dest, amount) in &capped.redirected {
+        tracing::info!(
+            "{} is at --max-bond-per-validator, bonding {} to {} instead",
+            src,
+            denom::display_nam(*amount),
+            dest
+        );
+    }
+    if !capped.unbonded.is_zero() {
+        tracing::warn!(
+            "Every validator is at --max-bond-per-validator, {} stays liquid",
+            denom::display_nam(capped.unbonded)
+        );
+    }
+
+    let distribution = capped
+        .distribution
+        .into_iter()
+        .filter(|(_, amount)| !amount.is_zero())
+        .collect::<Vec<_>>();
+    let total = distribution
+        .iter()
+        .try_fold(token::Amount::zero(), |total, (_, amount)| {
+            total.checked_add(*amount)
+        })
+        .context("Bond total overflows")?;
+    Ok((distribution, total))
+}
+
+/// A bond distribution within `--max-bond-per-validator`, see [`cap_bonds`].
+#[derive(Debug, PartialEq)]
+pub struct CappedBonds {
+    pub distribution: Vec<(Address, token::Amount)>,
+    /// Amounts moved from a validator at the cap to another, in order
+    pub redirected: Vec<(Address, Address, token::Amount)>,
+    /// What no validator had room for
+    pub unbonded: token::Amount,
+}
+
+/// Cap what `distribution` bonds to each validator so its total stake, per
+/// `stakes` in NAM, stays within `cap`. The overflow fills the room left by
+/// the validators of the distribution in order.
+pub fn cap_bonds(
+    mut distribution: Vec<(Address, token::Amount)>,
+    stakes: &HashMap<Address, f64>,
+    cap: f64,
+) -> CappedBonds {
+    let mut room = distribution
+        .iter()
+        .map(|(validator, _)| {
+            let stake = stakes.get(validator).copied().unwrap_or_default();
+            denom::amount_from_nam(cap - stake)
+        })
+        .collect::<Vec<_>>();
+
+    let mut overflows = vec![];
+    for ((validator, amount), room) in distribution.iter_mut().zip(&mut room) {
+        if *amount > *room {
+            overflows.push((
+                validator.clone(),
+                amount.checked_sub(*room).unwrap_or_default(),
+            ));
+            *amount = *room;
+        }
+        *room = room.checked_sub(*amount).unwrap_or_default();
+    }
+
+    let mut redirected = vec![];
+    let mut unbonded = token::Amount::zero();
+    for (src, mut overflow) in overflows {
+        for ((dest, amount), room) in distribution.iter_mut().zip(&mut room) {
+            let moved = overflow.min(*room);
+            if moved.is_zero() {
+                continue;
+            }
+            *amount = amount.checked_add(moved).unwrap_or(*amount);
+            *room = room.checked_sub(moved).unwrap_or_default();
+            overflow = overflow.checked_sub(moved).unwrap_or_default();
+            redirected.push((src.clone(), dest.clone(), moved));
+        }
+        unbonded = unbonded.checked_add(overflow).unwrap_or(unbonded);
+    }
+
+    CappedBonds {
+        distribution,
+        redirected,
+        unbonded,
+    }
+}
+
+/// Validators `--bond-target` or `--bond-validators` bond to, from the
+/// `validators` claimed from, before any is ruled out. Without an explicit
+/// target, only those with an active bond in `bonds`.
+fn bond_candidates(
+    config: &AppConfig,
+    validators: &BTreeSet<Address>,
+    bonds: &HashMap<Address, f64>,
+) -> BTreeSet<Address> {
+    if !config.bond_validators.is_empty() {
+        return config.bond_validators.iter().cloned().collect();
+    }
+
+    match &config.bond_target {
+        BondTarget::Validator(target) => BTreeSet::from([target.clone()]),
+        BondTarget::Even | BondTarget::Proportional | BondTarget::BestApr => validators
+            .difference(&unbonded_validators(validators, bonds))
+            .cloned()
+            .collect(),
+    }
+}
+
+/// Validators of `validators` whose bond in `bonds` is zero, e.g. fully
+/// unbonded from while rewards are still pending there. They're claimed from
+/// like the others, but not bonded to unless targeted. Those whose bond
+/// couldn't be queried aren't known to be unbonded.
+pub fn unbonded_validators(
+    validators: &BTreeSet<Address>,
+    bonds: &HashMap<Address, f64>,
+) -> BTreeSet<Address> {
+    validators
+        .iter()
+        .filter(|validator| bonds.get(*validator).is_some_and(|bond| *bond <= 0.0))
+        .cloned()
+        .collect()
+}
+
+/// Fail unless every `--bond-validators` address is an active validator, at
+/// startup rather than once the rewards are claimed.
+pub async fn check_bond_validators<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+) -> anyhow::Result<()> {
+    if config.bond_validators.is_empty() {
+        return Ok(());
+    }
+    anyhow::ensure!(
+        !matches!(config.bond_target, BondTarget::Validator(_)),
+        "--bond-validators can't be used with a validator --bond-target"
+    );
+
+    let epoch = rpc.get_current_epoch().await?;
+    for validator in &config.bond_validators {
+        match rpc.query_validator_state(validator, epoch).await? {
+            None => anyhow::bail!("--bond-validators {} is not a validator", validator),
+            Some(ValidatorState::Jailed | ValidatorState::Inactive) => {
+                anyhow::bail!("--bond-validators {} is not active", validator)
+            }
+            Some(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Log the validators whose claim failed too often to be retried again.
+fn give_up_claims(validators: Vec<String>) {
+    for validator in validators {
+        tracing::warn!(
+            "Claim from {} failed {} times in a row, leaving it to the next compound",
+            validator,
+            MAX_CLAIM_ATTEMPTS
+        );
+    }
+}
+
+/// Halt on the anomaly of a bond that went through, or may have, dropping
+/// the pending bond, or the accumulated rewards if it bonded those, rather
+/// than bonding it again from the principal. Other errors leave it to be
+/// retried.
+fn bond_anomaly(state: &mut State, err: anyhow::Error, accumulated: bool) -> anyhow::Error {
+    match err.downcast::<TaskError>() {
+        Ok(TaskError::Anomaly(reason)) => {
+            state.pending_bond = None;
+            if accumulated {
+                state.accumulated = token::Amount::zero();
+            }
+            halt(state, reason)
+        }
+        Ok(err) => err.into(),
+        Err(err) => err,
+    }
+}
+
+/// Pause compounding for `reason` until `--clear-anomaly`, saving the state
+/// right away so a restart doesn't resume it.
+fn halt(state: &mut State, reason: String) -> anyhow::Error {
+    tracing::error!(
+        "Anomaly detected, pausing compounding: {}. Check the account, then restart with --clear-anomaly",
+        reason
+    );
+    state.anomaly = Some(reason.clone());
+    if let Err(err) = state.checkpoint() {
+        tracing::error!("{:#}", err);
+    }
+
+    TaskError::Anomaly(reason).into()
+}
+
+/// Record the fees, metrics and projection accuracy of a compound whose
+/// `claim` was bonded, once the state is scheduled.
+fn compounded<R: NamadaRpc>(
+    state: &mut State,
+    config: &AppConfig,
+    claim: &PendingBond,
+    bonded: token::Amount,
+    receipts: Vec<TxReceipt>,
+    validators: usize,
+    fees: FeeEstimate,
+) -> RoundOutcome {
+    let gas_used = receipts.iter().map(|receipt| receipt.gas_used).sum::<u64>();
+    // Fees paid in a `--fee-token` aren't NAM, with no price to convert them
+    // at they're left out of the NAM fee average and totals
+    let fee_paid = if config.fee_token.is_some() {
+        0.0
+    } else {
+        // Txs resubmitted with a bumped fee paid more than the estimate
+        let fee_paid = receipts
+            .iter()
+            .map(|receipt| {
+                let gas_price = receipt
+                    .gas_price
+                    .unwrap_or(fees.per_tx / TX_GAS_LIMIT as f64);
+                receipt.gas_used as f64 * gas_price
+            })
+            .sum::<f64>();
+        state.record_fees(receipts.len() as u64, fee_paid);
+        fee_paid
+    };
+    state.record_compound(R::amount_to_f64(bonded).unwrap_or_default(), fee_paid);
+
+    let record = CompoundRecord {
+        timestamp: state.last_claimed_timestamp,
+        epoch: claim.epoch,
+        validators,
+        rewards: claim.rewards,
+        bonded,
+        fee_nam: fees.total,
+        balance_pre: claim.balance_pre,
+        balance_post: claim.balance_post,
+        gas_used,
+        fee_paid_nam: fee_paid,
+        projected_gain: claim.projected_gain,
+        realized_gain: R::amount_to_f64(claim.rewards).unwrap_or_default() - fee_paid,
+        tx_hashes: receipts.into_iter().map(|receipt| receipt.hash).collect(),
+        compounded_total: state.compounded_total,
+        fees_paid_total: state.fees_paid_total,
+    };
+
+    if let Some(projected_gain) = record.projected_gain {
+        if let Some(accuracy) = state.record_projection(projected_gain, record.realized_gain) {
+            METRICS.projection_accuracy.set(accuracy);
+        }
+    }
+    if let Some(accuracy) = record
+        .projection_accuracy()
+        .filter(|accuracy| (accuracy - 1.0).abs() > config.projection_band)
+    {
+        tracing::warn!(
+            "Compound gained {} where {} was projected ({:.0}%), the fee estimate or the APR may be off",
+            denom::display_nam_f64(record.realized_gain),
+            denom::display_nam_f64(record.projected_gain.unwrap_or_default()),
+            accuracy * 100.0
+        );
+    }
+
+    METRICS.compounds.inc();
+    METRICS
+        .last_claim_timestamp
+        .set(state.last_claimed_timestamp as i64);
+    METRICS
+        .rewards_claimed
+        .set(R::amount_to_f64(claim.rewards).unwrap_or_default());
+    METRICS.gas_used.set(gas_used as i64);
+    METRICS.fee_paid.set(fee_paid);
+    METRICS
+        .accumulated_rewards
+        .set(R::amount_to_f64(state.accumulated).unwrap_or_default());
+
+    RoundOutcome::Compounded(record)
+}
+
+/// Claim the rewards of every delegation and leave them liquid, or send them
+/// to `--reward-receiver`, for the `claim` subcommand. The state and the schedule are left alone. Returns
+/// the claimed amount, zero if there was nothing to claim.
+pub async fn claim_only<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    secret_key: &SecretKey,
+) -> anyhow::Result<token::Amount> {
+    let delegator_address = Address::from(&secret_key.to_public());
+    let current_epoch = rpc.get_current_epoch().await?;
+
+    let validators = delegations(rpc, config, &delegator_address, current_epoch).await?;
+    if validators.is_empty() {
+        tracing::info!("No active delegations, nothing to claim");
+        return Ok(token::Amount::zero());
+    }
+
+    let pending = rpc
+        .query_pos_rewards(&validators, &delegator_address)
+        .await?;
+    if pending.is_zero() {
+        tracing::info!("No pending rewards, nothing to claim");
+        return Ok(token::Amount::zero());
+    }
+
+    let native_token_address = rpc.query_native_token().await?;
+    let balance_pre = rpc
+        .query_balance(&delegator_address, &native_token_address)
+        .await?;
+
+    let report = rpc
+        .claim_rewards(
+            &delegator_address,
+            &validators,
+            secret_key,
+            config.batch_claims,
+        )
+        .await?;
+    tracing::info!("Claims: {}", report);
+
+    let balance_post = rpc
+        .query_balance(&delegator_address, &native_token_address)
+        .await?;
+
+    let claimed = rewards_delta(balance_pre, balance_post)
+        .map(|delta| claimed_rewards(pending, delta))
+        .unwrap_or_default();
+
+    if let Some(receiver) = config.reward_receiver(&delegator_address) {
+        send_rewards(rpc, secret_key, receiver, claimed).await;
+    }
+
+    Ok(claimed)
+}
+
+/// Send `amount` of the rewards to `receiver`, e.g. the retained ones to
+/// `--reward-receiver`. They stay liquid if the transfer fails, the compound
+/// having gone through already.
+async fn send_rewards<R: NamadaRpc>(
+    rpc: &R,
+    secret_key: &SecretKey,
+    receiver: &Address,
+    amount: token::Amount,
+) -> Option<TxReceipt> {
+    if amount.is_zero() {
+        return None;
+    }
+
+    let delegator_address = Address::from(&secret_key.to_public());
+    match rpc
+        .transfer(&delegator_address, receiver, amount, secret_key)
+        .await
+    {
+        Ok(receipt) => {
+            tracing::info!("Sent {} to {}", denom::display_nam(amount), receiver);
+            Some(receipt)
+        }
+        Err(err) => {
+            tracing::error!(
+                "Can't send {} to {}, it stays liquid: {:#}",
+                denom::display_nam(amount),
+                receiver,
+                err
+            );
+            None
+        }
+    }
+}
+
+/// Bond `amount` of the liquid balance as `--bond-target` says, without
+/// claiming, for the `bond` subcommand. The state and the schedule are left
+/// alone.
+pub async fn bond_only<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    secret_key: &SecretKey,
+    amount: token::Amount,
+) -> anyhow::Result<Vec<TxReceipt>> {
+    anyhow::ensure!(!amount.is_zero(), "Nothing to bond");
+
+    let delegator_address = Address::from(&secret_key.to_public());
+    let current_epoch = rpc.get_current_epoch().await?;
+
+    let native_token_address = rpc.query_native_token().await?;
+    let balance = rpc
+        .query_balance(&delegator_address, &native_token_address)
+        .await?;
+    anyhow::ensure!(
+        amount <= balance,
+        "Can't bond {}, the balance is only {}",
+        denom::display_nam(amount),
+        denom::display_nam(balance)
+    );
+
+    check_bond_validators(rpc, config).await?;
+    if let BondTarget::Validator(target) = &config.bond_target {
+        anyhow::ensure!(
+            rpc.query_validator_state(target, current_epoch)
+                .await?
+                .is_some(),
+            "--bond-target {} is not a validator",
+            target
+        );
+    }
+
+    let validators = delegations(rpc, config, &delegator_address, current_epoch).await?;
+    let bonds = rpc
+        .query_bonds(
+            &validators,
+            &delegator_address,
+            current_epoch,
+            config.strict,
+        )
+        .await?;
+    let bond_validators = bond_candidates(config, &validators, &bonds);
+    let bond_validators = if config.bond_to_jailed {
+        bond_validators
+    } else {
+        bondable_validators(rpc, bond_validators, current_epoch).await?
+    };
+    anyhow::ensure!(
+        !bond_validators.is_empty(),
+        "No validator to bond to, set --bond-target"
+    );
+    let commissions = rpc
+        .query_validators_commissions(&bond_validators, current_epoch, config.strict)
+        .await?;
+    let distribution = bond_distribution(
+        &config.bond_target,
+        amount,
+        &bond_validators,
+        &bonds,
+        &commissions,
+    )
+    .context("Can't split the bond between validators")?;
+
+    tracing::info!(
+        "Bonding {} ({})",
+        denom::display_nam(amount),
+        config.bond_target
+    );
+
+    let receipts = rpc
+        .bond(&delegator_address, &distribution, secret_key)
+        .await?;
+
+    Ok(receipts)
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{
+        collections::{BTreeMap, BTreeSet, HashMap, VecDeque},
+        str::FromStr,
+        sync::Mutex,
+        time::Duration,
+    };
+
+    use namada_sdk::{
+        address::{Address, EstablishedAddressGen},
+        key::common::SecretKey,
+        proof_of_stake::types::ValidatorState,
+        token,
+    };
+
+    use super::{
+        action_amounts, bond_distribution, bond_landed, bond_only, cap_bonds,
+        check_bond_validators, claim_only, claimed_rewards, covers_bond_fee, next_round,
+        portfolio_uptime, rank_validators, rewards_delta, run_once, run_round, split_rewards,
+        sticky_selection, sticky_target, top_by_stake, txs_per_round, validator_projections,
+        ChainRates, NextRound, RankedValidator, RoundOutcome, Trigger,
+    };
+    use crate::{
+        config::{test::config_with, AppConfig, BondTarget, RewardAction, ValidatorRanking},
+        error::{TaskError, TaskResult},
+        namada::{ClaimReport, ClaimResult, NamadaRpc, TxReceipt},
+        rebalance::Redelegation,
+        state::{
+            ChosenTarget, ChosenValidators, PendingBond, PendingClaim, State, MAX_CLAIM_ATTEMPTS,
+        },
+    };
+
+    const SECRET_KEY: &str = "000101010101010101010101010101010101010101010101010101010101010101";
+
+    /// In-memory chain the rounds run against, also used by the tests of
+    /// other modules.
+    pub(crate) struct MockNamadaRpc {
+        validators: BTreeSet<Address>,
+        balances: Mutex<VecDeque<token::Amount>>,
+        bonded: Mutex<Vec<token::Amount>>,
+        bonded_to: Mutex<Vec<BTreeSet<Address>>>,
+        claimed_from: Mutex<Vec<BTreeSet<Address>>>,
+        jailed: BTreeSet<Address>,
+        no_bond: BTreeSet<Address>,
+        /// Fully unbonded from, a zero bond
+        zero_bond: BTreeSet<Address>,
+        slashed: BTreeSet<Address>,
+        unbonding: BTreeSet<Address>,
+        no_commission: BTreeSet<Address>,
+        pending: token::Amount,
+        /// Rewards each claim reports, an even share of `pending` if unset
+        claim_rewards: Option<token::Amount>,
+        /// Claims from these fail, the others go through
+        fail_claim: BTreeSet<Address>,
+        fail_bond: bool,
+        /// Bonds go through without landing
+        phantom_bond: bool,
+        /// Bonded by the mock, in NAM, in effect after the current epoch
+        landed: Mutex<HashMap<Address, f64>>,
+        pub(crate) gas_price: Option<f64>,
+        /// Decimals of every token
+        pub(crate) decimals: u8,
+        /// Gas price the bond was resubmitted at after a fee bump
+        bumped_bond: Option<f64>,
+        consensus: Vec<(Address, f64)>,
+        /// Total stake of each validator, none if left out
+        stakes: HashMap<Address, f64>,
+        sent: Mutex<Vec<(Address, token::Amount)>>,
+        no_inflation: bool,
+        /// Txs in the order they were submitted
+        submitted: Mutex<Vec<String>>,
+    }
+
+    impl MockNamadaRpc {
+        pub(crate) fn new(balances: Vec<token::Amount>) -> Self {
+            let mut address_gen = EstablishedAddressGen::new("validators");
+            Self {
+                validators: (0..2)
+                    .map(|i| address_gen.generate_address(i.to_string()))
+                    .collect(),
+                balances: Mutex::new(balances.into()),
+                bonded: Mutex::new(vec![]),
+                bonded_to: Mutex::new(vec![]),
+                claimed_from: Mutex::new(vec![]),
+                jailed: BTreeSet::new(),
+                no_bond: BTreeSet::new(),
+                zero_bond: BTreeSet::new(),
+                slashed: BTreeSet::new(),
+                unbonding: BTreeSet::new(),
+                no_commission: BTreeSet::new(),
+                pending: token::Amount::native_whole(5),
+                claim_rewards: None,
+                fail_claim: BTreeSet::new(),
+                fail_bond: false,
+                phantom_bond: false,
+                landed: Mutex::new(HashMap::new()),
+                gas_price: None,
+                decimals: 6,
+                bumped_bond: None,
+                consensus: vec![],
+                stakes: HashMap::new(),
+                sent: Mutex::new(vec![]),
+                no_inflation: false,
+                submitted: Mutex::new(vec![]),
+            }
+        }
+    }
+
+    impl NamadaRpc for MockNamadaRpc {
+        async fn get_current_epoch(&self) -> TaskResult<u64> {
+            Ok(100)
+        }
+
+        async fn time_to_next_epoch(&self) -> TaskResult<Duration> {
+            Ok(Duration::from_secs(600))
+        }
+
+        async fn epoch_duration(&self) -> TaskResult<Duration> {
+            Ok(Duration::from_secs(3_600))
+        }
+
+        async fn get_pos_inflation_rate(&self) -> TaskResult<f64> {
+            if self.no_inflation {
+                return Err(TaskError::RpcUnavailable(
+                    "Failed fetching staking rewards".to_string(),
+                ));
+            }
+            Ok(0.1)
+        }
+
+        async fn get_delegators_validators(
+            &self,
+            _address: &Address,
+            _epoch: u64,
+        ) -> TaskResult<BTreeSet<Address>> {
+            Ok(self.validators.clone())
+        }
+
+        async fn query_native_token(&self) -> TaskResult<Address> {
+            Ok(EstablishedAddressGen::new("token").generate_address("nam"))
+        }
+
+        async fn query_pos_rewards(
+            &self,
+            _validators: &BTreeSet<Address>,
+            _delegator_address: &Address,
+        ) -> TaskResult<token::Amount> {
+            Ok(self.pending)
+        }
+
+        async fn query_bond(
+            &self,
+            validator: &Address,
+            _delegator: &Address,
+            epoch: u64,
+        ) -> TaskResult<f64> {
+            if self.no_bond.contains(validator) {
+                return Err(TaskError::RpcUnavailable("Bond query failed".to_string()));
+            }
+            let bond = if self.zero_bond.contains(validator) {
+                0.0
+            } else {
+                1_000.0
+            };
+            let landed = if epoch > 100 {
+                self.landed
+                    .lock()
+                    .unwrap()
+                    .get(validator)
+                    .copied()
+                    .unwrap_or_default()
+            } else {
+                0.0
+            };
+            Ok(bond + landed)
+        }
+
+        async fn query_balance(
+            &self,
+            _address: &Address,
+            _native_token_address: &Address,
+        ) -> TaskResult<token::Amount> {
+            self.balances
+                .lock()
+                .unwrap()
+                .pop_front()
+                .ok_or_else(|| TaskError::RpcUnavailable("No balance left".to_string()))
+        }
+
+        async fn query_min_gas_price(&self, _token: &Address) -> TaskResult<f64> {
+            self.gas_price
+                .ok_or_else(|| TaskError::InvalidResponse("No gas price for token".to_string()))
+        }
+
+        async fn query_denomination(&self, _token: &Address) -> TaskResult<u8> {
+            Ok(self.decimals)
+        }
+
+        async fn claim_rewards(
+            &self,
+            _delegator_address: &Address,
+            validators: &BTreeSet<Address>,
+            _secret_key: &SecretKey,
+            _batch: bool,
+        ) -> TaskResult<ClaimReport> {
+            if !validators.is_empty() && validators.is_subset(&self.fail_claim) {
+                return Err(TaskError::TxRejected("Claim rejected".to_string()));
+            }
+            self.claimed_from
+                .lock()
+                .unwrap()
+                .push(validators.difference(&self.fail_claim).cloned().collect());
+            self.submitted.lock().unwrap().push("claim".to_string());
+            // An even share of the pending rewards each
+            let rewards = self
+                .claim_rewards
+                .or_else(|| self.pending.checked_div_u64(validators.len() as u64));
+            Ok(ClaimReport {
+                results: validators
+                    .iter()
+                    .map(|validator| ClaimResult {
+                        validator: validator.clone(),
+                        rewards,
+                        outcome: if self.fail_claim.contains(validator) {
+                            Err("Claim rejected".to_string())
+                        } else {
+                            Ok(receipt("claim"))
+                        },
+                        timed_out: false,
+                    })
+                    .collect(),
+            })
+        }
+
+        async fn bond(
+            &self,
+            _delegator_address: &Address,
+            distribution: &[(Address, token::Amount)],
+            _secret_key: &SecretKey,
+        ) -> TaskResult<Vec<TxReceipt>> {
+            if self.fail_bond {
+                return Err(TaskError::TxRejected("Bond rejected".to_string()));
+            }
+            let amount = distribution
+                .iter()
+                .try_fold(token::Amount::zero(), |total, (_, amount)| {
+                    total.checked_add(*amount)
+                })
+                .unwrap();
+            self.bonded.lock().unwrap().push(amount);
+            if !self.phantom_bond {
+                let mut landed = self.landed.lock().unwrap();
+                for (validator, amount) in distribution {
+                    *landed.entry(validator.clone()).or_default() +=
+                        Self::amount_to_f64(*amount).unwrap();
+                }
+            }
+            self.submitted.lock().unwrap().extend(
+                distribution
+                    .iter()
+                    .map(|(validator, _)| format!("bond {}", validator)),
+            );
+            self.bonded_to.lock().unwrap().push(
+                distribution
+                    .iter()
+                    .map(|(validator, _)| validator.clone())
+                    .collect(),
+            );
+            Ok(vec![TxReceipt {
+                gas_price: self.bumped_bond,
+                ..receipt("bond")
+            }])
+        }
+
+        async fn query_validator_commissions(
+            &self,
+            validator: &Address,
+            _epoch: u64,
+        ) -> TaskResult<f64> {
+            if self.no_commission.contains(validator) {
+                return Err(TaskError::InvalidResponse(format!(
+                    "{} has no commission rate",
+                    validator
+                )));
+            }
+            Ok(0.05)
+        }
+
+        async fn query_pending_slashes(&self) -> TaskResult<BTreeSet<Address>> {
+            Ok(self.slashed.clone())
+        }
+
+        async fn query_uptime(&self) -> TaskResult<HashMap<Address, f64>> {
+            Ok(HashMap::new())
+        }
+
+        async fn redelegate(
+            &self,
+            _delegator_address: &Address,
+            _redelegations: &[Redelegation],
+            _secret_key: &SecretKey,
+        ) -> TaskResult<Vec<TxReceipt>> {
+            Ok(vec![])
+        }
+
+        async fn transfer(
+            &self,
+            _source: &Address,
+            target: &Address,
+            amount: token::Amount,
+            _secret_key: &SecretKey,
+        ) -> TaskResult<TxReceipt> {
+            self.sent.lock().unwrap().push((target.clone(), amount));
+            Ok(receipt("transfer"))
+        }
+
+        async fn redelegation_cooldown(
+            &self,
+            _delegator: &Address,
+            _validator: &Address,
+            _epoch: u64,
+        ) -> TaskResult<Option<u64>> {
+            Ok(None)
+        }
+
+        async fn query_consensus_validators(&self, _epoch: u64) -> TaskResult<Vec<(Address, f64)>> {
+            Ok(self.consensus.clone())
+        }
+
+        async fn query_validator_stake(&self, validator: &Address, _epoch: u64) -> TaskResult<f64> {
+            Ok(self.stakes.get(validator).copied().unwrap_or_default())
+        }
+
+        async fn pipeline_len(&self) -> TaskResult<u64> {
+            Ok(2)
+        }
+
+        async fn validate_txs(
+            &self,
+            _delegator_address: &Address,
+            _validators: &BTreeSet<Address>,
+            distribution: &[(Address, token::Amount)],
+            _secret_key: &SecretKey,
+        ) -> TaskResult<()> {
+            match distribution
+                .iter()
+                .find(|(validator, _)| self.jailed.contains(validator))
+            {
+                Some((validator, _)) => Err(TaskError::TxBuildFailed(format!(
+                    "Bond to {}: validator is jailed",
+                    validator
+                ))),
+                None => Ok(()),
+            }
+        }
+
+        async fn has_pending_unbonds(
+            &self,
+            _delegator: &Address,
+            validator: &Address,
+            _epoch: u64,
+        ) -> TaskResult<bool> {
+            Ok(self.unbonding.contains(validator))
+        }
+
+        async fn query_validator_state(
+            &self,
+            validator: &Address,
+            _epoch: u64,
+        ) -> TaskResult<Option<ValidatorState>> {
+            if self.jailed.contains(validator) {
+                Ok(Some(ValidatorState::Jailed))
+            } else {
+                Ok(Some(ValidatorState::Consensus))
+            }
+        }
+    }
+
+    fn receipt(hash: &str) -> TxReceipt {
+        TxReceipt {
+            hash: hash.to_string(),
+            gas_used: 20_000,
+            gas_price: None,
+        }
+    }
+
+    fn config() -> AppConfig {
+        config_with(&[])
+    }
+
+    pub(crate) fn secret_key() -> SecretKey {
+        SecretKey::from_str(SECRET_KEY).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_bonds_balance_difference() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.rewards == token::Amount::native_whole(5)
+        ));
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(5)]
+        );
+        assert!(state.claimed_first_time);
+        assert!(state.next_due_timestamp > Some(state.last_claimed_timestamp));
+        assert_eq!(
+            state.last_round.as_ref().map(|round| round.epoch),
+            Some(100)
+        );
+        // Whole epochs of the mock's hour
+        assert_eq!(
+            state.interval_secs.map(|interval| interval % 3_600),
+            Some(0)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skip_first_compound() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+
+        let outcome = run_once(
+            &rpc,
+            &mut state,
+            &config_with(&["--skip-first-compound"]),
+            &secret_key(),
+        )
+        .await
+        .unwrap();
+
+        assert!(matches!(outcome, RoundOutcome::NotDue));
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+        assert!(state.claimed_first_time);
+        assert_eq!(state.last_claimed_epoch, 100);
+    }
+
+    #[tokio::test]
+    async fn test_observe_epochs() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        let config = config_with(&["--observe-epochs", "3"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::Observed(rpc.pending));
+        assert!(rpc.submitted.lock().unwrap().is_empty());
+        // Scheduled as if compounded, so the next round isn't due
+        assert!(state.claimed_first_time);
+        assert!(state.next_due_timestamp > Some(state.last_claimed_timestamp));
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        assert_eq!(outcome, RoundOutcome::NotDue);
+    }
+
+    #[tokio::test]
+    async fn test_state_unchanged_on_failed_bond() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.fail_bond = true;
+        let mut state = State::init();
+        let last_claimed_timestamp = state.last_claimed_timestamp;
+
+        let res = run_once(&rpc, &mut state, &config(), &secret_key()).await;
+
+        assert!(res.is_err());
+        assert!(!state.claimed_first_time);
+        assert_eq!(state.last_claimed_timestamp, last_claimed_timestamp);
+    }
+
+    #[tokio::test]
+    async fn test_partial_claim() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::from_u64(102_500_000),
+        ]);
+        let [failing, claimed] =
+            <[Address; 2]>::try_from(rpc.validators.iter().cloned().collect::<Vec<_>>()).unwrap();
+        rpc.fail_claim.insert(failing.clone());
+        let mut state = State::init();
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        // Only the half that was claimed is bonded
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::from_u64(2_500_000)]
+        );
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.validators == 1
+        ));
+        // The failed one is due again
+        assert!(state.validators.contains_key(&claimed.to_string()));
+        assert!(!state.validators.contains_key(&failing.to_string()));
+
+        assert_eq!(
+            state.failed_claims.keys().collect::<Vec<_>>(),
+            [&failing.to_string()]
+        );
+        let last_claimed_timestamp = state.last_claimed_timestamp;
+
+        // Not retried within the same epoch
+        let rpc_retry = MockNamadaRpc::new(vec![
+            token::Amount::from_u64(102_500_000),
+            token::Amount::native_whole(105),
+        ]);
+        let outcome = run_once(&rpc_retry, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+        assert_eq!(outcome, RoundOutcome::NotDue);
+        assert!(rpc_retry.claimed_from.lock().unwrap().is_empty());
+
+        // Not due yet, but in a later epoch the failed claim is retried on
+        // its own
+        state
+            .failed_claims
+            .get_mut(&failing.to_string())
+            .unwrap()
+            .epoch = 99;
+        let outcome = run_once(&rpc_retry, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.validators == 1
+        ));
+        assert_eq!(
+            *rpc_retry.claimed_from.lock().unwrap(),
+            vec![BTreeSet::from([failing.clone()])]
+        );
+        assert!(state.failed_claims.is_empty());
+        assert!(state.validators.contains_key(&failing.to_string()));
+        assert_eq!(state.last_claimed_timestamp, last_claimed_timestamp);
+
+        // Failing them all fails the round
+        rpc.fail_claim.insert(claimed);
+        let mut state = State::init();
+        assert!(run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_failed_claim_retries() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::from_u64(102_500_000),
+            token::Amount::from_u64(102_500_000),
+            token::Amount::from_u64(102_500_000),
+        ]);
+        let failing = rpc.validators.first().unwrap().clone();
+        rpc.fail_claim.insert(failing.clone());
+        let mut state = State::init();
+        run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        // Each retry that fails counts, until given up on
+        for attempts in 2..=MAX_CLAIM_ATTEMPTS {
+            state
+                .failed_claims
+                .get_mut(&failing.to_string())
+                .unwrap()
+                .epoch = 99;
+            assert!(run_once(&rpc, &mut state, &config(), &secret_key())
+                .await
+                .is_err());
+            match state.failed_claims.get(&failing.to_string()) {
+                Some(claim) => assert_eq!(claim.attempts, attempts),
+                None => assert_eq!(attempts, MAX_CLAIM_ATTEMPTS),
+            }
+        }
+        assert!(state.failed_claims.is_empty());
+
+        // No longer retried on its own
+        assert_eq!(
+            run_once(&rpc, &mut state, &config(), &secret_key())
+                .await
+                .unwrap(),
+            RoundOutcome::NotDue
+        );
+    }
+
+    #[tokio::test]
+    async fn test_phantom_bond() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.phantom_bond = true;
+        let mut state = State::init();
+        let last_claimed_timestamp = state.last_claimed_timestamp;
+
+        let res = run_once(&rpc, &mut state, &config(), &secret_key()).await;
+
+        // Submitted, but the bonds didn't grow: halted, never bonded again
+        let err = res.unwrap_err().downcast::<TaskError>().unwrap();
+        assert!(matches!(err, TaskError::Anomaly(_)));
+        assert_eq!(rpc.bonded.lock().unwrap().len(), 1);
+        assert_eq!(state.last_claimed_timestamp, last_claimed_timestamp);
+        assert!(state.pending_bond.is_none());
+        assert!(state.anomaly.is_some());
+
+        assert!(bond_landed(2_000.0, 2_004.99, 5.0));
+        assert!(!bond_landed(2_000.0, 2_000.0, 5.0));
+    }
+
+    #[test]
+    fn test_rewards_delta() {
+        assert_eq!(
+            rewards_delta(
+                token::Amount::native_whole(100),
+                token::Amount::native_whole(105)
+            ),
+            Some(token::Amount::native_whole(5))
+        );
+        assert_eq!(
+            rewards_delta(
+                token::Amount::native_whole(100),
+                token::Amount::native_whole(99)
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_claimed_rewards() {
+        let pending = token::Amount::native_whole(5);
+
+        // Within the tolerance, e.g. the claim fee, the pending rewards win
+        let delta = token::Amount::from_u64(4_990_000);
+        assert_eq!(claimed_rewards(pending, delta), pending);
+
+        // An unrelated transfer, or fees beyond the tolerance
+        let delta = token::Amount::native_whole(15);
+        assert_eq!(claimed_rewards(pending, delta), pending);
+        let delta = token::Amount::native_whole(4);
+        assert_eq!(claimed_rewards(pending, delta), delta);
+    }
+
+    #[tokio::test]
+    async fn test_ignores_unrelated_transfer() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(115),
+        ]);
+        let mut state = State::init();
+
+        run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(5)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_claimed_amount_pending() {
+        // No balance to query
+        let rpc = MockNamadaRpc::new(vec![]);
+        let mut state = State::init();
+        let config = config_with(&["--claimed-amount", "pending"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record)
+                if record.rewards == token::Amount::native_whole(5)
+                    && record.balance_pre.is_none()
+                    && record.balance_post.is_none()
+        ));
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(5)]
+        );
+
+        // What the claims paid out, not what was pending before them
+        let mut rpc = MockNamadaRpc::new(vec![]);
+        rpc.claim_rewards = Some(token::Amount::native_whole(3));
+        let mut state = State::init();
+        run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(6)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skips_bond_when_balance_decreases() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(99),
+        ]);
+        let mut state = State::init();
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::NoRewards);
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_skips_missing_commission() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let new_validator = rpc.validators.iter().next().unwrap().clone();
+        rpc.no_commission.insert(new_validator);
+        let mut state = State::init();
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(outcome, RoundOutcome::Compounded(_)));
+    }
+
+    #[tokio::test]
+    async fn test_failed_bond_query() {
+        let mut rpc = MockNamadaRpc::new(vec![]);
+        let flaky = rpc.validators.iter().next().unwrap().clone();
+        rpc.no_bond.insert(flaky);
+        let mut state = State::init();
+
+        let outcome = run_once(
+            &rpc,
+            &mut state,
+            &config_with(&["--dry-run"]),
+            &secret_key(),
+        )
+        .await
+        .unwrap();
+
+        // Left out, not counted as a zero bond
+        assert_eq!(outcome, RoundOutcome::DryRun);
+        assert_eq!(
+            state.last_round.map(|last_round| last_round.bonded_amount),
+            Some(1_000.0)
+        );
+
+        let res = run_once(
+            &rpc,
+            &mut State::init(),
+            &config_with(&["--dry-run", "--strict"]),
+            &secret_key(),
+        )
+        .await;
+        assert!(res.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_strategy() {
+        let rpc = MockNamadaRpc::new(vec![]);
+        let mut state = State::init();
+        state.update();
+
+        // Claimed just now, but the rewards are all that matters
+        let outcome = run_once(
+            &rpc,
+            &mut state,
+            &config_with(&["--strategy", "threshold:10"]),
+            &secret_key(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, RoundOutcome::BelowMinimum);
+
+        let outcome = run_once(
+            &rpc,
+            &mut state,
+            &config_with(&["--strategy", "fixed-interval:6"]),
+            &secret_key(),
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, RoundOutcome::NotDue);
+        assert_eq!(state.interval_secs, Some(6 * 3600));
+    }
+
+    #[tokio::test]
+    async fn test_skips_without_pending_rewards() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(100),
+        ]);
+        rpc.pending = token::Amount::zero();
+        let mut state = State::init();
+        let config = config_with(&["--force"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::BelowMinimum);
+        // Never got to claim, so the balances weren't read
+        assert_eq!(rpc.balances.lock().unwrap().len(), 2);
+        assert!(!state.claimed_first_time);
+    }
+
+    #[tokio::test]
+    async fn test_validate_txs() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let jailed = address_gen.generate_address("jailed");
+        let mut rpc = MockNamadaRpc::new(vec![]);
+        let config = config_with(&["--dry-run", "--validate-txs"]);
+
+        let outcome = run_once(&rpc, &mut State::init(), &config, &secret_key())
+            .await
+            .unwrap();
+        assert_eq!(outcome, RoundOutcome::DryRun);
+
+        let bond_target = jailed.to_string();
+        let config = config_with(&["--dry-run", "--validate-txs", "--bond-target", &bond_target]);
+        rpc.jailed.insert(jailed);
+        let err = run_once(&rpc, &mut State::init(), &config, &secret_key())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TaskError>(),
+            Some(TaskError::TxBuildFailed(_))
+        ));
+        // Nothing was claimed nor bonded
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_claim_only() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+
+        let claimed = claim_only(&rpc, &config(), &secret_key()).await.unwrap();
+
+        assert_eq!(claimed, token::Amount::native_whole(5));
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_bond_only() {
+        let rpc = MockNamadaRpc::new(vec![token::Amount::native_whole(100)]);
+
+        let receipts = bond_only(
+            &rpc,
+            &config(),
+            &secret_key(),
+            token::Amount::native_whole(40),
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(receipts.len(), 1);
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            [token::Amount::native_whole(40)]
+        );
+
+        // Beyond the liquid balance
+        let rpc = MockNamadaRpc::new(vec![token::Amount::native_whole(10)]);
+        let res = bond_only(
+            &rpc,
+            &config(),
+            &secret_key(),
+            token::Amount::native_whole(40),
+        )
+        .await;
+        assert!(res.is_err());
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_no_delegations() {
+        let mut rpc = MockNamadaRpc::new(vec![]);
+        rpc.validators.clear();
+        let mut state = State::init();
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::NoDelegations);
+        assert!(!state.claimed_first_time);
+    }
+
+    #[tokio::test]
+    async fn test_skips_unprofitable_round() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        let config = config_with(&["--base-fee-nam", "2.5"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::Unprofitable);
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+        assert!(!state.claimed_first_time);
+
+        let config = config_with(&["--base-fee-nam", "2.5", "--force"]);
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.rewards == token::Amount::native_whole(5)
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_fee_from_gas_price() {
+        let mut rpc = MockNamadaRpc::new(vec![]);
+        // 0.0001 * 50_000 = 5 per tx, 15 for the batched claim and two bonds,
+        // above the 5 rewards
+        rpc.gas_price = Some(0.0001);
+        let mut state = State::init();
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::Unprofitable);
+    }
+
+    #[tokio::test]
+    async fn test_fee_from_receipts() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.gas_price = Some(0.0001);
+        let mut state = State::init();
+        // Txs so far cost 0.5 each, not the 5 at the gas limit
+        state.record_fees(3, 1.5);
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        // 0.0001 * 20_000 = 2 for each of the claim and bond
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record)
+                if record.gas_used == 40_000 && (record.fee_paid_nam - 4.0).abs() < 1e-9
+        ));
+        assert!((state.avg_fee_per_tx.unwrap() - 1.1).abs() < 1e-9);
+        assert_eq!(state.fee_samples, 5);
+    }
+
+    #[tokio::test]
+    async fn test_bumped_fee_from_receipts() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.gas_price = Some(0.0001);
+        rpc.bumped_bond = Some(0.00015);
+        let mut state = State::init();
+        // Cheap enough for the round to go ahead
+        state.record_fees(3, 1.5);
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        // 2 for the claim, and 3 for the bond resubmitted at the bumped price
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if (record.fee_paid_nam - 5.0).abs() < 1e-9
+        ));
+        assert!((state.avg_fee_per_tx.unwrap() - 1.3).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_fee_cap() {
+        let rpc = MockNamadaRpc::new(vec![]);
+        let mut state = State::init();
+        // The fallback fee is 0.05 * 3 = 0.15 for the round
+        let config = config_with(&["--max-fee-nam", "0.1"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::FeeTooHigh);
+        assert!(!state.claimed_first_time);
+    }
+
+    #[tokio::test]
+    async fn test_min_liquid_balance() {
+        let rpc = MockNamadaRpc::new(vec![token::Amount::native_whole(1)]);
+        let mut state = State::init();
+        // 1 NAM liquid less the 0.15 fallback fee for the round
+        let config = config_with(&["--min-liquid-balance", "0.9"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::LowBalance);
+        assert!(!state.claimed_first_time);
+    }
+
+    #[tokio::test]
+    async fn test_anomaly_pauses_compounding() {
+        let rpc = MockNamadaRpc::new(vec![]);
+        let mut state = State::init();
+        // 2 validators with 1_000 bonded each, down from 2_500
+        state.last_bonded_amount = Some(2_500.0);
+        let config = config_with(&["--anomaly-threshold-pct", "10"]);
+
+        for _ in 0..2 {
+            let err = run_once(&rpc, &mut state, &config, &secret_key())
+                .await
+                .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref::<TaskError>(),
+                Some(TaskError::Anomaly(_))
+            ));
+        }
+        assert!(state.anomaly.as_ref().unwrap().contains("dropped 20.00%"));
+        assert!(!state.claimed_first_time);
+
+        // The 5 NAM pending are more than 0.1% of the 2_000 bonded
+        let mut state = State::init();
+        let config = config_with(&["--anomaly-threshold-pct", "0.1"]);
+        let err = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<TaskError>(),
+            Some(TaskError::Anomaly(_))
+        ));
+        assert!(state.anomaly.is_some());
+
+        // A dry run only reports what it would do
+        let mut state = State::init();
+        let config = config_with(&["--anomaly-threshold-pct", "0.1", "--dry-run"]);
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        assert_eq!(outcome, RoundOutcome::DryRun);
+        assert!(state.anomaly.is_none());
+    }
+
+    #[test]
+    fn test_rank_validators() {
+        let mut address_gen = EstablishedAddressGen::new("consensus");
+        let [big, mid, small] = [0, 1, 2].map(|i| address_gen.generate_address(i.to_string()));
+        let validators = vec![
+            (small.clone(), 1_000.0),
+            (big.clone(), 6_000.0),
+            (mid.clone(), 3_000.0),
+        ];
+
+        assert_eq!(
+            top_by_stake(validators.clone(), 2, None),
+            [(big.clone(), 6_000.0), (mid.clone(), 3_000.0)]
+        );
+        // The big one holds 60% of the stake
+        assert_eq!(
+            top_by_stake(validators.clone(), 2, Some(0.5)),
+            [(mid.clone(), 3_000.0)]
+        );
+
+        let commissions = HashMap::from([(big.clone(), 0.1), (small.clone(), 0.05)]);
+        let uptimes = HashMap::from([
+            (big.clone(), 0.9),
+            (mid.clone(), 0.99),
+            (small.clone(), 0.99),
+        ]);
+        let rank = |ranking| {
+            rank_validators(validators.clone(), ranking, &commissions, &uptimes)
+                .into_iter()
+                .map(|validator| validator.address)
+                .collect::<Vec<_>>()
+        };
+
+        // Unknown commission last
+        assert_eq!(
+            rank(ValidatorRanking::Commission),
+            [small.clone(), big.clone(), mid.clone()]
+        );
+        assert_eq!(
+            rank(ValidatorRanking::VotingPower),
+            [big.clone(), mid.clone(), small.clone()]
+        );
+        // Equal uptimes go to the most staked
+        assert_eq!(rank(ValidatorRanking::Uptime), [mid, small, big]);
+    }
+
+    #[tokio::test]
+    async fn test_auto_select_validators() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut address_gen = EstablishedAddressGen::new("consensus");
+        let [big, mid] = [0, 1].map(|i| address_gen.generate_address(i.to_string()));
+        rpc.consensus = vec![(big.clone(), 6_000.0), (mid.clone(), 3_000.0)];
+        let mut state = State::init();
+        let config = config_with(&[
+            "--auto-select-validators",
+            "--auto-select-by",
+            "voting-power",
+            "--auto-select-max-voting-power",
+            "0.5",
+        ]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(outcome, RoundOutcome::Compounded(_)));
+        // Outside the delegations, and not the oversaturated one
+        assert_eq!(
+            *rpc.bonded_to.lock().unwrap(),
+            [BTreeSet::from([mid.clone()])]
+        );
+        assert_eq!(
+            state
+                .auto_selected
+                .map(|chosen| (chosen.validators, chosen.epoch)),
+            Some((BTreeSet::from([mid.to_string()]), 100))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_submission_order() {
+        let mut submissions = vec![];
+        for _ in 0..3 {
+            let rpc = MockNamadaRpc::new(vec![
+                token::Amount::native_whole(100),
+                token::Amount::native_whole(105),
+            ]);
+            let mut state = State::init();
+            run_once(&rpc, &mut state, &config(), &secret_key())
+                .await
+                .unwrap();
+
+            let validators = rpc.validators.iter().collect::<Vec<_>>();
+            let submitted = rpc.submitted.into_inner().unwrap();
+            // Claim first, then bond validator by validator
+            assert_eq!(submitted[0], "claim");
+            assert_eq!(
+                submitted[1..],
+                validators
+                    .iter()
+                    .map(|validator| format!("bond {}", validator))
+                    .collect::<Vec<_>>()
+            );
+            submissions.push(submitted);
+        }
+
+        assert!(submissions.windows(2).all(|pair| pair[0] == pair[1]));
+    }
+
+    #[tokio::test]
+    async fn test_bond_then_claim() {
+        // Only the balance before the bond
+        let rpc = MockNamadaRpc::new(vec![token::Amount::native_whole(100)]);
+        let mut state = State::init();
+        let config = config_with(&["--round-order", "bond-then-claim"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.bonded == token::Amount::native_whole(5)
+        ));
+        let submitted = rpc.submitted.into_inner().unwrap();
+        assert!(submitted[..2].iter().all(|tx| tx.starts_with("bond ")));
+        assert_eq!(submitted[2..], ["claim"]);
+        assert!(state.pending_bond.is_none());
+
+        // The liquid balance can't take the bond
+        let rpc = MockNamadaRpc::new(vec![token::Amount::native_whole(1)]);
+        let mut state = State::init();
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::LowBalance);
+        assert!(rpc.submitted.into_inner().unwrap().is_empty());
+        assert!(!state.claimed_first_time);
+    }
+
+    #[tokio::test]
+    async fn test_bond_then_failed_claim() {
+        let mut rpc = MockNamadaRpc::new(vec![token::Amount::native_whole(100)]);
+        rpc.fail_claim = rpc.validators.clone();
+        let mut state = State::init();
+        let config = config_with(&["--round-order", "bond-then-claim"]);
+
+        // Bonded, then the claims failed
+        assert!(run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .is_err());
+        assert_eq!(
+            state.pending_claim.as_ref().map(|claim| claim.bond.amount),
+            Some(token::Amount::native_whole(5))
+        );
+
+        // Only claimed again, and recorded
+        rpc.fail_claim.clear();
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.bonded == token::Amount::native_whole(5)
+        ));
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(5)]
+        );
+        assert_eq!(
+            *rpc.claimed_from.lock().unwrap(),
+            vec![rpc.validators.clone()]
+        );
+        assert!(state.pending_claim.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_fee_token_balance() {
+        let fee_token = EstablishedAddressGen::new("tokens").generate_address("0");
+        // NAM before the claim, then the fee token
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(1),
+        ]);
+        // 0.0001 * 50_000 = 5 for each of the 3 txs
+        rpc.gas_price = Some(0.0001);
+        let mut state = State::init();
+        let config = config_with(&["--fee-token", &fee_token.to_string()]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::LowBalance);
+        assert!(!state.claimed_first_time);
+    }
+
+    #[tokio::test]
+    async fn test_fee_token_cap() {
+        let fee_token = EstablishedAddressGen::new("tokens")
+            .generate_address("0")
+            .to_string();
+
+        // 15 of the fee token for the round, and 0.15 NAM at the fallback fee.
+        // The NAM cap doesn't apply to fees paid in another token.
+        for (max_fees, compounds) in [
+            (&["--max-token-fee", "10"][..], false),
+            (&["--max-token-fee", "20", "--max-fee-nam", "0.1"][..], true),
+        ] {
+            let mut rpc = MockNamadaRpc::new(vec![
+                token::Amount::native_whole(100),
+                token::Amount::native_whole(1000),
+                token::Amount::native_whole(105),
+            ]);
+            rpc.gas_price = Some(0.0001);
+            let config = config_with(&[&["--fee-token", &fee_token][..], max_fees].concat());
+
+            let outcome = run_once(&rpc, &mut State::init(), &config, &secret_key())
+                .await
+                .unwrap();
+
+            if compounds {
+                assert!(matches!(outcome, RoundOutcome::Compounded(_)));
+            } else {
+                assert_eq!(outcome, RoundOutcome::FeeTooHigh);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fee_token_fees_left_out_of_nam_totals() {
+        let fee_token = EstablishedAddressGen::new("tokens").generate_address("0");
+        // NAM before the claim, the fee token, then NAM after it
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(1000),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.gas_price = Some(0.0001);
+        let mut state = State::init();
+        state.record_fees(3, 1.5);
+        let config = config_with(&["--fee-token", &fee_token.to_string()]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        // The 2 of the fee token each tx paid aren't NAM
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record)
+                if record.gas_used == 40_000 && record.fee_paid_nam == 0.0 && record.realized_gain == 5.0
+        ));
+        assert_eq!(state.avg_fee_per_tx, Some(0.5));
+        assert_eq!(state.fee_samples, 3);
+        assert_eq!(state.fees_paid_total, 0.0);
+    }
+
+    #[test]
+    fn test_covers_bond_fee() {
+        let nam = |unam| token::Amount::from_u64(unam);
+        assert!(covers_bond_fee(nam(1_000_000), 2, 0.05));
+        assert!(!covers_bond_fee(nam(100_000), 2, 0.05));
+        assert!(!covers_bond_fee(nam(50_000), 2, 0.05));
+        assert!(covers_bond_fee(nam(50_000), 0, 0.05));
+        // Exact where the f64 of a huge bond would swallow the fee
+        assert!(covers_bond_fee(nam((1 << 53) + 1), 1, 0.0));
+    }
+
+    #[test]
+    fn test_txs_per_round() {
+        assert_eq!(txs_per_round(3, false), 6);
+        assert_eq!(txs_per_round(3, true), 4);
+        assert_eq!(txs_per_round(1, true), 2);
+        assert_eq!(txs_per_round(0, true), 0);
+    }
+
+    #[tokio::test]
+    async fn test_resumes_bond_after_crash() {
+        let path = std::env::temp_dir().join(format!(
+            "autocompound-pending-bond-{}.json",
+            std::process::id()
+        ));
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.fail_bond = true;
+        let mut state = State::load(&path).unwrap();
+
+        let res = run_once(&rpc, &mut state, &config(), &secret_key()).await;
+        assert!(res.is_err());
+
+        // Restart from what was persisted between the claim and the bond
+        let mut state = State::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let pending_bond = state.pending_bond.clone().unwrap();
+        assert_eq!(pending_bond.amount, token::Amount::native_whole(5));
+
+        rpc.fail_bond = false;
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        // Bonded without claiming again, which would have needed more balances
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record)
+                if record.bonded == token::Amount::native_whole(5) && record.epoch == pending_bond.epoch
+        ));
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            [token::Amount::native_whole(5)]
+        );
+        assert!(state.pending_bond.is_none());
+        assert!(state.claimed_first_time);
+    }
+
+    #[test]
+    fn test_split_rewards() {
+        let rewards = token::Amount::native_whole(5);
+
+        assert_eq!(
+            split_rewards(rewards, 1.0),
+            Some((rewards, token::Amount::zero()))
+        );
+        assert_eq!(
+            split_rewards(rewards, 0.0),
+            Some((token::Amount::zero(), rewards))
+        );
+        assert_eq!(
+            split_rewards(rewards, 0.3),
+            Some((
+                token::Amount::from_u64(1_500_000),
+                token::Amount::from_u64(3_500_000)
+            ))
+        );
+        // Rounds down to whole micro units
+        assert_eq!(
+            split_rewards(token::Amount::from_u64(3), 0.5),
+            Some((token::Amount::from_u64(1), token::Amount::from_u64(2)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bonds_compound_ratio() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        let config = config_with(&["--compound-ratio", "0.5"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.rewards == token::Amount::native_whole(5)
+        ));
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::from_u64(2_500_000)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fallback_apr() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.no_inflation = true;
+
+        let err = run_once(&rpc, &mut State::init(), &config(), &secret_key())
+            .await
+            .unwrap_err();
+        assert!(format!("{:#}", err).contains("--fallback-apr"));
+
+        let config = config_with(&["--fallback-apr", "0.1"]);
+        let outcome = run_once(&rpc, &mut State::init(), &config, &secret_key())
+            .await
+            .unwrap();
+        assert!(matches!(outcome, RoundOutcome::Compounded(_)));
+    }
+
+    #[tokio::test]
+    async fn test_invalid_apr() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+
+        // The mock's 9.5% net of commission
+        let config = config_with(&["--max-apr", "0.05"]);
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::InvalidApr);
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+        assert_eq!(state.apr_ema, None);
+    }
+
+    #[tokio::test]
+    async fn test_reward_receiver() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        let receiver = EstablishedAddressGen::new("receiver").generate_address("cold");
+        let receiver_arg = receiver.to_string();
+        let config = config_with(&[
+            "--reward-receiver",
+            &receiver_arg,
+            "--compound-ratio",
+            "0.4",
+        ]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        // The receiver gets what isn't bonded back
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(2)]
+        );
+        assert_eq!(
+            *rpc.sent.lock().unwrap(),
+            vec![(receiver, token::Amount::native_whole(3))]
+        );
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.tx_hashes.last().unwrap() == "transfer"
+        ));
+    }
+
+    #[test]
+    fn test_action_amounts() {
+        let receiver = EstablishedAddressGen::new("receiver").generate_address("sub");
+        let transfer = |share| RewardAction::Transfer {
+            receiver: receiver.clone(),
+            share,
+        };
+        let rewards = token::Amount::native_whole(5);
+
+        assert_eq!(
+            action_amounts(&[RewardAction::Bond { share: None }], rewards),
+            Some(vec![rewards])
+        );
+        assert_eq!(
+            action_amounts(
+                &[transfer(Some(0.2)), RewardAction::Bond { share: None }],
+                rewards
+            ),
+            Some(vec![
+                token::Amount::native_whole(1),
+                token::Amount::native_whole(4)
+            ])
+        );
+        // Nothing left after the first one
+        assert_eq!(
+            action_amounts(
+                &[transfer(None), RewardAction::Bond { share: None }],
+                rewards
+            ),
+            Some(vec![rewards, token::Amount::zero()])
+        );
+        assert_eq!(
+            action_amounts(
+                &[RewardAction::Bond { share: Some(0.8) }, transfer(Some(0.8))],
+                rewards
+            ),
+            Some(vec![
+                token::Amount::native_whole(4),
+                token::Amount::native_whole(1)
+            ])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_reward_actions() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        let sub_account = EstablishedAddressGen::new("receiver").generate_address("sub");
+        let actions = format!("transfer:{}:0.2,bond", sub_account);
+        let config = config_with(&["--reward-actions", &actions]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        // A fifth of the claimed rewards is sent first, the rest bonded
+        assert_eq!(
+            *rpc.sent.lock().unwrap(),
+            vec![(sub_account, token::Amount::native_whole(1))]
+        );
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(4)]
+        );
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record)
+                if record.bonded == token::Amount::native_whole(4)
+                    && record.tx_hashes[1] == "transfer"
+        ));
+        assert!(state.pending_bond.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_bond_validators() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut address_gen = EstablishedAddressGen::new("preferred");
+        let [preferred, jailed] = [0, 1].map(|i| address_gen.generate_address(i.to_string()));
+        rpc.jailed.insert(jailed.clone());
+        let preferred_arg = preferred.to_string();
+        let config = config_with(&["--bond-validators", &preferred_arg]);
+
+        check_bond_validators(&rpc, &config).await.unwrap();
+        run_once(&rpc, &mut State::init(), &config, &secret_key())
+            .await
+            .unwrap();
+
+        // Not one of the delegations claimed from
+        assert!(!rpc.validators.contains(&preferred));
+        assert_eq!(
+            *rpc.bonded_to.lock().unwrap(),
+            [BTreeSet::from([preferred.clone()])]
+        );
+
+        let both = format!("{},{}", preferred, jailed);
+        let config = config_with(&["--bond-validators", &both]);
+        assert!(check_bond_validators(&rpc, &config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_skips_bonding_to_jailed() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let jailed = rpc.validators.iter().next().unwrap().clone();
+        rpc.jailed.insert(jailed.clone());
+        let mut state = State::init();
+
+        run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        let bonded_to = rpc.bonded_to.lock().unwrap();
+        assert_eq!(bonded_to.len(), 1);
+        assert_eq!(bonded_to[0].len(), 1);
+        assert!(!bonded_to[0].contains(&jailed));
+    }
+
+    #[tokio::test]
+    async fn test_bond_to_jailed_override() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.jailed = rpc.validators.clone();
+        let mut state = State::init();
+        let config = config_with(&["--bond-to-jailed"]);
+
+        run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(*rpc.bonded_to.lock().unwrap(), vec![rpc.validators.clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_skips_bonding_during_slashing() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut validators = rpc.validators.iter().cloned();
+        rpc.slashed.insert(validators.next().unwrap());
+        rpc.unbonding.insert(validators.next().unwrap());
+        let mut state = State::init();
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        // Claimed, but left liquid
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.bonded.is_zero()
+        ));
+        assert!(rpc.bonded_to.lock().unwrap().is_empty());
+
+        let config = config_with(&["--bond-during-slashing"]);
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.slashed = rpc.validators.clone();
+
+        run_once(&rpc, &mut State::init(), &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(*rpc.bonded_to.lock().unwrap(), vec![rpc.validators.clone()]);
+    }
+
+    #[tokio::test]
+    async fn test_per_validator_schedule() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        let config = config_with(&["--per-validator-schedule"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(outcome, RoundOutcome::Compounded(record) if record.validators == 2));
+        assert_eq!(state.validators.len(), 2);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(outcome, RoundOutcome::NotDue);
+    }
+
+    #[tokio::test]
+    async fn test_epoch_aligned() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        // Due by wall-clock, but the mock chain is still at epoch 100
+        state.claimed_first_time = true;
+        state.last_claimed_timestamp = 0;
+        state.last_claimed_epoch = 100;
+        let config = config_with(&["--epoch-aligned"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        assert_eq!(outcome, RoundOutcome::NotDue);
+
+        state.last_claimed_epoch = 99;
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        assert!(matches!(outcome, RoundOutcome::Compounded(_)));
+        assert_eq!(state.last_claimed_epoch, 100);
+    }
+
+    #[tokio::test]
+    async fn test_compound_on_epoch_change() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        // Just compounded, but the mock chain moved on to epoch 100
+        state.update();
+        state.last_claimed_epoch = 99;
+
+        let outcome = run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+        assert_eq!(outcome, RoundOutcome::NotDue);
+
+        let config = config_with(&["--compound-on-epoch-change"]);
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        assert!(matches!(outcome, RoundOutcome::Compounded(_)));
+        assert_eq!(state.last_claimed_epoch, 100);
+
+        // Once per epoch
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        assert_eq!(outcome, RoundOutcome::NotDue);
+    }
+
+    #[tokio::test]
+    async fn test_manual_trigger_ignores_schedule() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        state.update();
+
+        let outcome = run_round(
+            &rpc,
+            &mut state,
+            &config(),
+            &secret_key(),
+            Trigger::Scheduled,
+        )
+        .await
+        .unwrap();
+        assert_eq!(outcome, RoundOutcome::NotDue);
+
+        let outcome = run_round(&rpc, &mut state, &config(), &secret_key(), Trigger::Manual)
+            .await
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.tx_hashes == ["claim", "bond"]
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_accumulate_until() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+            token::Amount::native_whole(105),
+            token::Amount::native_whole(110),
+        ]);
+        let config = config_with(&["--accumulate-until", "8"]);
+        let mut state = State::init();
+
+        // Claimed, but left liquid
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.bonded.is_zero()
+        ));
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+        assert_eq!(state.accumulated, token::Amount::native_whole(5));
+
+        // Past the threshold, both rounds' rewards go in a single bond
+        let outcome = run_round(&rpc, &mut state, &config, &secret_key(), Trigger::Manual)
+            .await
+            .unwrap();
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.bonded == token::Amount::native_whole(10)
+        ));
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(10)]
+        );
+        assert!(state.accumulated.is_zero());
+    }
+
+    #[tokio::test]
+    async fn test_accumulated_bond_fails() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+            token::Amount::native_whole(105),
+            token::Amount::native_whole(110),
+            token::Amount::native_whole(110),
+            token::Amount::native_whole(115),
+        ]);
+        let config = config_with(&["--accumulate-until", "8"]);
+        let mut state = State::init();
+        run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        // Claimed into the accumulated rewards, whose bond fails
+        rpc.fail_bond = true;
+        assert!(
+            run_round(&rpc, &mut state, &config, &secret_key(), Trigger::Manual)
+                .await
+                .is_err()
+        );
+        assert_eq!(state.accumulated, token::Amount::native_whole(10));
+        assert!(state.pending_bond.is_none());
+
+        // Bonded once with the next rewards, nothing twice
+        rpc.fail_bond = false;
+        run_round(&rpc, &mut state, &config, &secret_key(), Trigger::Manual)
+            .await
+            .unwrap();
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(15)]
+        );
+        assert!(state.accumulated.is_zero());
+    }
+
+    #[test]
+    fn test_validator_projections() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let large = address_gen.generate_address("0");
+        let dust = address_gen.generate_address("1");
+        let validators = BTreeSet::from([large.clone(), dust.clone()]);
+        let bonds = HashMap::from([(large.clone(), 100_000.0), (dust.clone(), 0.01)]);
+        let commissions = HashMap::from([(large.clone(), 0.1), (dust.clone(), 0.05)]);
+        let uptimes = HashMap::from([(dust.clone(), 0.5)]);
+
+        let projections = validator_projections(
+            &config(),
+            &validators,
+            &bonds,
+            &commissions,
+            &uptimes,
+            ChainRates {
+                pos_inflation: 0.1,
+                fee_per_tx: 0.1,
+                epochs_per_year: None,
+            },
+        );
+
+        let large = projections
+            .iter()
+            .find(|projection| projection.address == large.to_string())
+            .unwrap();
+        assert_eq!(large.bond, 100_000.0);
+        assert!((large.net_apr - 0.09).abs() < 1e-9);
+        assert!(large.compounding_frequency_hours.is_some());
+
+        let dust = projections
+            .iter()
+            .find(|projection| projection.address == dust.to_string())
+            .unwrap();
+        assert!((dust.net_apr - 0.0475).abs() < 1e-9);
+        assert!(dust.compounding_frequency_hours.is_none());
+
+        // Not projected at no commission
+        let unknown = address_gen.generate_address("2");
+        let projections = validator_projections(
+            &config(),
+            &BTreeSet::from([unknown]),
+            &bonds,
+            &commissions,
+            &uptimes,
+            ChainRates {
+                pos_inflation: 0.1,
+                fee_per_tx: 0.1,
+                epochs_per_year: None,
+            },
+        );
+        assert!(projections.is_empty());
+    }
+
+    #[test]
+    fn test_portfolio_uptime() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let reliable = address_gen.generate_address("0");
+        let flaky = address_gen.generate_address("1");
+        let bonds = HashMap::from([(reliable, 300.0), (flaky.clone(), 100.0)]);
+
+        assert_eq!(portfolio_uptime(&HashMap::new(), &bonds), 1.0);
+        let uptime = portfolio_uptime(&HashMap::from([(flaky, 0.6)]), &bonds);
+        assert!((uptime - 0.9).abs() < 1e-12);
+        assert_eq!(portfolio_uptime(&HashMap::new(), &HashMap::new()), 1.0);
+    }
+
+    #[test]
+    fn test_bond_distribution() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let cheap = address_gen.generate_address("0");
+        let expensive = address_gen.generate_address("1");
+        let validators = BTreeSet::from([cheap.clone(), expensive.clone()]);
+        let bonds = HashMap::from([(cheap.clone(), 1_000.0), (expensive.clone(), 3_000.0)]);
+        let commissions = HashMap::from([(cheap.clone(), 0.05), (expensive.clone(), 0.1)]);
+        let amount = token::Amount::native_whole(10);
+
+        let distribute = |target: BondTarget| {
+            bond_distribution(&target, amount, &validators, &bonds, &commissions)
+                .unwrap()
+                .into_iter()
+                .collect::<HashMap<_, _>>()
+        };
+
+        let even = distribute(BondTarget::Even);
+        assert_eq!(even[&cheap], token::Amount::native_whole(5));
+        assert_eq!(even[&expensive], token::Amount::native_whole(5));
+
+        let proportional = distribute(BondTarget::Proportional);
+        assert_eq!(
+            proportional[&cheap].checked_add(proportional[&expensive]),
+            Some(amount)
+        );
+        assert_eq!(proportional[&expensive], token::Amount::from_u64(7_500_000));
+
+        // Even rather than nothing to the validator of the failed query
+        let partial = HashMap::from([(expensive.clone(), 3_000.0)]);
+        let distribution = bond_distribution(
+            &BondTarget::Proportional,
+            amount,
+            &validators,
+            &partial,
+            &commissions,
+        )
+        .unwrap();
+        assert_eq!(distribution.into_iter().collect::<HashMap<_, _>>(), even);
+
+        let best = distribute(BondTarget::BestApr);
+        assert_eq!(best, HashMap::from([(cheap.clone(), amount)]));
+    }
+
+    #[tokio::test]
+    async fn test_claims_from_unbonded_validator() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let [bonded, unbonded] =
+            <[Address; 2]>::try_from(rpc.validators.iter().cloned().collect::<Vec<_>>()).unwrap();
+        rpc.zero_bond.insert(unbonded.clone());
+        let mut state = State::init();
+
+        run_once(&rpc, &mut state, &config(), &secret_key())
+            .await
+            .unwrap();
+
+        // The claim set has the rewards of both, the bond set only the bond
+        assert_eq!(
+            *rpc.claimed_from.lock().unwrap(),
+            vec![BTreeSet::from([bonded.clone(), unbonded.clone()])]
+        );
+        assert_eq!(
+            *rpc.bonded_to.lock().unwrap(),
+            vec![BTreeSet::from([bonded])]
+        );
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(5)]
+        );
+
+        // Unless targeted
+        let rpc = MockNamadaRpc {
+            zero_bond: BTreeSet::from([unbonded.clone()]),
+            ..MockNamadaRpc::new(vec![
+                token::Amount::native_whole(100),
+                token::Amount::native_whole(105),
+            ])
+        };
+        let config = config_with(&["--bond-target", &unbonded.to_string()]);
+        run_once(&rpc, &mut State::init(), &config, &secret_key())
+            .await
+            .unwrap();
+        assert_eq!(
+            *rpc.bonded_to.lock().unwrap(),
+            vec![BTreeSet::from([unbonded])]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bond_target_outside_delegations() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let target = EstablishedAddressGen::new("target").generate_address("0");
+        let mut state = State::init();
+        let config = config_with(&["--bond-target", &target.to_string()]);
+
+        run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            *rpc.bonded_to.lock().unwrap(),
+            vec![BTreeSet::from([target])]
+        );
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(5)]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_commission_skips_bond() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let mut state = State::init();
+        // The mock validators all charge 5%
+        let config = config_with(&["--max-commission", "0.01"]);
+
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.bonded.is_zero()
+        ));
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_cap_bonds() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let [full, half, empty] = [0, 1, 2].map(|i| address_gen.generate_address(i.to_string()));
+        let stakes = HashMap::from([
+            (full.clone(), 1_000.0),
+            (half.clone(), 996.0),
+            (empty.clone(), 999.0),
+        ]);
+        let distribution = vec![
+            (full.clone(), token::Amount::native_whole(3)),
+            (half.clone(), token::Amount::native_whole(3)),
+            (empty.clone(), token::Amount::zero()),
+        ];
+
+        // 3 NAM over at the first, 1 over at the second, room for 1 at the last
+        let capped = cap_bonds(distribution.clone(), &stakes, 1_000.0);
+        assert_eq!(
+            capped.distribution,
+            [
+                (full.clone(), token::Amount::zero()),
+                (half.clone(), token::Amount::native_whole(4)),
+                (empty.clone(), token::Amount::native_whole(1)),
+            ]
+        );
+        assert_eq!(
+            capped.redirected,
+            [
+                (full.clone(), half.clone(), token::Amount::native_whole(1)),
+                (full.clone(), empty.clone(), token::Amount::native_whole(1)),
+            ]
+        );
+        assert_eq!(capped.unbonded, token::Amount::native_whole(1));
+
+        // Room everywhere, untouched
+        let capped = cap_bonds(distribution.clone(), &stakes, 10_000.0);
+        assert_eq!(capped.distribution, distribution);
+        assert!(capped.redirected.is_empty());
+        assert!(capped.unbonded.is_zero());
+    }
+
+    #[test]
+    fn test_sticky_target() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let current = address_gen.generate_address("0");
+        let best = address_gen.generate_address("1");
+        let validators = BTreeSet::from([current.clone(), best.clone()]);
+        let chosen = ChosenTarget {
+            validator: current.to_string(),
+            epoch: 100,
+        };
+        let sticky = |commissions: &HashMap<Address, f64>, epoch| {
+            sticky_target(
+                Some(&chosen),
+                &best,
+                &validators,
+                commissions,
+                epoch,
+                10,
+                0.01,
+            )
+        };
+
+        // 95.0% against 95.5% net, within the margin
+        let marginal = HashMap::from([(current.clone(), 0.05), (best.clone(), 0.045)]);
+        assert_eq!(sticky(&marginal, 105), Some(current.clone()));
+        // Not once the cooldown is over
+        assert_eq!(sticky(&marginal, 110), None);
+
+        let better = HashMap::from([(current.clone(), 0.05), (best.clone(), 0.02)]);
+        assert_eq!(sticky(&better, 105), None);
+
+        // No longer a candidate
+        let others = BTreeSet::from([best.clone()]);
+        assert_eq!(
+            sticky_target(Some(&chosen), &best, &others, &marginal, 105, 10, 0.01),
+            None
+        );
+        assert_eq!(
+            sticky_target(None, &best, &validators, &marginal, 105, 10, 0.01),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chosen_target_after_bond() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        rpc.fail_bond = true;
+        let mut state = State::init();
+        let config = config_with(&[
+            "--bond-target",
+            "best-apr",
+            "--target-cooldown-epochs",
+            "10",
+        ]);
+
+        assert!(run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .is_err());
+        // Nothing was bonded to it
+        assert_eq!(state.chosen_target, None);
+
+        rpc.fail_bond = false;
+        run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+        let bonded_to = rpc.bonded_to.lock().unwrap()[0].clone();
+        assert_eq!(
+            state
+                .chosen_target
+                .map(|chosen| (chosen.validator, chosen.epoch)),
+            bonded_to
+                .first()
+                .map(|validator| (validator.to_string(), 100))
+        );
+    }
+
+    #[test]
+    fn test_sticky_selection() {
+        let mut address_gen = EstablishedAddressGen::new("consensus");
+        let [best, second, third] = [0, 1, 2].map(|i| address_gen.generate_address(i.to_string()));
+        let ranked = [
+            (best.clone(), 0.02),
+            (second.clone(), 0.045),
+            (third.clone(), 0.05),
+        ]
+        .map(|(address, commission)| RankedValidator {
+            address,
+            stake: 1_000.0,
+            commission: Some(commission),
+            uptime: None,
+        });
+        let chosen = ChosenValidators {
+            validators: BTreeSet::from([best.to_string(), third.to_string()]),
+            epoch: 100,
+        };
+        let select = |ranked: &[RankedValidator], epoch| {
+            sticky_selection(
+                ranked,
+                2,
+                ValidatorRanking::Commission,
+                Some(&chosen),
+                epoch,
+                10,
+                0.01,
+            )
+            .into_iter()
+            .map(|validator| validator.address)
+            .collect::<Vec<_>>()
+        };
+
+        // 95.0% against 95.5% net, within the margin
+        assert_eq!(select(&ranked, 105), [best.clone(), third.clone()]);
+        // Not once the cooldown is over
+        assert_eq!(select(&ranked, 110), [best.clone(), second.clone()]);
+
+        let mut better = ranked.clone();
+        better[1].commission = Some(0.03);
+        assert_eq!(select(&better, 105), [best.clone(), second.clone()]);
+
+        // Only the top without a selection
+        assert_eq!(
+            sticky_selection(
+                &ranked,
+                2,
+                ValidatorRanking::Commission,
+                None,
+                105,
+                10,
+                0.01
+            )
+            .into_iter()
+            .map(|validator| validator.address)
+            .collect::<Vec<_>>(),
+            [best, second]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_max_bond_per_validator() {
+        let mut rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let [saturated, open] =
+            <[Address; 2]>::try_from(rpc.validators.iter().cloned().collect::<Vec<_>>()).unwrap();
+        rpc.stakes = HashMap::from([(saturated.clone(), 1_000.0), (open.clone(), 10.0)]);
+        let mut state = State::init();
+        let config = config_with(&["--max-bond-per-validator", "1000"]);
+
+        run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        // The whole compound goes to the one with room
+        assert_eq!(
+            *rpc.bonded_to.lock().unwrap(),
+            vec![BTreeSet::from([open.clone()])]
+        );
+        assert_eq!(
+            *rpc.bonded.lock().unwrap(),
+            vec![token::Amount::native_whole(5)]
+        );
+
+        // No room left anywhere, the rewards stay liquid
+        let rpc = MockNamadaRpc {
+            stakes: HashMap::from([(saturated, 1_000.0), (open, 1_000.0)]),
+            ..MockNamadaRpc::new(vec![
+                token::Amount::native_whole(100),
+                token::Amount::native_whole(105),
+            ])
+        };
+        let mut state = State::init();
+        let outcome = run_once(&rpc, &mut state, &config, &secret_key())
+            .await
+            .unwrap();
+
+        assert!(matches!(
+            outcome,
+            RoundOutcome::Compounded(record) if record.bonded.is_zero()
+        ));
+        assert!(rpc.bonded.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_next_round() {
+        const NOW: u64 = 1_700_000_000;
+        let config = config();
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let [validator, failing] = [0, 1].map(|i| address_gen.generate_address(i.to_string()));
+        let validators = BTreeSet::from([validator.clone(), failing.clone()]);
+
+        // Never compounded, the first round starts right away
+        let mut state = State::init();
+        assert_eq!(
+            next_round(
+                &state,
+                &config,
+                &validators,
+                10,
+                3600,
+                Trigger::Scheduled,
+                NOW
+            ),
+            NextRound::CompoundNow
+        );
+        let skip_first = config_with(&["--skip-first-compound"]);
+        assert_eq!(
+            next_round(
+                &state,
+                &skip_first,
+                &validators,
+                10,
+                3600,
+                Trigger::Scheduled,
+                NOW
+            ),
+            NextRound::WaitSecs(3600)
+        );
+
+        state.update_at(NOW - 600);
+        state.last_claimed_epoch = 10;
+        assert_eq!(
+            next_round(
+                &state,
+                &config,
+                &validators,
+                10,
+                3600,
+                Trigger::Scheduled,
+                NOW
+            ),
+            NextRound::WaitSecs(3000)
+        );
+        assert_eq!(
+            next_round(
+                &state,
+                &config,
+                &validators,
+                10,
+                3600,
+                Trigger::Scheduled,
+                NOW + 3000
+            ),
+            NextRound::CompoundNow
+        );
+
+        let on_epoch = config_with(&["--compound-on-epoch-change"]);
+        assert_eq!(
+            next_round(
+                &state,
+                &on_epoch,
+                &validators,
+                11,
+                3600,
+                Trigger::Scheduled,
+                NOW
+            ),
+            NextRound::CompoundOnNewEpoch
+        );
+
+        let aligned = config_with(&["--epoch-aligned", "--epochs-between-compounds", "3"]);
+        assert_eq!(
+            next_round(
+                &state,
+                &aligned,
+                &validators,
+                11,
+                3600,
+                Trigger::Scheduled,
+                NOW + 3000
+            ),
+            NextRound::WaitEpoch(13)
+        );
+
+        // An unfinished round is resumed before anything else is due
+        state.pending_bond = Some(PendingBond {
+            epoch: 10,
+            rewards: token::Amount::native_whole(5),
+            amount: token::Amount::native_whole(5),
+            balance_pre: None,
+            balance_post: None,
+            projected_gain: None,
+        });
+        assert_eq!(
+            next_round(
+                &state,
+                &config,
+                &validators,
+                10,
+                3600,
+                Trigger::Scheduled,
+                NOW
+            ),
+            NextRound::ResumeBond
+        );
+
+        // Claimed in an earlier epoch, and not due to the clock otherwise
+        state.pending_bond = None;
+        state.record_claims(
+            [&failing],
+            &BTreeMap::from([(failing.to_string(), "Claim rejected".to_string())]),
+            10,
+        );
+        assert_eq!(
+            next_round(
+                &state,
+                &config,
+                &validators,
+                11,
+                3600,
+                Trigger::Scheduled,
+                NOW
+            ),
+            NextRound::RetryClaims(1)
+        );
+        assert_eq!(
+            next_round(&state, &config, &validators, 11, 3600, Trigger::Manual, NOW),
+            NextRound::CompoundNow
+        );
+
+        state.pending_claim = Some(PendingClaim {
+            validators: BTreeSet::from([failing.to_string()]),
+            bond: PendingBond {
+                epoch: 10,
+                rewards: token::Amount::zero(),
+                amount: token::Amount::zero(),
+                balance_pre: None,
+                balance_post: None,
+                projected_gain: None,
+            },
+        });
+        assert_eq!(
+            next_round(
+                &state,
+                &config,
+                &validators,
+                11,
+                3600,
+                Trigger::Scheduled,
+                NOW
+            ),
+            NextRound::ResumeClaim
+        );
+        // No longer delegated to
+        assert_eq!(
+            next_round(
+                &state,
+                &config,
+                &BTreeSet::from([validator]),
+                11,
+                3600,
+                Trigger::Scheduled,
+                NOW
+            ),
+            NextRound::WaitSecs(3000)
+        );
+    }
+}
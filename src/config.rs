@@ -1,20 +1,1654 @@
+use std::{
+    collections::{HashMap, HashSet},
+    ffi::OsString,
+    fmt::{self, Display},
+    net::SocketAddr,
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+
+use anyhow::Context;
+use clap::{
+    builder::Resettable, parser::ValueSource, ArgGroup, ArgMatches, CommandFactory, FromArgMatches,
+};
+use namada_sdk::{address::Address, chain::ChainId, masp::ExtendedViewingKey, token};
+use rand::Rng;
+use reqwest::Url;
+use tracing::Level;
+
+use crate::{
+    denom,
+    key::KeyScheme,
+    opt::{self, IntervalRounding, Optimizer},
+    strategy::Strategy,
+    utils,
+};
+
+const KEY_ARGS: [&str; 6] = [
+    "secret_key",
+    "secret_key_file",
+    "mnemonic",
+    "mnemonic_file",
+    "accounts_file",
+    "chains_file",
+];
+
+/// Longest `--tx-memo`. The chain only bounds the whole tx, this keeps the
+/// memo a small part of it.
+const MAX_TX_MEMO_BYTES: usize = 256;
+
+/// Options whose value `--print-config` masks. Webhook URLs carry their
+/// token in the path.
+const SECRET_ARGS: [&str; 3] = ["secret_key", "mnemonic", "webhook_url"];
+
 #[derive(clap::Parser)]
+#[clap(subcommand_negates_reqs = true)]
+#[clap(group(
+    ArgGroup::new("key")
+        .required(true)
+        .args(KEY_ARGS)
+))]
 pub struct AppConfig {
+    #[clap(subcommand)]
+    pub command: Option<Command>,
+
+    /// TOML file with any of the options below (as `snake_case` or
+    /// `kebab-case` keys). Flags and env vars take precedence over it
+    #[clap(long, env)]
+    pub config: Option<PathBuf>,
+
+    /// One or more RPC endpoints (comma separated or repeated), tried in
+    /// order of health and freshness.
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        required_unless_present = "chains_file"
+    )]
+    pub namada_rpc: Vec<String>,
+
     #[clap(long, env)]
-    pub namada_rpc: String,
+    pub secret_key: Option<String>,
+
+    /// Read the secret key from this file instead (`-` for stdin)
+    #[clap(long, env)]
+    pub secret_key_file: Option<PathBuf>,
+
+    /// Derive the key from this BIP39 mnemonic instead
+    #[clap(long, env)]
+    pub mnemonic: Option<String>,
+
+    /// Read the mnemonic from this file instead (`-` for stdin)
+    #[clap(long, env)]
+    pub mnemonic_file: Option<PathBuf>,
+
+    /// HD derivation path used with the mnemonic, the Namada default for
+    /// `--key-scheme` if unset
+    #[clap(long, env)]
+    pub derivation_path: Option<String>,
+
+    /// Signature scheme of the delegator key
+    #[clap(long, env, value_enum, default_value_t = KeyScheme::Ed25519)]
+    pub key_scheme: KeyScheme,
 
+    /// TOML file of `[[account]]` tables (`alias`, `secret_key` or
+    /// `secret_key_file`, optional `state_file`) to compound several
+    /// delegators, each on its own schedule
     #[clap(long, env)]
-    pub secret_key: String,
+    pub accounts_file: Option<PathBuf>,
+
+    /// TOML file of `[[chain]]` tables (`name`, and a `config` file of the
+    /// options below) to compound on several chains, each with its own RPC,
+    /// keys, wallet and state. Only the logging and `--metrics-addr` options
+    /// are then taken from the command line
+    #[clap(long, env)]
+    pub chains_file: Option<PathBuf>,
+
+    /// How many accounts of `--accounts-file` compound at once. The txs of
+    /// each account are still submitted one after the other
+    #[clap(
+        long,
+        env,
+        default_value_t = 1,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub account_concurrency: usize,
+
+    /// Abort if the secret key doesn't control this address
+    #[clap(long, env, conflicts_with = "accounts_file")]
+    pub expected_address: Option<Address>,
+
+    /// Abort at startup if an RPC endpoint serves another chain
+    #[clap(long, env)]
+    pub chain_id: Option<ChainId>,
 
     #[clap(long, env)]
     pub dry_run: bool,
 
-    #[clap(long, env, default_value_t = 0.05)]
-    pub base_fee_unam: f64,
+    /// With `--dry-run`, also build and sign the claim and bond txs, without
+    /// submitting them, to catch a wrong key or a validator that can't take
+    /// a bond
+    #[clap(long, env, requires = "dry_run")]
+    pub validate_txs: bool,
+
+    /// Format of the dry-run report, and of the next compound in one-time mode
+    #[clap(long, env, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Fee per tx, used when it can't be estimated from the chain's gas price
+    /// nor from the fees of past compounds. In NAM, or in micro-NAM with a
+    /// `unam` suffix
+    #[clap(
+        long,
+        env,
+        alias = "base-fee-unam",
+        default_value_t = 0.05,
+        value_parser = denom::parse_nam
+    )]
+    pub base_fee_nam: f64,
+
+    /// Skip the round if the estimated fees for all claims and bonds exceed
+    /// this, in NAM or with a `unam` suffix. A tx rejected for its fee is
+    /// resubmitted at a higher one only if that stays under it too. Fees
+    /// paid in a `--fee-token` are capped by `--max-token-fee` instead
+    #[clap(long, env, alias = "max-fee-unam", value_parser = denom::parse_nam)]
+    pub max_fee_nam: Option<f64>,
+
+    /// Increasing fees per tx (comma separated, in NAM or with a `unam`
+    /// suffix) to resubmit a tx rejected for its fee at, one after the other
+    /// until it lands, instead of a single bump. The ones below the chain's
+    /// minimum are skipped. With a `--fee-token`, in whole tokens of it
+    #[clap(long, env, value_delimiter = ',', value_parser = denom::parse_nam)]
+    pub fee_ladder: Vec<f64>,
+
+    /// Pay the claim and bond fees in this token instead of NAM. With no NAM
+    /// price for it, the optimizer then counts `--base-fee-nam` per tx, and
+    /// the fees paid are left out of the NAM fee average and totals
+    #[clap(long, env)]
+    pub fee_token: Option<Address>,
+
+    /// `--max-fee-nam` for the fees paid in the `--fee-token`, in whole
+    /// tokens of it
+    #[clap(long, env, requires = "fee_token", value_parser = parse_token_fee)]
+    pub max_token_fee: Option<f64>,
+
+    /// Memo set on every claim and bond tx, to find them in a block
+    /// explorer. At most 256 bytes
+    #[clap(long, env, value_parser = parse_memo)]
+    pub tx_memo: Option<String>,
+
+    /// Skip the round if paying its estimated fees would leave less than
+    /// this liquid, in NAM or with a `unam` suffix
+    #[clap(long, env, value_parser = denom::parse_nam)]
+    pub min_liquid_balance: Option<f64>,
+
+    /// Warn when a compound gains more than this share above or below the
+    /// projection, e.g. 0.5 for outside 50% to 150% of it
+    #[clap(long, env, default_value_t = 0.5, value_parser = parse_ratio)]
+    pub projection_band: f64,
+
+    /// Pause compounding, and alert, when the bonded amount drops by more
+    /// than this percentage between two rounds or the pending rewards exceed
+    /// this percentage of it, until restarted with `--clear-anomaly`
+    #[clap(long, env, value_parser = parse_percent)]
+    pub anomaly_threshold_pct: Option<f64>,
+
+    /// Resume compounding paused by `--anomaly-threshold-pct`
+    #[clap(long, env)]
+    pub clear_anomaly: bool,
+
+    /// Start the lifetime tally of compounded rewards and fees over
+    #[clap(long, env)]
+    pub reset_stats: bool,
+
+    /// Only compound on these validators (must be part of the delegation set)
+    #[clap(long, env, value_delimiter = ',')]
+    pub only_validators: Vec<Address>,
+
+    /// Never compound on these validators
+    #[clap(long, env, value_delimiter = ',')]
+    pub exclude_validators: Vec<Address>,
+
+    /// Also bond to jailed or inactive validators
+    #[clap(long, env)]
+    pub bond_to_jailed: bool,
+
+    /// Also bond to validators with slashes pending, or that the delegator is
+    /// unbonding from
+    #[clap(long, env)]
+    pub bond_during_slashing: bool,
+
+    /// Where the claimed rewards are bonded: `even` across the validators
+    /// claimed from, `proportional` to the current bonds, all to the
+    /// `best-apr` (lowest commission) one, or all to a validator address
+    #[clap(long, env, default_value = "even")]
+    pub bond_target: BondTarget,
+
+    /// With `--bond-target best-apr` or `--auto-select-validators`, keep
+    /// bonding to the validators chosen for this many epochs, unless another
+    /// one pays, or ranks, more than `--target-switch-margin` better. The
+    /// `rebalance` subcommand doesn't move stake off them meanwhile
+    #[clap(long, env, default_value_t = 0)]
+    pub target_cooldown_epochs: u64,
+
+    /// How much better, as a share of the rewards net of commission or of
+    /// the ranking score, another validator must be for
+    /// `--target-cooldown-epochs` to give way, e.g. 0.02 for 2%. Also how
+    /// far off its target, as a share of it, `rebalance` leaves a bond
+    #[clap(long, env, default_value_t = 0.01, value_parser = parse_ratio)]
+    pub target_switch_margin: f64,
+
+    /// Bond the claimed rewards to these validators, spread as `even`,
+    /// `proportional` or `best-apr` `--bond-target` says, rather than to the
+    /// ones they're claimed from. They may be new delegations, but must be
+    /// active validators
+    #[clap(
+        long,
+        env,
+        value_delimiter = ',',
+        conflicts_with = "auto_select_validators"
+    )]
+    pub bond_validators: Vec<Address>,
+
+    /// Bond the claimed rewards evenly to the best validators of the whole
+    /// consensus set, as `--auto-select-by` ranks them, instead of as
+    /// `--bond-target` says
+    #[clap(long, env)]
+    pub auto_select_validators: bool,
+
+    /// What `--auto-select-validators` ranks the validators by
+    #[clap(long, env, value_enum, default_value_t = ValidatorRanking::Commission)]
+    pub auto_select_by: ValidatorRanking,
+
+    /// Only rank the validators with the most stake, this many of them
+    #[clap(long, env, default_value_t = 100)]
+    pub auto_select_top: usize,
+
+    /// How many of the best ranked validators to bond to
+    #[clap(long, env, default_value_t = 1)]
+    pub auto_select_count: usize,
+
+    /// Never auto-select a validator holding more than this share of the
+    /// stake, e.g. 0.05 for 5%, to avoid oversaturated ones
+    #[clap(long, env, value_parser = parse_ratio)]
+    pub auto_select_max_voting_power: Option<f64>,
+
+    /// Stop bonding new rewards to validators whose commission rate is above
+    /// this (e.g. 0.1 for 10%). Rewards are still claimed from them
+    #[clap(long, env, value_parser = parse_ratio)]
+    pub max_commission: Option<f64>,
+
+    /// Don't let the bonds of a compound take a validator's total stake
+    /// above this, in NAM or with a `unam` suffix. What doesn't fit goes to
+    /// the other validators bonded to that have room left, or else stays
+    /// liquid
+    #[clap(long, env, value_parser = denom::parse_nam)]
+    pub max_bond_per_validator: Option<f64>,
+
+    /// Leave the rewards to bond liquid until they add up to this, in NAM
+    /// or with a `unam` suffix, then bond them all at once: fewer bond txs
+    /// for compounding later. What's accumulated so far is kept in the state
+    #[clap(long, env, value_parser = denom::parse_nam)]
+    pub accumulate_until: Option<f64>,
+
+    /// Staking rewards rate before commission, e.g. 0.1 for 10%, to carry on
+    /// with when the chain can't be queried for it
+    #[clap(long, env, value_parser = parse_ratio)]
+    pub fallback_apr: Option<f64>,
+
+    /// Highest net APR taken as real, e.g. 1.0 for 100%. Rounds seeing a
+    /// higher (or a negative) one are skipped, as it's most likely a bad RPC
+    /// response the optimizer would turn into a bizarre frequency
+    #[clap(long, env, default_value_t = 1.0, value_parser = parse_max_apr)]
+    pub max_apr: f64,
+
+    /// How per-validator commissions are combined into the net APR
+    #[clap(long, env, value_enum, default_value_t = CommissionAggregation::Mean)]
+    pub commission_aggregation: CommissionAggregation,
+
+    /// Skip the round when pending rewards are below this amount, in NAM or
+    /// with a `unam` suffix
+    #[clap(
+        long,
+        env,
+        alias = "min-claim-unam",
+        default_value_t = 0.0,
+        value_parser = denom::parse_nam
+    )]
+    pub min_claim_nam: f64,
+
+    /// Share of the claimed rewards to bond back, the rest stays liquid or
+    /// goes to `--reward-receiver`. All of them by default, none with a
+    /// `--reward-receiver`
+    #[clap(long, env, value_parser = parse_ratio)]
+    pub compound_ratio: Option<f64>,
+
+    /// How the amount a claim paid out is known, before bonding it
+    #[clap(long, env, value_enum, default_value_t = ClaimedAmount::BalanceDiff)]
+    pub claimed_amount: ClaimedAmount,
+
+    /// Order of the txs of a compound, the state moving on only once the
+    /// last one went through
+    #[clap(long, env, value_enum, default_value_t = RoundOrder::ClaimThenBond)]
+    pub round_order: RoundOrder,
+
+    /// Send the claimed rewards that aren't bonded back to this address,
+    /// e.g. a cold wallet, rather than leaving them liquid
+    #[clap(long, env)]
+    pub reward_receiver: Option<Address>,
+
+    /// What to do, in order, with the claimed rewards that
+    /// `--compound-ratio` compounds: `bond` them as `--bond-target` says, or
+    /// `transfer:<address>` them, e.g. to a sub-account. Each takes a share
+    /// with a `:<ratio>` suffix, e.g. `transfer:tnam1...:0.2,bond`, or all
+    /// the earlier ones left without
+    #[clap(long, env, value_delimiter = ',', default_value = "bond")]
+    pub reward_actions: Vec<RewardAction>,
+
+    /// When to compound: at the interval the `optimizer` picks, every
+    /// `fixed-interval:<hours>`, or as soon as the pending rewards reach
+    /// `threshold:<NAM>` whatever the time
+    #[clap(long, env, default_value = "optimizer")]
+    pub strategy: Strategy,
+
+    /// Optimize and compound each validator on its own schedule instead of
+    /// all of them together. Only with the `optimizer` strategy
+    #[clap(long, env)]
+    pub per_validator_schedule: bool,
+
+    /// Also wait for new epochs since the last compound, as rewards only
+    /// accrue once per epoch
+    #[clap(long, env)]
+    pub epoch_aligned: bool,
+
+    /// Epochs to wait between two compounds with `--epoch-aligned`
+    #[clap(long, env, default_value_t = 1)]
+    pub epochs_between_compounds: u64,
+
+    /// Also compound before the interval elapses once a new epoch brought
+    /// rewards above the minimum claim, at most once per epoch
+    #[clap(long, env)]
+    pub compound_on_epoch_change: bool,
+
+    /// Don't compound as soon as the bot first starts: schedule the first
+    /// compound from then like any other. Only a fresh state is affected,
+    /// one saved by a previous compound already has its schedule
+    #[clap(long, env)]
+    pub skip_first_compound: bool,
+
+    /// How the optimal compounding frequency is computed
+    #[clap(long, env, value_enum, default_value_t = Optimizer::Search)]
+    pub optimizer: Optimizer,
+
+    /// What the interval between compounds is rounded up to before it is
+    /// scheduled and logged
+    #[clap(long, env, value_enum, default_value_t = IntervalRounding::Epoch)]
+    pub interval_rounding: IntervalRounding,
+
+    /// Investment horizon the compounding frequency is optimized over
+    #[clap(long, env, default_value_t = 1.0)]
+    pub horizon_years: f64,
+
+    /// Never recommend compounding more often than this, e.g. to stay within
+    /// the chain's or your own rate limits
+    #[clap(long, env, default_value_t = 1.0, value_parser = parse_hours)]
+    pub min_hours_between_compounds: f64,
+
+    /// Weight of each new epoch in the moving average of the APR fed to the
+    /// optimizer. The default remembers about a week of daily epochs, 1.0
+    /// disables the smoothing
+    #[clap(long, env, default_value_t = 0.25, value_parser = parse_alpha)]
+    pub apr_ema_alpha: f64,
+
+    /// Share of the optimal frequency to compound at, below 1.0 to compound
+    /// less often as a margin against fee spikes. 1.0 operates at the exact
+    /// optimum
+    #[clap(long, env, default_value_t = 1.0, value_parser = parse_alpha)]
+    pub frequency_safety_factor: f64,
+
+    /// Compound only as often as it takes to yield this APY net of fees,
+    /// e.g. 0.08 for 8%, instead of the most. The schedule stays at the
+    /// optimum, and the shortfall is logged, if fees put it out of reach
+    #[clap(long, env, value_parser = parse_ratio)]
+    pub target_apy: Option<f64>,
+
+    /// Relative change of the bonded amount, APR or fee below which the last
+    /// optimization is reused instead of recomputed. 0.0 recomputes on any
+    /// change
+    #[clap(long, env, default_value_t = 0.001, value_parser = parse_ratio)]
+    pub reoptimize_epsilon: f64,
+
+    /// Discount the APR of each validator by its uptime, the share of the
+    /// chain's liveness window it signed, averaged over this many epochs.
+    /// Off by default
+    #[clap(
+        long,
+        env,
+        value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..)
+    )]
+    pub uptime_window_epochs: Option<u64>,
+
+    /// Claim from every validator in a single tx, paying one fee instead of
+    /// one per validator. Falls back to a tx per validator if the batch can't
+    /// be built
+    #[clap(long, env, default_value_t = true, action = clap::ArgAction::Set)]
+    pub batch_claims: bool,
+
+    /// Compound even when the fees exceed the pending rewards
+    #[clap(long, env)]
+    pub force: bool,
+
+    /// Abort the round if the bond or commission of any validator can't be
+    /// fetched, instead of leaving that validator out
+    #[clap(long, env)]
+    pub strict: bool,
 
+    /// Run a single round and exit with its outcome
     #[clap(long, env)]
     pub one_time: bool,
 
+    /// Run the loop as usual for this many epochs, logging the compounds it
+    /// would make instead of submitting them, then summarize its decisions
+    /// and exit. The state files are read but never written
+    #[clap(
+        long,
+        env,
+        conflicts_with_all = ["dry_run", "one_time"],
+        value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..)
+    )]
+    pub observe_epochs: Option<u64>,
+
+    /// Exit after this many successful compounds, counted across accounts
+    #[clap(
+        long,
+        env,
+        value_parser = clap::builder::RangedU64ValueParser::<u64>::new().range(1..)
+    )]
+    pub max_compounds: Option<u64>,
+
+    /// Directory of the SDK wallet used to sign claim and bond txs
+    #[clap(long, env, default_value = "./sdk-wallet")]
+    pub wallet_dir: PathBuf,
+
+    /// Replace the key a wallet shared with the CLI holds under the
+    /// `autocompound` alias. Otherwise an existing key is left alone, and
+    /// reused if it is the delegator's
+    #[clap(long, env)]
+    pub overwrite_wallet: bool,
+
+    /// Max concurrent queries per fan-out over the validators. Setting this
+    /// too high may get you throttled by public RPCs
+    #[clap(
+        long,
+        env,
+        default_value_t = 20,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    pub rpc_concurrency: usize,
+
+    /// Give up on an RPC call (and retry or rotate endpoints) after this long
+    #[clap(long, env, default_value_t = 30)]
+    pub rpc_timeout_secs: u64,
+
+    /// How long to wait for a claim or bond tx to be applied
+    #[clap(long, env, default_value_t = 120)]
+    pub tx_timeout_secs: u64,
+
+    /// Wait this long between the claim or bond txs of the validators, when
+    /// they aren't batched. Raise it if some of them get dropped from a busy
+    /// mempool
+    #[clap(long, env, default_value_t = 0)]
+    pub inter_tx_delay_ms: u64,
+
+    /// Append a CSV row to this file after every compound
+    #[clap(long, env)]
+    pub history_file: Option<PathBuf>,
+
+    /// POST a JSON event here after each compound and on errors
+    #[clap(long, env)]
+    pub webhook_url: Option<Url>,
+
+    /// Persist the compounding schedule here so it survives restarts
+    #[clap(long, env)]
+    pub state_file: Option<PathBuf>,
+
+    /// Write the current timestamp here at the start of every loop, for
+    /// supervisors to tell a hung process by the file going stale
+    #[clap(long, env)]
+    pub heartbeat_file: Option<PathBuf>,
+
+    /// Skip compounding while this file exists, e.g. for a chain upgrade,
+    /// without restarting. SIGUSR1 toggles the same pause. Once resumed, the
+    /// next round is whenever the schedule says, not one per round missed
+    #[clap(long, env)]
+    pub pause_file: Option<PathBuf>,
+
     #[clap(long, env, default_value_t = 5)]
     pub sleep_for: u64,
+
+    /// Wait this long before the first round, to stagger instances started
+    /// together
+    #[clap(long, env, default_value_t = 0)]
+    pub start_delay_secs: u64,
+
+    /// Add a random wait of up to this long to `--start-delay-secs`
+    #[clap(long, env, default_value_t = 0)]
+    pub start_jitter_secs: u64,
+
+    /// When the pending rewards are below the minimum, sleep until the next
+    /// epoch distributes more instead of `--sleep-for`
+    #[clap(long, env)]
+    pub wait_for_epoch: bool,
+
+    /// Sleep until the next compound is due, and with `--epoch-aligned` its
+    /// epoch started, instead of `--sleep-for`, which becomes the shortest
+    /// sleep
+    #[clap(long, env)]
+    pub adaptive_sleep: bool,
+
+    /// Longest adaptive sleep, so the APR and bonds are still checked
+    /// regularly
+    #[clap(long, env, default_value_t = 3600)]
+    pub max_sleep_secs: u64,
+
+    /// Most verbose level logged. `RUST_LOG` directives, if set, apply on top
+    #[clap(long, env, default_value_t = Level::INFO)]
+    pub log_level: Level,
+
+    #[clap(long, env, value_enum, default_value_t = LogFormat::Human)]
+    pub log_format: LogFormat,
+
+    /// Only log a summary of each compound, warnings and errors. The details
+    /// of every round still show at `--log-level debug`
+    #[clap(long, env)]
+    pub quiet: bool,
+
+    /// Stream what the loop does to stdout for a log processor, as the
+    /// `events` module documents, and the logs to stderr
+    #[clap(long, env, value_enum)]
+    pub events: Option<EventsFormat>,
+
+    #[clap(long, env, default_value_t = 3)]
+    pub retry_attempts: u32,
+
+    #[clap(long, env, default_value_t = 500)]
+    pub retry_base_delay_ms: u64,
+
+    /// Serve Prometheus metrics on this address (e.g. 0.0.0.0:9100)
+    #[clap(long, env)]
+    pub metrics_addr: Option<SocketAddr>,
+
+    /// Serve `POST /compound` and `GET /status` on this address to trigger a
+    /// round on demand (e.g. 127.0.0.1:9200)
+    #[clap(long, env)]
+    pub control_addr: Option<SocketAddr>,
+
+    /// Log every option in effect, and whether it comes from a flag, an env
+    /// var, the config file or the defaults, with secrets masked. `exit`
+    /// exits right after
+    #[clap(
+        long,
+        env,
+        value_enum,
+        num_args = 0..=1,
+        default_missing_value = "log",
+        require_equals = true
+    )]
+    pub print_config: Option<PrintConfig>,
+
+    /// Every option in effect, filled in by [`AppConfig::load_from`]
+    #[clap(skip)]
+    pub resolved: Vec<ResolvedOption>,
+}
+
+impl AppConfig {
+    /// Parse the process args, env and `--config` file.
+    pub fn load() -> anyhow::Result<Self> {
+        Self::load_from(std::env::args_os())
+    }
+
+    /// Like [`AppConfig::load`] but from the given args. Values from the
+    /// config file become the defaults of the matching args, so the
+    /// precedence is CLI > env > file > built-in defaults.
+    pub fn load_from<I, T>(args: I) -> anyhow::Result<Self>
+    where
+        I: IntoIterator<Item = T>,
+        T: Into<OsString> + Clone,
+    {
+        let args = args.into_iter().map(Into::into).collect::<Vec<OsString>>();
+
+        let mut command = Self::command();
+        let mut from_file = HashSet::new();
+        if let Some(path) = config_file_path(&args) {
+            let content = std::fs::read_to_string(&path)
+                .with_context(|| format!("Can't read config file {}", path.display()))?;
+            let table = content
+                .parse::<toml::Table>()
+                .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+            for (key, value) in table {
+                // The old name of a renamed option, e.g. `min_claim_unam`, is
+                // one of its aliases
+                let (id, alias) = (key.replace('-', "_"), key.replace('_', "-"));
+                let id = command
+                    .get_arguments()
+                    .find(|arg| {
+                        arg.get_id() == &id
+                            || arg
+                                .get_all_aliases()
+                                .is_some_and(|aliases| aliases.contains(&alias.as_str()))
+                    })
+                    .map(|arg| arg.get_id().to_string())
+                    .filter(|id| id != "config")
+                    .with_context(|| format!("Unknown config file option: {}", key))?;
+
+                let values = toml_values(&value)
+                    .with_context(|| format!("Unsupported value for {}", key))?;
+                command = command.mut_arg(&id, |arg| {
+                    arg.default_values(values)
+                        .required(false)
+                        .required_unless_present(Resettable::Reset)
+                });
+                from_file.insert(id.clone());
+
+                if KEY_ARGS.contains(&id.as_str()) {
+                    command = command.mut_group("key", |group| group.required(false));
+                }
+            }
+        }
+
+        // A renamed option also reads the env var of its old name, e.g.
+        // `MIN_CLAIM_UNAM`, unless the new one is set
+        let legacy_envs = command
+            .get_arguments()
+            .filter_map(|arg| {
+                if std::env::var_os(arg.get_env()?).is_some() {
+                    return None;
+                }
+                let env = arg
+                    .get_all_aliases()?
+                    .into_iter()
+                    .map(|alias| alias.replace('-', "_").to_uppercase())
+                    .find(|env| std::env::var_os(env).is_some())?;
+                Some((arg.get_id().to_string(), env))
+            })
+            .collect::<Vec<_>>();
+        for (id, env) in legacy_envs {
+            command = command.mut_arg(id, |arg| arg.env(env));
+        }
+
+        let matches = command
+            .clone()
+            .try_get_matches_from(args)
+            .unwrap_or_else(|err| err.exit());
+
+        let mut config = Self::from_arg_matches(&matches)?;
+        check_fee_ladder(&config.fee_ladder, config.max_tx_fee())
+            .map_err(|err| anyhow::anyhow!("Invalid --fee-ladder: {}", err))?;
+        check_reward_actions(&config.reward_actions)
+            .map_err(|err| anyhow::anyhow!("Invalid --reward-actions: {}", err))?;
+        config.resolved = resolve_options(&command, &matches, &from_file);
+        Ok(config)
+    }
+
+    /// `--reward-receiver`, unless it's `delegator` itself, which the
+    /// rewards land on anyway.
+    pub fn reward_receiver(&self, delegator: &Address) -> Option<&Address> {
+        self.reward_receiver
+            .as_ref()
+            .filter(|receiver| *receiver != delegator)
+    }
+
+    /// `--compound-ratio` for `delegator`, see [`Self::reward_receiver`].
+    pub fn compound_ratio(&self, delegator: &Address) -> f64 {
+        self.compound_ratio
+            .unwrap_or_else(|| match self.reward_receiver(delegator) {
+                Some(_) => 0.0,
+                None => 1.0,
+            })
+    }
+
+    /// Upper bound of the optimizer's compounds per year.
+    pub fn max_frequency(&self) -> f64 {
+        opt::max_frequency(self.min_hours_between_compounds)
+    }
+
+    /// Cap on the fees of a tx, in the token paying them: `--max-token-fee`
+    /// with a `--fee-token`, else `--max-fee-nam`.
+    pub fn max_tx_fee(&self) -> Option<f64> {
+        if self.fee_token.is_some() {
+            self.max_token_fee
+        } else {
+            self.max_fee_nam
+        }
+    }
+
+    /// `--start-delay-secs` plus a random share of `--start-jitter-secs`.
+    pub fn start_delay(&self) -> Duration {
+        let jitter = rand::thread_rng().gen_range(0..=self.start_jitter_secs);
+        Duration::from_secs(self.start_delay_secs.saturating_add(jitter))
+    }
+}
+
+/// The `--fee-ladder` rungs must increase, each staying under
+/// [`AppConfig::max_tx_fee`], if set.
+fn check_fee_ladder(ladder: &[f64], max_fee: Option<f64>) -> Result<(), String> {
+    if let Some(rungs) = ladder.windows(2).find(|rungs| rungs[0] >= rungs[1]) {
+        return Err(format!("{} is not above {}", rungs[1], rungs[0]));
+    }
+    match (ladder.last(), max_fee) {
+        (Some(top), Some(max_fee)) if *top > max_fee => {
+            Err(format!("{} is above the max fee ({})", top, max_fee))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// The shares of `--reward-actions` can't add up to more than the rewards.
+fn check_reward_actions(actions: &[RewardAction]) -> Result<(), String> {
+    let shares = actions.iter().filter_map(RewardAction::share).sum::<f64>();
+    if shares > 1.0 {
+        Err(format!("the shares add up to {}, above 1.0", shares))
+    } else {
+        Ok(())
+    }
+}
+
+fn parse_ratio(value: &str) -> Result<f64, String> {
+    let ratio = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if (0.0..=1.0).contains(&ratio) {
+        Ok(ratio)
+    } else {
+        Err(format!("{} is not between 0.0 and 1.0", ratio))
+    }
+}
+
+fn parse_memo(value: &str) -> Result<String, String> {
+    if value.len() <= MAX_TX_MEMO_BYTES {
+        Ok(value.to_string())
+    } else {
+        Err(format!(
+            "{} bytes is longer than the {} bytes allowed",
+            value.len(),
+            MAX_TX_MEMO_BYTES
+        ))
+    }
+}
+
+fn parse_percent(value: &str) -> Result<f64, String> {
+    let percent = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if percent > 0.0 && percent <= 100.0 {
+        Ok(percent)
+    } else {
+        Err(format!("{} is not in (0, 100]", percent))
+    }
+}
+
+fn parse_alpha(value: &str) -> Result<f64, String> {
+    let alpha = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if alpha > 0.0 && alpha <= 1.0 {
+        Ok(alpha)
+    } else {
+        Err(format!("{} is not in (0.0, 1.0]", alpha))
+    }
+}
+
+fn parse_max_apr(value: &str) -> Result<f64, String> {
+    let apr = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if apr.is_finite() && apr > 0.0 {
+        Ok(apr)
+    } else {
+        Err(format!("{} is not a positive APR", apr))
+    }
+}
+
+fn parse_token_fee(value: &str) -> Result<f64, String> {
+    let fee = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if fee.is_finite() && fee >= 0.0 {
+        Ok(fee)
+    } else {
+        Err(format!("{} is not a positive amount", fee))
+    }
+}
+
+fn parse_hours(value: &str) -> Result<f64, String> {
+    let hours = value.parse::<f64>().map_err(|err| err.to_string())?;
+    if hours.is_finite() && hours > 0.0 {
+        Ok(hours)
+    } else {
+        Err(format!("{} is not a positive number of hours", hours))
+    }
+}
+
+/// Value and origin of every top-level option given a value, in declaration
+/// order. `from_file` holds the options the config file set.
+fn resolve_options(
+    command: &clap::Command,
+    matches: &ArgMatches,
+    from_file: &HashSet<String>,
+) -> Vec<ResolvedOption> {
+    command
+        .get_arguments()
+        .filter_map(|arg| {
+            let id = arg.get_id().as_str();
+            let values = matches.try_get_raw(id).ok()??;
+            let value = if SECRET_ARGS.contains(&id) {
+                "<redacted>".to_string()
+            } else {
+                values
+                    .map(|value| redact_url(&value.to_string_lossy()))
+                    .collect::<Vec<_>>()
+                    .join(",")
+            };
+            let source = match matches.value_source(id) {
+                Some(ValueSource::CommandLine) => OptionSource::CommandLine,
+                Some(ValueSource::EnvVariable) => OptionSource::Env,
+                _ if from_file.contains(id) => OptionSource::ConfigFile,
+                _ => OptionSource::Default,
+            };
+
+            Some(ResolvedOption {
+                name: id.replace('_', "-"),
+                value,
+                source,
+            })
+        })
+        .collect()
+}
+
+/// `value` without the credentials and query of a URL, e.g. an RPC endpoint
+/// with an API key, which `--print-config` would otherwise show.
+fn redact_url(value: &str) -> String {
+    let Ok(mut url) = Url::parse(value) else {
+        return value.to_string();
+    };
+    if !url.has_host()
+        || (url.username().is_empty() && url.password().is_none() && url.query().is_none())
+    {
+        return value.to_string();
+    }
+
+    let _ = url.set_username("");
+    let _ = url.set_password(None);
+    url.set_query(None);
+    url.to_string()
+}
+
+/// `--config <path>`, `--config=<path>` or the `CONFIG` env var.
+fn config_file_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter().skip(1);
+    while let Some(arg) = args.next() {
+        let arg = arg.to_string_lossy();
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--config=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+
+    std::env::var_os("CONFIG").map(PathBuf::from)
+}
+
+fn toml_values(value: &toml::Value) -> Option<Vec<String>> {
+    match value {
+        toml::Value::String(value) => Some(vec![value.clone()]),
+        toml::Value::Integer(value) => Some(vec![value.to_string()]),
+        toml::Value::Float(value) => Some(vec![value.to_string()]),
+        toml::Value::Boolean(value) => Some(vec![value.to_string()]),
+        toml::Value::Array(values) => values
+            .iter()
+            .map(|value| toml_values(value)?.pop())
+            .collect(),
+        toml::Value::Datetime(_) | toml::Value::Table(_) => None,
+    }
+}
+
+/// See `--claimed-amount`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ClaimedAmount {
+    /// The pending rewards queried before the claim, checked against the
+    /// balance change around it
+    BalanceDiff,
+    /// The pending rewards queried before the claim as is, which a claim
+    /// pays out exactly: rewards only accrue at epoch boundaries and a claim
+    /// landing in the next epoch pays more, not less. Saves the two balance
+    /// queries, the one before the claim still being made for
+    /// `--min-liquid-balance`
+    Pending,
+}
+
+/// See `--round-order`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum RoundOrder {
+    /// Claim, run the `--reward-actions` on the claimed rewards as
+    /// `--claimed-amount` measures them, then send the rest to
+    /// `--reward-receiver`
+    ClaimThenBond,
+    /// Run the `--reward-actions` on the pending rewards out of the liquid
+    /// balance, claim them to
+    /// make up for it, then send the rest to `--reward-receiver`. A claim
+    /// failing after the bond leaves the liquid balance short, and the next
+    /// round bonds from it again
+    BondThenClaim,
+}
+
+/// Best first, see `--auto-select-by`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum ValidatorRanking {
+    /// Lowest commission rate
+    Commission,
+    /// Most stake
+    VotingPower,
+    /// Most blocks signed over the liveness window
+    Uptime,
+}
+
+/// Distribution the `rebalance` subcommand moves the bonds towards.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum RebalanceTarget {
+    /// The same bond on every validator
+    Even,
+    /// Bonds in proportion to what each validator leaves of the rewards,
+    /// i.e. one minus its commission
+    Commission,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum CommissionAggregation {
+    Mean,
+    Median,
+    /// Weight each validator's commission by the amount bonded to it
+    BondWeighted,
+}
+
+impl CommissionAggregation {
+    /// `commissions` and `bonds` are by validator, a validator without a bond
+    /// weighs nothing.
+    pub fn aggregate(
+        &self,
+        commissions: &HashMap<Address, f64>,
+        bonds: &HashMap<Address, f64>,
+    ) -> Option<f64> {
+        let (commissions, bonds): (Vec<_>, Vec<_>) = commissions
+            .iter()
+            .map(|(validator, commission)| {
+                let bond = bonds.get(validator).copied().unwrap_or_default();
+                (*commission, bond)
+            })
+            .unzip();
+
+        match self {
+            CommissionAggregation::Mean => utils::mean(&commissions),
+            CommissionAggregation::Median => utils::median(&commissions),
+            CommissionAggregation::BondWeighted => utils::weighted_mean(&commissions, &bonds),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Plain lines for a terminal
+    Human,
+    /// One JSON object per line, for log ingestion
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum EventsFormat {
+    /// One JSON object per event and line, with a schema version
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum PrintConfig {
+    /// Log the options and carry on
+    Log,
+    /// Log the options and exit
+    Exit,
+}
+
+/// Where the value of an option comes from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OptionSource {
+    CommandLine,
+    Env,
+    ConfigFile,
+    Default,
+}
+
+impl Display for OptionSource {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Self::CommandLine => "flag",
+            Self::Env => "env",
+            Self::ConfigFile => "config file",
+            Self::Default => "default",
+        })
+    }
+}
+
+/// An option in effect, for `--print-config`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct ResolvedOption {
+    /// As the flag, without the leading `--`
+    pub name: String,
+    /// Comma separated if repeated, `<redacted>` for secrets
+    pub value: String,
+    pub source: OptionSource,
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Human-readable log lines
+    Text,
+    /// A single JSON object on stdout
+    Json,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum BondTarget {
+    Even,
+    Proportional,
+    BestApr,
+    /// Any validator, even outside the current delegations
+    Validator(Address),
+}
+
+impl FromStr for BondTarget {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "even" => Ok(Self::Even),
+            "proportional" => Ok(Self::Proportional),
+            "best-apr" => Ok(Self::BestApr),
+            address => Address::from_str(address)
+                .map(Self::Validator)
+                .map_err(|err| {
+                    format!(
+                        "expected even, proportional, best-apr or a validator address: {}",
+                        err
+                    )
+                }),
+        }
+    }
+}
+
+impl Display for BondTarget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Even => f.write_str("even"),
+            Self::Proportional => f.write_str("proportional"),
+            Self::BestApr => f.write_str("best-apr"),
+            Self::Validator(address) => address.fmt(f),
+        }
+    }
+}
+
+/// A step of `--reward-actions`. Without a share it takes all the earlier
+/// ones left of the rewards to compound.
+#[derive(Clone, Debug, PartialEq)]
+pub enum RewardAction {
+    /// Bond as `--bond-target` says
+    Bond { share: Option<f64> },
+    /// Transfer to an address, where they stay liquid
+    Transfer {
+        receiver: Address,
+        share: Option<f64>,
+    },
+}
+
+impl RewardAction {
+    pub fn share(&self) -> Option<f64> {
+        match self {
+            Self::Bond { share } | Self::Transfer { share, .. } => *share,
+        }
+    }
+}
+
+impl FromStr for RewardAction {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let mut parts = value.split(':');
+        let action = parts.next().unwrap_or_default();
+        let receiver = match action {
+            "bond" => None,
+            "transfer" => {
+                let receiver = parts
+                    .next()
+                    .ok_or("transfer needs an address, as transfer:<address>")?;
+                Some(
+                    Address::from_str(receiver)
+                        .map_err(|err| format!("invalid transfer address: {}", err))?,
+                )
+            }
+            _ => return Err(format!("expected bond or transfer, got {}", action)),
+        };
+        let share = parts.next().map(parse_ratio).transpose()?;
+        if parts.next().is_some() {
+            return Err(format!("too many parameters in {}", value));
+        }
+
+        Ok(match receiver {
+            Some(receiver) => Self::Transfer { receiver, share },
+            None => Self::Bond { share },
+        })
+    }
+}
+
+impl Display for RewardAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Bond { .. } => f.write_str("bond")?,
+            Self::Transfer { receiver, .. } => write!(f, "transfer:{}", receiver)?,
+        }
+        match self.share() {
+            Some(share) => write!(f, ":{}", share),
+            None => Ok(()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, clap::Subcommand)]
+pub enum Command {
+    /// Print per-validator bonds, commissions and pending rewards, then exit
+    Status,
+    /// Print the address and public key of the delegator key, offline
+    Address,
+    /// Check the whole setup, from the key and the RPC to building the txs
+    /// of a round without submitting them, and exit non-zero if it isn't
+    /// ready to compound
+    Preflight,
+    /// Project the balance after every compounding round, offline
+    Simulate(SimulateArgs),
+    /// Print the APR needed for compounding to pay for its fees, offline
+    BreakEven(BreakEvenArgs),
+    /// Print the final balance against the number of compounds per year,
+    /// for plotting the optimizer's curve, offline
+    Curve(CurveArgs),
+    /// Sync the MASP notes of a viewing key and print its shielded balance,
+    /// which MASP rewards accrue to, then exit
+    ShieldedStatus(ShieldedStatusArgs),
+    /// Claim the pending rewards without bonding them, sending them to
+    /// --reward-receiver if set, then exit
+    Claim,
+    /// Bond part of the liquid balance without claiming, then exit
+    Bond(BondArgs),
+    /// Print the redelegations that would spread the bonds as --target says,
+    /// then exit
+    Rebalance(RebalanceArgs),
+    /// Replay past epochs from their bonds and commissions and print, as
+    /// CSV, when compounding would have happened and the balance it would
+    /// have led to, then exit
+    Backtest(BacktestArgs),
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct SimulateArgs {
+    /// Bonded amount to start from
+    #[clap(long)]
+    pub principal: f64,
+
+    /// Net APR, e.g. 0.1 for 10%
+    #[clap(long)]
+    pub apr: f64,
+
+    /// Fee paid on each compounding round
+    #[clap(long)]
+    pub fee: f64,
+
+    /// Compounds per year, the optimizer's pick if unset
+    #[clap(long)]
+    pub frequency: Option<u64>,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct BreakEvenArgs {
+    /// Bonded amount to start from
+    #[clap(long)]
+    pub principal: f64,
+
+    /// Fee paid on each compounding round
+    #[clap(long)]
+    pub fee: f64,
+
+    /// Compounds per year
+    #[clap(long)]
+    pub frequency: u64,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct CurveArgs {
+    /// Bonded amount to start from
+    #[clap(long)]
+    pub principal: f64,
+
+    /// Net APR, e.g. 0.1 for 10%
+    #[clap(long)]
+    pub apr: f64,
+
+    /// Fee paid on each compounding round
+    #[clap(long)]
+    pub fee: f64,
+
+    /// Frequencies to evaluate, spread log-uniformly from once to every
+    /// hour of the year
+    #[clap(
+        long,
+        default_value_t = 100,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new()
+            .range(2..=opt::MAX_CURVE_SAMPLES as u64)
+    )]
+    pub samples: usize,
+
+    #[clap(long, value_enum, default_value_t = CurveFormat::Csv)]
+    pub format: CurveFormat,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum CurveFormat {
+    /// A `frequency,balance` header then one line per frequency
+    Csv,
+    /// An array of `{"frequency", "balance"}` objects
+    Json,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct ShieldedStatusArgs {
+    /// Viewing key of the shielded account
+    #[clap(long, env)]
+    pub viewing_key: ExtendedViewingKey,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct BondArgs {
+    /// Amount to bond, in NAM or with a `unam` suffix, spread as
+    /// --bond-target says
+    #[clap(value_parser = denom::parse_amount)]
+    pub amount: token::Amount,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct RebalanceArgs {
+    /// Distribution to move the bonds towards
+    #[clap(long, value_enum, default_value_t = RebalanceTarget::Even)]
+    pub target: RebalanceTarget,
+
+    /// Skip moves smaller than this amount, in NAM or with a `unam` suffix
+    #[clap(long, default_value_t = 1.0, value_parser = denom::parse_nam)]
+    pub min_move: f64,
+
+    /// Submit the redelegations rather than only printing them, unless
+    /// --dry-run
+    #[clap(long)]
+    pub execute: bool,
+}
+
+#[derive(Clone, Debug, clap::Args)]
+pub struct BacktestArgs {
+    /// First epoch replayed
+    #[clap(long)]
+    pub from_epoch: u64,
+
+    /// Last epoch replayed, at most the current one
+    #[clap(long)]
+    pub to_epoch: u64,
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::{collections::HashMap, time::Duration};
+
+    use clap::Parser;
+    use namada_sdk::{address::EstablishedAddressGen, token};
+    use tracing::Level;
+
+    use super::{
+        check_reward_actions, AppConfig, BondArgs, Command, CommissionAggregation, CurveArgs,
+        CurveFormat, LogFormat, OptionSource, PrintConfig, RewardAction, SimulateArgs,
+        MAX_TX_MEMO_BYTES,
+    };
+
+    /// The command line of a run against a local node, which the tests of
+    /// other modules add their options to too.
+    pub(crate) const ARGS: [&str; 5] = [
+        "autocompound",
+        "--namada-rpc",
+        "http://127.0.0.1:26657",
+        "--secret-key",
+        "00",
+    ];
+
+    pub(crate) fn try_config_with(extra: &[&str]) -> Result<AppConfig, clap::Error> {
+        AppConfig::try_parse_from(ARGS.iter().chain(extra))
+    }
+
+    pub(crate) fn config_with(extra: &[&str]) -> AppConfig {
+        try_config_with(extra).unwrap()
+    }
+
+    #[test]
+    fn test_config_file_merges_with_defaults() {
+        let path = std::env::temp_dir().join(format!("autocompound-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            r#"
+namada_rpc = ["http://a:26657", "http://b:26657"]
+secret_key = "00"
+min-claim-nam = 1.5
+sleep_for = 60
+dry_run = true
+commission_aggregation = "median"
+"#,
+        )
+        .unwrap();
+
+        let config = AppConfig::load_from([
+            "autocompound",
+            "--config",
+            path.to_str().unwrap(),
+            "--sleep-for",
+            "10",
+        ])
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(config.namada_rpc, ["http://a:26657", "http://b:26657"]);
+        assert_eq!(config.secret_key.as_deref(), Some("00"));
+        assert_eq!(config.min_claim_nam, 1.5);
+        assert!(config.dry_run);
+        assert!(matches!(
+            config.commission_aggregation,
+            CommissionAggregation::Median
+        ));
+        // The CLI wins over the file
+        assert_eq!(config.sleep_for, 10);
+        // Untouched options keep their defaults
+        assert_eq!(config.base_fee_nam, 0.05);
+        assert_eq!(config.retry_attempts, 3);
+    }
+
+    #[test]
+    fn test_resolved_options() {
+        let path =
+            std::env::temp_dir().join(format!("autocompound-resolved-{}.toml", std::process::id()));
+        std::fs::write(&path, "sleep_for = 60\nsecret_key = \"00\"\n").unwrap();
+
+        let config = AppConfig::load_from([
+            "autocompound",
+            "--config",
+            path.to_str().unwrap(),
+            "--namada-rpc",
+            "http://a:26657,http://user:pass@b:26657/?apikey=abc",
+            "--webhook-url",
+            "https://hooks.slack.com/services/T0/B0/token",
+            "--print-config=exit",
+        ])
+        .unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let option = |name: &str| {
+            config
+                .resolved
+                .iter()
+                .find(|option| option.name == name)
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(config.print_config, Some(PrintConfig::Exit));
+        assert_eq!(option("namada-rpc").value, "http://a:26657,http://b:26657/");
+        assert_eq!(option("namada-rpc").source, OptionSource::CommandLine);
+        assert_eq!(option("sleep-for").value, "60");
+        assert_eq!(option("sleep-for").source, OptionSource::ConfigFile);
+        assert_eq!(option("secret-key").value, "<redacted>");
+        assert_eq!(option("webhook-url").value, "<redacted>");
+        assert_eq!(option("retry-attempts").source, OptionSource::Default);
+        // Unset options are left out
+        assert!(!config
+            .resolved
+            .iter()
+            .any(|option| option.name == "mnemonic"));
+    }
+
+    #[test]
+    fn test_compound_ratio_range() {
+        let parse = |ratio: &str| try_config_with(&["--compound-ratio", ratio]);
+
+        assert_eq!(parse("0.25").unwrap().compound_ratio, Some(0.25));
+        assert!(parse("1.5").is_err());
+        assert!(parse("-0.1").is_err());
+    }
+
+    #[test]
+    fn test_base_fee() {
+        let parse = |fee: &str| try_config_with(&[&format!("--base-fee-nam={}", fee)]);
+
+        assert_eq!(parse("0").unwrap().base_fee_nam, 0.0);
+        assert_eq!(parse("50000unam").unwrap().base_fee_nam, 0.05);
+        assert!(parse("-0.05").is_err());
+    }
+
+    #[test]
+    fn test_unam_names() {
+        let config = config_with(&["--base-fee-unam", "0.1", "--max-fee-unam", "1"]);
+        assert_eq!(config.base_fee_nam, 0.1);
+        assert_eq!(config.max_fee_nam, Some(1.0));
+
+        let path =
+            std::env::temp_dir().join(format!("autocompound-unam-{}.toml", std::process::id()));
+        std::fs::write(
+            &path,
+            "namada_rpc = [\"http://a:26657\"]\nsecret_key = \"00\"\nmin_claim_unam = 1.5\n",
+        )
+        .unwrap();
+        let config =
+            AppConfig::load_from(["autocompound", "--config", path.to_str().unwrap()]).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(config.min_claim_nam, 1.5);
+        assert!(config
+            .resolved
+            .iter()
+            .any(|option| option.name == "min-claim-nam"
+                && option.source == OptionSource::ConfigFile));
+    }
+
+    #[test]
+    fn test_reward_receiver_ratio() {
+        let mut address_gen = EstablishedAddressGen::new("receiver");
+        let [delegator, receiver] = [0, 1].map(|i| address_gen.generate_address(i.to_string()));
+        let parse = config_with;
+
+        assert_eq!(parse(&[]).compound_ratio(&delegator), 1.0);
+        let receiver_arg = receiver.to_string();
+        let config = parse(&["--reward-receiver", &receiver_arg]);
+        assert_eq!(config.reward_receiver(&delegator), Some(&receiver));
+        assert_eq!(config.compound_ratio(&delegator), 0.0);
+        // Already where the rewards land
+        assert_eq!(config.reward_receiver(&receiver), None);
+        assert_eq!(config.compound_ratio(&receiver), 1.0);
+
+        let config = parse(&[
+            "--reward-receiver",
+            &receiver_arg,
+            "--compound-ratio",
+            "0.25",
+        ]);
+        assert_eq!(config.compound_ratio(&delegator), 0.25);
+    }
+
+    #[test]
+    fn test_reward_actions() {
+        let receiver = EstablishedAddressGen::new("receiver").generate_address("0");
+        let parse = |actions: &str| {
+            try_config_with(&["--reward-actions", actions]).map(|config| config.reward_actions)
+        };
+
+        assert_eq!(parse("bond").unwrap(), [RewardAction::Bond { share: None }]);
+        let actions = parse(&format!("transfer:{}:0.2,bond", receiver)).unwrap();
+        assert_eq!(
+            actions,
+            [
+                RewardAction::Transfer {
+                    receiver: receiver.clone(),
+                    share: Some(0.2),
+                },
+                RewardAction::Bond { share: None },
+            ]
+        );
+        assert_eq!(actions[0].to_string(), format!("transfer:{}:0.2", receiver));
+        assert!(parse("shield").is_err());
+        assert!(parse("transfer").is_err());
+        assert!(parse("bond:1.5").is_err());
+        assert!(parse("bond:0.5:0.5").is_err());
+
+        assert!(check_reward_actions(&actions).is_ok());
+        assert!(check_reward_actions(&[
+            RewardAction::Bond { share: Some(0.6) },
+            RewardAction::Bond { share: Some(0.6) },
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_min_hours_between_compounds() {
+        let parse = |hours: &str| try_config_with(&["--min-hours-between-compounds", hours]);
+
+        assert_eq!(parse("168").unwrap().max_frequency(), 52.0);
+        assert!(parse("0").is_err());
+        assert!(parse("-1").is_err());
+    }
+
+    #[test]
+    fn test_simulate_needs_no_rpc_or_key() {
+        let config = AppConfig::try_parse_from([
+            "autocompound",
+            "simulate",
+            "--principal",
+            "1000",
+            "--apr",
+            "0.1",
+            "--fee",
+            "0.05",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            config.command,
+            Some(Command::Simulate(SimulateArgs {
+                frequency: None,
+                ..
+            }))
+        ));
+
+        let curve = |samples: &str| {
+            AppConfig::try_parse_from([
+                "autocompound",
+                "curve",
+                "--principal",
+                "1000",
+                "--apr",
+                "0.1",
+                "--fee",
+                "0.05",
+                "--samples",
+                samples,
+                "--format",
+                "json",
+            ])
+        };
+        assert!(matches!(
+            curve("50").unwrap().command,
+            Some(Command::Curve(CurveArgs {
+                samples: 50,
+                format: CurveFormat::Json,
+                ..
+            }))
+        ));
+        assert!(curve("1").is_err());
+    }
+
+    #[test]
+    fn test_bond_amount_in_nam() {
+        let parse = |amount| AppConfig::try_parse_from(["autocompound", "bond", amount]);
+
+        assert!(matches!(
+            parse("12.5").unwrap().command,
+            Some(Command::Bond(BondArgs { amount })) if amount == token::Amount::from_u64(12_500_000)
+        ));
+        assert!(parse("ten").is_err());
+    }
+
+    #[test]
+    fn test_aggregate_by_validator() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let cheap = address_gen.generate_address("0");
+        let expensive = address_gen.generate_address("1");
+        let commissions = HashMap::from([(cheap.clone(), 0.05), (expensive.clone(), 0.2)]);
+        let bonds = HashMap::from([(expensive, 1_000.0), (cheap, 3_000.0)]);
+
+        let weighted = CommissionAggregation::BondWeighted
+            .aggregate(&commissions, &bonds)
+            .unwrap();
+        assert!((weighted - 0.0875).abs() < 1e-9);
+
+        let mean = CommissionAggregation::Mean
+            .aggregate(&commissions, &bonds)
+            .unwrap();
+        assert!((mean - 0.125).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_log_options() {
+        let config = config_with(&["--log-level", "debug", "--log-format", "json"]);
+
+        assert_eq!(config.log_level, Level::DEBUG);
+        assert!(matches!(config.log_format, LogFormat::Json));
+    }
+
+    #[test]
+    fn test_fee_ladder() {
+        let load = |extra: &[&str]| AppConfig::load_from(ARGS.iter().chain(extra));
+
+        let config = load(&["--fee-ladder", "0.05,100000unam,0.2 NAM"]).unwrap();
+        assert_eq!(config.fee_ladder, [0.05, 0.1, 0.2]);
+        assert!(load(&["--fee-ladder", "0.1,0.05"]).is_err());
+        assert!(load(&["--fee-ladder", "0.1,0.1"]).is_err());
+        assert!(load(&["--fee-ladder", "0.05,0.2", "--max-fee-nam", "0.1"]).is_err());
+
+        // In the fee token, and capped in it, when fees are paid in one
+        let fee_token = EstablishedAddressGen::new("tokens")
+            .generate_address("0")
+            .to_string();
+        let ladder = ["--fee-token", &fee_token, "--fee-ladder", "0.05,0.2"];
+        let config = load(&[&ladder[..], &["--max-fee-nam", "0.1"]].concat()).unwrap();
+        assert_eq!(config.max_tx_fee(), None);
+        assert!(load(&[&ladder[..], &["--max-token-fee", "0.1"]].concat()).is_err());
+    }
+
+    #[test]
+    fn test_tx_memo_length() {
+        let parse = |memo: &str| try_config_with(&["--tx-memo", memo]);
+
+        assert_eq!(
+            parse("autocompound:alice").unwrap().tx_memo.as_deref(),
+            Some("autocompound:alice")
+        );
+        assert!(parse(&"x".repeat(MAX_TX_MEMO_BYTES)).is_ok());
+        assert!(parse(&"x".repeat(MAX_TX_MEMO_BYTES + 1)).is_err());
+    }
+
+    #[test]
+    fn test_start_delay() {
+        let config = config_with(&[]);
+        assert_eq!(config.start_delay(), Duration::ZERO);
+
+        let config = config_with(&["--start-delay-secs", "10", "--start-jitter-secs", "5"]);
+        for _ in 0..20 {
+            let delay = config.start_delay().as_secs();
+            assert!((10..=15).contains(&delay));
+        }
+    }
 }
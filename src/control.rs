@@ -0,0 +1,980 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::SocketAddr,
+    sync::{Arc, Mutex, PoisonError},
+    time::Duration,
+};
+
+use anyhow::Context;
+use axum::{
+    extract,
+    http::StatusCode,
+    routing::{get, post},
+    Json, Router,
+};
+use futures::StreamExt;
+use namada_sdk::{address::Address, token};
+use serde::{Deserialize, Serialize};
+use tokio::net::TcpListener;
+
+use crate::{
+    accounts::Account,
+    compound::{self, RoundOutcome, Trigger},
+    config::{AppConfig, OutputFormat},
+    denom,
+    error::TaskError,
+    events::{self, Event},
+    metrics::METRICS,
+    namada::{NamadaRpc, NamadaSdk},
+    opt::{self, OptimizationResult},
+    pause::{Pause, PAUSE_POLL},
+    scheduler::{self, EpochClock, WakeInputs},
+    shutdown::Shutdown,
+    simulate,
+    state::{LastRound, State},
+    utils,
+    webhook::{Webhook, WebhookEvent},
+    CompoundOutcome,
+};
+
+/// Runs compounding rounds one at a time, whether they come from the loop or
+/// from the control server, and records their outcome. Each round goes over
+/// every account, up to `--account-concurrency` at once, one failing doesn't
+/// stop the others. Generic over the RPC for embedding in another service.
+pub struct Compounder<R = NamadaSdk> {
+    rpc: R,
+    config: Arc<AppConfig>,
+    accounts: Vec<Account>,
+    webhook: Webhook,
+    /// Held for the whole round, so two rounds never claim concurrently
+    round: tokio::sync::Mutex<()>,
+    /// Only locked to read or replace a state, so `GET /status` doesn't
+    /// wait for a round to finish
+    states: Mutex<HashMap<Address, State>>,
+}
+
+impl<R: NamadaRpc> Compounder<R> {
+    /// Start from the state persisted for each account, if any, resuming
+    /// the paused ones with `--clear-anomaly` and starting their stats over
+    /// with `--reset-stats`. With `--observe-epochs` the states are never
+    /// written back.
+    pub fn new(
+        rpc: R,
+        accounts: Vec<Account>,
+        config: Arc<AppConfig>,
+        webhook: Webhook,
+    ) -> anyhow::Result<Self> {
+        let states = accounts
+            .iter()
+            .map(|account| {
+                let mut state = match &account.state_file {
+                    Some(path) => State::load(path)?,
+                    None => State::init(),
+                };
+                if config.clear_anomaly {
+                    state.clear_anomaly();
+                }
+                if config.reset_stats {
+                    state.reset_stats();
+                }
+                if config.observe_epochs.is_some() {
+                    state.path = None;
+                }
+                Ok((account.address.clone(), state))
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Self {
+            rpc,
+            config,
+            accounts,
+            webhook,
+            round: tokio::sync::Mutex::new(()),
+            states: Mutex::new(states),
+        })
+    }
+
+    pub fn states(&self) -> HashMap<Address, State> {
+        self.states
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    fn state(&self, address: &Address) -> State {
+        self.states
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(address)
+            .cloned()
+            .unwrap_or_else(State::init)
+    }
+
+    /// Wait for the round in progress, if any, then run one.
+    pub async fn compound(
+        &self,
+        trigger: Trigger,
+    ) -> Vec<(&Account, anyhow::Result<RoundOutcome>)> {
+        let _round = self.round.lock().await;
+        self.run(trigger).await
+    }
+
+    /// Wait for the round in progress, if any, then run a scheduled one: what
+    /// each account claimed and bonded, with the txs, in the accounts' order.
+    pub async fn run_once(&self) -> Vec<(&Account, anyhow::Result<CompoundOutcome>)> {
+        self.compound(Trigger::Scheduled)
+            .await
+            .into_iter()
+            .map(|(account, res)| (account, res.map(CompoundOutcome::from)))
+            .collect()
+    }
+
+    /// Run rounds as the schedule says until shutdown or `--max-compounds`.
+    /// In one-time mode or after a dry run, only the first round runs.
+    /// Rounds are skipped while `pause` says so. Returns the exit code, see
+    /// [`TaskError::exit_code`]. Each round's outcomes are counted under
+    /// `chain`, if any.
+    pub async fn run_loop(&self, shutdown: &Shutdown, pause: &Pause, chain: Option<&str>) -> i32 {
+        let start_delay = self.config.start_delay();
+        if !start_delay.is_zero() {
+            tracing::info!("Waiting {}s before the first round", start_delay.as_secs());
+            shutdown.sleep(start_delay).await;
+        }
+
+        events::emit(Event::LoopStarted {
+            accounts: self.accounts.len(),
+            chain: chain.map(str::to_string),
+        });
+
+        let mut compounds = 0;
+        let mut observations = Observations::default();
+        let mut paused = false;
+        while !shutdown.is_requested() {
+            if let Some(path) = &self.config.heartbeat_file {
+                utils::touch_heartbeat(path);
+            }
+
+            // Resuming goes back to the schedule, the rounds missed meanwhile
+            // aren't made up for
+            if pause.is_paused() {
+                if !paused {
+                    tracing::info!("Compounding paused");
+                    paused = true;
+                }
+                shutdown.sleep(PAUSE_POLL).await;
+                continue;
+            }
+            if paused {
+                tracing::info!("Compounding resumed");
+                paused = false;
+            }
+
+            let outcomes = self.run_once().await;
+            if let Some(chain) = chain {
+                for (_, res) in &outcomes {
+                    let outcome = res.as_ref().map_or("error", |outcome| outcome.round.name());
+                    METRICS
+                        .chain_rounds
+                        .with_label_values(&[chain, outcome])
+                        .inc();
+                }
+            }
+            if outcomes.iter().any(|(_, res)| {
+                matches!(
+                    res,
+                    Ok(CompoundOutcome {
+                        round: RoundOutcome::DryRun,
+                        ..
+                    })
+                )
+            }) {
+                return 0;
+            }
+
+            // The first failed account decides the exit code in one-time mode
+            let exit_code = outcomes
+                .iter()
+                .find_map(|(_, res)| res.as_ref().err())
+                .map_or(0, |err| {
+                    err.downcast_ref::<TaskError>()
+                        .map_or(1, TaskError::exit_code)
+                });
+
+            if self.config.one_time {
+                self.print_schedules(&outcomes);
+                return exit_code;
+            }
+
+            compounds += outcomes
+                .iter()
+                .filter(|(_, res)| {
+                    matches!(
+                        res,
+                        Ok(CompoundOutcome {
+                            round: RoundOutcome::Compounded(_),
+                            ..
+                        })
+                    )
+                })
+                .count() as u64;
+            if self
+                .config
+                .max_compounds
+                .is_some_and(|max| compounds >= max)
+            {
+                tracing::info!("Done after {} compounds", compounds);
+                break;
+            }
+
+            if let Some(epochs) = self.config.observe_epochs {
+                let epoch = self
+                    .states()
+                    .values()
+                    .filter_map(|state| state.last_round.as_ref().map(|round| round.epoch))
+                    .max();
+                observations.record(epoch, &outcomes);
+                if observations.epochs() >= epochs {
+                    tracing::info!("Done observing {} epochs", observations.epochs());
+                    break;
+                }
+            }
+
+            let sleep = self.sleep_after(&outcomes).await;
+            shutdown.sleep(sleep).await;
+        }
+
+        if self.config.observe_epochs.is_some() {
+            for line in observations.summary() {
+                tracing::info!("Observed {}", line);
+            }
+        }
+
+        tracing::info!("Shutting down");
+        self.save_states();
+
+        0
+    }
+
+    /// Run a round, or `None` if one is already in progress.
+    pub async fn try_compound(
+        &self,
+        trigger: Trigger,
+    ) -> Option<Vec<(&Account, anyhow::Result<RoundOutcome>)>> {
+        let _round = self.round.try_lock().ok()?;
+        Some(self.run(trigger).await)
+    }
+
+    async fn run(&self, trigger: Trigger) -> Vec<(&Account, anyhow::Result<RoundOutcome>)> {
+        self.rpc.select_endpoint().await;
+
+        // The txs of an account are sequential, see `NamadaRpc`, so accounts
+        // are the unit of concurrency. Outcomes stay in the accounts' order.
+        // By index, as a future borrowing its account from the stream can't be
+        // told to be `Send`, which the control server needs
+        futures::stream::iter(0..self.accounts.len())
+            .map(|i| async move {
+                let account = &self.accounts[i];
+                (account, self.run_account(account, trigger).await)
+            })
+            .buffered(self.config.account_concurrency)
+            .collect()
+            .await
+    }
+
+    async fn run_account(
+        &self,
+        account: &Account,
+        trigger: Trigger,
+    ) -> anyhow::Result<RoundOutcome> {
+        if self.accounts.len() > 1 && !self.config.quiet {
+            tracing::info!("Account {} ({})", account.alias, account.address);
+        }
+
+        let mut state = self.state(&account.address);
+        let halted = state.anomaly.is_some();
+        let res = compound::run_round(
+            &self.rpc,
+            &mut state,
+            &self.config,
+            &account.secret_key,
+            trigger,
+        )
+        .await
+        .with_context(|| format!("Account {}", account.alias));
+        if let Ok(RoundOutcome::Compounded(record)) = &res {
+            tracing::info!("{}: {}", account.alias, record.summary(state.next_due_in()));
+            tracing::info!("{}: {}", account.alias, state.stats_summary());
+        }
+        let failed_claims = state.failed_claims.clone();
+        self.states
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(account.address.clone(), state);
+
+        events::emit(match &res {
+            Ok(outcome) => Event::Decision {
+                delegator: account.address.to_string(),
+                outcome: outcome.name().to_string(),
+            },
+            Err(err) => Event::Error {
+                delegator: Some(account.address.to_string()),
+                error: format!("{:#}", err),
+            },
+        });
+        match &res {
+            Ok(RoundOutcome::Compounded(record)) => {
+                self.save_state(account);
+                if let Some(path) = &self.config.history_file {
+                    if let Err(err) = record.append(path) {
+                        tracing::error!("{:#}", err);
+                    }
+                }
+                self.webhook.notify(WebhookEvent::compounded(record)).await;
+                if !failed_claims.is_empty() {
+                    self.webhook
+                        .notify(WebhookEvent::claims_failed(&failed_claims))
+                        .await;
+                }
+            }
+            Ok(RoundOutcome::DryRun) => {}
+            // Skipped rounds still update the APR moving average
+            Ok(
+                RoundOutcome::NoRewards
+                | RoundOutcome::NoDelegations
+                | RoundOutcome::NotDue
+                | RoundOutcome::BelowMinimum
+                | RoundOutcome::Unprofitable
+                | RoundOutcome::FeeTooHigh
+                | RoundOutcome::LowBalance
+                | RoundOutcome::InvalidApr
+                | RoundOutcome::Observed(_),
+            ) => self.save_state(account),
+            Err(err) => {
+                tracing::error!("{:#}", err);
+                // A halted account fails every round until --clear-anomaly,
+                // the webhook only goes out as it halts
+                if !halted {
+                    self.webhook.notify(WebhookEvent::error(err)).await;
+                }
+            }
+        }
+
+        res
+    }
+
+    /// How long to sleep after a scheduled round, see
+    /// [`scheduler::next_wake`]. The epochs are only queried when it needs
+    /// them.
+    pub async fn sleep_after(
+        &self,
+        outcomes: &[(&Account, anyhow::Result<CompoundOutcome>)],
+    ) -> Duration {
+        let below_minimum = !outcomes.is_empty()
+            && outcomes.iter().all(|(_, res)| {
+                matches!(
+                    res,
+                    Ok(CompoundOutcome {
+                        round: RoundOutcome::BelowMinimum,
+                        ..
+                    })
+                )
+            });
+        let epoch = if scheduler::needs_epoch(&self.config, below_minimum) {
+            self.epoch_clock().await
+        } else {
+            None
+        };
+
+        let states = self.states();
+        let inputs = WakeInputs {
+            now: scheduler::now(),
+            next_due: states
+                .values()
+                .filter_map(|state| state.next_due_timestamp)
+                .min(),
+            due_epoch: states
+                .values()
+                .filter_map(|state| state.next_due_epoch(self.config.epochs_between_compounds))
+                .min(),
+            epoch,
+            below_minimum,
+        };
+        let sleep = scheduler::sleep_until_wake(&self.config, inputs);
+        if self.config.wait_for_epoch && below_minimum && epoch.is_some() {
+            tracing::info!("Waiting {}s for the next epoch", sleep.as_secs());
+        }
+
+        sleep
+    }
+
+    /// Where the chain is in its epochs, `None` if that can't be told.
+    async fn epoch_clock(&self) -> Option<EpochClock> {
+        let clock = async {
+            Ok::<_, TaskError>(EpochClock {
+                current: self.rpc.get_current_epoch().await?,
+                next_in: self.rpc.time_to_next_epoch().await?.as_secs(),
+                length: self.rpc.epoch_duration().await?.as_secs(),
+            })
+        }
+        .await;
+
+        clock
+            .inspect_err(|err| tracing::warn!("Can't tell when the next epoch starts: {:#}", err))
+            .ok()
+    }
+
+    fn save_state(&self, account: &Account) {
+        if self.config.observe_epochs.is_some() {
+            return;
+        }
+        if let Some(path) = &account.state_file {
+            if let Err(err) = self.state(&account.address).save(path) {
+                tracing::error!("{:#}", err);
+            }
+        }
+    }
+
+    pub fn save_states(&self) {
+        for account in &self.accounts {
+            self.save_state(account);
+        }
+    }
+
+    /// For external schedulers in one-time mode: the optimal interval and
+    /// when the next compound is due, for each account that ran.
+    fn print_schedules(&self, outcomes: &[(&Account, anyhow::Result<CompoundOutcome>)]) {
+        let states = self.states();
+
+        for (account, _) in outcomes.iter().filter(|(_, res)| res.is_ok()) {
+            let Some(state) = states.get(&account.address) else {
+                continue;
+            };
+            let (Some(interval_secs), Some(next_due_timestamp)) =
+                (state.interval_secs, state.next_due_timestamp)
+            else {
+                continue;
+            };
+
+            match self.config.output {
+                OutputFormat::Text => println!(
+                    "Next compound of {}: every {:.2} hours, due at {}",
+                    account.alias,
+                    interval_secs as f64 / 3600.0,
+                    next_due_timestamp
+                ),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::json!({
+                        "account": account.alias,
+                        "interval_secs": interval_secs,
+                        "next_due_timestamp": next_due_timestamp,
+                    })
+                ),
+            }
+        }
+    }
+}
+
+/// Decisions of the rounds run with `--observe-epochs`, by account alias.
+#[derive(Debug, Default)]
+pub struct Observations {
+    first_epoch: Option<u64>,
+    last_epoch: Option<u64>,
+    decisions: BTreeMap<String, BTreeMap<&'static str, u64>>,
+    would_compound: BTreeMap<String, token::Amount>,
+}
+
+impl Observations {
+    /// Record the `outcomes` of a round at `epoch`, if it got that far.
+    pub fn record(
+        &mut self,
+        epoch: Option<u64>,
+        outcomes: &[(&Account, anyhow::Result<CompoundOutcome>)],
+    ) {
+        if let Some(epoch) = epoch {
+            self.first_epoch.get_or_insert(epoch);
+            self.last_epoch = Some(epoch);
+        }
+
+        for (account, res) in outcomes {
+            let outcome = res.as_ref().map_or("error", |outcome| outcome.round.name());
+            *self
+                .decisions
+                .entry(account.alias.clone())
+                .or_default()
+                .entry(outcome)
+                .or_default() += 1;
+
+            if let Ok(CompoundOutcome {
+                round: RoundOutcome::Observed(amount),
+                ..
+            }) = res
+            {
+                let total = self
+                    .would_compound
+                    .entry(account.alias.clone())
+                    .or_default();
+                *total = total.checked_add(*amount).unwrap_or(*total);
+            }
+        }
+    }
+
+    /// Epochs between the first round observed and the last.
+    pub fn epochs(&self) -> u64 {
+        match (self.first_epoch, self.last_epoch) {
+            (Some(first), Some(last)) => last.saturating_sub(first),
+            _ => 0,
+        }
+    }
+
+    /// A line per account: how many rounds decided what, and the rewards
+    /// they would have compounded.
+    pub fn summary(&self) -> Vec<String> {
+        self.decisions
+            .iter()
+            .map(|(alias, decisions)| {
+                let decisions = decisions
+                    .iter()
+                    .map(|(outcome, rounds)| format!("{} {}", rounds, outcome))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                let compounded = self.would_compound.get(alias).copied().unwrap_or_default();
+                format!(
+                    "{}: {}, would have compounded {}",
+                    alias,
+                    decisions,
+                    denom::display_nam(compounded)
+                )
+            })
+            .collect()
+    }
+}
+
+/// Result of a `POST /compound` for one account.
+#[derive(Debug, Serialize)]
+pub struct CompoundResponse {
+    pub account: String,
+    pub outcome: &'static str,
+    pub claimed: Option<String>,
+    pub bonded: Option<String>,
+    pub tx_hashes: Vec<String>,
+    pub error: Option<String>,
+}
+
+impl CompoundResponse {
+    fn new(account: &Account, res: anyhow::Result<RoundOutcome>) -> Self {
+        let skipped = |outcome| Self {
+            account: account.alias.clone(),
+            outcome,
+            claimed: None,
+            bonded: None,
+            tx_hashes: vec![],
+            error: None,
+        };
+
+        match res {
+            Ok(RoundOutcome::Compounded(record)) => Self {
+                claimed: Some(record.rewards.to_string_native()),
+                bonded: Some(record.bonded.to_string_native()),
+                tx_hashes: record.tx_hashes,
+                ..skipped("compounded")
+            },
+            Ok(outcome) => skipped(outcome.name()),
+            Err(err) => Self {
+                error: Some(format!("{:#}", err)),
+                ..skipped("error")
+            },
+        }
+    }
+}
+
+/// Fails with 500 if any account failed, and with 409 if a round is already
+/// running.
+async fn compound_now(
+    extract::State(compounder): extract::State<Arc<Compounder>>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let Some(outcomes) = compounder.try_compound(Trigger::Manual).await else {
+        return (
+            StatusCode::CONFLICT,
+            Json(serde_json::json!({ "error": "A compound is already running" })),
+        );
+    };
+
+    let failed = outcomes.iter().any(|(_, res)| res.is_err());
+    let responses = outcomes
+        .into_iter()
+        .map(|(account, res)| CompoundResponse::new(account, res))
+        .collect::<Vec<_>>();
+    let status = if failed {
+        StatusCode::INTERNAL_SERVER_ERROR
+    } else {
+        StatusCode::OK
+    };
+
+    (status, Json(serde_json::json!(responses)))
+}
+
+/// Persisted state of an account, with what its last round computed.
+#[derive(Debug, Serialize)]
+pub struct AccountStatus {
+    #[serde(flatten)]
+    pub state: State,
+    #[serde(flatten)]
+    pub last_round: Option<LastRound>,
+    pub next_reclaim_in_secs: Option<u64>,
+    /// Lifetime compounded over compounded plus fees
+    pub efficiency: Option<f64>,
+    pub net_gain: f64,
+}
+
+impl From<State> for AccountStatus {
+    fn from(state: State) -> Self {
+        Self {
+            last_round: state.last_round.clone(),
+            next_reclaim_in_secs: state.next_due_in(),
+            efficiency: state.efficiency(),
+            net_gain: state.compounded_total - state.fees_paid_total,
+            state,
+        }
+    }
+}
+
+/// The status of every account, by address. Never queries the chain.
+async fn status(
+    extract::State(compounder): extract::State<Arc<Compounder>>,
+) -> Json<BTreeMap<String, AccountStatus>> {
+    Json(
+        compounder
+            .states()
+            .into_iter()
+            .map(|(address, state)| (address.to_string(), AccountStatus::from(state)))
+            .collect(),
+    )
+}
+
+/// Hypothetical position for `GET /optimize`, amounts in NAM.
+#[derive(Debug, Deserialize)]
+pub struct OptimizeQuery {
+    pub principal: f64,
+    pub apr: f64,
+    /// Fee of a whole compounding round
+    pub fee: f64,
+    /// In years, `--horizon-years` if not given
+    pub horizon: Option<f64>,
+}
+
+/// What the optimizer makes of an [`OptimizeQuery`].
+#[derive(Debug, Serialize)]
+pub struct OptimizeResponse {
+    #[serde(flatten)]
+    pub result: OptimizationResult,
+    pub hours_between_compounding: f64,
+    pub days_between_compounding: f64,
+    pub apy: f64,
+}
+
+/// Most frequencies `GET /curve` evaluates, far fewer than the command
+/// line's [`opt::MAX_CURVE_SAMPLES`] since anyone reaching the server can ask.
+const MAX_SERVED_CURVE_SAMPLES: usize = 500;
+
+/// Longest horizon, in years, a what-if query is optimized over: the search
+/// grows with it.
+const MAX_HORIZON_YEARS: f64 = 100.0;
+
+/// Why a hypothetical position can't be optimized, if it can't.
+fn check_position(principal: f64, apr: f64, fee: f64, horizon: f64) -> Result<(), String> {
+    if !(principal.is_finite() && principal > 0.0) {
+        return Err("principal must be a positive amount".to_string());
+    }
+    if principal > opt::MAX_EXACT_AMOUNT {
+        return Err(format!(
+            "principal must be at most {} NAM",
+            opt::MAX_EXACT_AMOUNT
+        ));
+    }
+    if !(apr.is_finite() && apr >= 0.0) {
+        return Err("apr must be a positive rate".to_string());
+    }
+    if !(fee.is_finite() && fee >= 0.0) {
+        return Err("fee must be a positive amount".to_string());
+    }
+    if !(horizon.is_finite() && horizon > 0.0) {
+        return Err("horizon must be a positive number of years".to_string());
+    }
+    if horizon > MAX_HORIZON_YEARS {
+        return Err(format!(
+            "horizon must be at most {} years",
+            MAX_HORIZON_YEARS
+        ));
+    }
+    Ok(())
+}
+
+impl OptimizeQuery {
+    /// Run the optimizer the rounds use on the query, with no epoch cap
+    /// since nothing is asked of the chain.
+    pub fn optimize(
+        &self,
+        horizon_years: f64,
+        max_frequency: f64,
+    ) -> Result<OptimizeResponse, String> {
+        let horizon = self.horizon.unwrap_or(horizon_years);
+        check_position(self.principal, self.apr, self.fee, horizon)?;
+
+        let result = opt::compute_frequency_opt(
+            self.principal,
+            self.apr,
+            self.fee,
+            None,
+            horizon,
+            max_frequency,
+        )
+        .ok_or_else(|| "the fees eat up the whole principal".to_string())?;
+
+        Ok(OptimizeResponse {
+            hours_between_compounding: result.hours_between_compounding(),
+            days_between_compounding: result.days_between_compounding(),
+            apy: result.apy(self.principal),
+            result,
+        })
+    }
+}
+
+/// Hypothetical position for `GET /curve`, as [`OptimizeQuery`].
+#[derive(Debug, Deserialize)]
+pub struct CurveQuery {
+    pub principal: f64,
+    pub apr: f64,
+    pub fee: f64,
+    pub horizon: Option<f64>,
+    /// Frequencies to evaluate, 100 if not given
+    pub samples: Option<usize>,
+}
+
+impl CurveQuery {
+    /// [`opt::cost_curve`] of the query.
+    pub fn curve(&self, horizon_years: f64) -> Result<Vec<(f64, f64)>, String> {
+        let horizon = self.horizon.unwrap_or(horizon_years);
+        check_position(self.principal, self.apr, self.fee, horizon)?;
+        let samples = self.samples.unwrap_or(100);
+        if !(2..=MAX_SERVED_CURVE_SAMPLES).contains(&samples) {
+            return Err(format!(
+                "samples must be between 2 and {}",
+                MAX_SERVED_CURVE_SAMPLES
+            ));
+        }
+
+        Ok(opt::cost_curve(
+            self.principal,
+            self.apr,
+            self.fee,
+            horizon,
+            samples,
+        ))
+    }
+}
+
+/// What-if run of the optimizer, fails with 400 on a bad query. Never
+/// queries the chain. The search runs off the async workers, so it doesn't
+/// hold up the rounds.
+async fn optimize(
+    extract::State(compounder): extract::State<Arc<Compounder>>,
+    extract::Query(query): extract::Query<OptimizeQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let horizon_years = compounder.config.horizon_years;
+    let max_frequency = compounder.config.max_frequency();
+    let res = tokio::task::spawn_blocking(move || query.optimize(horizon_years, max_frequency))
+        .await
+        .unwrap_or_else(|err| Err(format!("optimizer failed: {}", err)));
+    match res {
+        Ok(response) => (StatusCode::OK, Json(serde_json::json!(response))),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": err })),
+        ),
+    }
+}
+
+/// [`CurveQuery`] as `{"frequency", "balance"}` objects, fails with 400 on a
+/// bad query. Never queries the chain, and runs off the async workers like
+/// [`optimize`].
+async fn curve(
+    extract::State(compounder): extract::State<Arc<Compounder>>,
+    extract::Query(query): extract::Query<CurveQuery>,
+) -> (StatusCode, Json<serde_json::Value>) {
+    let horizon_years = compounder.config.horizon_years;
+    let res = tokio::task::spawn_blocking(move || query.curve(horizon_years))
+        .await
+        .unwrap_or_else(|err| Err(format!("curve failed: {}", err)));
+    match res {
+        Ok(curve) => (StatusCode::OK, Json(simulate::curve_json(&curve))),
+        Err(err) => (
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({ "error": err })),
+        ),
+    }
+}
+
+pub async fn serve(addr: SocketAddr, compounder: Arc<Compounder>) -> anyhow::Result<()> {
+    let app = Router::new()
+        .route("/compound", post(compound_now))
+        .route("/status", get(status))
+        .route("/optimize", get(optimize))
+        .route("/curve", get(curve))
+        .with_state(compounder);
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Can't bind control server to {}", addr))?;
+
+    tracing::info!("Serving the control API on http://{}", addr);
+
+    axum::serve(listener, app)
+        .await
+        .context("Control server failed")
+}
+
+#[cfg(test)]
+mod test {
+    use std::{str::FromStr, sync::Arc};
+
+    use namada_sdk::{key::common::SecretKey, token};
+
+    use super::{Compounder, CurveQuery, Observations, OptimizeQuery};
+    use crate::{
+        accounts::Account,
+        compound::{
+            test::{secret_key, MockNamadaRpc},
+            RoundOutcome,
+        },
+        config::test::config_with,
+        denom, opt,
+        webhook::Webhook,
+        CompoundOutcome,
+    };
+
+    fn query(principal: f64, horizon: Option<f64>) -> OptimizeQuery {
+        OptimizeQuery {
+            principal,
+            apr: 0.1,
+            fee: 0.1,
+            horizon,
+        }
+    }
+
+    #[test]
+    fn test_optimize() {
+        let response = query(10_000.0, None).optimize(1.0, 8760.0).unwrap();
+        assert!(response.result.optimal_frequency > 1);
+        assert!(response.apy > 0.1);
+        assert!(
+            (response.hours_between_compounding * response.result.optimal_frequency as f64
+                - 8760.0)
+                .abs()
+                < 1e-6
+        );
+        assert!(
+            (response.days_between_compounding * 24.0 - response.hours_between_compounding).abs()
+                < 1e-9
+        );
+
+        // The balance keeps growing over a longer horizon
+        let longer = query(10_000.0, Some(5.0)).optimize(1.0, 8760.0).unwrap();
+        assert!(longer.result.max_balance > response.result.max_balance);
+
+        assert!(query(0.0, None).optimize(1.0, 8760.0).is_err());
+        assert!(query(10_000.0, Some(-1.0)).optimize(1.0, 8760.0).is_err());
+        assert!(query(10_000.0, Some(1e9)).optimize(1.0, 8760.0).is_err());
+        assert!(query(1e30, None).optimize(1.0, 8760.0).is_err());
+    }
+
+    #[test]
+    fn test_curve() {
+        let query = |principal, samples| CurveQuery {
+            principal,
+            apr: 0.1,
+            fee: 0.1,
+            horizon: None,
+            samples,
+        };
+
+        let curve = query(10_000.0, None).curve(1.0).unwrap();
+        assert!(curve.len() > 50 && curve.len() <= 100);
+        assert_eq!(query(10_000.0, Some(2)).curve(1.0).unwrap().len(), 2);
+        assert!(query(10_000.0, Some(1)).curve(1.0).is_err());
+        assert!(query(10_000.0, Some(1_000_000)).curve(1.0).is_err());
+        assert!(query(10_000.0, Some(opt::MAX_CURVE_SAMPLES))
+            .curve(1.0)
+            .is_err());
+        assert!(query(-1.0, None).curve(1.0).is_err());
+    }
+
+    #[test]
+    fn test_observations() {
+        let secret_key = SecretKey::from_str(
+            "000101010101010101010101010101010101010101010101010101010101010101",
+        )
+        .unwrap();
+        let account = Account::new("main".to_string(), secret_key, None);
+        let mut observations = Observations::default();
+
+        observations.record(
+            Some(10),
+            &[(
+                &account,
+                Ok(CompoundOutcome::from(RoundOutcome::Observed(
+                    token::Amount::native_whole(5),
+                ))),
+            )],
+        );
+        observations.record(
+            Some(11),
+            &[(&account, Ok(CompoundOutcome::from(RoundOutcome::NotDue)))],
+        );
+        observations.record(
+            Some(12),
+            &[(
+                &account,
+                Ok(CompoundOutcome::from(RoundOutcome::Observed(
+                    token::Amount::native_whole(2),
+                ))),
+            )],
+        );
+        observations.record(None, &[(&account, Err(anyhow::anyhow!("RPC down")))]);
+
+        assert_eq!(observations.epochs(), 2);
+        assert_eq!(
+            observations.summary(),
+            [format!(
+                "main: 1 error, 1 not_due, 2 observed, would have compounded {}",
+                denom::display_nam(token::Amount::native_whole(7))
+            )]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_once() {
+        let rpc = MockNamadaRpc::new(vec![
+            token::Amount::native_whole(100),
+            token::Amount::native_whole(105),
+        ]);
+        let account = Account::new("main".to_string(), secret_key(), None);
+        let compounder = Compounder::new(
+            rpc,
+            vec![account],
+            Arc::new(config_with(&[])),
+            Webhook::new(None),
+        )
+        .unwrap();
+
+        let outcomes = compounder.run_once().await;
+        let (account, outcome) = &outcomes[0];
+        let outcome = outcome.as_ref().unwrap();
+        assert_eq!(account.alias, "main");
+        assert!(matches!(outcome.round, RoundOutcome::Compounded(_)));
+        assert_eq!(outcome.claimed, token::Amount::native_whole(5));
+        assert_eq!(outcome.bonded, token::Amount::native_whole(5));
+        assert_eq!(outcome.tx_hashes, ["claim", "bond"]);
+        assert!(compounder.states()[&account.address].claimed_first_time);
+    }
+}
@@ -0,0 +1,198 @@
+use namada_sdk::token;
+
+/// Micro-NAM (`unam`) in a NAM, the native token having 6 decimals.
+pub const UNAM_PER_NAM: u64 = 1_000_000;
+
+/// `amount` for the logs, e.g. `123.45 NAM`.
+pub fn display_nam(amount: token::Amount) -> String {
+    format!("{} NAM", amount.to_string_native())
+}
+
+/// Like [`display_nam`] for the f64 NAM the optimizer and fee estimates work
+/// with.
+pub fn display_nam_f64(nam: f64) -> String {
+    format!("{:.6} NAM", nam)
+}
+
+pub fn unam_to_nam(unam: u64) -> f64 {
+    unam as f64 / UNAM_PER_NAM as f64
+}
+
+/// Rounded to the nearest micro-NAM, `None` if negative or not finite.
+pub fn nam_to_unam(nam: f64) -> Option<u64> {
+    let unam = (nam * UNAM_PER_NAM as f64).round();
+    (unam.is_finite() && unam >= 0.0 && unam <= u64::MAX as f64).then_some(unam as u64)
+}
+
+/// `nam` as an exact amount, rounded to the nearest micro-NAM. Negative or
+/// NaN amounts are zero and huge ones saturate, so thresholds and fee
+/// estimates from the optimizer's f64 can always be compared to balances
+/// without losing their micro-NAM.
+pub fn amount_from_nam(nam: f64) -> token::Amount {
+    token::Amount::from_u64((nam * UNAM_PER_NAM as f64).round() as u64)
+}
+
+/// `whole` tokens of a token with `decimals` decimals, e.g. a fee in the
+/// `--fee-token`, rounded to its smallest unit. Like [`amount_from_nam`],
+/// negative or NaN amounts are zero and infinite ones saturate.
+pub fn amount_from_whole(whole: f64, decimals: u8) -> token::Amount {
+    let units = (whole * 10f64.powi(decimals.into())).round();
+    if units.is_nan() || units <= 0.0 {
+        token::Amount::zero()
+    } else if units.is_infinite() {
+        token::Amount::max()
+    } else {
+        token::Amount::from_str(format!("{:.0}", units), 0).unwrap_or_else(|_| token::Amount::max())
+    }
+}
+
+/// Price per gas unit to pay a fee at, in whole tokens of a fee token with
+/// `decimals` decimals, `None` if it rounds to nothing.
+pub fn gas_price_amount(gas_price: f64, decimals: u8) -> Option<token::DenominatedAmount> {
+    let amount = amount_from_whole(gas_price, decimals);
+    (gas_price.is_finite() && !amount.is_zero())
+        .then(|| token::DenominatedAmount::new(amount, token::Denomination(decimals)))
+}
+
+/// Amount in NAM given on the command line: a plain number or one suffixed
+/// with `NAM`, or a whole number of micro-NAM suffixed with `unam`.
+pub fn parse_nam(value: &str) -> Result<f64, String> {
+    let (number, unit) = split_unit(value);
+
+    let nam = match unit.to_ascii_lowercase().as_str() {
+        "" | "nam" => number
+            .parse::<f64>()
+            .map_err(|err| format!("Invalid amount {}: {}", value, err))?,
+        "unam" => number
+            .parse::<u64>()
+            .map(unam_to_nam)
+            .map_err(|err| format!("Invalid amount {}: {}", value, err))?,
+        _ => return Err(format!("Unknown unit {}, expected NAM or unam", unit)),
+    };
+
+    if nam.is_finite() && nam >= 0.0 {
+        Ok(nam)
+    } else {
+        Err(format!("{} is not a positive amount", value))
+    }
+}
+
+/// Exact counterpart of [`parse_nam`], for amounts sent in txs.
+pub fn parse_amount(value: &str) -> Result<token::Amount, String> {
+    let (number, unit) = split_unit(value);
+
+    match unit.to_ascii_lowercase().as_str() {
+        "" | "nam" => token::Amount::from_str(number, token::NATIVE_MAX_DECIMAL_PLACES)
+            .map_err(|err| format!("Invalid amount {}: {}", value, err)),
+        "unam" => number
+            .parse::<u64>()
+            .map(token::Amount::from_u64)
+            .map_err(|err| format!("Invalid amount {}: {}", value, err)),
+        _ => Err(format!("Unknown unit {}, expected NAM or unam", unit)),
+    }
+}
+
+/// `"1.5 NAM"` into `("1.5", "NAM")`.
+fn split_unit(value: &str) -> (&str, &str) {
+    let value = value.trim();
+    let unit_start = value
+        .find(|c: char| c.is_ascii_alphabetic())
+        .unwrap_or(value.len());
+    let (number, unit) = value.split_at(unit_start);
+
+    (number.trim_end(), unit)
+}
+
+#[cfg(test)]
+mod test {
+    use namada_sdk::token;
+
+    use super::{
+        amount_from_nam, amount_from_whole, display_nam, gas_price_amount, nam_to_unam,
+        parse_amount, parse_nam, unam_to_nam,
+    };
+
+    #[test]
+    fn test_round_trip() {
+        for unam in [0, 1, 50_000, 1_500_000, 123_450_000] {
+            assert_eq!(nam_to_unam(unam_to_nam(unam)), Some(unam));
+        }
+        assert_eq!(nam_to_unam(-1.0), None);
+        assert_eq!(nam_to_unam(f64::NAN), None);
+    }
+
+    #[test]
+    fn test_parse_units() {
+        assert_eq!(parse_nam("1.5"), Ok(1.5));
+        assert_eq!(parse_nam("1.5NAM"), Ok(1.5));
+        assert_eq!(parse_nam("1.5 nam"), Ok(1.5));
+        assert_eq!(parse_nam("50000unam"), Ok(0.05));
+        assert!(parse_nam("0.5unam").is_err());
+        assert!(parse_nam("1.5 ETH").is_err());
+        assert!(parse_nam("-1").is_err());
+
+        assert_eq!(
+            parse_amount("12.5 NAM"),
+            Ok(token::Amount::from_u64(12_500_000))
+        );
+        assert_eq!(
+            parse_amount("12500000unam"),
+            Ok(token::Amount::from_u64(12_500_000))
+        );
+    }
+
+    #[test]
+    fn test_amount_from_nam() {
+        assert_eq!(amount_from_nam(0.05), token::Amount::from_u64(50_000));
+        assert_eq!(amount_from_nam(-1.0), token::Amount::zero());
+        assert_eq!(amount_from_nam(f64::NAN), token::Amount::zero());
+        assert_eq!(
+            amount_from_nam(f64::INFINITY),
+            token::Amount::from_u64(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_fee_token_decimals() {
+        assert_eq!(amount_from_whole(0.05, 6), amount_from_nam(0.05));
+        assert_eq!(
+            amount_from_whole(0.05, 8),
+            token::Amount::from_u64(5_000_000)
+        );
+        assert_eq!(amount_from_whole(-1.0, 8), token::Amount::zero());
+
+        let gas_price = gas_price_amount(0.00015, 8).unwrap();
+        assert_eq!(gas_price.amount(), token::Amount::from_u64(15_000));
+        assert_eq!(gas_price.denom(), token::Denomination(8));
+        assert_eq!(
+            gas_price_amount(2.0, 0).unwrap().amount(),
+            token::Amount::from_u64(2)
+        );
+        assert_eq!(gas_price_amount(0.4, 0), None);
+        assert_eq!(gas_price_amount(f64::NAN, 6), None);
+    }
+
+    #[test]
+    fn test_exact_beyond_f64_range() {
+        // 2^53 + 1 micro-NAM, the first amount f64 can't hold
+        let pre = token::Amount::from_u64((1 << 53) + 1);
+        let post = pre.checked_add(token::Amount::from_u64(1)).unwrap();
+        let as_f64 = |amount: token::Amount| amount.to_string_native().parse::<f64>().unwrap();
+
+        // f64 loses the micro-NAM of rewards, the amounts don't
+        assert_eq!(as_f64(post) - as_f64(pre), 0.0);
+        assert_eq!(post.checked_sub(pre), Some(token::Amount::from_u64(1)));
+
+        // Beyond it a fee still comes off the balance
+        let balance = token::Amount::from_u64(1 << 60);
+        let fee = amount_from_nam(0.000001);
+        assert!(balance.checked_sub(fee).unwrap() < balance);
+        assert_eq!(as_f64(balance) - 0.000001, as_f64(balance));
+    }
+
+    #[test]
+    fn test_display_parses_back() {
+        let amount = token::Amount::from_u64(123_450_000);
+        assert_eq!(parse_amount(&display_nam(amount)), Ok(amount));
+    }
+}
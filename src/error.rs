@@ -0,0 +1,204 @@
+use std::{fmt::Display, future::Future, time::Duration};
+
+use crate::utils::Retryable;
+
+pub type TaskResult<T> = Result<T, TaskError>;
+
+#[derive(Debug, thiserror::Error)]
+pub enum TaskError {
+    #[error("RPC unavailable: {0}")]
+    RpcUnavailable(String),
+    /// The node answered HTTP 429, `retry_after` is how long it asked to
+    /// wait if it said
+    #[error("RPC rate limited: {message}")]
+    RateLimited {
+        message: String,
+        retry_after: Option<Duration>,
+    },
+    #[error("Unexpected RPC response: {0}")]
+    InvalidResponse(String),
+    #[error("Failed building tx: {0}")]
+    TxBuildFailed(String),
+    #[error("Tx rejected: {0}")]
+    TxRejected(String),
+    /// Rejected for its gas price, so it may go through at a higher one
+    #[error("Tx fee too low: {0}")]
+    FeeTooLow(String),
+    /// Ran out of gas, so it may go through with a higher gas limit
+    #[error("Tx gas limit too low: {0}")]
+    GasLimitTooLow(String),
+    /// The fee payer can't cover the fee, a higher one won't help
+    #[error("Balance can't pay the fee: {0}")]
+    LowBalance(String),
+    #[error("Not enough rewards: {0}")]
+    InsufficientRewards(String),
+    #[error("Invalid key: {0}")]
+    InvalidKey(String),
+    #[error("Failed loading the shielded context: {0}")]
+    ShieldedSync(String),
+    #[error("Timed out after {0:?}")]
+    Timeout(Duration),
+    #[error(
+        "RPC returned data this binary can't parse, the chain may have upgraded: update the bot ({0})"
+    )]
+    Incompatible(String),
+    /// See `--anomaly-threshold-pct`
+    #[error("Compounding paused: {0}")]
+    Anomaly(String),
+}
+
+impl TaskError {
+    /// Map an RPC client error, e.g. `.map_err(TaskError::rpc("Error fetching epoch"))`.
+    /// A response that doesn't decode won't on a retry either, so it isn't
+    /// reported as the RPC being unavailable.
+    pub fn rpc<E: Display>(context: &'static str) -> impl FnOnce(E) -> Self {
+        move |err| {
+            let message = format!("{}: {}", context, err);
+            if is_decode_error(&message) {
+                Self::Incompatible(message)
+            } else if is_rate_limit_error(&message) {
+                Self::RateLimited {
+                    message,
+                    retry_after: None,
+                }
+            } else {
+                Self::RpcUnavailable(message)
+            }
+        }
+    }
+
+    /// Process exit code in one-time mode, so scripts can tell a
+    /// misconfiguration from a flaky node.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            TaskError::RpcUnavailable(_)
+            | TaskError::RateLimited { .. }
+            | TaskError::InvalidResponse(_)
+            | TaskError::ShieldedSync(_) => 1,
+            TaskError::InvalidKey(_) => 2,
+            TaskError::TxBuildFailed(_)
+            | TaskError::TxRejected(_)
+            | TaskError::FeeTooLow(_)
+            | TaskError::GasLimitTooLow(_) => 3,
+            TaskError::InsufficientRewards(_) | TaskError::LowBalance(_) => 4,
+            TaskError::Timeout(_) => 5,
+            TaskError::Incompatible(_) => 6,
+            TaskError::Anomaly(_) => 7,
+        }
+    }
+}
+
+/// Whether an RPC error comes from decoding the response rather than from
+/// reaching the node. The SDK only tells them apart in the message.
+fn is_decode_error(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    [
+        "decod",
+        "deserializ",
+        "borsh",
+        "unexpected variant",
+        "unexpected length of input",
+    ]
+    .iter()
+    .any(|marker| message.contains(marker))
+}
+
+/// Whether an RPC error is the node throttling us. The client only keeps the
+/// status of a failed HTTP request, in the message, where a bare `429` may
+/// as well be part of a height or hash.
+fn is_rate_limit_error(message: &str) -> bool {
+    let message = message.to_ascii_lowercase();
+    message.contains("status code: 429") || message.contains("too many requests")
+}
+
+/// Fail `fut` with [`TaskError::Timeout`] if it doesn't complete within
+/// `duration`.
+pub async fn with_timeout<T>(
+    duration: Duration,
+    fut: impl Future<Output = TaskResult<T>>,
+) -> TaskResult<T> {
+    tokio::time::timeout(duration, fut)
+        .await
+        .unwrap_or(Err(TaskError::Timeout(duration)))
+}
+
+impl Retryable for TaskError {
+    fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            TaskError::RpcUnavailable(_)
+                | TaskError::RateLimited { .. }
+                | TaskError::ShieldedSync(_)
+                | TaskError::Timeout(_)
+        )
+    }
+
+    fn retry_after(&self) -> Option<Duration> {
+        match self {
+            TaskError::RateLimited { retry_after, .. } => *retry_after,
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{with_timeout, TaskError};
+    use crate::utils::Retryable;
+
+    #[test]
+    fn test_only_transient_errors_are_retried() {
+        assert!(TaskError::RpcUnavailable("down".to_string()).is_retryable());
+        assert!(!TaskError::InvalidKey("bad".to_string()).is_retryable());
+        assert!(!TaskError::TxRejected("nope".to_string()).is_retryable());
+        assert!(TaskError::Timeout(Duration::from_secs(1)).is_retryable());
+    }
+
+    #[test]
+    fn test_decode_errors_are_incompatible() {
+        let err =
+            TaskError::rpc("Error fetching bonds")("Decoding error: Unexpected variant index");
+        assert!(matches!(err, TaskError::Incompatible(_)));
+        assert!(!err.is_retryable());
+        assert!(err.to_string().contains("update the bot"));
+
+        let err = TaskError::rpc("Error fetching bonds")("connection refused");
+        assert!(matches!(err, TaskError::RpcUnavailable(_)));
+    }
+
+    #[test]
+    fn test_rate_limit_errors() {
+        let err = TaskError::rpc("Error fetching bonds")(
+            "HTTP request failed with non-200 status code: 429 Too Many Requests",
+        );
+        assert!(matches!(
+            err,
+            TaskError::RateLimited {
+                retry_after: None,
+                ..
+            }
+        ));
+        assert!(err.is_retryable());
+        assert_eq!(err.retry_after(), None);
+
+        let err = TaskError::rpc("Error fetching bonds")("No block at height 1429");
+        assert!(matches!(err, TaskError::RpcUnavailable(_)));
+
+        let err = TaskError::RateLimited {
+            message: "slow down".to_string(),
+            retry_after: Some(Duration::from_secs(2)),
+        };
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(2)));
+        assert_eq!(err.exit_code(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_fires() {
+        let res: Result<(), TaskError> =
+            with_timeout(Duration::from_millis(10), std::future::pending()).await;
+
+        assert!(matches!(res, Err(TaskError::Timeout(_))));
+    }
+}
@@ -0,0 +1,136 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use serde::Serialize;
+
+use crate::scheduler;
+
+/// Bumped when a field of [`Event`] is renamed, retyped or removed. Adding
+/// a field or an event keeps the version.
+pub const SCHEMA_VERSION: u32 = 1;
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Start writing the events to stdout, for `--events json`.
+pub fn enable() {
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::SeqCst)
+}
+
+/// What `--events json` streams, one JSON object per line with the event
+/// name under `event`, along with `version`, see [`SCHEMA_VERSION`], and
+/// `timestamp` in unix seconds. Amounts are in NAM, as strings so they
+/// aren't rounded, and addresses are bech32m.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    /// The compounding loop of `accounts` started, under `chain` of
+    /// `--chains-file` if any
+    LoopStarted {
+        accounts: usize,
+        chain: Option<String>,
+    },
+    /// The compounding frequency of `delegator` was computed at `epoch`
+    Optimized {
+        delegator: String,
+        epoch: u64,
+        bonded_amount: String,
+        net_apr: f64,
+        optimal_frequency_hours: f64,
+    },
+    /// A round of `delegator` ended as `outcome`, see
+    /// `RoundOutcome::name`
+    Decision { delegator: String, outcome: String },
+    /// The claim from one `validator`, with `error` set if it failed
+    Claim {
+        delegator: String,
+        validator: String,
+        rewards: Option<String>,
+        tx_hash: Option<String>,
+        error: Option<String>,
+    },
+    /// `amount` was bonded, spread over the validators of `distribution`
+    Bond {
+        delegator: String,
+        amount: String,
+        distribution: BTreeMap<String, String>,
+        tx_hashes: Vec<String>,
+    },
+    /// A round of `delegator` failed, or without one, the run itself, e.g.
+    /// at startup
+    Error {
+        delegator: Option<String>,
+        error: String,
+    },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    version: u32,
+    timestamp: u64,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+impl Event {
+    fn to_line(&self, timestamp: u64) -> String {
+        let line = Line {
+            version: SCHEMA_VERSION,
+            timestamp,
+            event: self,
+        };
+        serde_json::to_string(&line).unwrap_or_default()
+    }
+}
+
+/// Write `event` to stdout if `--events json` is on. A closed stdout is
+/// ignored, like the logs.
+pub fn emit(event: Event) {
+    if !is_enabled() {
+        return;
+    }
+
+    let line = event.to_line(scheduler::now());
+    let _ = writeln!(std::io::stdout().lock(), "{}", line);
+}
+
+#[cfg(test)]
+mod test {
+    use super::Event;
+
+    #[test]
+    fn test_event_line() {
+        let event = Event::Decision {
+            delegator: "tnam1q".to_string(),
+            outcome: "not_due".to_string(),
+        };
+        assert_eq!(
+            event.to_line(1_700_000_000),
+            r#"{"version":1,"timestamp":1700000000,"event":"decision","delegator":"tnam1q","outcome":"not_due"}"#
+        );
+
+        let event = Event::LoopStarted {
+            accounts: 2,
+            chain: None,
+        };
+        assert_eq!(
+            event.to_line(1_700_000_000),
+            r#"{"version":1,"timestamp":1700000000,"event":"loop_started","accounts":2,"chain":null}"#
+        );
+
+        let event = Event::Error {
+            delegator: None,
+            error: "Can't connect".to_string(),
+        };
+        assert_eq!(
+            event.to_line(1_700_000_000),
+            r#"{"version":1,"timestamp":1700000000,"event":"error","delegator":null,"error":"Can't connect"}"#
+        );
+    }
+}
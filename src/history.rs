@@ -0,0 +1,264 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, ErrorKind, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Context;
+use namada_sdk::token;
+
+use crate::{denom, state};
+
+const HEADER: &str = "timestamp,epoch,validators,rewards_claimed,amount_bonded,fee_nam,\
+balance_pre,balance_post,gas_used,fee_paid_nam,projected_gain,realized_gain,projection_accuracy,\
+compounded_total,fees_paid_total,efficiency";
+
+/// [`HEADER`] before the fee columns were renamed from micro-NAM, whose
+/// values were in NAM already. Rows are still appended under it.
+const UNAM_HEADER: &str = "timestamp,epoch,validators,rewards_claimed,amount_bonded,fee_unam,\
+balance_pre,balance_post,gas_used,fee_paid_unam,projected_gain,realized_gain,projection_accuracy,\
+compounded_total,fees_paid_total,efficiency";
+
+/// One successful compound, as a row of the `--history-file` CSV.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompoundRecord {
+    pub timestamp: u64,
+    pub epoch: u64,
+    pub validators: usize,
+    pub rewards: token::Amount,
+    pub bonded: token::Amount,
+    /// Estimated from the gas price, the actual fee may be lower
+    pub fee_nam: f64,
+    /// `None` if not queried, see `--claimed-amount`
+    pub balance_pre: Option<token::Amount>,
+    pub balance_post: Option<token::Amount>,
+    /// Gas used by the claim and bond txs
+    pub gas_used: u64,
+    /// Fee of `gas_used` at the estimated gas price, zero when paid in a
+    /// `--fee-token`
+    pub fee_paid_nam: f64,
+    /// Rewards less fees the optimizer's inputs projected since the last
+    /// compound, `None` for the first one
+    pub projected_gain: Option<f64>,
+    /// Rewards claimed less `fee_paid_nam`
+    pub realized_gain: f64,
+    /// Claim then bond txs, not written to the CSV
+    pub tx_hashes: Vec<String>,
+    /// Lifetime tally including this compound, see [`crate::state::State`]
+    pub compounded_total: f64,
+    pub fees_paid_total: f64,
+}
+
+impl CompoundRecord {
+    /// Realized over projected gain, `None` without a positive projection.
+    pub fn projection_accuracy(&self) -> Option<f64> {
+        self.projected_gain
+            .filter(|projected| *projected > 0.0)
+            .map(|projected| self.realized_gain / projected)
+    }
+
+    fn to_csv_row(&self) -> String {
+        let optional = |value: Option<f64>| value.map(|value| format!("{:.6}", value));
+        let balance = |amount: Option<token::Amount>| {
+            amount
+                .map(|amount| amount.to_string_native())
+                .unwrap_or_default()
+        };
+        format!(
+            "{},{},{},{},{},{:.6},{},{},{},{:.6},{},{:.6},{},{:.6},{:.6},{}\n",
+            self.timestamp,
+            self.epoch,
+            self.validators,
+            self.rewards.to_string_native(),
+            self.bonded.to_string_native(),
+            self.fee_nam,
+            balance(self.balance_pre),
+            balance(self.balance_post),
+            self.gas_used,
+            self.fee_paid_nam,
+            optional(self.projected_gain).unwrap_or_default(),
+            self.realized_gain,
+            optional(self.projection_accuracy()).unwrap_or_default(),
+            self.compounded_total,
+            self.fees_paid_total,
+            optional(state::efficiency(
+                self.compounded_total,
+                self.fees_paid_total
+            ))
+            .unwrap_or_default()
+        )
+    }
+
+    /// One line for the log, with the seconds until the next compound when
+    /// known.
+    pub fn summary(&self, next_in: Option<u64>) -> String {
+        let mut summary = format!(
+            "Compounded {} across {} validators",
+            denom::display_nam(self.bonded),
+            self.validators
+        );
+        if let Some(next_in) = next_in {
+            summary.push_str(&format!(", next in {}h", next_in / 60 / 60));
+        }
+        summary
+    }
+
+    /// Append the record to `path`, writing the header first if the file is
+    /// new. Each row goes out in a single write and is synced before
+    /// returning, so a crash can at worst truncate the last row.
+    pub fn append(&self, path: &Path) -> anyhow::Result<()> {
+        rotate_outdated(path)?;
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Can't open history file {}", path.display()))?;
+
+        let is_new = file.metadata()?.len() == 0;
+        let mut row = String::new();
+        if is_new {
+            row.push_str(HEADER);
+            row.push('\n');
+        }
+        row.push_str(&self.to_csv_row());
+
+        file.write_all(row.as_bytes())
+            .and_then(|_| file.sync_data())
+            .with_context(|| format!("Can't write history file {}", path.display()))
+    }
+}
+
+/// Move a history file with other columns than [`HEADER`], written by
+/// another version, to `<path>.old`, so that rows don't get appended under
+/// the wrong header. Fails rather than overwrite an earlier `<path>.old`.
+fn rotate_outdated(path: &Path) -> anyhow::Result<()> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == ErrorKind::NotFound => return Ok(()),
+        Err(err) => {
+            return Err(err).with_context(|| format!("Can't open history file {}", path.display()))
+        }
+    };
+    let mut header = String::new();
+    BufReader::new(file)
+        .read_line(&mut header)
+        .with_context(|| format!("Can't read history file {}", path.display()))?;
+    if header.is_empty() || [HEADER, UNAM_HEADER].contains(&header.trim_end()) {
+        return Ok(());
+    }
+
+    let mut rotated = path.as_os_str().to_owned();
+    rotated.push(".old");
+    let rotated = PathBuf::from(rotated);
+    anyhow::ensure!(
+        !rotated.exists(),
+        "History file {} has other columns than this version writes and {} exists, move one of them away",
+        path.display(),
+        rotated.display()
+    );
+    std::fs::rename(path, &rotated)
+        .with_context(|| format!("Can't move history file {}", path.display()))?;
+    tracing::warn!(
+        "History file {} has other columns than this version writes, moved it to {}",
+        path.display(),
+        rotated.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use namada_sdk::token;
+
+    use super::{CompoundRecord, HEADER, UNAM_HEADER};
+
+    fn record() -> CompoundRecord {
+        CompoundRecord {
+            timestamp: 1_700_000_000,
+            epoch: 100,
+            validators: 2,
+            rewards: token::Amount::native_whole(5),
+            bonded: token::Amount::native_whole(5),
+            fee_nam: 0.2,
+            balance_pre: Some(token::Amount::native_whole(100)),
+            balance_post: Some(token::Amount::native_whole(105)),
+            gas_used: 40_000,
+            fee_paid_nam: 0.16,
+            projected_gain: Some(4.0),
+            realized_gain: 4.84,
+            tx_hashes: vec![],
+            compounded_total: 15.0,
+            fees_paid_total: 0.6,
+        }
+    }
+
+    #[test]
+    fn test_append_writes_header_once() {
+        let path =
+            std::env::temp_dir().join(format!("autocompound-history-{}.csv", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let record = record();
+        record.append(&path).unwrap();
+        record.append(&path).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], HEADER);
+        assert_eq!(lines[1], lines[2]);
+        assert!(lines[1].starts_with("1700000000,100,2,"));
+        assert!(lines[1]
+            .ends_with(",40000,0.160000,4.000000,4.840000,1.210000,15.000000,0.600000,0.961538"));
+        // Left empty when not queried
+        let unqueried = CompoundRecord {
+            balance_pre: None,
+            balance_post: None,
+            ..record.clone()
+        };
+        assert!(unqueried.to_csv_row().contains(",0.200000,,,40000,"));
+        assert_eq!(
+            record.summary(Some(26 * 60 * 60 + 59)),
+            "Compounded 5.000000 NAM across 2 validators, next in 26h"
+        );
+    }
+
+    #[test]
+    fn test_append_to_old_format() {
+        let path = std::env::temp_dir().join(format!(
+            "autocompound-history-old-{}.csv",
+            std::process::id()
+        ));
+        let old = path.with_extension("csv.old");
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&old);
+        let old_content = "timestamp,epoch,validators,rewards_claimed\n1600000000,90,1,2.0\n";
+        std::fs::write(&path, old_content).unwrap();
+
+        record().append(&path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(content.lines().next(), Some(HEADER));
+        assert_eq!(content.lines().count(), 2);
+        assert_eq!(std::fs::read_to_string(&old).unwrap(), old_content);
+
+        // The earlier rotated file is kept
+        std::fs::write(&path, old_content).unwrap();
+        let err = record().append(&path).unwrap_err();
+        assert!(err.to_string().contains("move one of them away"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), old_content);
+
+        std::fs::remove_file(&path).unwrap();
+        std::fs::remove_file(&old).unwrap();
+
+        // Only the names of the fee columns changed since that one
+        std::fs::write(&path, format!("{}\n", UNAM_HEADER)).unwrap();
+        record().append(&path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(content.lines().next(), Some(UNAM_HEADER));
+        assert_eq!(content.lines().count(), 2);
+        assert!(!old.exists());
+    }
+}
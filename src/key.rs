@@ -0,0 +1,235 @@
+use std::{io::Read, path::Path, str::FromStr};
+
+use anyhow::Context;
+use bip39::{Language, Mnemonic, Seed};
+use namada_sdk::{
+    address::Address,
+    key::{common::SecretKey, SchemeType},
+    wallet::{derivation_path::DerivationPath, derive_hd_secret_key},
+};
+use zeroize::Zeroizing;
+
+use crate::{config::AppConfig, error::TaskError};
+
+/// Default Namada path for ed25519 transparent keys.
+pub const DEFAULT_DERIVATION_PATH: &str = "m/44'/877'/0'/0'/0'";
+/// Default Namada path for secp256k1 transparent keys, non-hardened at the
+/// end as BIP44 wallets expect.
+pub const DEFAULT_SECP256K1_DERIVATION_PATH: &str = "m/44'/877'/0'/0/0";
+
+/// Signature scheme of the delegator key. It determines the implicit address,
+/// so it must match the account's.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyScheme {
+    Ed25519,
+    Secp256k1,
+}
+
+impl KeyScheme {
+    pub fn scheme_type(self) -> SchemeType {
+        match self {
+            KeyScheme::Ed25519 => SchemeType::Ed25519,
+            KeyScheme::Secp256k1 => SchemeType::Secp256k1,
+        }
+    }
+
+    pub fn default_derivation_path(self) -> &'static str {
+        match self {
+            KeyScheme::Ed25519 => DEFAULT_DERIVATION_PATH,
+            KeyScheme::Secp256k1 => DEFAULT_SECP256K1_DERIVATION_PATH,
+        }
+    }
+
+    fn of(secret_key: &SecretKey) -> Self {
+        match secret_key {
+            SecretKey::Ed25519(_) => KeyScheme::Ed25519,
+            SecretKey::Secp256k1(_) => KeyScheme::Secp256k1,
+        }
+    }
+}
+
+/// Resolve the delegator key from whichever of `--secret-key-file`,
+/// `--secret-key`, `--mnemonic-file` or `--mnemonic` was given.
+pub fn resolve_secret_key(config: &AppConfig) -> anyhow::Result<SecretKey> {
+    if let Some(path) = &config.secret_key_file {
+        let raw = read_secret(path)?;
+        return parse_secret_key(&raw, config.key_scheme, "Can't parse secret key file");
+    }
+
+    if let Some(raw) = config.secret_key.as_deref() {
+        return parse_secret_key(raw, config.key_scheme, "Can't parse secret key");
+    }
+
+    let phrase = match (&config.mnemonic_file, &config.mnemonic) {
+        (Some(path), _) => read_secret(path)?,
+        (None, Some(phrase)) => Zeroizing::new(phrase.clone()),
+        (None, None) => anyhow::bail!(
+            "One of --secret-key, --secret-key-file, --mnemonic or --mnemonic-file is required"
+        ),
+    };
+
+    let derivation_path = config
+        .derivation_path
+        .as_deref()
+        .unwrap_or(config.key_scheme.default_derivation_path());
+    derive_secret_key(&phrase, derivation_path, config.key_scheme)
+}
+
+/// Parse a secret key, rejecting it if it isn't of the expected `scheme`.
+pub fn parse_secret_key(raw: &str, scheme: KeyScheme, context: &str) -> anyhow::Result<SecretKey> {
+    let secret_key = SecretKey::from_str(raw.trim())
+        .map_err(|err| TaskError::InvalidKey(format!("{}: {}", context, err)))?;
+
+    let actual = KeyScheme::of(&secret_key);
+    if actual != scheme {
+        return Err(TaskError::InvalidKey(format!(
+            "{}: this is a {:?} key, not {:?} (see --key-scheme)",
+            context, actual, scheme
+        ))
+        .into());
+    }
+
+    Ok(secret_key)
+}
+
+/// Derive a key of the given `scheme` from a BIP39 mnemonic, like the Namada
+/// wallet does. The mnemonic and seed are wiped from memory once the key is
+/// derived.
+pub fn derive_secret_key(
+    phrase: &str,
+    derivation_path: &str,
+    scheme: KeyScheme,
+) -> anyhow::Result<SecretKey> {
+    let mnemonic = Mnemonic::from_phrase(phrase.trim(), Language::English)
+        .map_err(|err| TaskError::InvalidKey(format!("Invalid mnemonic: {}", err)))?;
+    let derivation_path = DerivationPath::from_path_string_for_transparent_scheme(
+        scheme.scheme_type(),
+        derivation_path,
+    )
+    .map_err(|err| TaskError::InvalidKey(format!("Invalid derivation path: {}", err)))?;
+
+    let seed = Seed::new(&mnemonic, "");
+    Ok(derive_hd_secret_key(
+        scheme.scheme_type(),
+        seed.as_bytes(),
+        derivation_path,
+    ))
+}
+
+/// The implicit address and public key of `secret_key`, as `address` prints
+/// them.
+pub fn key_summary(secret_key: &SecretKey) -> String {
+    let public_key = secret_key.to_public();
+    format!(
+        "Address: {}\nPublic key: {}",
+        Address::from(&public_key),
+        public_key
+    )
+}
+
+/// Read a secret from `path`, or from stdin when `path` is `-`.
+pub fn read_secret(path: &Path) -> anyhow::Result<Zeroizing<String>> {
+    let mut buffer = Zeroizing::new(String::new());
+
+    if path == Path::new("-") {
+        std::io::stdin()
+            .read_to_string(&mut buffer)
+            .context("Can't read secret from stdin")?;
+    } else {
+        std::fs::File::open(path)
+            .and_then(|mut file| file.read_to_string(&mut buffer))
+            .with_context(|| format!("Can't read secret from {}", path.display()))?;
+    }
+
+    Ok(buffer)
+}
+
+#[cfg(test)]
+mod test {
+    use namada_sdk::address::Address;
+
+    use super::{
+        derive_secret_key, key_summary, parse_secret_key, KeyScheme, DEFAULT_DERIVATION_PATH,
+        DEFAULT_SECP256K1_DERIVATION_PATH,
+    };
+
+    const MNEMONIC: &str = "abandon abandon abandon abandon abandon abandon abandon abandon \
+                            abandon abandon abandon abandon abandon abandon abandon abandon \
+                            abandon abandon abandon abandon abandon abandon abandon art";
+
+    const ED25519_KEY: &str = "000101010101010101010101010101010101010101010101010101010101010101";
+    const SECP256K1_KEY: &str =
+        "010101010101010101010101010101010101010101010101010101010101010101";
+
+    #[test]
+    fn test_derive_secret_key() {
+        let key = derive_secret_key(MNEMONIC, DEFAULT_DERIVATION_PATH, KeyScheme::Ed25519).unwrap();
+        let same =
+            derive_secret_key(MNEMONIC, DEFAULT_DERIVATION_PATH, KeyScheme::Ed25519).unwrap();
+        let other = derive_secret_key(MNEMONIC, "m/44'/877'/0'/0'/1'", KeyScheme::Ed25519).unwrap();
+
+        assert_eq!(key.to_public(), same.to_public());
+        assert_ne!(key.to_public(), other.to_public());
+    }
+
+    #[test]
+    fn test_derive_secp256k1_key() {
+        let ed25519 =
+            derive_secret_key(MNEMONIC, DEFAULT_DERIVATION_PATH, KeyScheme::Ed25519).unwrap();
+        let secp256k1 = derive_secret_key(
+            MNEMONIC,
+            DEFAULT_SECP256K1_DERIVATION_PATH,
+            KeyScheme::Secp256k1,
+        )
+        .unwrap();
+
+        assert_eq!(KeyScheme::of(&secp256k1), KeyScheme::Secp256k1);
+        assert_ne!(
+            Address::from(&ed25519.to_public()),
+            Address::from(&secp256k1.to_public())
+        );
+    }
+
+    #[test]
+    fn test_invalid_mnemonic() {
+        assert!(derive_secret_key(
+            "not a mnemonic",
+            DEFAULT_DERIVATION_PATH,
+            KeyScheme::Ed25519
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_key_scheme_must_match() {
+        for (raw, scheme, address) in [
+            (
+                ED25519_KEY,
+                KeyScheme::Ed25519,
+                "tnam1qrzlgt2vncx7qd2s76ke6n7cq29k726spseneu0z",
+            ),
+            (
+                SECP256K1_KEY,
+                KeyScheme::Secp256k1,
+                "tnam1qprw260qkcmgyy5femu7tgp727yd5nrx8g4pw77j",
+            ),
+        ] {
+            let key = parse_secret_key(raw, scheme, "test").unwrap();
+            assert_eq!(Address::from(&key.to_public()).to_string(), address);
+        }
+
+        assert!(parse_secret_key(ED25519_KEY, KeyScheme::Secp256k1, "test").is_err());
+        assert!(parse_secret_key(SECP256K1_KEY, KeyScheme::Ed25519, "test").is_err());
+    }
+
+    #[test]
+    fn test_key_summary() {
+        let key = parse_secret_key(ED25519_KEY, KeyScheme::Ed25519, "test").unwrap();
+
+        assert_eq!(
+            key_summary(&key),
+            "Address: tnam1qrzlgt2vncx7qd2s76ke6n7cq29k726spseneu0z\n\
+             Public key: tpknam1qz9g3c7awsylr90a2tdj6096t4ev5ecfhuwegysm7d6gsqd5pah4cgftgzc"
+        );
+    }
+}
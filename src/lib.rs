@@ -0,0 +1,98 @@
+use compound::RoundOutcome;
+use namada_sdk::token;
+
+pub mod accounts;
+pub mod backtest;
+pub mod chains;
+pub mod compound;
+pub mod config;
+pub mod control;
+pub mod denom;
+pub mod error;
+pub mod events;
+pub mod history;
+pub mod key;
+pub mod metrics;
+pub mod namada;
+pub mod opt;
+pub mod pause;
+pub mod preflight;
+pub mod rebalance;
+pub mod rpc_pool;
+pub mod scheduler;
+pub mod sdk;
+pub mod shutdown;
+pub mod simulate;
+pub mod state;
+pub mod status;
+pub mod strategy;
+pub mod utils;
+pub mod webhook;
+
+/// Outcome of [`control::Compounder::run_once`] for one account, for
+/// embedding in another service. The amounts are zero and there are no tx
+/// hashes unless `round` is [`RoundOutcome::Compounded`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompoundOutcome {
+    pub round: RoundOutcome,
+    pub claimed: token::Amount,
+    pub bonded: token::Amount,
+    /// Claim then bond txs
+    pub tx_hashes: Vec<String>,
+}
+
+impl From<RoundOutcome> for CompoundOutcome {
+    fn from(round: RoundOutcome) -> Self {
+        match &round {
+            RoundOutcome::Compounded(record) => Self {
+                claimed: record.rewards,
+                bonded: record.bonded,
+                tx_hashes: record.tx_hashes.clone(),
+                round,
+            },
+            _ => Self {
+                round,
+                claimed: token::Amount::zero(),
+                bonded: token::Amount::zero(),
+                tx_hashes: vec![],
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use namada_sdk::token;
+
+    use super::CompoundOutcome;
+    use crate::{compound::RoundOutcome, history::CompoundRecord};
+
+    #[test]
+    fn test_compound_outcome() {
+        let outcome = CompoundOutcome::from(RoundOutcome::NotDue);
+        assert!(outcome.claimed.is_zero());
+        assert!(outcome.tx_hashes.is_empty());
+
+        let record = CompoundRecord {
+            timestamp: 1_700_000_000,
+            epoch: 100,
+            validators: 1,
+            rewards: token::Amount::native_whole(5),
+            bonded: token::Amount::native_whole(4),
+            fee_nam: 0.1,
+            balance_pre: Some(token::Amount::native_whole(100)),
+            balance_post: Some(token::Amount::native_whole(105)),
+            gas_used: 40_000,
+            fee_paid_nam: 0.08,
+            projected_gain: None,
+            realized_gain: 4.92,
+            tx_hashes: vec!["claim".to_string(), "bond".to_string()],
+            compounded_total: 4.0,
+            fees_paid_total: 0.08,
+        };
+        let outcome = CompoundOutcome::from(RoundOutcome::Compounded(record));
+        assert_eq!(outcome.claimed, token::Amount::native_whole(5));
+        assert_eq!(outcome.bonded, token::Amount::native_whole(4));
+        assert_eq!(outcome.tx_hashes, ["claim", "bond"]);
+    }
+}
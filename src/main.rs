@@ -1,139 +1,361 @@
-use std::{str::FromStr, time::Duration};
+use std::{net::SocketAddr, sync::Arc};
 
 use anyhow::Context;
-use clap::Parser;
-use config::AppConfig;
-use namada::{NamadaRpc, NamadaSdk};
-use namada_sdk::{address::Address, key::common::SecretKey};
-use state::State;
-use tendermint_rpc::HttpClient;
-use tokio::time::sleep;
-use tracing::Level;
-use tracing_subscriber::FmtSubscriber;
-
-pub mod config;
-pub mod namada;
-pub mod opt;
-pub mod state;
-pub mod utils;
+use futures::future::join_all;
+use namada_autocompund_rewards::{
+    accounts::{self, Account},
+    backtest, chains, compound,
+    config::{AppConfig, Command, LogFormat, PrintConfig},
+    control::{self, Compounder},
+    denom,
+    events::{self, Event},
+    key, metrics,
+    namada::{NamadaRpc, NamadaSdk},
+    pause::Pause,
+    preflight, rebalance,
+    rpc_pool::RpcPool,
+    shutdown::Shutdown,
+    simulate, status, utils,
+    webhook::Webhook,
+};
+use tracing::{Instrument, Level};
+use tracing_subscriber::{
+    filter::LevelFilter, fmt::writer::BoxMakeWriter, EnvFilter, FmtSubscriber,
+};
+
+/// Modules logging the details of every round, silenced by `--quiet`.
+const QUIET_TARGETS: [&str; 2] = [
+    "namada_autocompund_rewards::compound",
+    "namada_autocompund_rewards::namada",
+];
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = AppConfig::parse();
-    let mut state = State::init();
+    let config = AppConfig::load()?;
 
-    FmtSubscriber::builder().with_max_level(Level::INFO).init();
+    init_tracing(&config);
+    if config.events.is_some() {
+        events::enable();
+    }
 
-    tracing::info!("version: {}", env!("VERGEN_GIT_SHA").to_string());
+    // The rounds' own errors went out with their delegator
+    run(config).await.inspect_err(|err| {
+        events::emit(Event::Error {
+            delegator: None,
+            error: format!("{:#}", err),
+        })
+    })
+}
 
-    let client = HttpClient::new(config.namada_rpc.as_str()).context("Invalid http url")?;
-    let namada_sdk = NamadaSdk::new(client);
+async fn run(config: AppConfig) -> anyhow::Result<()> {
+    tracing::info!("version: {}", env!("VERGEN_GIT_SHA").to_string());
 
-    loop {
-        let current_epoch = namada_sdk.get_current_epoch().await?;
+    if let Some(print_config) = config.print_config {
+        for option in &config.resolved {
+            tracing::info!("{} = {} ({})", option.name, option.value, option.source);
+        }
+        if print_config == PrintConfig::Exit {
+            return Ok(());
+        }
+    }
 
-        let pos_inflation = namada_sdk.get_pos_inflation_rate().await?;
+    match &config.command {
+        Some(Command::Simulate(args)) => return simulate::print_simulation(args, &config),
+        Some(Command::BreakEven(args)) => return simulate::print_break_even(args, &config),
+        Some(Command::Curve(args)) => return simulate::print_cost_curve(args, &config),
+        Some(Command::Address) => {
+            let secret_key = key::resolve_secret_key(&config)?;
+            println!("{}", key::key_summary(&secret_key));
+            return Ok(());
+        }
+        Some(Command::Preflight) => {
+            let checklist = preflight::preflight(&config).await;
+            println!("{}", checklist);
+            if !checklist.passed() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(
+            Command::Status
+            | Command::ShieldedStatus(_)
+            | Command::Claim
+            | Command::Bond(_)
+            | Command::Rebalance(_)
+            | Command::Backtest(_),
+        )
+        | None => {}
+    }
 
-        tracing::info!("Inflation rate is: {}", pos_inflation);
+    let shutdown = Shutdown::listen();
+    let pause = Pause::listen(config.pause_file.clone());
 
-        let secret_key =
-            SecretKey::from_str(&config.secret_key).context("Can't parse secret key")?;
-        let public_key = secret_key.to_public();
-        let delegator_address = Address::from(&public_key);
+    if config.chains_file.is_some() {
+        anyhow::ensure!(
+            config.command.is_none(),
+            "Subcommands run on a single chain, not with --chains-file"
+        );
+        let exit_code = run_chains(&config, &shutdown, &pause).await?;
+        if exit_code != 0 {
+            std::process::exit(exit_code);
+        }
+        return Ok(());
+    }
 
-        tracing::info!("Delegator address is: {}", delegator_address);
+    let (accounts, namada_sdk) = connect(&config).await?;
 
-        let validators = namada_sdk
-            .get_delegators_validators(&delegator_address, current_epoch)
-            .await?;
+    if let Some(Command::Status) = config.command {
+        namada_sdk.select_endpoint().await;
+        for account in &accounts {
+            status::print_status(&namada_sdk, &config, &account.address).await?;
+            println!();
+        }
+        return Ok(());
+    }
 
-        let commissions = namada_sdk
-            .query_validators_commissions(&validators, current_epoch)
+    if let Some(Command::ShieldedStatus(args)) = &config.command {
+        namada_sdk.select_endpoint().await;
+        let balance = namada_sdk
+            .query_shielded_balance(&args.viewing_key, &accounts[0].secret_key)
             .await?;
+        println!("Shielded balance: {}", denom::display_nam(balance));
+        return Ok(());
+    }
 
-        let mean_commissions =
-            utils::mean(&commissions).context("Can't compute mean commissions")?;
-
-        let bonded_amount = namada_sdk
-            .query_bonds(&validators, &delegator_address, current_epoch)
-            .await?
-            .iter()
-            .sum::<f64>();
-
-        let net_apr = pos_inflation - (pos_inflation * mean_commissions);
-
-        let optimization_result = opt::compute_frequency_opt(
-            bonded_amount,
-            net_apr,
-            config.base_fee_unam * (validators.len() * 2) as f64,
-        )
-        .context("Failed optimizing frequency")?;
-
-        if config.dry_run {
-            tracing::info!("Dry-run mode");
-            tracing::info!(
-                "- Compunding frequency: {:.2} hours / {:.2} days",
-                optimization_result.hours_between_compounding_rounded(),
-                optimization_result.days_between_compounding_rounded()
-            );
-            tracing::info!("- Current bonded balance: {:.2}", bonded_amount);
-            tracing::info!(
-                "- Balance in 1 year: {:.2}",
-                optimization_result.max_balance
+    if let Some(Command::Claim) = config.command {
+        namada_sdk.select_endpoint().await;
+        for account in &accounts {
+            let claimed = compound::claim_only(&namada_sdk, &config, &account.secret_key)
+                .await
+                .with_context(|| format!("Account {}", account.alias))?;
+            println!(
+                "Claimed {} for {}",
+                denom::display_nam(claimed),
+                account.alias
             );
-            tracing::info!("- APR: {:.2}%", net_apr * 100.0);
-            tracing::info!(
-                "- APY: {:.2}%",
-                ((optimization_result.max_balance / bonded_amount) - 1.0) * 100.0
+        }
+        return Ok(());
+    }
+
+    if let Some(Command::Bond(args)) = &config.command {
+        namada_sdk.select_endpoint().await;
+        for account in &accounts {
+            let receipts =
+                compound::bond_only(&namada_sdk, &config, &account.secret_key, args.amount)
+                    .await
+                    .with_context(|| format!("Account {}", account.alias))?;
+            println!(
+                "Bonded {} for {}: {}",
+                denom::display_nam(args.amount),
+                account.alias,
+                receipts
+                    .iter()
+                    .map(|receipt| receipt.hash.as_str())
+                    .collect::<Vec<_>>()
+                    .join(", ")
             );
+        }
+        return Ok(());
+    }
 
-            std::process::exit(0)
+    if let Some(Command::Rebalance(args)) = &config.command {
+        namada_sdk.select_endpoint().await;
+        for account in &accounts {
+            rebalance::rebalance(
+                &namada_sdk,
+                &config,
+                &account.secret_key,
+                account.state_file.as_deref(),
+                args,
+            )
+            .await
+            .with_context(|| format!("Account {}", account.alias))?;
+            println!();
         }
+        return Ok(());
+    }
 
-        if !state.should_reclaim(optimization_result.optimal_frequency) {
-            tracing::info!(
-                "Next reclaim in {} hours...",
-                state.next_reclaim_in(optimization_result.optimal_frequency) / 60 / 60
-            );
-            exit_or_continue(&config, false).await;
-            continue;
+    if let Some(Command::Backtest(args)) = &config.command {
+        namada_sdk.select_endpoint().await;
+        for account in &accounts {
+            backtest::backtest(&namada_sdk, &config, &account.secret_key, args)
+                .await
+                .with_context(|| format!("Account {}", account.alias))?;
+            println!();
         }
+        return Ok(());
+    }
 
-        let native_token_address = namada_sdk.query_native_token().await?;
+    if let Some(metrics_addr) = config.metrics_addr {
+        spawn_metrics(metrics_addr);
+    }
 
-        let balance_pre = namada_sdk
-            .query_balance(&delegator_address, &native_token_address)
-            .await?;
+    let exit_code = run_compounder(
+        Arc::new(config),
+        accounts,
+        namada_sdk,
+        &shutdown,
+        &pause,
+        None,
+    )
+    .await?;
+    if exit_code != 0 {
+        std::process::exit(exit_code);
+    }
 
-        tracing::info!("Pre balance: {}", balance_pre.to_string_native());
+    Ok(())
+}
 
-        namada_sdk
-            .claim_rewards(&delegator_address, &validators, &secret_key)
-            .await?;
+/// Load the accounts of `config` and connect to its RPC endpoints.
+async fn connect(config: &AppConfig) -> anyhow::Result<(Vec<Account>, NamadaSdk)> {
+    let accounts = accounts::load_accounts(config)?;
 
-        let balance_post = namada_sdk
-            .query_balance(&delegator_address, &native_token_address)
-            .await?;
+    // Conflicts with `--accounts-file`, so there's a single account
+    if let Some(expected_address) = &config.expected_address {
+        anyhow::ensure!(
+            &accounts[0].address == expected_address,
+            "The secret key controls {}, not the expected address {}",
+            accounts[0].address,
+            expected_address
+        );
+    }
 
-        tracing::info!("Post balance: {}", balance_post.to_string_native());
+    for account in &accounts {
+        tracing::info!(
+            "Delegator address of {} is: {}",
+            account.alias,
+            account.address
+        );
+    }
 
-        let rewards = balance_post.checked_sub(balance_pre).unwrap();
+    utils::ensure_writable_dir(&config.wallet_dir)?;
 
-        namada_sdk
-            .bond(&delegator_address, &validators, rewards, &secret_key)
-            .await?;
+    let rpc_pool = RpcPool::new(&config.namada_rpc)?;
+    rpc_pool.log_versions().await;
+    if let Some(chain_id) = &config.chain_id {
+        rpc_pool.check_chain_id(chain_id).await?;
+        tracing::info!("Chain id is: {}", chain_id);
+    }
+    let namada_sdk = NamadaSdk::from_config(rpc_pool, config);
 
-        state.update();
+    Ok((accounts, namada_sdk))
+}
 
-        exit_or_continue(&config, false).await
+/// Compound each chain of `--chains-file` on its own: a chain failing to
+/// start or erroring leaves the others running. Returns the first non-zero
+/// exit code of the chains.
+async fn run_chains(config: &AppConfig, shutdown: &Shutdown, pause: &Pause) -> anyhow::Result<i32> {
+    let chains = chains::load_chains(config)?;
+
+    if let Some(metrics_addr) = config.metrics_addr {
+        spawn_metrics(metrics_addr);
     }
+
+    let runs = chains.into_iter().map(|chain| {
+        let span = tracing::info_span!("chain", name = %chain.name);
+        async move {
+            let res = async {
+                let (accounts, namada_sdk) = connect(&chain.config).await?;
+                run_compounder(
+                    Arc::new(chain.config),
+                    accounts,
+                    namada_sdk,
+                    shutdown,
+                    pause,
+                    Some(&chain.name),
+                )
+                .await
+            }
+            .await;
+
+            match res {
+                Ok(exit_code) => exit_code,
+                Err(err) => {
+                    tracing::error!("Chain {} stopped: {:#}", chain.name, err);
+                    events::emit(Event::Error {
+                        delegator: None,
+                        error: format!("Chain {} stopped: {:#}", chain.name, err),
+                    });
+                    1
+                }
+            }
+        }
+        .instrument(span)
+    });
+    let exit_codes = join_all(runs).await;
+
+    Ok(exit_codes
+        .into_iter()
+        .find(|exit_code| *exit_code != 0)
+        .unwrap_or_default())
+}
+
+fn spawn_metrics(addr: SocketAddr) {
+    tokio::spawn(async move {
+        if let Err(err) = metrics::serve(addr).await {
+            tracing::error!("{:#}", err);
+        }
+    });
+}
+
+/// Serve `--control-addr` and run the compounding loop of `accounts`, see
+/// [`Compounder::run_loop`]. Returns its exit code.
+async fn run_compounder(
+    config: Arc<AppConfig>,
+    accounts: Vec<Account>,
+    namada_sdk: NamadaSdk,
+    shutdown: &Shutdown,
+    pause: &Pause,
+    chain: Option<&str>,
+) -> anyhow::Result<i32> {
+    namada_sdk.select_endpoint().await;
+    compound::check_bond_validators(&namada_sdk, &config).await?;
+
+    let compounder = Arc::new(Compounder::new(
+        namada_sdk,
+        accounts,
+        config.clone(),
+        Webhook::new(config.webhook_url.clone()),
+    )?);
+
+    if let Some(control_addr) = config.control_addr {
+        let compounder = compounder.clone();
+        tokio::spawn(async move {
+            if let Err(err) = control::serve(control_addr, compounder).await {
+                tracing::error!("{:#}", err);
+            }
+        });
+    }
+
+    Ok(compounder.run_loop(shutdown, pause, chain).await)
 }
 
-pub async fn exit_or_continue(config: &AppConfig, with_error: bool) {
-    if config.one_time {
-        let exit_code = if with_error { 1 } else { 0 };
-        std::process::exit(exit_code)
+/// Log at `--log-level`, or as `RUST_LOG` says for the targets it names.
+/// `--quiet` raises the modules logging the details of every round to
+/// warnings, unless debugging. With `--events` the logs go to stderr, out of
+/// the way of the events on stdout.
+fn init_tracing(config: &AppConfig) {
+    let mut directives = vec![];
+    if config.quiet && config.log_level < Level::DEBUG {
+        directives.extend(QUIET_TARGETS.map(|target| format!("{}=warn", target)));
+    }
+    // Last, so they win over `--quiet`
+    directives.extend(std::env::var("RUST_LOG").ok());
+
+    let filter = EnvFilter::builder()
+        .with_default_directive(LevelFilter::from_level(config.log_level).into())
+        .parse_lossy(directives.join(","));
+    let writer = if config.events.is_some() {
+        BoxMakeWriter::new(std::io::stderr)
     } else {
-        sleep(Duration::from_secs(config.sleep_for)).await;
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    let subscriber = FmtSubscriber::builder()
+        .with_env_filter(filter)
+        .with_writer(writer);
+
+    match config.log_format {
+        LogFormat::Human => subscriber.init(),
+        LogFormat::Json => subscriber.json().init(),
     }
 }
@@ -0,0 +1,149 @@
+use std::{net::SocketAddr, sync::LazyLock};
+
+use anyhow::Context;
+use axum::{routing::get, Router};
+use prometheus::{Gauge, IntCounter, IntCounterVec, IntGauge, Opts, Registry, TextEncoder};
+use tokio::net::TcpListener;
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+pub struct Metrics {
+    registry: Registry,
+    pub compounds: IntCounter,
+    pub failed_compounds: IntCounter,
+    pub failed_claims: IntCounter,
+    pub last_claim_timestamp: IntGauge,
+    pub bonded_amount: Gauge,
+    pub net_apr: Gauge,
+    pub optimal_frequency: Gauge,
+    pub rewards_claimed: Gauge,
+    pub gas_used: IntGauge,
+    pub fee_paid: Gauge,
+    pub projection_accuracy: Gauge,
+    pub accumulated_rewards: Gauge,
+    /// By `chain` of `--chains-file` and round `outcome`, the others being
+    /// shared by all chains
+    pub chain_rounds: IntCounterVec,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("autocompound".to_string()), None)
+            .expect("Invalid metrics registry");
+
+        let compounds = IntCounter::new("compounds_total", "Successful compounding rounds")
+            .expect("Invalid metric");
+        let failed_compounds =
+            IntCounter::new("failed_compounds_total", "Failed compounding rounds")
+                .expect("Invalid metric");
+        let failed_claims = IntCounter::new(
+            "failed_claims_total",
+            "Claims from a validator that failed while others went through",
+        )
+        .expect("Invalid metric");
+        let last_claim_timestamp = IntGauge::new(
+            "last_claim_timestamp_seconds",
+            "Unix timestamp of the last successful claim",
+        )
+        .expect("Invalid metric");
+        let bonded_amount =
+            Gauge::new("bonded_amount", "Currently bonded amount in NAM").expect("Invalid metric");
+        let net_apr =
+            Gauge::new("net_apr", "APR net of validator commissions").expect("Invalid metric");
+        let optimal_frequency = Gauge::new(
+            "optimal_frequency",
+            "Optimal number of compounding rounds per year",
+        )
+        .expect("Invalid metric");
+        let rewards_claimed = Gauge::new(
+            "rewards_claimed",
+            "Rewards claimed in the last compounding round in NAM",
+        )
+        .expect("Invalid metric");
+        let gas_used = IntGauge::new(
+            "gas_used",
+            "Gas used by the txs of the last compounding round",
+        )
+        .expect("Invalid metric");
+        let fee_paid = Gauge::new(
+            "fee_paid",
+            "Fee paid in NAM in the last compounding round, at the estimated gas price",
+        )
+        .expect("Invalid metric");
+        let projection_accuracy = Gauge::new(
+            "projection_accuracy",
+            "Realized over projected gain of all compounds so far",
+        )
+        .expect("Invalid metric");
+        let accumulated_rewards = Gauge::new(
+            "accumulated_rewards",
+            "Rewards left liquid until they reach --accumulate-until in NAM",
+        )
+        .expect("Invalid metric");
+        let chain_rounds = IntCounterVec::new(
+            Opts::new(
+                "chain_rounds_total",
+                "Rounds of each account by chain and outcome",
+            ),
+            &["chain", "outcome"],
+        )
+        .expect("Invalid metric");
+
+        for collector in [
+            Box::new(compounds.clone()) as Box<dyn prometheus::core::Collector>,
+            Box::new(failed_compounds.clone()),
+            Box::new(failed_claims.clone()),
+            Box::new(last_claim_timestamp.clone()),
+            Box::new(bonded_amount.clone()),
+            Box::new(net_apr.clone()),
+            Box::new(optimal_frequency.clone()),
+            Box::new(rewards_claimed.clone()),
+            Box::new(gas_used.clone()),
+            Box::new(fee_paid.clone()),
+            Box::new(projection_accuracy.clone()),
+            Box::new(accumulated_rewards.clone()),
+            Box::new(chain_rounds.clone()),
+        ] {
+            registry
+                .register(collector)
+                .expect("Duplicate metric registration");
+        }
+
+        Self {
+            registry,
+            compounds,
+            failed_compounds,
+            failed_claims,
+            last_claim_timestamp,
+            bonded_amount,
+            net_apr,
+            optimal_frequency,
+            rewards_claimed,
+            gas_used,
+            fee_paid,
+            projection_accuracy,
+            accumulated_rewards,
+            chain_rounds,
+        }
+    }
+
+    fn render(&self) -> String {
+        TextEncoder::new()
+            .encode_to_string(&self.registry.gather())
+            .unwrap_or_default()
+    }
+}
+
+pub async fn serve(addr: SocketAddr) -> anyhow::Result<()> {
+    let app = Router::new().route("/metrics", get(|| async { METRICS.render() }));
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .with_context(|| format!("Can't bind metrics server to {}", addr))?;
+
+    tracing::info!("Serving metrics on http://{}/metrics", addr);
+
+    axum::serve(listener, app)
+        .await
+        .context("Metrics server failed")
+}
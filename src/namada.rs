@@ -1,115 +1,619 @@
-use std::collections::HashSet;
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    fmt::{self, Display},
+    future::Future,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
-use anyhow::Context;
 use futures::{FutureExt, StreamExt};
 use namada_sdk::{
-    address::Address, dec::Dec, key::common::SecretKey, queries::RPC, rpc,
-    state::Epoch as NamadaEpoch, token,
+    address::Address,
+    args::{self, SdkTypes, TxBuilder},
+    dec::Dec,
+    key::common::SecretKey,
+    masp::ExtendedViewingKey,
+    parameters,
+    proof_of_stake::types::ValidatorState,
+    queries::RPC,
+    rpc,
+    signing::{default_sign, SigningTxData},
+    state::Epoch as NamadaEpoch,
+    token,
+    tx::{
+        build_batch,
+        data::{GasLimit, ResultCode},
+        ProcessTxResponse, Tx,
+    },
+    Namada,
+};
+use tendermint_rpc::{Client, HttpClient};
+use tokio::sync::{Mutex, OnceCell};
+
+use crate::{
+    config::AppConfig,
+    denom,
+    error::{with_timeout, TaskError, TaskResult},
+    rebalance::Redelegation,
+    rpc_pool::{RpcPool, MAX_RETRY_AFTER},
+    sdk::namada::Sdk,
+    utils::{retry_with_backoff, Retryable},
 };
-use tendermint_rpc::HttpClient;
 
+/// The error of a tx the chain applied with the non-zero `code`, with the
+/// chain's `info` and `log` about it.
+fn rejection(code: ResultCode, info: &str, log: &str) -> TaskError {
+    let details = [info, log]
+        .into_iter()
+        .filter(|details| !details.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ");
+    let message = format!("{:?}: {}", code, details);
+
+    match code {
+        ResultCode::FeeError if message.to_ascii_lowercase().contains("insufficient") => {
+            TaskError::LowBalance(message)
+        }
+        ResultCode::FeeError => TaskError::FeeTooLow(message),
+        ResultCode::TxGasLimit => TaskError::GasLimitTooLow(message),
+        _ => TaskError::TxRejected(message),
+    }
+}
+
+/// Gas limit of every claim and bond tx, and of each claim of a batch, also
+/// used to estimate their fees.
+pub const TX_GAS_LIMIT: u64 = 50_000;
+
+/// How many times its gas limit a tx that ran out of gas is resubmitted
+/// with, up to [`MAX_GAS_LIMIT_BUMPS`] times.
+pub const GAS_LIMIT_BUMP: u64 = 2;
+
+/// See [`GAS_LIMIT_BUMP`].
+pub const MAX_GAS_LIMIT_BUMPS: u32 = 2;
+
+/// Gas limit and price a tx is submitted with, see [`submit_with_fee_bump`].
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TxFee {
+    pub gas_limit: u64,
+    /// Price per gas unit in whole fee tokens, `None` at the chain's minimum
+    pub gas_price: Option<f64>,
+    /// Decimals of the fee token
+    pub decimals: u8,
+}
+
+impl TxFee {
+    pub fn at_min_price(gas_limit: u64) -> Self {
+        Self {
+            gas_limit,
+            gas_price: None,
+            decimals: token::NATIVE_MAX_DECIMAL_PLACES,
+        }
+    }
+}
+
+/// Minimum gas price of the `fee_token`, or else the native token, and its
+/// decimals.
+async fn fee_token_min_gas_price<R: NamadaRpc>(
+    rpc: &R,
+    fee_token: Option<&Address>,
+) -> TaskResult<(f64, u8)> {
+    let fee_token = match fee_token {
+        Some(fee_token) => fee_token.clone(),
+        None => rpc.query_native_token().await?,
+    };
+    let min_gas_price = rpc.query_min_gas_price(&fee_token).await?;
+    let decimals = rpc.query_denomination(&fee_token).await?;
+
+    Ok((min_gas_price, decimals))
+}
+
+/// Submit a tx with `submit` at `gas_limit` and the chain's minimum gas
+/// price. If it runs out of gas, submit it again at a [`GAS_LIMIT_BUMP`]
+/// times higher limit, and if it is rejected for its fee, again at each
+/// [`resubmit_gas_prices`] of the `fee_token`, until it lands. A rejected tx
+/// was applied, so unlike a timed out one it can't land twice. `max_fee` and
+/// the `fee_ladder` are in the `fee_token`, or else in NAM.
+pub async fn submit_with_fee_bump<R, F, Fut>(
+    rpc: &R,
+    fee_token: Option<&Address>,
+    max_fee: Option<f64>,
+    fee_ladder: &[f64],
+    gas_limit: u64,
+    submit: F,
+) -> TaskResult<TxReceipt>
+where
+    R: NamadaRpc,
+    F: Fn(TxFee) -> Fut,
+    Fut: Future<Output = TaskResult<TxReceipt>>,
+{
+    let mut fee = TxFee::at_min_price(gas_limit);
+    let mut gas_limit_bumps = 0;
+    let mut gas_prices: Option<std::vec::IntoIter<f64>> = None;
+    let mut rung = 0;
+    loop {
+        match submit(fee).await {
+            Ok(receipt) => {
+                if rung > 0 && !fee_ladder.is_empty() {
+                    tracing::info!(
+                        "Landed at {} per gas unit, rung {} of --fee-ladder",
+                        fee.gas_price.unwrap_or_default(),
+                        rung
+                    );
+                }
+                // Its fee makes it into the average of the past fees
+                return Ok(TxReceipt {
+                    gas_price: fee.gas_price.or(receipt.gas_price),
+                    ..receipt
+                });
+            }
+            Err(TaskError::GasLimitTooLow(err)) => {
+                if gas_limit_bumps == MAX_GAS_LIMIT_BUMPS {
+                    return Err(TaskError::GasLimitTooLow(format!(
+                        "{}, even at a gas limit of {}",
+                        err, fee.gas_limit
+                    )));
+                }
+                let raised = fee.gas_limit * GAS_LIMIT_BUMP;
+                if let Some(max_fee) = max_fee {
+                    let gas_price = match fee.gas_price {
+                        Some(gas_price) => gas_price,
+                        None => fee_token_min_gas_price(rpc, fee_token).await?.0,
+                    };
+                    if gas_price * raised as f64 > max_fee {
+                        return Err(TaskError::GasLimitTooLow(format!(
+                            "{}, and a raised gas limit would exceed the max fee",
+                            err
+                        )));
+                    }
+                }
+                tracing::warn!(
+                    "Resubmitting with a gas limit of {} after the tx ran out of gas",
+                    raised
+                );
+                fee.gas_limit = raised;
+                gas_limit_bumps += 1;
+            }
+            Err(TaskError::FeeTooLow(err)) => {
+                if gas_prices.is_none() {
+                    let (min_gas_price, decimals) = fee_token_min_gas_price(rpc, fee_token).await?;
+                    let resubmit =
+                        resubmit_gas_prices(min_gas_price, fee.gas_limit, max_fee, fee_ladder);
+                    if resubmit.is_empty() {
+                        return Err(TaskError::FeeTooLow(if fee_ladder.is_empty() {
+                            format!("{}, and a bumped fee would exceed the max fee", err)
+                        } else {
+                            format!("{}, and no --fee-ladder fee is above the minimum", err)
+                        }));
+                    }
+                    gas_prices = Some(resubmit.into_iter());
+                    fee.decimals = decimals;
+                }
+                // The gas limit may have been raised since
+                let gas_limit = fee.gas_limit;
+                let Some(gas_price) = gas_prices.iter_mut().flatten().find(|gas_price| {
+                    max_fee.map_or(true, |max_fee| gas_price * gas_limit as f64 <= max_fee)
+                }) else {
+                    return Err(TaskError::FeeTooLow(err));
+                };
+                tracing::warn!(
+                    "Resubmitting at {} per gas unit after the tx was rejected for its fee",
+                    gas_price
+                );
+                fee.gas_price = Some(gas_price);
+                rung += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+/// How much more than the chain's minimum gas price a tx rejected for its
+/// fee pays when it is resubmitted.
+pub const FEE_BUMP: f64 = 1.5;
+
+/// Gas price to resubmit a tx with `gas_limit` rejected for its fee at,
+/// `None` if its fee would then exceed `max_fee`.
+pub fn bumped_gas_price(min_gas_price: f64, gas_limit: u64, max_fee: Option<f64>) -> Option<f64> {
+    let gas_price = min_gas_price * FEE_BUMP;
+    let fee = gas_price * gas_limit as f64;
+    (fee.is_finite() && max_fee.map_or(true, |max_fee| fee <= max_fee)).then_some(gas_price)
+}
+
+/// Gas prices to resubmit a tx rejected for its fee at, in order: those of
+/// the `--fee-ladder` fees above the chain's minimum, or without a ladder
+/// the [`bumped_gas_price`], if any.
+pub fn resubmit_gas_prices(
+    min_gas_price: f64,
+    gas_limit: u64,
+    max_fee: Option<f64>,
+    ladder: &[f64],
+) -> Vec<f64> {
+    if ladder.is_empty() {
+        return bumped_gas_price(min_gas_price, gas_limit, max_fee)
+            .into_iter()
+            .collect();
+    }
+
+    ladder
+        .iter()
+        .map(|fee| fee / gas_limit as f64)
+        .filter(|gas_price| *gas_price > min_gas_price)
+        .collect()
+}
+
+/// Wallet alias of the delegator key, and the name of the account when not
+/// using `--accounts-file`.
+pub const KEY_ALIAS: &str = "autocompound";
+
+/// A tx applied on chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxReceipt {
+    pub hash: String,
+    pub gas_used: u64,
+    /// Price per gas unit it paid once its fee was bumped, `None` at the
+    /// chain's minimum
+    pub gas_price: Option<f64>,
+}
+
+/// How the claim from one validator went, see [`NamadaRpc::claim_rewards`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClaimResult {
+    pub validator: Address,
+    /// Its pending rewards right before the claim, `None` if unknown
+    pub rewards: Option<token::Amount>,
+    /// The claim tx, shared by the validators of a batch, or why it failed
+    pub outcome: Result<TxReceipt, String>,
+    /// The claim tx timed out, so it may still land and isn't reported as
+    /// failed
+    pub timed_out: bool,
+}
+
+impl ClaimResult {
+    /// One structured line, so the results of a round can be told apart
+    /// per validator in the JSON logs.
+    fn log(&self) {
+        let rewards = self
+            .rewards
+            .map_or_else(|| "unknown".to_string(), denom::display_nam);
+        match &self.outcome {
+            Ok(receipt) => tracing::info!(
+                validator = %self.validator,
+                rewards = %rewards,
+                tx = %receipt.hash,
+                "Claimed {} from {}",
+                rewards,
+                self.validator
+            ),
+            Err(err) => tracing::warn!(
+                validator = %self.validator,
+                rewards = %rewards,
+                error = %err,
+                "Claim from {} failed: {}",
+                self.validator,
+                err
+            ),
+        }
+    }
+}
+
+/// The claims of a round, by validator in address order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClaimReport {
+    pub results: Vec<ClaimResult>,
+}
+
+impl ClaimReport {
+    /// Receipts of the claims that went through, once per tx.
+    pub fn receipts(&self) -> Vec<TxReceipt> {
+        let mut receipts: Vec<TxReceipt> = vec![];
+        for receipt in self
+            .results
+            .iter()
+            .filter_map(|result| result.outcome.as_ref().ok())
+        {
+            if !receipts.iter().any(|known| known.hash == receipt.hash) {
+                receipts.push(receipt.clone());
+            }
+        }
+        receipts
+    }
+
+    /// Validators claimed from.
+    pub fn claimed(&self) -> BTreeSet<Address> {
+        self.results
+            .iter()
+            .filter(|result| result.outcome.is_ok())
+            .map(|result| result.validator.clone())
+            .collect()
+    }
+
+    /// Claims that didn't go through, those that timed out aside.
+    pub fn failed(&self) -> Vec<&ClaimResult> {
+        self.results
+            .iter()
+            .filter(|result| result.outcome.is_err() && !result.timed_out)
+            .collect()
+    }
+
+    /// Validators whose claim timed out, which may still land.
+    pub fn unconfirmed(&self) -> BTreeSet<Address> {
+        self.results
+            .iter()
+            .filter(|result| result.timed_out)
+            .map(|result| result.validator.clone())
+            .collect()
+    }
+
+    /// Rewards of the validators claimed from, `None` if any is unknown.
+    pub fn claimed_rewards(&self) -> Option<token::Amount> {
+        self.results
+            .iter()
+            .filter(|result| result.outcome.is_ok())
+            .try_fold(token::Amount::zero(), |total, result| {
+                total.checked_add(result.rewards?)
+            })
+    }
+}
+
+/// E.g. `claimed from 3/5 validators, 1 failed: <address> (<reason>), 1
+/// unconfirmed: <address>`.
+impl Display for ClaimReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let failed = self.failed();
+        let unconfirmed = self.unconfirmed();
+        write!(
+            f,
+            "claimed from {}/{} validators",
+            self.claimed().len(),
+            self.results.len()
+        )?;
+
+        if !failed.is_empty() {
+            write!(f, ", {} failed: ", failed.len())?;
+            for (i, result) in failed.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                let reason = result.outcome.as_ref().err().map_or("", String::as_str);
+                write!(f, "{} ({})", result.validator, reason)?;
+            }
+        }
+        if !unconfirmed.is_empty() {
+            write!(f, ", {} unconfirmed: ", unconfirmed.len())?;
+            for (i, validator) in unconfirmed.iter().enumerate() {
+                if i > 0 {
+                    f.write_str(", ")?;
+                }
+                write!(f, "{}", validator)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Txs are submitted in the order given, each once the previous one is in a
+/// block: the txs of an account draw on the same fee balance and the chain
+/// takes them in arrival order, so concurrent ones could fail one another.
+/// Only different accounts may submit concurrently. Validator sets are
+/// ordered by address, so the txs and logs go over them in the same order
+/// on every run.
 pub trait NamadaRpc {
-    async fn get_current_epoch(&self) -> anyhow::Result<u64>;
+    /// How many queries may be in flight at once when fanning out over the
+    /// validator set.
+    fn rpc_concurrency(&self) -> usize {
+        20
+    }
+
+    /// Answer the queries from the same endpoint until unpinned, rather than
+    /// rotating to another one on failures.
+    fn pin_endpoint(&self, _pinned: bool) {}
+
+    /// Point subsequent queries at the healthiest, most up-to-date endpoint.
+    async fn select_endpoint(&self) {}
+
+    async fn get_current_epoch(&self) -> TaskResult<u64>;
+
+    /// How long until the next epoch, going by when the current one started
+    /// and the minimum epoch duration. Zero if it's overdue.
+    async fn time_to_next_epoch(&self) -> TaskResult<Duration>;
 
-    async fn get_pos_inflation_rate(&self) -> anyhow::Result<f64>;
+    /// Minimum duration of an epoch, per the chain parameters.
+    async fn epoch_duration(&self) -> TaskResult<Duration>;
+
+    async fn get_pos_inflation_rate(&self) -> TaskResult<f64>;
 
     async fn get_delegators_validators(
         &self,
         address: &Address,
         epoch: u64,
-    ) -> anyhow::Result<HashSet<Address>>;
+    ) -> TaskResult<BTreeSet<Address>>;
 
-    async fn query_native_token(&self) -> anyhow::Result<Address>;
+    async fn query_native_token(&self) -> TaskResult<Address>;
 
     async fn query_pos_rewards(
         &self,
-        validators: &HashSet<Address>,
+        validators: &BTreeSet<Address>,
         delegator_address: &Address,
-    ) -> anyhow::Result<f64>;
+    ) -> TaskResult<token::Amount>;
 
     async fn query_bond(
         &self,
         validator: &Address,
         delegator: &Address,
         epoch: u64,
-    ) -> anyhow::Result<f64>;
+    ) -> TaskResult<f64>;
 
+    /// Validators whose bond can't be fetched are left out with a warning,
+    /// rather than counted as zero, unless `strict`, which fails instead.
     async fn query_bonds(
         &self,
-        validators: &HashSet<Address>,
+        validators: &BTreeSet<Address>,
         delegator: &Address,
         epoch: u64,
-    ) -> anyhow::Result<Vec<f64>> {
-        let bonds = futures::stream::iter(validators)
+        strict: bool,
+    ) -> TaskResult<HashMap<Address, f64>> {
+        // Owned, as a future borrowing its validator from the stream can't be
+        // told to be `Send`, which the control server needs
+        let bonds = futures::stream::iter(validators.iter().cloned())
             .map(|validator_address| async move {
-                self.query_bond(validator_address, delegator, epoch)
-                    .await
-                    .unwrap_or_default()
+                let bond = self.query_bond(&validator_address, delegator, epoch).await;
+                (validator_address, bond)
             })
-            .buffer_unordered(20)
+            .buffer_unordered(self.rpc_concurrency())
             .collect::<Vec<_>>()
             .await;
 
-        Ok(bonds)
+        per_validator(bonds, strict, "bond")
     }
 
     async fn query_balance(
         &self,
         address: &Address,
         native_token_address: &Address,
-    ) -> anyhow::Result<token::Amount>;
+    ) -> TaskResult<token::Amount>;
+
+    /// Minimum gas price accepted by the chain for `token`, in whole tokens
+    /// per gas unit.
+    async fn query_min_gas_price(&self, token: &Address) -> TaskResult<f64>;
 
+    /// Decimals of `token`, e.g. 6 for NAM.
+    async fn query_denomination(&self, token: &Address) -> TaskResult<u8>;
+
+    /// With `batch`, claim from every validator in a single tx, falling back
+    /// to one tx per validator, in address order, if the SDK can't build the
+    /// batch. A validator failing to claim from doesn't stop the others: it
+    /// is only an error if none went through.
     async fn claim_rewards(
         &self,
         delegator_address: &Address,
-        validators: &HashSet<Address>,
+        validators: &BTreeSet<Address>,
         secret_key: &SecretKey,
-    ) -> anyhow::Result<()>;
+        batch: bool,
+    ) -> TaskResult<ClaimReport>;
 
+    /// Bond each amount to its validator, see [`crate::compound::bond_distribution`].
     async fn bond(
         &self,
         delegator_address: &Address,
-        validators: &HashSet<Address>,
+        distribution: &[(Address, token::Amount)],
+        secret_key: &SecretKey,
+    ) -> TaskResult<Vec<TxReceipt>>;
+
+    /// Redelegate each amount from its source to its destination validator,
+    /// see [`crate::rebalance::plan_redelegations`].
+    async fn redelegate(
+        &self,
+        delegator_address: &Address,
+        redelegations: &[Redelegation],
+        secret_key: &SecretKey,
+    ) -> TaskResult<Vec<TxReceipt>>;
+
+    /// Transparent transfer of `amount` of the native token, for
+    /// `--reward-receiver`.
+    async fn transfer(
+        &self,
+        source: &Address,
+        target: &Address,
         amount: token::Amount,
         secret_key: &SecretKey,
-    ) -> anyhow::Result<()>;
+    ) -> TaskResult<TxReceipt>;
 
-    async fn query_validator_commissions(
+    /// Build and sign, but never submit, a claim from each of `validators`
+    /// and the bonds of `distribution`, for `--validate-txs`.
+    async fn validate_txs(
+        &self,
+        delegator_address: &Address,
+        validators: &BTreeSet<Address>,
+        distribution: &[(Address, token::Amount)],
+        secret_key: &SecretKey,
+    ) -> TaskResult<()>;
+
+    async fn query_validator_commissions(&self, validator: &Address, epoch: u64)
+        -> TaskResult<f64>;
+
+    /// `None` if the address isn't a validator at `epoch`.
+    async fn query_validator_state(
+        &self,
+        validator: &Address,
+        epoch: u64,
+    ) -> TaskResult<Option<ValidatorState>>;
+
+    /// Validators with slashes enqueued but not processed yet.
+    async fn query_pending_slashes(&self) -> TaskResult<BTreeSet<Address>>;
+
+    /// Share of the blocks of the chain's liveness window each consensus
+    /// validator signed.
+    async fn query_uptime(&self) -> TaskResult<HashMap<Address, f64>>;
+
+    /// Consensus validators at `epoch` with their bonded stake, in NAM.
+    async fn query_consensus_validators(&self, epoch: u64) -> TaskResult<Vec<(Address, f64)>>;
+
+    /// Total stake bonded to `validator` at `epoch`, in NAM.
+    async fn query_validator_stake(&self, validator: &Address, epoch: u64) -> TaskResult<f64>;
+
+    /// Epochs until a bond takes effect, per the PoS parameters.
+    async fn pipeline_len(&self) -> TaskResult<u64>;
+
+    /// Whether `delegator` is unbonding from `validator`, i.e. has unbonds not
+    /// withdrawable yet at `epoch`.
+    async fn has_pending_unbonds(
+        &self,
+        delegator: &Address,
+        validator: &Address,
+        epoch: u64,
+    ) -> TaskResult<bool>;
+
+    /// The epoch from which the stake `delegator` redelegated to `validator`
+    /// can be redelegated again, `None` if that's already the case at
+    /// `epoch`. The chain refuses redelegating it while it is still
+    /// slashable for the source validator.
+    async fn redelegation_cooldown(
         &self,
+        delegator: &Address,
         validator: &Address,
         epoch: u64,
-    ) -> anyhow::Result<f64>;
+    ) -> TaskResult<Option<u64>>;
 
+    /// Validators whose commission can't be fetched, e.g. not set yet right
+    /// after their creation, are left out with a warning, unless `strict`.
     async fn query_validators_commissions(
         &self,
-        validators: &HashSet<Address>,
+        validators: &BTreeSet<Address>,
         epoch: u64,
-    ) -> anyhow::Result<Vec<f64>> {
-        let commissions = futures::stream::iter(validators)
+        strict: bool,
+    ) -> TaskResult<HashMap<Address, f64>> {
+        // Owned, see `query_bonds`
+        let commissions = futures::stream::iter(validators.iter().cloned())
             .map(|address| async move {
-                self.query_validator_commissions(address, epoch)
-                    .await
-                    .unwrap_or_default()
+                let commission = self.query_validator_commissions(&address, epoch).await;
+                (address, commission)
             })
-            .buffer_unordered(20)
+            .buffer_unordered(self.rpc_concurrency())
             .collect::<Vec<_>>()
             .await;
 
-        Ok(commissions)
+        per_validator(commissions, strict, "commission")
     }
 
-    fn amount_to_f64(amount: token::Amount) -> anyhow::Result<f64> {
-        amount
+    /// Lossy above [`crate::opt::MAX_EXACT_AMOUNT`], never `inf` or `NaN`.
+    fn amount_to_f64(amount: token::Amount) -> TaskResult<f64> {
+        let value = amount
             .to_string_native()
             .parse::<f64>()
-            .context("Invalid convertion from amount to f64")
+            .map_err(|err| TaskError::InvalidResponse(format!("Invalid amount: {}", err)))?;
+        finite(value, "amount")
     }
 
-    fn dec_to_f64(amount: Dec) -> anyhow::Result<f64> {
-        amount
+    fn dec_to_f64(amount: Dec) -> TaskResult<f64> {
+        let value = amount
             .to_string()
             .parse::<f64>()
-            .context("Invalid convertion from dec to f64")
+            .map_err(|err| TaskError::InvalidResponse(format!("Invalid decimal: {}", err)))?;
+        finite(value, "decimal")
     }
 
     fn to_sdk_epoch(epoch: u64) -> NamadaEpoch {
@@ -117,22 +621,484 @@ pub trait NamadaRpc {
     }
 }
 
+/// The values of the validators whose query succeeded. With `strict`, the
+/// first failure instead.
+fn per_validator(
+    results: Vec<(Address, TaskResult<f64>)>,
+    strict: bool,
+    what: &str,
+) -> TaskResult<HashMap<Address, f64>> {
+    let mut values = HashMap::with_capacity(results.len());
+    for (validator, res) in results {
+        match res {
+            Ok(value) => {
+                values.insert(validator, value);
+            }
+            Err(err) if strict => return Err(err),
+            Err(err) => tracing::warn!("Skipping the {} of {}: {}", what, validator, err),
+        }
+    }
+
+    Ok(values)
+}
+
+/// Reject the values that would poison the optimizer.
+fn finite(value: f64, what: &str) -> TaskResult<f64> {
+    if value.is_finite() {
+        Ok(value)
+    } else {
+        Err(TaskError::InvalidResponse(format!(
+            "The {} doesn't fit in a f64: {}",
+            what, value
+        )))
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct NamadaSdk {
-    client: HttpClient,
+    pool: Arc<RpcPool>,
+    retry_attempts: u32,
+    retry_base_delay: Duration,
+    wallet_dir: PathBuf,
+    overwrite_wallet: bool,
+    tx_timeout: Duration,
+    rpc_timeout: Duration,
+    rpc_concurrency: usize,
+    /// Fan-out actually allowed, cut when an endpoint throttles us and
+    /// recovering towards `rpc_concurrency` as queries go through
+    concurrency: Arc<AtomicUsize>,
+    native_token: Arc<OnceCell<Address>>,
+    /// SDK context of each endpoint index and delegator public key, built on
+    /// first use since it loads the wallet and queries the chain id
+    contexts: Arc<Mutex<HashMap<(usize, String), Arc<Sdk>>>>,
+    fee_token: Option<Address>,
+    max_fee: Option<f64>,
+    memo: Option<String>,
+    inter_tx_delay: Duration,
+    fee_ladder: Vec<f64>,
 }
 
 impl NamadaSdk {
-    pub fn new(client: HttpClient) -> Self {
-        Self { client }
+    pub fn new(
+        pool: RpcPool,
+        retry_attempts: u32,
+        retry_base_delay: Duration,
+        wallet_dir: PathBuf,
+        tx_timeout: Duration,
+        rpc_timeout: Duration,
+        rpc_concurrency: usize,
+    ) -> Self {
+        Self {
+            pool: Arc::new(pool),
+            retry_attempts,
+            retry_base_delay,
+            wallet_dir,
+            overwrite_wallet: false,
+            tx_timeout,
+            rpc_timeout,
+            rpc_concurrency,
+            concurrency: Arc::new(AtomicUsize::new(rpc_concurrency.max(1))),
+            native_token: Arc::new(OnceCell::new()),
+            contexts: Arc::new(Mutex::new(HashMap::new())),
+            fee_token: None,
+            max_fee: None,
+            memo: None,
+            inter_tx_delay: Duration::ZERO,
+            fee_ladder: vec![],
+        }
+    }
+
+    /// Talk to `pool` with the retries, timeouts and tx options of `config`.
+    pub fn from_config(pool: RpcPool, config: &AppConfig) -> Self {
+        Self::new(
+            pool,
+            config.retry_attempts,
+            Duration::from_millis(config.retry_base_delay_ms),
+            config.wallet_dir.clone(),
+            Duration::from_secs(config.tx_timeout_secs),
+            Duration::from_secs(config.rpc_timeout_secs),
+            config.rpc_concurrency,
+        )
+        .overwrite_wallet(config.overwrite_wallet)
+        .fee_token(config.fee_token.clone())
+        .max_fee(config.max_tx_fee())
+        .memo(config.tx_memo.clone())
+        .inter_tx_delay(Duration::from_millis(config.inter_tx_delay_ms))
+        .fee_ladder(config.fee_ladder.clone())
+    }
+
+    /// Let the delegator key replace whatever the wallet holds under
+    /// [`KEY_ALIAS`], see `--overwrite-wallet`.
+    pub fn overwrite_wallet(mut self, overwrite: bool) -> Self {
+        self.overwrite_wallet = overwrite;
+        self
+    }
+
+    /// Pay the fees of every tx in `fee_token` instead of the native token,
+    /// see `--fee-token`.
+    pub fn fee_token(mut self, fee_token: Option<Address>) -> Self {
+        self.fee_token = fee_token;
+        self
+    }
+
+    /// Don't bump the fee of a tx past `max_fee`, in the token paying it, see
+    /// [`AppConfig::max_tx_fee`].
+    pub fn max_fee(mut self, max_fee: Option<f64>) -> Self {
+        self.max_fee = max_fee;
+        self
+    }
+
+    /// Resubmit a tx rejected for its fee at each of the `fee_ladder` fees
+    /// in turn, see `--fee-ladder`.
+    pub fn fee_ladder(mut self, fee_ladder: Vec<f64>) -> Self {
+        self.fee_ladder = fee_ladder;
+        self
+    }
+
+    /// Tag the txs with `memo`, see `--tx-memo`.
+    pub fn memo(mut self, memo: Option<String>) -> Self {
+        self.memo = memo;
+        self
+    }
+
+    /// Space out the txs of a claim or bond by `delay`, see
+    /// `--inter-tx-delay-ms`.
+    pub fn inter_tx_delay(mut self, delay: Duration) -> Self {
+        self.inter_tx_delay = delay;
+        self
+    }
+
+    /// Wait `--inter-tx-delay-ms` before every tx but the first `submitted`
+    /// one.
+    async fn pace(&self, submitted: usize) {
+        if submitted > 0 && !self.inter_tx_delay.is_zero() {
+            tokio::time::sleep(self.inter_tx_delay).await;
+        }
+    }
+
+    /// `builder` with the `--tx-memo`, if any, paying its fees in the
+    /// `--fee-token`, if any, at the gas limit and price of `fee`.
+    fn with_tx_args<B: TxBuilder<SdkTypes>>(&self, builder: B, fee: TxFee) -> B {
+        let builder = builder.gas_limit(GasLimit::from(fee.gas_limit));
+        let builder = match &self.memo {
+            Some(memo) => builder.memo(memo.as_bytes().to_vec()),
+            None => builder,
+        };
+        let builder = match &self.fee_token {
+            Some(fee_token) => builder.fee_token(fee_token.clone()),
+            None => builder,
+        };
+        match fee
+            .gas_price
+            .and_then(|gas_price| denom::gas_price_amount(gas_price, fee.decimals))
+        {
+            Some(gas_price) => builder.fee_amount(args::InputAmount::Unvalidated(gas_price)),
+            None => builder,
+        }
+    }
+
+    /// See [`submit_with_fee_bump`], with the `--fee-token`, its max fee and
+    /// the `--fee-ladder`.
+    async fn with_fee_bump<F, Fut>(&self, gas_limit: u64, submit: F) -> TaskResult<TxReceipt>
+    where
+        F: Fn(TxFee) -> Fut,
+        Fut: Future<Output = TaskResult<TxReceipt>>,
+    {
+        submit_with_fee_bump(
+            self,
+            self.fee_token.as_ref(),
+            self.max_fee,
+            &self.fee_ladder,
+            gas_limit,
+            submit,
+        )
+        .await
+    }
+
+    /// SDK context holding `secret_key` against the active endpoint, built
+    /// once per endpoint and key.
+    async fn sdk(&self, secret_key: &SecretKey) -> TaskResult<Arc<Sdk>> {
+        let (index, client) = self.pool.client();
+        let key = (index, secret_key.to_public().to_string());
+        let mut contexts = self.contexts.lock().await;
+        if let Some(sdk) = contexts.get(&key) {
+            return Ok(sdk.clone());
+        }
+
+        let sdk = Arc::new(
+            Sdk::new(
+                client,
+                &self.wallet_dir,
+                secret_key,
+                KEY_ALIAS,
+                self.overwrite_wallet,
+            )
+            .await?,
+        );
+        contexts.insert(key, sdk.clone());
+        Ok(sdk)
+    }
+
+    /// Shielded native token balance of `viewing_key`, see
+    /// [`Sdk::shielded_balance`]. The sync may take a while on the first run,
+    /// so unlike queries it isn't bounded by `--rpc-timeout-secs`.
+    pub async fn query_shielded_balance(
+        &self,
+        viewing_key: &ExtendedViewingKey,
+        secret_key: &SecretKey,
+    ) -> TaskResult<token::Amount> {
+        let native_token = self.native_token().await?;
+        let sdk = self.sdk(secret_key).await?;
+
+        sdk.shielded_balance(viewing_key, &native_token).await
+    }
+
+    /// The chain's native token, fetched on first use and cached afterwards
+    /// since it never changes for a given chain.
+    pub async fn native_token(&self) -> TaskResult<Address> {
+        self.native_token
+            .get_or_try_init(|| {
+                self.call(1, |client| async move {
+                    rpc::query_native_token(&client)
+                        .await
+                        .map_err(TaskError::rpc("Error fetching native token"))
+                })
+            })
+            .await
+            .cloned()
+    }
+
+    /// Sign and broadcast `tx`, then wait until it is applied on chain and
+    /// return its receipt. A batch has the signing data of each of its inner
+    /// txs.
+    ///
+    /// This is never retried: a tx that timed out may still land, and
+    /// resubmitting a bond would stake the rewards twice.
+    async fn sign_and_submit(
+        &self,
+        sdk: &Sdk,
+        mut tx: Tx,
+        args: &args::Tx,
+        signing_data: Vec<SigningTxData>,
+    ) -> TaskResult<TxReceipt> {
+        // Fail with a clear error rather than a generic signing one if the
+        // wallet can't hand out the delegator key
+        sdk.find_secret_key().await?;
+
+        for signing_data in signing_data {
+            sdk.namada
+                .sign(&mut tx, args, signing_data, default_sign, ())
+                .await
+                .map_err(|err| TaskError::InvalidKey(format!("Can't sign: {}", err)))?;
+        }
+
+        let response = with_timeout(self.tx_timeout, async {
+            sdk.namada
+                .submit(tx, args)
+                .await
+                .map_err(|err| TaskError::TxRejected(err.to_string()))
+        })
+        .await?;
+
+        match response {
+            ProcessTxResponse::Applied(resp) if matches!(resp.code, ResultCode::Ok) => {
+                Ok(TxReceipt {
+                    hash: resp.hash,
+                    gas_used: u64::from(resp.gas_used),
+                    gas_price: None,
+                })
+            }
+            ProcessTxResponse::Applied(resp) => {
+                let err = rejection(resp.code, &resp.info, &resp.log);
+                tracing::warn!("Tx {} was rejected: {}", resp.hash, err);
+                Err(err)
+            }
+            ProcessTxResponse::Broadcast(resp) if resp.code.is_err() => {
+                Err(TaskError::TxRejected(format!(
+                    "broadcast failed with code {}: {}",
+                    resp.code.value(),
+                    resp.log
+                )))
+            }
+            _ => Err(TaskError::TxRejected(
+                "tx was broadcast but not applied".to_string(),
+            )),
+        }
+    }
+
+    /// Claim from every validator in a single wrapper tx, paying one fee.
+    async fn claim_rewards_batch(
+        &self,
+        delegator_address: &Address,
+        validators: &BTreeSet<Address>,
+        secret_key: &SecretKey,
+    ) -> TaskResult<TxReceipt> {
+        // Each inner tx uses up its own gas
+        let gas_limit = TX_GAS_LIMIT.saturating_mul(validators.len().max(1) as u64);
+        let receipt = self
+            .with_fee_bump(gas_limit, |fee| async move {
+                let (sdk, args, tx, signing_data) = self
+                    .call(self.retry_attempts, |_| async move {
+                        let sdk = self.sdk(secret_key).await?;
+                        let public_key = sdk.find_public_key().await?;
+
+                        let mut args = None;
+                        let mut txs = Vec::with_capacity(validators.len());
+                        for validator in validators {
+                            let claim = sdk
+                                .namada
+                                .new_claim_rewards(validator.clone())
+                                .source(delegator_address.clone())
+                                .signing_keys(vec![public_key.clone()])
+                                .wrapper_fee_payer(public_key.clone());
+                            let claim = self.with_tx_args(claim, fee);
+
+                            txs.push(
+                                claim
+                                    .build(&sdk.namada)
+                                    .await
+                                    .map_err(|err| TaskError::TxBuildFailed(err.to_string()))?,
+                            );
+                            args = Some(claim.tx);
+                        }
+
+                        let args = args.ok_or_else(|| {
+                            TaskError::TxBuildFailed("no validator to claim from".to_string())
+                        })?;
+                        let (tx, signing_data) = build_batch(txs)
+                            .map_err(|err| TaskError::TxBuildFailed(err.to_string()))?;
+
+                        Ok((sdk, args, tx, signing_data))
+                    })
+                    .await?;
+
+                self.sign_and_submit(&sdk, tx, &args, signing_data).await
+            })
+            .await?;
+
+        Ok(receipt)
+    }
+
+    /// Pending rewards of `delegator_address` from each of `validators`,
+    /// `None` if they can't be fetched.
+    async fn validator_rewards(
+        &self,
+        validators: &BTreeSet<Address>,
+        delegator_address: &Address,
+    ) -> TaskResult<BTreeMap<Address, Option<token::Amount>>> {
+        let (_, client) = self.pool.client();
+        let client = &client;
+
+        // Owned, see `NamadaRpc::query_bonds`
+        let rewards = futures::stream::iter(validators.iter().cloned())
+            .map(|validator_address| {
+                let delegator_address_clone = delegator_address.clone();
+                async move {
+                    let rewards = RPC
+                        .vp()
+                        .pos()
+                        .rewards(client, &validator_address, &Some(delegator_address_clone))
+                        .await
+                        .ok();
+                    (validator_address, rewards)
+                }
+            })
+            .buffer_unordered(self.rpc_concurrency())
+            .collect::<BTreeMap<_, _>>()
+            .map(Ok);
+
+        with_timeout(self.rpc_timeout, rewards).await
+    }
+
+    /// Run `f` against the active endpoint, rotating to another endpoint on
+    /// transient failures and retrying up to `attempts` times.
+    async fn call<F, Fut, T>(&self, attempts: u32, f: F) -> TaskResult<T>
+    where
+        F: Fn(HttpClient) -> Fut,
+        Fut: Future<Output = TaskResult<T>>,
+    {
+        retry_with_backoff(attempts, self.retry_base_delay, || {
+            let (index, client) = self.pool.client();
+            let res = with_timeout(self.rpc_timeout, f(client));
+            async move {
+                let res = match res.await {
+                    Err(TaskError::RateLimited {
+                        message,
+                        retry_after,
+                    }) => {
+                        // Honors a Retry-After, if the node's answer had one,
+                        // or else backs off as usual
+                        self.throttle();
+                        Err(TaskError::RateLimited {
+                            message,
+                            retry_after: retry_after
+                                .map(|retry_after| retry_after.min(MAX_RETRY_AFTER)),
+                        })
+                    }
+                    res => res,
+                };
+                match &res {
+                    Ok(_) => {
+                        self.pool.report_success(index);
+                        self.recover_concurrency();
+                    }
+                    Err(err) if err.is_retryable() => self.pool.report_failure(index),
+                    Err(_) => {}
+                }
+                res
+            }
+        })
+        .await
+    }
+
+    /// Halve the fan-out of the next queries, the endpoint is rate limiting
+    /// us.
+    fn throttle(&self) {
+        let previous = self
+            .concurrency
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |concurrency| {
+                Some((concurrency / 2).max(1))
+            })
+            .unwrap_or_default();
+        tracing::warn!(
+            "RPC {} is rate limiting, down to {} concurrent queries",
+            self.pool.active_url(),
+            (previous / 2).max(1)
+        );
+    }
+
+    /// Allow one more concurrent query, up to `--rpc-concurrency`.
+    fn recover_concurrency(&self) {
+        let _ =
+            self.concurrency
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |concurrency| {
+                    (concurrency < self.rpc_concurrency).then_some(concurrency + 1)
+                });
     }
 }
 
 impl NamadaRpc for NamadaSdk {
-    async fn get_pos_inflation_rate(&self) -> anyhow::Result<f64> {
-        let pos_inflation = rpc::get_staking_rewards_rate(&self.client)
-            .await
-            .context("Failed fetching staking rewards")?;
+    fn rpc_concurrency(&self) -> usize {
+        self.concurrency.load(Ordering::Relaxed)
+    }
+
+    fn pin_endpoint(&self, pinned: bool) {
+        self.pool.pin(pinned);
+    }
+
+    async fn select_endpoint(&self) {
+        self.pool.select_freshest().await;
+        tracing::debug!("Active RPC endpoint: {}", self.pool.active_url());
+    }
+
+    async fn get_pos_inflation_rate(&self) -> TaskResult<f64> {
+        let pos_inflation = self
+            .call(self.retry_attempts, |client| async move {
+                rpc::get_staking_rewards_rate(&client)
+                    .await
+                    .map_err(TaskError::rpc("Failed fetching staking rewards"))
+            })
+            .await?;
         Self::dec_to_f64(pos_inflation.inflation_rate)
     }
 
@@ -140,79 +1106,448 @@ impl NamadaRpc for NamadaSdk {
         &self,
         address: &Address,
         epoch: u64,
-    ) -> anyhow::Result<HashSet<Address>> {
+    ) -> TaskResult<BTreeSet<Address>> {
         let epoch = Self::to_sdk_epoch(epoch);
-        let index_set = rpc::get_delegation_validators(&self.client, address, epoch)
-            .await
-            .context("Failed fetching validators")?;
-        Ok(index_set.into_iter().collect::<HashSet<_>>())
+        let index_set = self
+            .call(1, |client| async move {
+                rpc::get_delegation_validators(&client, address, epoch)
+                    .await
+                    .map_err(TaskError::rpc("Failed fetching validators"))
+            })
+            .await?;
+        Ok(index_set.into_iter().collect::<BTreeSet<_>>())
     }
 
     async fn query_pos_rewards(
         &self,
-        validators: &HashSet<Address>,
+        validators: &BTreeSet<Address>,
         delegator_address: &Address,
-    ) -> anyhow::Result<f64> {
-        futures::stream::iter(validators)
-            .map(|validator_address| {
-                let delegator_address_clone = delegator_address.clone();
-                async move {
-                    RPC.vp()
-                        .pos()
-                        .rewards(
-                            &self.client,
-                            validator_address,
-                            &Some(delegator_address_clone),
-                        )
-                        .await
-                        .unwrap_or_default()
-                }
+    ) -> TaskResult<token::Amount> {
+        let rewards = self
+            .validator_rewards(validators, delegator_address)
+            .await?;
+        rewards
+            .into_iter()
+            .try_fold(token::Amount::zero(), |acc, (validator, amount)| {
+                let amount = amount.ok_or_else(|| {
+                    TaskError::RpcUnavailable(format!(
+                        "Error fetching the rewards from {}",
+                        validator
+                    ))
+                })?;
+                Ok(acc.checked_add(amount).unwrap())
             })
-            .buffer_unordered(20)
-            .fold(token::Amount::zero(), |acc, amount| async move {
-                acc.checked_add(amount).unwrap()
+    }
+
+    async fn get_current_epoch(&self) -> TaskResult<u64> {
+        self.call(self.retry_attempts, |client| async move {
+            rpc::query_epoch(&client)
+                .await
+                .map_err(TaskError::rpc("Error fetching epoch"))
+                .map(|epoch| epoch.0)
+        })
+        .await
+    }
+
+    async fn time_to_next_epoch(&self) -> TaskResult<Duration> {
+        let min_duration = self.epoch_duration().await?.as_secs();
+        let started_at = self
+            .call(self.retry_attempts, |client| async move {
+                let start_height = RPC
+                    .shell()
+                    .first_block_height_of_current_epoch(&client)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching epoch start height"))?;
+                let start_height = u32::try_from(start_height.0).map_err(|_| {
+                    TaskError::InvalidResponse(format!("Invalid block height {}", start_height))
+                })?;
+                let start_block = client
+                    .block(start_height)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching epoch start block"))?;
+                Ok(start_block.block.header.time.unix_timestamp())
             })
-            .map(Self::amount_to_f64)
-            .await
-            .context("Error fetching bonds")
+            .await?;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        let elapsed = now.saturating_sub(u64::try_from(started_at).unwrap_or_default());
+        Ok(Duration::from_secs(min_duration.saturating_sub(elapsed)))
     }
 
-    async fn get_current_epoch(&self) -> anyhow::Result<u64> {
-        rpc::query_epoch(&self.client)
-            .await
-            .context("Error fetching epoch")
-            .map(|epoch| epoch.0)
+    async fn epoch_duration(&self) -> TaskResult<Duration> {
+        let epoch_duration = self
+            .call(self.retry_attempts, |client| async move {
+                rpc::query_storage_value::<_, parameters::EpochDuration>(
+                    &client,
+                    &parameters::storage::get_epoch_duration_storage_key(),
+                )
+                .await
+                .map_err(TaskError::rpc("Error fetching epoch duration"))
+            })
+            .await?;
+        Ok(Duration::from_secs(epoch_duration.min_duration.0))
     }
 
     async fn query_balance(
         &self,
         address: &Address,
         native_token_address: &Address,
-    ) -> anyhow::Result<token::Amount> {
-        rpc::get_token_balance(&self.client, native_token_address, address, None)
-            .await
-            .context("Error fetching balance")
+    ) -> TaskResult<token::Amount> {
+        self.call(self.retry_attempts, |client| async move {
+            rpc::get_token_balance(&client, native_token_address, address, None)
+                .await
+                .map_err(TaskError::rpc("Error fetching balance"))
+        })
+        .await
+    }
+
+    async fn query_min_gas_price(&self, token: &Address) -> TaskResult<f64> {
+        let gas_prices = self
+            .call(1, |client| async move {
+                rpc::query_storage_value::<_, BTreeMap<Address, token::Amount>>(
+                    &client,
+                    &parameters::storage::get_gas_cost_key(),
+                )
+                .await
+                .map_err(TaskError::rpc("Error fetching minimum gas prices"))
+            })
+            .await?;
+
+        let gas_price = gas_prices.get(token).ok_or_else(|| {
+            TaskError::InvalidResponse(format!("{} is not accepted for gas", token))
+        })?;
+        let decimals = self.query_denomination(token).await?;
+
+        let value = token::DenominatedAmount::new(*gas_price, token::Denomination(decimals))
+            .to_string()
+            .parse::<f64>()
+            .map_err(|err| TaskError::InvalidResponse(format!("Invalid gas price: {}", err)))?;
+        finite(value, "gas price")
+    }
+
+    async fn query_denomination(&self, token: &Address) -> TaskResult<u8> {
+        let denomination = self
+            .call(1, |client| async move {
+                rpc::query_denom(&client, token).await.ok_or_else(|| {
+                    TaskError::RpcUnavailable(format!(
+                        "Error fetching the denomination of {}",
+                        token
+                    ))
+                })
+            })
+            .await?;
+
+        Ok(denomination.0)
     }
 
     async fn claim_rewards(
         &self,
         delegator_address: &Address,
-        validators: &HashSet<Address>,
+        validators: &BTreeSet<Address>,
         secret_key: &SecretKey,
-    ) -> anyhow::Result<()> {
+        batch: bool,
+    ) -> TaskResult<ClaimReport> {
+        // Only for the report, the amounts are left unknown if this fails
+        let rewards = self
+            .validator_rewards(validators, delegator_address)
+            .await
+            .unwrap_or_default();
+        let claim_result = |validator: &Address, outcome, timed_out| ClaimResult {
+            validator: validator.clone(),
+            rewards: rewards.get(validator).copied().flatten(),
+            outcome,
+            timed_out,
+        };
 
-        
+        if batch && validators.len() > 1 {
+            match self
+                .claim_rewards_batch(delegator_address, validators, secret_key)
+                .await
+            {
+                Ok(receipt) => {
+                    let report = ClaimReport {
+                        results: validators
+                            .iter()
+                            .map(|validator| claim_result(validator, Ok(receipt.clone()), false))
+                            .collect(),
+                    };
+                    report.results.iter().for_each(ClaimResult::log);
+                    return Ok(report);
+                }
+                Err(TaskError::TxBuildFailed(err)) => tracing::warn!(
+                    "Can't batch the claims ({}), claiming one validator at a time",
+                    err
+                ),
+                Err(err) => return Err(err),
+            }
+        }
 
-        Ok(())
+        let mut results = vec![];
+        let mut last_err = None;
+
+        for validator in validators {
+            self.pace(results.len()).await;
+            let receipt = self
+                .with_fee_bump(TX_GAS_LIMIT, |fee| async move {
+                    let (sdk, claim, tx, signing_data) = self
+                        .call(self.retry_attempts, |_| async move {
+                            let sdk = self.sdk(secret_key).await?;
+                            let public_key = sdk.find_public_key().await?;
+
+                            let claim = sdk
+                                .namada
+                                .new_claim_rewards(validator.clone())
+                                .source(delegator_address.clone())
+                                .signing_keys(vec![public_key.clone()])
+                                .wrapper_fee_payer(public_key);
+                            let claim = self.with_tx_args(claim, fee);
+
+                            let (tx, signing_data) = claim
+                                .build(&sdk.namada)
+                                .await
+                                .map_err(|err| TaskError::TxBuildFailed(err.to_string()))?;
+
+                            Ok((sdk, claim, tx, signing_data))
+                        })
+                        .await?;
+
+                    self.sign_and_submit(&sdk, tx, &claim.tx, vec![signing_data])
+                        .await
+                })
+                .await;
+
+            let result = match receipt {
+                Ok(receipt) => claim_result(validator, Ok(receipt), false),
+                Err(err) => {
+                    let timed_out = matches!(err, TaskError::Timeout(_));
+                    let result = claim_result(validator, Err(err.to_string()), timed_out);
+                    last_err = Some(err);
+                    result
+                }
+            };
+            result.log();
+            results.push(result);
+        }
+
+        let report = ClaimReport { results };
+        match last_err {
+            Some(err) if report.claimed().is_empty() => Err(err),
+            _ => Ok(report),
+        }
     }
 
     async fn bond(
         &self,
         delegator_address: &Address,
-        validators: &HashSet<Address>,
+        distribution: &[(Address, token::Amount)],
+        secret_key: &SecretKey,
+    ) -> TaskResult<Vec<TxReceipt>> {
+        let mut receipts = vec![];
+
+        if distribution.iter().all(|(_, amount)| amount.is_zero()) {
+            return Err(TaskError::InsufficientRewards(
+                "nothing to bond".to_string(),
+            ));
+        }
+
+        for (validator, validator_amount) in distribution {
+            let validator_amount = *validator_amount;
+            if validator_amount.is_zero() {
+                continue;
+            }
+            self.pace(receipts.len()).await;
+
+            let receipt = self
+                .with_fee_bump(TX_GAS_LIMIT, |fee| async move {
+                    let (sdk, bond, tx, signing_data) = self
+                        .call(self.retry_attempts, |_| async move {
+                            let sdk = self.sdk(secret_key).await?;
+                            let public_key = sdk.find_public_key().await?;
+
+                            let bond = sdk
+                                .namada
+                                .new_bond(validator.clone(), validator_amount)
+                                .source(delegator_address.clone())
+                                .signing_keys(vec![public_key.clone()])
+                                .wrapper_fee_payer(public_key);
+                            let bond = self.with_tx_args(bond, fee);
+
+                            let (tx, signing_data) = bond
+                                .build(&sdk.namada)
+                                .await
+                                .map_err(|err| TaskError::TxBuildFailed(err.to_string()))?;
+
+                            Ok((sdk, bond, tx, signing_data))
+                        })
+                        .await?;
+
+                    self.sign_and_submit(&sdk, tx, &bond.tx, vec![signing_data])
+                        .await
+                })
+                .await?;
+            receipts.push(receipt);
+
+            tracing::info!(
+                "Bonded {} to {}",
+                denom::display_nam(validator_amount),
+                validator
+            );
+        }
+
+        Ok(receipts)
+    }
+
+    async fn redelegate(
+        &self,
+        delegator_address: &Address,
+        redelegations: &[Redelegation],
+        secret_key: &SecretKey,
+    ) -> TaskResult<Vec<TxReceipt>> {
+        let mut receipts = vec![];
+
+        for redelegation in redelegations {
+            let receipt = self
+                .with_fee_bump(TX_GAS_LIMIT, |fee| async move {
+                    let (sdk, redelegate, tx, signing_data) = self
+                        .call(self.retry_attempts, |_| async move {
+                            let sdk = self.sdk(secret_key).await?;
+                            let public_key = sdk.find_public_key().await?;
+
+                            let redelegate = sdk
+                                .namada
+                                .new_redelegation(
+                                    delegator_address.clone(),
+                                    redelegation.src.clone(),
+                                    redelegation.dest.clone(),
+                                    redelegation.amount,
+                                )
+                                .signing_keys(vec![public_key.clone()])
+                                .wrapper_fee_payer(public_key);
+                            let redelegate = self.with_tx_args(redelegate, fee);
+
+                            let (tx, signing_data) = redelegate
+                                .build(&sdk.namada)
+                                .await
+                                .map_err(|err| TaskError::TxBuildFailed(err.to_string()))?;
+
+                            Ok((sdk, redelegate, tx, signing_data))
+                        })
+                        .await?;
+
+                    self.sign_and_submit(&sdk, tx, &redelegate.tx, vec![signing_data])
+                        .await
+                })
+                .await?;
+            receipts.push(receipt);
+
+            tracing::info!("Redelegated {}", redelegation);
+        }
+
+        Ok(receipts)
+    }
+
+    async fn transfer(
+        &self,
+        source: &Address,
+        target: &Address,
         amount: token::Amount,
         secret_key: &SecretKey,
-    ) -> anyhow::Result<()> {
+    ) -> TaskResult<TxReceipt> {
+        let native_token = &self.native_token().await?;
+
+        self.with_fee_bump(TX_GAS_LIMIT, |fee| async move {
+            let (sdk, transfer, tx, signing_data) = self
+                .call(self.retry_attempts, |_| async move {
+                    let sdk = self.sdk(secret_key).await?;
+                    let public_key = sdk.find_public_key().await?;
+
+                    let data = args::TxTransparentTransferData {
+                        source: source.clone(),
+                        target: target.clone(),
+                        token: native_token.clone(),
+                        amount: args::InputAmount::Unvalidated(token::DenominatedAmount::native(
+                            amount,
+                        )),
+                    };
+                    let transfer = sdk
+                        .namada
+                        .new_transparent_transfer(vec![data])
+                        .signing_keys(vec![public_key.clone()])
+                        .wrapper_fee_payer(public_key);
+                    let mut transfer = self.with_tx_args(transfer, fee);
+
+                    let (tx, signing_data) = transfer
+                        .build(&sdk.namada)
+                        .await
+                        .map_err(|err| TaskError::TxBuildFailed(err.to_string()))?;
+
+                    Ok((sdk, transfer, tx, signing_data))
+                })
+                .await?;
+
+            self.sign_and_submit(&sdk, tx, &transfer.tx, vec![signing_data])
+                .await
+        })
+        .await
+    }
+
+    async fn validate_txs(
+        &self,
+        delegator_address: &Address,
+        validators: &BTreeSet<Address>,
+        distribution: &[(Address, token::Amount)],
+        secret_key: &SecretKey,
+    ) -> TaskResult<()> {
+        let (sdk, txs) = self
+            .call(self.retry_attempts, |_| async move {
+                let sdk = self.sdk(secret_key).await?;
+                let public_key = sdk.find_public_key().await?;
+
+                let mut txs = Vec::with_capacity(validators.len() + distribution.len());
+                for validator in validators {
+                    let claim = sdk
+                        .namada
+                        .new_claim_rewards(validator.clone())
+                        .source(delegator_address.clone())
+                        .signing_keys(vec![public_key.clone()])
+                        .wrapper_fee_payer(public_key.clone());
+                    let claim = self.with_tx_args(claim, TxFee::at_min_price(TX_GAS_LIMIT));
+
+                    let (tx, signing_data) = claim.build(&sdk.namada).await.map_err(|err| {
+                        TaskError::TxBuildFailed(format!("Claim from {}: {}", validator, err))
+                    })?;
+                    txs.push((tx, claim.tx, signing_data));
+                }
+                for (validator, amount) in distribution {
+                    let bond = sdk
+                        .namada
+                        .new_bond(validator.clone(), *amount)
+                        .source(delegator_address.clone())
+                        .signing_keys(vec![public_key.clone()])
+                        .wrapper_fee_payer(public_key.clone());
+                    let bond = self.with_tx_args(bond, TxFee::at_min_price(TX_GAS_LIMIT));
+
+                    let (tx, signing_data) = bond.build(&sdk.namada).await.map_err(|err| {
+                        TaskError::TxBuildFailed(format!("Bond to {}: {}", validator, err))
+                    })?;
+                    txs.push((tx, bond.tx, signing_data));
+                }
+
+                Ok((sdk, txs))
+            })
+            .await?;
+
+        sdk.find_secret_key().await?;
+        for (mut tx, args, signing_data) in txs {
+            sdk.namada
+                .sign(&mut tx, &args, signing_data, default_sign, ())
+                .await
+                .map_err(|err| TaskError::InvalidKey(format!("Can't sign: {}", err)))?;
+        }
+
         Ok(())
     }
 
@@ -220,12 +1555,167 @@ impl NamadaRpc for NamadaSdk {
         &self,
         validator: &Address,
         epoch: u64,
-    ) -> anyhow::Result<f64> {
+    ) -> TaskResult<f64> {
         let epoch = Self::to_sdk_epoch(epoch);
-        let commission = rpc::query_commission_rate(&self.client, validator, Some(epoch))
-            .await
-            .context("Error fetching validator commissions")?;
-        Self::dec_to_f64(commission.commission_rate.unwrap())
+        let commission = self
+            .call(self.retry_attempts, |client| async move {
+                rpc::query_commission_rate(&client, validator, Some(epoch))
+                    .await
+                    .map_err(TaskError::rpc("Error fetching validator commissions"))
+            })
+            .await?;
+        let commission_rate = commission.commission_rate.ok_or_else(|| {
+            TaskError::InvalidResponse(format!("{} has no commission rate", validator))
+        })?;
+        Self::dec_to_f64(commission_rate)
+    }
+
+    async fn query_validator_state(
+        &self,
+        validator: &Address,
+        epoch: u64,
+    ) -> TaskResult<Option<ValidatorState>> {
+        let epoch = Self::to_sdk_epoch(epoch);
+        let (state, _) = self
+            .call(1, |client| async move {
+                rpc::get_validator_state(&client, validator, Some(epoch))
+                    .await
+                    .map_err(TaskError::rpc("Error fetching validator state"))
+            })
+            .await?;
+        Ok(state)
+    }
+
+    async fn query_pending_slashes(&self) -> TaskResult<BTreeSet<Address>> {
+        let slashes = self
+            .call(1, |client| async move {
+                RPC.vp()
+                    .pos()
+                    .enqueued_slashes(&client)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching enqueued slashes"))
+            })
+            .await?;
+        Ok(slashes
+            .into_iter()
+            .filter(|(_, slashes)| !slashes.is_empty())
+            .map(|(validator, _)| validator)
+            .collect())
+    }
+
+    async fn query_uptime(&self) -> TaskResult<HashMap<Address, f64>> {
+        let liveness = self
+            .call(1, |client| async move {
+                RPC.vp()
+                    .pos()
+                    .liveness_info(&client)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching validator liveness"))
+            })
+            .await?;
+        if liveness.liveness_window_len == 0 {
+            return Err(TaskError::InvalidResponse(
+                "Empty liveness window".to_string(),
+            ));
+        }
+
+        let window = liveness.liveness_window_len as f64;
+        Ok(liveness
+            .validators
+            .into_iter()
+            .map(|validator| {
+                let missed = (validator.missed_votes as f64).min(window);
+                (validator.native_address, 1.0 - missed / window)
+            })
+            .collect())
+    }
+
+    async fn query_consensus_validators(&self, epoch: u64) -> TaskResult<Vec<(Address, f64)>> {
+        let epoch = Self::to_sdk_epoch(epoch);
+        let validators = self
+            .call(self.retry_attempts, |client| async move {
+                rpc::get_all_consensus_validators(&client, epoch)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching consensus validators"))
+            })
+            .await?;
+
+        validators
+            .into_iter()
+            .map(|validator| {
+                Ok((
+                    validator.address,
+                    Self::amount_to_f64(validator.bonded_stake)?,
+                ))
+            })
+            .collect()
+    }
+
+    async fn query_validator_stake(&self, validator: &Address, epoch: u64) -> TaskResult<f64> {
+        let epoch = Self::to_sdk_epoch(epoch);
+        let stake = self
+            .call(self.retry_attempts, |client| async move {
+                rpc::get_validator_stake(&client, epoch, validator)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching validator stake"))
+            })
+            .await?;
+
+        Self::amount_to_f64(stake)
+    }
+
+    async fn has_pending_unbonds(
+        &self,
+        delegator: &Address,
+        validator: &Address,
+        epoch: u64,
+    ) -> TaskResult<bool> {
+        let unbonds = self
+            .call(1, |client| async move {
+                rpc::query_unbond_with_slashing(&client, delegator, validator)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching unbonds"))
+            })
+            .await?;
+        Ok(unbonds
+            .keys()
+            .any(|(_, withdrawable_epoch)| withdrawable_epoch.0 > epoch))
+    }
+
+    async fn redelegation_cooldown(
+        &self,
+        delegator: &Address,
+        validator: &Address,
+        epoch: u64,
+    ) -> TaskResult<Option<u64>> {
+        let (incoming, params) = self
+            .call(self.retry_attempts, |client| async move {
+                let incoming = rpc::query_incoming_redelegations(&client, validator, delegator)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching redelegations"))?;
+                let params = rpc::get_pos_params(&client)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching PoS parameters"))?;
+                Ok((incoming, params))
+            })
+            .await?;
+
+        // Slashable until the processing of the slashes of the last epoch
+        // the source validator held the stake
+        Ok(incoming
+            .map(|end_epoch| end_epoch.0.saturating_sub(1) + params.slash_processing_epoch_offset())
+            .filter(|cooldown_end| *cooldown_end > epoch))
+    }
+
+    async fn pipeline_len(&self) -> TaskResult<u64> {
+        let params = self
+            .call(self.retry_attempts, |client| async move {
+                rpc::get_pos_params(&client)
+                    .await
+                    .map_err(TaskError::rpc("Error fetching PoS parameters"))
+            })
+            .await?;
+        Ok(params.pipeline_len)
     }
 
     async fn query_bond(
@@ -233,17 +1723,361 @@ impl NamadaRpc for NamadaSdk {
         validator: &Address,
         delegator: &Address,
         epoch: u64,
-    ) -> anyhow::Result<f64> {
+    ) -> TaskResult<f64> {
         let epoch = Self::to_sdk_epoch(epoch);
-        let bonded_amount = rpc::query_bond(&self.client, delegator, validator, Some(epoch))
-            .await
-            .context("Error fetching bonds")?;
+        let bonded_amount = self
+            .call(self.retry_attempts, |client| async move {
+                rpc::query_bond(&client, delegator, validator, Some(epoch))
+                    .await
+                    .map_err(TaskError::rpc("Error fetching bonds"))
+            })
+            .await?;
         Self::amount_to_f64(bonded_amount)
     }
 
-    async fn query_native_token(&self) -> anyhow::Result<Address> {
-        rpc::query_native_token(&self.client)
+    async fn query_native_token(&self) -> TaskResult<Address> {
+        self.native_token().await
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use base64::{engine::general_purpose::STANDARD, Engine};
+    use namada_sdk::{
+        address::EstablishedAddressGen, borsh, state::Epoch, token, tx::data::ResultCode,
+    };
+    use serde_json::{json, Value};
+    use wiremock::{
+        matchers::{any, method},
+        Mock, MockServer, Request, ResponseTemplate,
+    };
+
+    use super::{
+        bumped_gas_price, rejection, resubmit_gas_prices, submit_with_fee_bump, ClaimReport,
+        ClaimResult, NamadaRpc, NamadaSdk, TxFee, TxReceipt, TX_GAS_LIMIT,
+    };
+    use crate::{compound::test::MockNamadaRpc, denom, error::TaskError, rpc_pool::RpcPool};
+
+    /// A node answering each `abci_query` whose path starts with one of the
+    /// prefixes with the matching borsh-encoded value, and failing the others.
+    async fn mock_node(values: Vec<(&'static str, Vec<u8>)>) -> MockServer {
+        let server = MockServer::start().await;
+
+        Mock::given(method("POST"))
+            .respond_with(move |request: &Request| {
+                let body = serde_json::from_slice::<Value>(&request.body).unwrap();
+                let path = body["params"]["path"].as_str().unwrap_or_default();
+                let response = match values.iter().find(|(prefix, _)| path.starts_with(prefix)) {
+                    Some((_, value)) => json!({
+                        "code": 0,
+                        "log": "",
+                        "info": "",
+                        "index": "0",
+                        "key": "",
+                        "value": STANDARD.encode(value),
+                        "proofOps": null,
+                        "height": "1",
+                        "codespace": "",
+                    }),
+                    None => json!({
+                        "code": 1,
+                        "log": format!("unknown path {}", path),
+                        "info": "",
+                        "index": "0",
+                        "key": "",
+                        "value": "",
+                        "proofOps": null,
+                        "height": "1",
+                        "codespace": "",
+                    }),
+                };
+
+                ResponseTemplate::new(200).set_body_json(json!({
+                    "jsonrpc": "2.0",
+                    "id": body["id"],
+                    "result": { "response": response },
+                }))
+            })
+            .mount(&server)
+            .await;
+
+        server
+    }
+
+    fn sdk(server: &MockServer) -> NamadaSdk {
+        NamadaSdk::new(
+            RpcPool::new(&[server.uri()]).unwrap(),
+            1,
+            Duration::from_millis(1),
+            std::env::temp_dir(),
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            1,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_queries_against_mock_node() {
+        let native_token = EstablishedAddressGen::new("token").generate_address("nam");
+        let owner = EstablishedAddressGen::new("owner").generate_address("0");
+        let server = mock_node(vec![
+            ("/shell/epoch", borsh::to_vec(&Epoch(42)).unwrap()),
+            ("/shell/native_token", borsh::to_vec(&native_token).unwrap()),
+            (
+                "/shell/value/",
+                borsh::to_vec(&token::Amount::native_whole(5)).unwrap(),
+            ),
+        ])
+        .await;
+        let sdk = sdk(&server);
+
+        assert_eq!(sdk.get_current_epoch().await.unwrap(), 42);
+        assert_eq!(sdk.query_native_token().await.unwrap(), native_token);
+        assert_eq!(
+            sdk.query_balance(&owner, &native_token).await.unwrap(),
+            token::Amount::native_whole(5)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_failed_query_is_an_error() {
+        let server = mock_node(vec![]).await;
+
+        assert!(sdk(&server).get_current_epoch().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limited_query_backs_off() {
+        let server = mock_node(vec![("/shell/epoch", borsh::to_vec(&Epoch(42)).unwrap())]).await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .with_priority(1)
+            .mount(&server)
+            .await;
+        let sdk = NamadaSdk::new(
+            RpcPool::new(&[server.uri()]).unwrap(),
+            3,
+            Duration::from_millis(1),
+            std::env::temp_dir(),
+            Duration::from_secs(5),
+            Duration::from_secs(5),
+            8,
+        );
+
+        assert_eq!(sdk.get_current_epoch().await.unwrap(), 42);
+        // The query and its retry, the node isn't asked again for the
+        // Retry-After the client drops
+        assert_eq!(server.received_requests().await.unwrap().len(), 2);
+        // Halved on the 429, then one back on the success
+        assert_eq!(sdk.rpc_concurrency(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_gas_limit_bump() {
+        let server = mock_node(vec![]).await;
+        let sdk = sdk(&server);
+        let receipt = TxReceipt {
+            hash: "AB".to_string(),
+            gas_used: 80_000,
+            gas_price: None,
+        };
+
+        let fees = std::sync::Mutex::new(vec![]);
+        let landed = sdk
+            .with_fee_bump(TX_GAS_LIMIT, |fee| {
+                fees.lock().unwrap().push(fee);
+                let res = if fee.gas_limit < 2 * TX_GAS_LIMIT {
+                    Err(TaskError::GasLimitTooLow("out of gas".to_string()))
+                } else {
+                    Ok(receipt.clone())
+                };
+                async move { res }
+            })
+            .await
+            .unwrap();
+        assert_eq!(landed, receipt);
+        assert_eq!(
+            *fees.lock().unwrap(),
+            vec![
+                TxFee::at_min_price(TX_GAS_LIMIT),
+                TxFee::at_min_price(2 * TX_GAS_LIMIT)
+            ]
+        );
+
+        // Given up on at 4 times the gas limit
+        let submitted = std::sync::Mutex::new(0);
+        let err = sdk
+            .with_fee_bump(TX_GAS_LIMIT, |_| {
+                *submitted.lock().unwrap() += 1;
+                async { Err(TaskError::GasLimitTooLow("out of gas".to_string())) }
+            })
             .await
-            .context("Error fetching native token")
+            .unwrap_err();
+        assert!(matches!(err, TaskError::GasLimitTooLow(_)));
+        assert_eq!(*submitted.lock().unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_fee_ladder() {
+        let mut rpc = MockNamadaRpc::new(vec![]);
+        rpc.gas_price = Some(0.0001);
+        rpc.decimals = 8;
+        let receipt = TxReceipt {
+            hash: "AB".to_string(),
+            gas_used: 40_000,
+            gas_price: None,
+        };
+        // 5 NAM is the minimum at 50_000 gas, so the first rung is skipped
+        let ladder = [5.0, 10.0, 20.0, 40.0];
+        // The chain rejects less than `accepted` per gas unit
+        let submit = |accepted: f64, fees: &std::sync::Mutex<Vec<TxFee>>, fee: TxFee| {
+            fees.lock().unwrap().push(fee);
+            let res = match fee.gas_price {
+                Some(gas_price) if gas_price >= accepted => Ok(receipt.clone()),
+                _ => Err(TaskError::FeeTooLow("below the minimum".to_string())),
+            };
+            async move { res }
+        };
+
+        let fees = std::sync::Mutex::new(vec![]);
+        let landed = submit_with_fee_bump(&rpc, None, None, &ladder, TX_GAS_LIMIT, |fee| {
+            submit(0.0004, &fees, fee)
+        })
+        .await
+        .unwrap();
+        let fees = fees.into_inner().unwrap();
+        assert_eq!(fees.len(), 3);
+        assert_eq!(fees[0], TxFee::at_min_price(TX_GAS_LIMIT));
+        assert!((fees[1].gas_price.unwrap() - 0.0002).abs() < 1e-12);
+        assert!((landed.gas_price.unwrap() - 0.0004).abs() < 1e-12);
+        // Paid in the fee token's smallest unit, not in micro-NAM
+        assert_eq!(fees[2].decimals, 8);
+        assert_eq!(
+            denom::gas_price_amount(fees[2].gas_price.unwrap(), fees[2].decimals)
+                .unwrap()
+                .amount(),
+            token::Amount::from_u64(40_000)
+        );
+
+        // Rejected at every rung
+        let fees = std::sync::Mutex::new(vec![]);
+        let err = submit_with_fee_bump(&rpc, None, None, &ladder, TX_GAS_LIMIT, |fee| {
+            submit(0.001, &fees, fee)
+        })
+        .await
+        .unwrap_err();
+        assert!(matches!(err, TaskError::FeeTooLow(_)));
+        assert_eq!(fees.into_inner().unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_claim_report() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let [first, second, third, fourth] =
+            [0, 1, 2, 3].map(|i| address_gen.generate_address(i.to_string()));
+        let batch = TxReceipt {
+            hash: "batch".to_string(),
+            gas_used: 40_000,
+            gas_price: None,
+        };
+        let report = ClaimReport {
+            results: vec![
+                ClaimResult {
+                    validator: first.clone(),
+                    rewards: Some(token::Amount::native_whole(2)),
+                    outcome: Ok(batch.clone()),
+                    timed_out: false,
+                },
+                ClaimResult {
+                    validator: second.clone(),
+                    rewards: Some(token::Amount::native_whole(3)),
+                    outcome: Ok(batch.clone()),
+                    timed_out: false,
+                },
+                ClaimResult {
+                    validator: third.clone(),
+                    rewards: None,
+                    outcome: Err("Tx rejected: out of gas".to_string()),
+                    timed_out: false,
+                },
+                ClaimResult {
+                    validator: fourth.clone(),
+                    rewards: Some(token::Amount::native_whole(1)),
+                    outcome: Err("Timed out after 30s".to_string()),
+                    timed_out: true,
+                },
+            ],
+        };
+
+        assert_eq!(report.receipts(), [batch]);
+        assert_eq!(report.claimed(), [first, second].into());
+        // The timed out claim may still land
+        assert_eq!(report.failed().len(), 1);
+        assert_eq!(report.unconfirmed(), [fourth.clone()].into());
+        // The failed one's rewards aren't needed
+        assert_eq!(
+            report.claimed_rewards(),
+            Some(token::Amount::native_whole(5))
+        );
+        assert_eq!(
+            report.to_string(),
+            format!(
+                "claimed from 2/4 validators, 1 failed: {} (Tx rejected: out of gas), 1 unconfirmed: {}",
+                third, fourth
+            )
+        );
+        assert_eq!(
+            ClaimReport::default().to_string(),
+            "claimed from 0/0 validators"
+        );
+    }
+
+    #[test]
+    fn test_rejection() {
+        let err = rejection(ResultCode::FeeError, "", "Insufficient funds for fee");
+        assert!(
+            matches!(&err, TaskError::LowBalance(message) if message.ends_with("Insufficient funds for fee"))
+        );
+        assert_eq!(err.exit_code(), 4);
+
+        let err = rejection(ResultCode::FeeError, "", "Fee amount below the minimum");
+        assert!(matches!(err, TaskError::FeeTooLow(_)));
+
+        let err = rejection(ResultCode::TxGasLimit, "", "Gas limit exceeded");
+        assert!(matches!(err, TaskError::GasLimitTooLow(_)));
+
+        let err = rejection(ResultCode::InvalidSig, "Bad signature", "");
+        assert!(matches!(err, TaskError::TxRejected(_)));
+    }
+
+    #[test]
+    fn test_bumped_gas_price() {
+        // 0.0001 * 1.5 * 50_000 = 7.5
+        let gas_price = bumped_gas_price(0.0001, TX_GAS_LIMIT, None).unwrap();
+        assert!((gas_price - 0.00015).abs() < 1e-12);
+        assert!(bumped_gas_price(0.0001, TX_GAS_LIMIT, Some(8.0)).is_some());
+        assert_eq!(bumped_gas_price(0.0001, TX_GAS_LIMIT, Some(7.0)), None);
+        // Twice the gas at twice the fee
+        assert_eq!(bumped_gas_price(0.0001, 2 * TX_GAS_LIMIT, Some(8.0)), None);
+    }
+
+    #[test]
+    fn test_resubmit_gas_prices() {
+        assert_eq!(
+            resubmit_gas_prices(0.0001, TX_GAS_LIMIT, None, &[]),
+            bumped_gas_price(0.0001, TX_GAS_LIMIT, None)
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+
+        // 5 NAM is the minimum at 50_000 gas
+        let gas_prices = resubmit_gas_prices(0.0001, TX_GAS_LIMIT, None, &[5.0, 10.0, 20.0]);
+        assert_eq!(gas_prices.len(), 2);
+        assert!((gas_prices[0] - 0.0002).abs() < 1e-12);
+        assert!((gas_prices[1] - 0.0004).abs() < 1e-12);
+        assert!(resubmit_gas_prices(0.001, TX_GAS_LIMIT, None, &[5.0, 10.0]).is_empty());
     }
 }
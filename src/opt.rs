@@ -1,149 +1,1429 @@
-use argmin::{
-    core::{CostFunction, Executor},
-    solver::neldermead::NelderMead,
-};
+use std::{cell::Cell, collections::HashMap};
+
+use namada_sdk::address::Address;
+use serde::Serialize;
 
 fn calculate_compound_balance(
     principal: f64,
     apr: f64,
     fee: f64,
     frequency: f64,
+    epochs_per_year: Option<f64>,
     time_in_years: f64,
 ) -> f64 {
-    let effective_rate = apr / frequency;
-    let fee_per_interval = fee;
+    compound(
+        principal,
+        apr,
+        fee,
+        frequency,
+        epochs_per_year,
+        time_in_years,
+        None,
+    )
+}
+
+/// Epochs in a year of `epoch_secs` long epochs, `None` without a duration.
+pub fn epochs_per_year(epoch_secs: Option<u64>) -> Option<f64> {
+    epoch_secs
+        .filter(|epoch_secs| *epoch_secs > 0)
+        .map(|epoch_secs| SECONDS_PER_YEAR / epoch_secs as f64)
+}
+
+/// Balance right after one compounding round.
+#[derive(Clone, Debug, PartialEq)]
+pub struct SimulationStep {
+    pub round: usize,
+    pub balance: f64,
+    pub fees_paid: f64,
+}
+
+/// Compound `frequency` times a year over `time_in_years`, pushing every
+/// intermediate balance to `trajectory` if given.
+///
+/// Rewards only materialize once per epoch, so with `epochs_per_year` the
+/// compounds beyond one per epoch only add their fees: the balance compounds
+/// once per epoch, paying the fees of every round in it.
+fn compound(
+    principal: f64,
+    apr: f64,
+    fee: f64,
+    frequency: f64,
+    epochs_per_year: Option<f64>,
+    time_in_years: f64,
+    mut trajectory: Option<&mut Vec<SimulationStep>>,
+) -> f64 {
+    let compounds = epochs_per_year.map_or(frequency, |epochs| frequency.min(epochs));
+    let effective_rate = apr / compounds;
+    let fee_per_interval = fee * frequency / compounds;
 
     let mut balance = principal;
 
-    for _ in 0..(frequency * time_in_years) as usize {
+    for round in 0..(compounds * time_in_years) as usize {
         balance = balance * (1.0 + effective_rate) - fee_per_interval;
         if balance <= 0.0 {
             return 0.0;
         }
+        if let Some(trajectory) = trajectory.as_deref_mut() {
+            trajectory.push(SimulationStep {
+                round: round + 1,
+                balance,
+                fees_paid: fee_per_interval * (round + 1) as f64,
+            });
+        }
     }
 
     balance
 }
 
-struct CompoundingOptimization {
+/// Every compounding round over the horizon, stopping early if the fees
+/// drain the balance.
+pub fn simulate(
     principal: f64,
     apr: f64,
     fee: f64,
+    frequency: u64,
     time_in_years: f64,
+) -> Vec<SimulationStep> {
+    let mut trajectory = vec![];
+    compound(
+        principal,
+        apr,
+        fee,
+        frequency as f64,
+        None,
+        time_in_years,
+        Some(&mut trajectory),
+    );
+    trajectory
 }
 
-impl CostFunction for CompoundingOptimization {
-    type Param = f64;
-    type Output = f64;
+/// Balance after `time_in_years` compounding `frequency` times a year, at
+/// most once per epoch, and paying `fee` each time.
+pub fn compound_balance(
+    principal: f64,
+    apr: f64,
+    fee: f64,
+    frequency: u64,
+    epochs_per_year: Option<f64>,
+    time_in_years: f64,
+) -> f64 {
+    calculate_compound_balance(
+        principal,
+        apr,
+        fee,
+        frequency as f64,
+        epochs_per_year,
+        time_in_years,
+    )
+}
+
+/// What one compounding round costs: `txs` transactions paying `fee_per_tx`
+/// each. The tx count depends on how the round is submitted, e.g. whether
+/// claims are batched.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RoundFee {
+    pub txs: u64,
+    pub fee_per_tx: f64,
+}
+
+impl RoundFee {
+    pub fn new(txs: u64, fee_per_tx: f64) -> Self {
+        Self { txs, fee_per_tx }
+    }
+
+    /// A round paying `fee` in a single tx.
+    pub fn flat(fee: f64) -> Self {
+        Self::new(1, fee)
+    }
+
+    pub fn total(&self) -> f64 {
+        self.txs as f64 * self.fee_per_tx
+    }
+}
+
+/// Largest amount, in NAM, the optimizer works on exactly. Amounts are f64
+/// here while txs use the exact `token::Amount`: a f64 holds every integer up
+/// to 2^53, i.e. every micro-NAM (6 decimals) up to about 9 billion NAM.
+/// Above that the principal is rounded to a few micro-NAM, which doesn't
+/// change the optimal frequency but makes the projections approximate.
+pub const MAX_EXACT_AMOUNT: f64 = (1_u64 << 53) as f64 / 1_000_000.0;
+
+pub const SECONDS_PER_YEAR: f64 = 365.0 * 24.0 * 60.0 * 60.0;
 
-    fn cost(&self, frequency: &Self::Param) -> Result<Self::Output, argmin::core::Error> {
-        if *frequency > 24.0 * 365.0 {
-            return Ok(f64::MAX);
+/// How far out of range an APR may be from float rounding in the
+/// commission math, rather than a bad input.
+const APR_TOLERANCE: f64 = 1e-9;
+
+/// `apr` clamped to `[0, max_apr]` when only rounding puts it out of range,
+/// else why the optimizer can't be trusted with it.
+pub fn checked_apr(apr: f64, max_apr: f64) -> Result<f64, String> {
+    if !apr.is_finite() {
+        Err(format!("APR {} is not a number", apr))
+    } else if apr < -APR_TOLERANCE {
+        Err(format!("APR {:.4}% is negative", apr * 100.0))
+    } else if apr > max_apr + APR_TOLERANCE {
+        Err(format!(
+            "APR {:.2}% is above --max-apr ({:.2}%)",
+            apr * 100.0,
+            max_apr * 100.0
+        ))
+    } else {
+        Ok(apr.clamp(0.0, max_apr))
+    }
+}
+const MIN_FREQUENCY: f64 = 1.0;
+pub const MAX_FREQUENCY: f64 = 24.0 * 365.0;
+const GRID_POINTS: usize = 64;
+const BREAK_EVEN_MAX_APR: f64 = 1_000.0;
+const BISECTION_STEPS: usize = 100;
+/// Compounds per year of free rounds when the epoch duration is unknown,
+/// see [`CompoundingOptimization::frequency_cap`].
+const FREE_ROUNDS_FREQUENCY: f64 = 365.0;
+
+/// Most compounds per year that still leave `min_hours_between` hours
+/// between two of them, within the optimizer's range.
+pub fn max_frequency(min_hours_between: f64) -> f64 {
+    (24.0 * 365.0 / min_hours_between)
+        .floor()
+        .clamp(MIN_FREQUENCY, MAX_FREQUENCY)
+}
+
+/// Peak found by [`CompoundingOptimization::maximize`].
+struct Maximum {
+    frequency: u64,
+    balance: f64,
+    /// Balances computed to find it
+    evaluations: usize,
+}
+
+struct CompoundingOptimization {
+    principal: f64,
+    apr: f64,
+    fee: f64,
+    epochs_per_year: Option<f64>,
+    time_in_years: f64,
+    max_frequency: f64,
+}
+
+impl CompoundingOptimization {
+    /// Most compounds per year searched: `max_frequency`, or once per epoch
+    /// (once a day if the epoch duration is unknown) for rounds without a
+    /// fee. Nothing would hold those back from the most frequent schedule
+    /// allowed, though rounds past the first of an epoch find no new
+    /// rewards.
+    fn frequency_cap(&self) -> f64 {
+        if self.fee > 0.0 {
+            return self.max_frequency;
         }
+        let per_epoch = self.epochs_per_year.unwrap_or(FREE_ROUNDS_FREQUENCY);
+        self.max_frequency.min(per_epoch.floor().max(MIN_FREQUENCY))
+    }
 
-        let balance = calculate_compound_balance(
+    fn balance(&self, frequency: u64) -> f64 {
+        calculate_compound_balance(
             self.principal,
             self.apr,
             self.fee,
-            *frequency,
+            frequency as f64,
+            self.epochs_per_year,
             self.time_in_years,
-        );
+        )
+    }
 
-        if balance <= 0.0 {
-            return Ok(f64::MAX);
+    /// [`analytical_optimal_frequency`], which compounding more than once
+    /// per epoch can't beat.
+    fn analytical_frequency(&self) -> f64 {
+        let frequency =
+            analytical_optimal_frequency(self.principal, self.apr, self.fee, self.time_in_years);
+        self.epochs_per_year
+            .map_or(frequency, |epochs| frequency.min(epochs.max(MIN_FREQUENCY)))
+            .min(self.frequency_cap())
+    }
+
+    /// Whether compounding more often than `frequency` would pay off but
+    /// `max_frequency` forbids it.
+    fn is_clamped(&self, frequency: u64) -> bool {
+        frequency as f64 >= self.max_frequency
+            && self.balance(frequency + 1) > self.balance(frequency)
+    }
+
+    /// The balance is unimodal in the (whole) number of compounds per year:
+    /// bracket the peak around the analytical estimate (or on a log-spaced
+    /// grid if the estimate misses it), or by walking uphill from
+    /// `warm_start` when given, narrow it down with a golden-section search
+    /// and finally scan the integers around it.
+    ///
+    /// Only whole frequencies are evaluated: a fractional one compounds
+    /// `floor(frequency)` times at `apr / frequency`, silently dropping part of
+    /// an interval, which would make the curve jagged.
+    fn maximize(&self, warm_start: Option<u64>) -> Maximum {
+        let evaluations = Cell::new(0);
+        let balance = |frequency: u64| {
+            evaluations.set(evaluations.get() + 1);
+            self.balance(frequency)
+        };
+        let rounded_balance = |frequency: f64| balance(frequency.round() as u64);
+
+        let peak = match warm_start {
+            Some(start) => {
+                let (lo, hi) = self.walk_bracket(start, balance);
+                golden_section_max(rounded_balance, lo as f64, hi as f64)
+            }
+            None => {
+                let seed = self.analytical_frequency();
+                let lo = (seed / 2.0).max(MIN_FREQUENCY);
+                let hi = (seed * 2.0).min(self.frequency_cap());
+                let peak = golden_section_max(rounded_balance, lo, hi);
+
+                let at_edge = (peak - lo < 1.0 && lo > MIN_FREQUENCY)
+                    || (hi - peak < 1.0 && hi < self.frequency_cap());
+                if at_edge {
+                    let (lo, hi) = self.grid_bracket();
+                    golden_section_max(rounded_balance, lo, hi)
+                } else {
+                    peak
+                }
+            }
+        };
+
+        let from = (peak.floor() as u64)
+            .saturating_sub(2)
+            .max(MIN_FREQUENCY as u64);
+        let to = (peak.ceil() as u64 + 2).min(self.frequency_cap() as u64);
+
+        let (frequency, balance) = (from..=to)
+            .map(|frequency| (frequency, balance(frequency)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .unwrap_or((MIN_FREQUENCY as u64, balance(MIN_FREQUENCY as u64)));
+
+        Maximum {
+            frequency,
+            balance,
+            evaluations: evaluations.get(),
+        }
+    }
+
+    /// Frequencies around the peak, found by stepping from `start` towards
+    /// the higher balance in doubling steps until it drops. Close to the
+    /// peak, as the last optimum usually is, that takes a few steps.
+    fn walk_bracket(&self, start: u64, balance: impl Fn(u64) -> f64) -> (u64, u64) {
+        let (min, max) = (MIN_FREQUENCY as u64, self.frequency_cap() as u64);
+        let start = start.clamp(min, max);
+        let mut current = (start, balance(start));
+
+        let up = start < max && balance(start + 1) > current.1;
+        let mut previous = start;
+        let mut step = 1;
+        loop {
+            let next = if up {
+                current.0.saturating_add(step).min(max)
+            } else {
+                current.0.saturating_sub(step).max(min)
+            };
+            if next == current.0 {
+                return (previous.min(next), previous.max(next));
+            }
+
+            let next_balance = balance(next);
+            if next_balance <= current.1 {
+                return (previous.min(next), previous.max(next));
+            }
+            previous = current.0;
+            current = (next, next_balance);
+            step *= 2;
+        }
+    }
+
+    /// Neighbours of the best point on a log-spaced grid over the feasible
+    /// range.
+    fn grid_bracket(&self) -> (f64, f64) {
+        let mut grid = (0..GRID_POINTS)
+            .map(|i| {
+                let t = i as f64 / (GRID_POINTS - 1) as f64;
+                (MIN_FREQUENCY * (self.frequency_cap() / MIN_FREQUENCY).powf(t)).round() as u64
+            })
+            .collect::<Vec<_>>();
+        grid.dedup();
+
+        let best = grid
+            .iter()
+            .map(|frequency| self.balance(*frequency))
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+            .map(|(i, _)| i)
+            .unwrap_or_default();
+
+        (
+            grid[best.saturating_sub(1)] as f64,
+            grid[(best + 1).min(grid.len() - 1)] as f64,
+        )
+    }
+}
+
+/// Most points [`cost_curve`] is asked for from the command line or the
+/// control server.
+pub const MAX_CURVE_SAMPLES: usize = 10_000;
+
+/// Balance after `horizon` years against the number of compounds per year,
+/// as `(frequency, balance)` pairs at up to `samples` whole frequencies
+/// spread log-uniformly over the optimizer's range. Close frequencies round
+/// to the same whole one at the low end, so there may be fewer pairs.
+pub fn cost_curve(
+    principal: f64,
+    apr: f64,
+    fee: f64,
+    horizon: f64,
+    samples: usize,
+) -> Vec<(f64, f64)> {
+    let mut frequencies = (0..samples)
+        .map(|i| {
+            let t = i as f64 / (samples - 1).max(1) as f64;
+            (MIN_FREQUENCY * (MAX_FREQUENCY / MIN_FREQUENCY).powf(t)).round()
+        })
+        .collect::<Vec<_>>();
+    frequencies.dedup();
+
+    frequencies
+        .into_iter()
+        .map(|frequency| {
+            let balance = calculate_compound_balance(principal, apr, fee, frequency, None, horizon);
+            (frequency, balance)
+        })
+        .collect()
+}
+
+/// Closed-form approximation of the optimal number of compounds per year.
+///
+/// Expanding `(1 + r/N)^(N*T) ~ e^(r*T) * (1 - r^2*T / 2N)` and summing the
+/// per-interval fee as `F * N * (e^(r*T) - 1) / r`, the balance is maximized
+/// where the marginal compounding gain equals the marginal fee:
+/// `N = sqrt(P * e^(r*T) * r^3 * T / (2 * F * (e^(r*T) - 1)))`.
+pub fn analytical_optimal_frequency(principal: f64, apr: f64, fee: f64, horizon: f64) -> f64 {
+    if apr <= 0.0 || principal <= 0.0 || horizon <= 0.0 {
+        return MIN_FREQUENCY;
+    }
+    if fee <= 0.0 {
+        return MAX_FREQUENCY;
+    }
+
+    let growth = (apr * horizon).exp();
+    let frequency =
+        (principal * growth * apr.powi(3) * horizon / (2.0 * fee * (growth - 1.0))).sqrt();
+
+    frequency.clamp(MIN_FREQUENCY, MAX_FREQUENCY)
+}
+
+/// APR at which compounding `frequency` times a year over `horizon` years
+/// just pays for its fees, i.e. ends on `principal`. Infinite if no sensible
+/// APR gets there.
+pub fn break_even_apr(principal: f64, fee: f64, frequency: u64, horizon: f64) -> f64 {
+    let balance =
+        |apr: f64| calculate_compound_balance(principal, apr, fee, frequency as f64, None, horizon);
+
+    if balance(0.0) >= principal {
+        return 0.0;
+    }
+    if balance(BREAK_EVEN_MAX_APR) < principal {
+        return f64::INFINITY;
+    }
+
+    // The balance grows with the APR, so bisect
+    let (mut lo, mut hi) = (0.0, BREAK_EVEN_MAX_APR);
+    for _ in 0..BISECTION_STEPS {
+        let mid = (lo + hi) / 2.0;
+        if balance(mid) < principal {
+            lo = mid;
+        } else {
+            hi = mid;
         }
+    }
+
+    hi
+}
 
-        Ok(-balance)
+fn golden_section_max(f: impl Fn(f64) -> f64, mut lo: f64, mut hi: f64) -> f64 {
+    let inv_phi = (5_f64.sqrt() - 1.0) / 2.0;
+
+    let mut x1 = hi - inv_phi * (hi - lo);
+    let mut x2 = lo + inv_phi * (hi - lo);
+    let mut f1 = f(x1);
+    let mut f2 = f(x2);
+
+    while hi - lo > 0.5 {
+        if f1 < f2 {
+            lo = x1;
+            x1 = x2;
+            f1 = f2;
+            x2 = lo + inv_phi * (hi - lo);
+            f2 = f(x2);
+        } else {
+            hi = x2;
+            x2 = x1;
+            f2 = f1;
+            x1 = hi - inv_phi * (hi - lo);
+            f1 = f(x1);
+        }
     }
+
+    (lo + hi) / 2.0
 }
 
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct OptimizationResult {
     pub max_balance: f64,
-    pub optimal_frequency: u64, // in hours
+    pub optimal_frequency: u64, // compounds per year
+    pub apr: f64,
+    pub fee: f64,
+    pub epochs_per_year: Option<f64>,
+    /// The optimum lies above the `--min-hours-between-compounds` bound and
+    /// `optimal_frequency` was capped to it
+    pub clamped: bool,
 }
 
 impl OptimizationResult {
+    /// Yearly yield net of fees when compounding `principal` at the optimal
+    /// frequency. Zero when there's nothing bonded.
+    pub fn apy(&self, principal: f64) -> f64 {
+        if principal <= 0.0 {
+            return 0.0;
+        }
+
+        let balance = compound_balance(
+            principal,
+            self.apr,
+            self.fee,
+            self.optimal_frequency,
+            self.epochs_per_year,
+            1.0,
+        );
+        balance / principal - 1.0
+    }
+
+    /// Compound `factor` times as often as the optimum, at least once a
+    /// year, for a margin against fee spikes. `principal` and
+    /// `horizon_years` are the ones optimized for.
+    pub fn with_safety_factor(self, factor: f64, principal: f64, horizon_years: f64) -> Self {
+        let optimal_frequency = ((self.optimal_frequency as f64 * factor).floor() as u64).max(1);
+        let max_balance = compound_balance(
+            principal,
+            self.apr,
+            self.fee,
+            optimal_frequency,
+            self.epochs_per_year,
+            horizon_years,
+        );
+
+        Self {
+            max_balance,
+            optimal_frequency,
+            ..self
+        }
+    }
+
+    /// Compound only as often as it takes for the APY on `principal` to
+    /// come closest to `target`, for `--target-apy`, rather than at the
+    /// optimum. If even the optimum falls short, it's kept and the shortfall
+    /// is returned along.
+    pub fn with_target_apy(
+        self,
+        target: f64,
+        principal: f64,
+        horizon_years: f64,
+    ) -> (Self, Option<f64>) {
+        let shortfall = target - self.apy(principal);
+        if shortfall > 0.0 {
+            return (self, Some(shortfall));
+        }
+
+        let apy = |optimal_frequency| {
+            Self {
+                optimal_frequency,
+                ..self.clone()
+            }
+            .apy(principal)
+        };
+        // The APY grows with the frequency up to the optimum: find the first
+        // frequency reaching the target, then the closest of it and the one
+        // before
+        let (mut lo, mut hi) = (1, self.optimal_frequency);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if apy(mid) >= target {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        let optimal_frequency = if lo > 1 && target - apy(lo - 1) < apy(lo) - target {
+            lo - 1
+        } else {
+            lo
+        };
+        let max_balance = compound_balance(
+            principal,
+            self.apr,
+            self.fee,
+            optimal_frequency,
+            self.epochs_per_year,
+            horizon_years,
+        );
+
+        let clamped = self.clamped && optimal_frequency == self.optimal_frequency;
+        (
+            Self {
+                max_balance,
+                optimal_frequency,
+                clamped,
+                ..self
+            },
+            None,
+        )
+    }
+
     pub fn seconds_between_compunding(&self) -> f64 {
-        365.0 * 24.0 * 60.0 * 60.0 / self.optimal_frequency as f64
+        SECONDS_PER_YEAR / self.optimal_frequency as f64
     }
 
     pub fn hours_between_compounding(&self) -> f64 {
         self.seconds_between_compunding() / 60.0 / 60.0
     }
 
-    pub fn hours_between_compounding_rounded(&self) -> f64 {
-        self.round_up_to_next_multiple(self.hours_between_compounding(), 4.0)
+    /// Seconds the scheduler waits between compounds, rounded up as
+    /// `rounding` says.
+    pub fn interval_secs(&self, rounding: IntervalRounding, epoch_secs: Option<u64>) -> f64 {
+        rounding.round(self.seconds_between_compunding(), epoch_secs)
+    }
+
+    pub fn hours_between_compounding_rounded(
+        &self,
+        rounding: IntervalRounding,
+        epoch_secs: Option<u64>,
+    ) -> f64 {
+        self.interval_secs(rounding, epoch_secs) / 60.0 / 60.0
     }
 
     pub fn days_between_compounding(&self) -> f64 {
         self.hours_between_compounding() / 24.0
     }
 
-    pub fn days_between_compounding_rounded(&self) -> f64 {
-        self.round_up_to_next_multiple(self.days_between_compounding(), 4.0)
+    pub fn days_between_compounding_rounded(
+        &self,
+        rounding: IntervalRounding,
+        epoch_secs: Option<u64>,
+    ) -> f64 {
+        self.hours_between_compounding_rounded(rounding, epoch_secs) / 24.0
     }
+}
 
-    fn round_up_to_next_multiple(&self, value: f64, n: f64) -> f64 {
-        if n == 0.0 {
-            panic!("n cannot be zero");
-        }
-        (value / n).ceil() * n
+/// How the interval between compounds is rounded up, see
+/// `--interval-rounding`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum IntervalRounding {
+    /// The optimizer's interval as is
+    None,
+    /// Whole hours
+    Hour,
+    /// Whole days
+    Day,
+    /// Whole epochs, since rewards only grow once per epoch
+    #[default]
+    Epoch,
+}
+
+impl IntervalRounding {
+    /// `seconds` between compounds rounded up to the policy's unit. Epochs
+    /// leave them unchanged without an epoch duration.
+    pub fn round(self, seconds: f64, epoch_secs: Option<u64>) -> f64 {
+        let unit = match self {
+            Self::None => None,
+            Self::Hour => Some(60 * 60),
+            Self::Day => Some(24 * 60 * 60),
+            Self::Epoch => epoch_secs,
+        };
+        unit.and_then(|unit| round_up_to_next_multiple(seconds, unit as f64))
+            .unwrap_or(seconds)
+    }
+}
+
+/// Round `value` up to a multiple of `n`, or `None` if `n` isn't positive.
+/// Values within float noise of a multiple are kept as is.
+pub fn round_up_to_next_multiple(value: f64, n: f64) -> Option<f64> {
+    if n <= 0.0 || !n.is_finite() {
+        return None;
     }
+
+    let ratio = value / n;
+    let nearest = ratio.round();
+    if (ratio - nearest).abs() < 1e-9 {
+        Some(nearest * n)
+    } else {
+        Some(ratio.ceil() * n)
+    }
+}
+
+pub fn compute_frequency_opt(
+    principal: f64,
+    apr: f64,
+    fee: f64,
+    epochs_per_year: Option<f64>,
+    horizon_years: f64,
+    max_frequency: f64,
+) -> Option<OptimizationResult> {
+    compute_frequency_opt_from(
+        principal,
+        apr,
+        fee,
+        epochs_per_year,
+        horizon_years,
+        max_frequency,
+        None,
+    )
 }
 
-pub fn compute_frequency_opt(principal: f64, apr: f64, fee: f64) -> Option<OptimizationResult> {
+/// Like [`compute_frequency_opt`], searching around `warm_start` compounds
+/// per year, e.g. the last optimum, if given. Inputs that moved little since
+/// then converge in fewer steps.
+pub fn compute_frequency_opt_from(
+    principal: f64,
+    apr: f64,
+    fee: f64,
+    epochs_per_year: Option<f64>,
+    horizon_years: f64,
+    max_frequency: f64,
+    warm_start: Option<u64>,
+) -> Option<OptimizationResult> {
     let problem = CompoundingOptimization {
         principal,
         apr,
         fee,
-        time_in_years: 1_f64,
+        epochs_per_year,
+        time_in_years: horizon_years,
+        max_frequency,
     };
 
-    let params = vec![1.0, 24.0 * 365.0 / 4.0];
-    let solver = NelderMead::new(params);
+    let Maximum {
+        frequency: optimal_frequency,
+        balance: max_balance,
+        ..
+    } = problem.maximize(warm_start);
 
-    let result = Executor::new(problem, solver)
-        .configure(|state| state.max_iters(1000))
-        .run()
-        .ok()?;
+    if max_balance <= 0.0 {
+        return None;
+    }
 
-    let optimal_frequency = result.state().param.unwrap();
-    let max_balance = -result.state().cost;
+    Some(OptimizationResult {
+        max_balance,
+        optimal_frequency,
+        apr,
+        fee,
+        epochs_per_year,
+        clamped: problem.is_clamped(optimal_frequency),
+    })
+}
+
+/// Like [`compute_frequency_opt`] but trusting the closed-form estimate
+/// instead of searching for the optimum.
+pub fn compute_frequency_analytic(
+    principal: f64,
+    apr: f64,
+    fee: f64,
+    epochs_per_year: Option<f64>,
+    horizon_years: f64,
+    max_frequency: f64,
+) -> Option<OptimizationResult> {
+    let problem = CompoundingOptimization {
+        principal,
+        apr,
+        fee,
+        epochs_per_year,
+        time_in_years: horizon_years,
+        max_frequency,
+    };
+
+    let optimal_frequency = problem.analytical_frequency().round() as u64;
+    let max_balance = problem.balance(optimal_frequency);
+
+    if max_balance <= 0.0 {
+        return None;
+    }
 
     Some(OptimizationResult {
         max_balance,
-        optimal_frequency: optimal_frequency as u64,
+        optimal_frequency,
+        apr,
+        fee,
+        epochs_per_year,
+        clamped: problem.is_clamped(optimal_frequency),
     })
 }
 
+/// Optimize each validator on its own, from its bond, its commission-adjusted
+/// APR and what `fee_of` charges for compounding it. Validators without a
+/// fee or a profitable schedule are left out.
+pub fn compute_frequency_per_validator<'a>(
+    optimizer: Optimizer,
+    validators: impl IntoIterator<Item = (&'a Address, f64, f64)>,
+    fee_of: impl Fn(&Address) -> Option<RoundFee>,
+    epochs_per_year: Option<f64>,
+    horizon_years: f64,
+    max_frequency: f64,
+) -> HashMap<Address, OptimizationResult> {
+    validators
+        .into_iter()
+        .filter_map(|(validator, bond, apr)| {
+            let result = optimizer.optimize(
+                bond,
+                apr,
+                fee_of(validator)?,
+                epochs_per_year,
+                horizon_years,
+                max_frequency,
+            )?;
+            Some((validator.clone(), result))
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum Optimizer {
+    /// Closed-form approximation, no iteration
+    Analytic,
+    /// Numeric search seeded with the analytical estimate
+    Search,
+}
+
+impl Optimizer {
+    pub fn optimize(
+        &self,
+        principal: f64,
+        apr: f64,
+        fee: RoundFee,
+        epochs_per_year: Option<f64>,
+        horizon_years: f64,
+        max_frequency: f64,
+    ) -> Option<OptimizationResult> {
+        let fee = fee.total();
+        match self {
+            Optimizer::Analytic => compute_frequency_analytic(
+                principal,
+                apr,
+                fee,
+                epochs_per_year,
+                horizon_years,
+                max_frequency,
+            ),
+            Optimizer::Search => compute_frequency_opt(
+                principal,
+                apr,
+                fee,
+                epochs_per_year,
+                horizon_years,
+                max_frequency,
+            ),
+        }
+    }
+
+    /// [`Self::optimize`] with the search warm-started from `warm_start`
+    /// compounds per year, see [`compute_frequency_opt_from`].
+    pub fn optimize_from(
+        &self,
+        inputs: &OptimizationInputs,
+        warm_start: Option<u64>,
+    ) -> Option<OptimizationResult> {
+        match self {
+            Optimizer::Analytic => self.optimize(
+                inputs.principal,
+                inputs.apr,
+                RoundFee::flat(inputs.fee),
+                inputs.epochs_per_year,
+                inputs.horizon_years,
+                inputs.max_frequency,
+            ),
+            Optimizer::Search => compute_frequency_opt_from(
+                inputs.principal,
+                inputs.apr,
+                inputs.fee,
+                inputs.epochs_per_year,
+                inputs.horizon_years,
+                inputs.max_frequency,
+                warm_start,
+            ),
+        }
+    }
+}
+
+/// What [`Optimizer::optimize`] depends on, the fee being the round total.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OptimizationInputs {
+    pub principal: f64,
+    pub apr: f64,
+    pub fee: f64,
+    pub epochs_per_year: Option<f64>,
+    pub horizon_years: f64,
+    pub max_frequency: f64,
+}
+
+impl OptimizationInputs {
+    /// Whether each input is within `epsilon`, relative, of `other`'s.
+    fn close_to(&self, other: &Self, epsilon: f64) -> bool {
+        let close = |a: f64, b: f64| (a - b).abs() <= epsilon * a.abs().max(b.abs());
+
+        close(self.principal, other.principal)
+            && close(self.apr, other.apr)
+            && close(self.fee, other.fee)
+            && self.epochs_per_year == other.epochs_per_year
+            && self.horizon_years == other.horizon_years
+            && self.max_frequency == other.max_frequency
+    }
+}
+
+/// The last optimization, reused until its inputs change materially, see
+/// `--reoptimize-epsilon`, and otherwise the warm start of the next one.
+#[derive(Clone, Debug, Default)]
+pub struct OptimizationCache {
+    last: Option<(OptimizationInputs, Option<OptimizationResult>)>,
+}
+
+impl OptimizationCache {
+    pub fn optimize(
+        &mut self,
+        optimizer: Optimizer,
+        inputs: OptimizationInputs,
+        epsilon: f64,
+    ) -> Option<OptimizationResult> {
+        if let Some((last_inputs, result)) = &self.last {
+            if inputs.close_to(last_inputs, epsilon) {
+                tracing::debug!("Optimization inputs unchanged, reusing the last result");
+                return result.clone();
+            }
+        }
+
+        let warm_start = self
+            .last
+            .as_ref()
+            .and_then(|(_, result)| result.as_ref())
+            .map(|result| result.optimal_frequency);
+        let result = optimizer.optimize_from(&inputs, warm_start);
+        self.last = Some((inputs, result.clone()));
+        result
+    }
+}
+
 #[cfg(test)]
 mod test {
-    use super::{calculate_compound_balance, compute_frequency_opt};
+    use std::collections::HashMap;
+
+    use namada_sdk::address::EstablishedAddressGen;
+
+    use super::{
+        analytical_optimal_frequency, break_even_apr, calculate_compound_balance, checked_apr,
+        compound_balance, compute_frequency_analytic, compute_frequency_opt,
+        compute_frequency_per_validator, cost_curve, epochs_per_year, max_frequency,
+        round_up_to_next_multiple, simulate, CompoundingOptimization, IntervalRounding,
+        OptimizationCache, OptimizationInputs, Optimizer, RoundFee, MAX_FREQUENCY, MIN_FREQUENCY,
+    };
 
     #[test]
     fn test() {
         let p = 3_000_000_f64;
         let apr = 0.118_f64;
-        let res = compute_frequency_opt(p, apr, 5.0_f64).unwrap();
+        let res = compute_frequency_opt(p, apr, 5.0_f64, None, 1.0_f64, MAX_FREQUENCY).unwrap();
 
         assert!(res.max_balance - p >= p * apr);
-        assert_eq!(res.hours_between_compounding(), 25.53935860058309);
+        // Nelder-Mead used to stop at 343 compounds a year (~25.5 hours); an
+        // exhaustive scan over 1..=8760 confirms 66 is the actual optimum.
+        assert_eq!(res.optimal_frequency, 66);
+        assert!((res.hours_between_compounding() - 8760.0 / 66.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_checked_apr() {
+        assert_eq!(checked_apr(0.09, 1.0), Ok(0.09));
+        // Rounding in the commission math
+        assert_eq!(checked_apr(-1e-12, 1.0), Ok(0.0));
+        assert_eq!(checked_apr(1.0 + 1e-12, 1.0), Ok(1.0));
+
+        assert!(checked_apr(-0.05, 1.0).is_err());
+        assert!(checked_apr(1e6, 1.0).is_err());
+        assert!(checked_apr(1.5, 2.0).is_ok());
+        assert!(checked_apr(f64::NAN, 1.0).is_err());
+        assert!(checked_apr(f64::INFINITY, 1.0).is_err());
     }
 
     #[test]
     fn test_1() {
         let p = 1000_f64;
         let apr = 0.09_f64;
-        let res = compute_frequency_opt(p, apr, 0.005_f64).unwrap();
+        let res = compute_frequency_opt(p, apr, 0.005_f64, None, 1.0_f64, MAX_FREQUENCY).unwrap();
 
         assert!(res.max_balance - p >= p * apr - 0.06_f64);
-        assert_eq!(res.hours_between_compounding(), 50.93023255813954);
+        // Nelder-Mead used to stop at 172 (~50.9 hours), exhaustive scan gives 29
+        assert_eq!(res.optimal_frequency, 29);
+        assert!((res.hours_between_compounding() - 8760.0 / 29.0).abs() < 1e-9);
     }
 
     #[test]
     pub fn test_2() {
-        let res = calculate_compound_balance(1000.0, 0.05, 0.06, 81.0, 1.0);
-        assert_eq!(res, 1046.272905533)
+        let res = calculate_compound_balance(1000.0, 0.05, 0.06, 81.0, None, 1.0);
+        assert!((res - 1046.272905533).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_invariants() {
+        for optimizer in [Optimizer::Search, Optimizer::Analytic] {
+            for principal in [100.0, 10_000.0, 1_000_000.0, 100_000_000.0] {
+                for apr in [0.01, 0.05, 0.12, 0.3] {
+                    let mut last_frequency = u64::MAX;
+                    for fee in [0.001, 0.01, 0.1, 1.0, 10.0] {
+                        let Some(res) = optimizer.optimize(
+                            principal,
+                            apr,
+                            RoundFee::flat(fee),
+                            None,
+                            1.0,
+                            MAX_FREQUENCY,
+                        ) else {
+                            continue;
+                        };
+                        let case = format!("{:?} {} {} {}", optimizer, principal, apr, fee);
+
+                        assert!(
+                            (1..=MAX_FREQUENCY as u64).contains(&res.optimal_frequency),
+                            "{}",
+                            case
+                        );
+                        // Compounding once already beats the principal
+                        if principal * apr > fee {
+                            assert!(res.max_balance > principal, "{}", case);
+                        }
+                        // Pricier rounds are never worth doing more often
+                        assert!(res.optimal_frequency <= last_frequency, "{}", case);
+                        last_frequency = res.optimal_frequency;
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_horizon() {
+        let p = 10_000_f64;
+        let apr = 0.1_f64;
+        let one_year =
+            compute_frequency_opt(p, apr, 1.0_f64, None, 1.0_f64, MAX_FREQUENCY).unwrap();
+        let five_years =
+            compute_frequency_opt(p, apr, 1.0_f64, None, 5.0_f64, MAX_FREQUENCY).unwrap();
+
+        assert_ne!(one_year.optimal_frequency, five_years.optimal_frequency);
+        assert!(five_years.max_balance > one_year.max_balance);
+    }
+
+    #[test]
+    fn test_analytical_matches_search() {
+        for (p, apr, fee) in [
+            (3_000_000_f64, 0.118_f64, 5.0_f64),
+            (1000_f64, 0.09_f64, 0.005_f64),
+            (10_000_f64, 0.1_f64, 1.0_f64),
+            (50_000_f64, 0.05_f64, 0.1_f64),
+        ] {
+            let search = compute_frequency_opt(p, apr, fee, None, 1.0_f64, MAX_FREQUENCY).unwrap();
+            let analytic = analytical_optimal_frequency(p, apr, fee, 1.0_f64);
+
+            let error = (analytic - search.optimal_frequency as f64).abs();
+            assert!(error <= 0.1 * search.optimal_frequency as f64 + 1.0);
+
+            let analytic =
+                compute_frequency_analytic(p, apr, fee, None, 1.0_f64, MAX_FREQUENCY).unwrap();
+            assert!((search.max_balance - analytic.max_balance) / search.max_balance < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_analytical_bounds() {
+        assert_eq!(
+            analytical_optimal_frequency(1000.0, 0.1, 0.0, 1.0),
+            24.0 * 365.0
+        );
+        assert_eq!(analytical_optimal_frequency(1000.0, 0.0, 1.0, 1.0), 1.0);
+    }
+
+    #[test]
+    fn test_apy() {
+        let res = compute_frequency_opt(10_000.0, 0.1, 1.0, None, 1.0, MAX_FREQUENCY).unwrap();
+        let apy = res.apy(10_000.0);
+
+        // Compounding beats the APR, minus what the fees take
+        assert_eq!(apy, res.max_balance / 10_000.0 - 1.0);
+        assert!(apy > 0.1 - 7.0 / 10_000.0);
+        assert!(apy < (1.0 + 0.1 / 7.0_f64).powi(7) - 1.0);
+    }
+
+    #[test]
+    fn test_apy_zero_principal() {
+        let res = compute_frequency_opt(10_000.0, 0.1, 1.0, None, 1.0, MAX_FREQUENCY).unwrap();
+        assert_eq!(res.apy(0.0), 0.0);
+    }
+
+    #[test]
+    fn test_per_validator() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let high_apr = address_gen.generate_address("0");
+        let low_apr = address_gen.generate_address("1");
+        let unbonded = address_gen.generate_address("2");
+
+        let schedule = compute_frequency_per_validator(
+            Optimizer::Search,
+            [
+                (&high_apr, 10_000.0, 0.1),
+                (&low_apr, 10_000.0, 0.02),
+                (&unbonded, 0.0, 0.1),
+            ],
+            |_| Some(RoundFee::flat(1.0)),
+            None,
+            1.0,
+            MAX_FREQUENCY,
+        );
+
+        assert_eq!(schedule.len(), 2);
+        assert!(schedule[&high_apr].optimal_frequency > schedule[&low_apr].optimal_frequency);
+    }
+
+    #[test]
+    fn test_per_validator_fees() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let cheap = address_gen.generate_address("0");
+        let costly = address_gen.generate_address("1");
+        let unpriced = address_gen.generate_address("2");
+        let validators = [
+            (&cheap, 10_000.0, 0.1),
+            (&costly, 10_000.0, 0.1),
+            (&unpriced, 10_000.0, 0.1),
+        ];
+
+        let fees = HashMap::from([
+            (cheap.clone(), RoundFee::new(2, 0.1)),
+            (costly.clone(), RoundFee::new(2, 1.0)),
+        ]);
+        let schedule = compute_frequency_per_validator(
+            Optimizer::Search,
+            validators,
+            |validator| fees.get(validator).copied(),
+            None,
+            1.0,
+            MAX_FREQUENCY,
+        );
+        assert_eq!(schedule.len(), 2);
+        assert!(schedule[&cheap].optimal_frequency > schedule[&costly].optimal_frequency);
+
+        // A validator's own fee optimizes like the same fee for all
+        let aggregate = compute_frequency_per_validator(
+            Optimizer::Search,
+            validators,
+            |_| Some(RoundFee::new(2, 1.0)),
+            None,
+            1.0,
+            MAX_FREQUENCY,
+        );
+        assert_eq!(aggregate.len(), 3);
+        assert_eq!(
+            schedule[&costly].optimal_frequency,
+            aggregate[&costly].optimal_frequency
+        );
+    }
+
+    #[test]
+    fn test_max_frequency_clamps() {
+        assert_eq!(max_frequency(1.0), MAX_FREQUENCY);
+        assert_eq!(max_frequency(0.5), MAX_FREQUENCY);
+        assert_eq!(max_frequency(24.0 * 7.0), 52.0);
+        assert_eq!(max_frequency(24.0 * 365.0 * 2.0), 1.0);
+
+        // Unconstrained the optimum is 66 compounds a year
+        let (p, apr, fee) = (3_000_000_f64, 0.118_f64, 5.0_f64);
+        let free = compute_frequency_opt(p, apr, fee, None, 1.0, MAX_FREQUENCY).unwrap();
+        assert!(!free.clamped);
+
+        let weekly = max_frequency(24.0 * 7.0);
+        for optimizer in [Optimizer::Search, Optimizer::Analytic] {
+            let res = optimizer
+                .optimize(p, apr, RoundFee::flat(fee), None, 1.0, weekly)
+                .unwrap();
+            assert_eq!(res.optimal_frequency, 52);
+            assert!(res.clamped);
+            assert!(res.max_balance < free.max_balance);
+        }
+    }
+
+    #[test]
+    fn test_batched_claims_compound_more_often() {
+        // Three validators, claimed and bonded one tx each or with a single
+        // batched claim
+        let per_validator = RoundFee::new(6, 0.5);
+        let batched = RoundFee::new(4, 0.5);
+
+        for optimizer in [Optimizer::Search, Optimizer::Analytic] {
+            let optimize = |fee| {
+                optimizer
+                    .optimize(100_000.0, 0.1, fee, None, 1.0, MAX_FREQUENCY)
+                    .unwrap()
+            };
+            let per_validator = optimize(per_validator);
+            let batched = optimize(batched);
+
+            assert!(batched.optimal_frequency > per_validator.optimal_frequency);
+            assert!(batched.max_balance > per_validator.max_balance);
+        }
+    }
+
+    #[test]
+    fn test_simulate() {
+        let trajectory = simulate(1000.0, 0.05, 0.06, 81, 1.0);
+
+        assert_eq!(trajectory.len(), 81);
+        assert_eq!(trajectory[0].round, 1);
+        assert_eq!(
+            trajectory.last().unwrap().balance,
+            calculate_compound_balance(1000.0, 0.05, 0.06, 81.0, None, 1.0)
+        );
+        assert!((trajectory.last().unwrap().fees_paid - 81.0 * 0.06).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_cost_curve() {
+        let curve = cost_curve(10_000.0, 0.1, 0.1, 1.0, 200);
+        let optimum = compute_frequency_opt(10_000.0, 0.1, 0.1, None, 1.0, MAX_FREQUENCY).unwrap();
+
+        assert!(curve.len() > 100 && curve.len() <= 200);
+        assert_eq!(curve[0].0, MIN_FREQUENCY);
+        assert_eq!(curve.last().unwrap().0, MAX_FREQUENCY);
+        assert!(curve.windows(2).all(|pair| pair[0].0 < pair[1].0));
+
+        // A single peak, at the optimizer's pick
+        let peak = curve
+            .iter()
+            .enumerate()
+            .max_by(|(_, a), (_, b)| a.1.total_cmp(&b.1))
+            .map(|(i, _)| i)
+            .unwrap();
+        assert!(curve[..=peak].windows(2).all(|pair| pair[0].1 <= pair[1].1));
+        assert!(curve[peak..].windows(2).all(|pair| pair[0].1 >= pair[1].1));
+        assert!(curve[peak].1 <= optimum.max_balance);
+        assert!(optimum.max_balance - curve[peak].1 < 0.01);
+
+        assert!(cost_curve(10_000.0, 0.1, 0.1, 1.0, 0).is_empty());
+        assert_eq!(cost_curve(10_000.0, 0.1, 0.1, 1.0, 1).len(), 1);
+    }
+
+    #[test]
+    fn test_break_even_apr() {
+        // Each round's interest exactly pays its fee: apr = frequency * fee / principal
+        assert!((break_even_apr(1_000.0, 1.0, 12, 1.0) - 0.012).abs() < 1e-9);
+        assert!((break_even_apr(10_000.0, 0.5, 365, 2.0) - 0.01825).abs() < 1e-9);
+        assert_eq!(break_even_apr(1_000.0, 0.0, 12, 1.0), 0.0);
+        assert_eq!(break_even_apr(0.001, 10.0, 1, 1.0), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_round_up_to_next_multiple() {
+        assert_eq!(round_up_to_next_multiple(8.0, 4.0), Some(8.0));
+        assert_eq!(round_up_to_next_multiple(8.1, 4.0), Some(12.0));
+        assert_eq!(round_up_to_next_multiple(0.5, 4.0), Some(4.0));
+        assert_eq!(round_up_to_next_multiple(0.0, 4.0), Some(0.0));
+        // Float noise from the interval computation doesn't bump a whole day
+        assert_eq!(round_up_to_next_multiple(1.0 + 1e-12, 1.0), Some(1.0));
+        assert_eq!(round_up_to_next_multiple(8.0, 0.0), None);
+        assert_eq!(round_up_to_next_multiple(8.0, -4.0), None);
+    }
+
+    #[test]
+    fn test_safety_factor() {
+        let res = compute_frequency_opt(10_000.0, 0.1, 1.0, None, 1.0, MAX_FREQUENCY).unwrap();
+
+        let exact = res.clone().with_safety_factor(1.0, 10_000.0, 1.0);
+        assert_eq!(exact.optimal_frequency, res.optimal_frequency);
+
+        let safe = res.clone().with_safety_factor(0.8, 10_000.0, 1.0);
+        assert!(safe.seconds_between_compunding() > res.seconds_between_compunding());
+        assert!(safe.max_balance <= res.max_balance);
+
+        assert_eq!(
+            res.with_safety_factor(1e-9, 10_000.0, 1.0)
+                .optimal_frequency,
+            1
+        );
+    }
+
+    #[test]
+    fn test_target_apy() {
+        let res = compute_frequency_opt(10_000.0, 0.1, 1.0, None, 1.0, MAX_FREQUENCY).unwrap();
+        let max_apy = res.apy(10_000.0);
+
+        // Reached compounding less often than the optimum
+        let (target, shortfall) = res.clone().with_target_apy(0.1, 10_000.0, 1.0);
+        assert_eq!(shortfall, None);
+        assert!(target.optimal_frequency < res.optimal_frequency);
+        assert!((target.apy(10_000.0) - 0.1).abs() < 1e-4);
+        assert!(target.max_balance <= res.max_balance);
+
+        // Beyond what the fees allow, the optimum stays
+        let (target, shortfall) = res.clone().with_target_apy(0.2, 10_000.0, 1.0);
+        assert_eq!(target.optimal_frequency, res.optimal_frequency);
+        assert!((shortfall.unwrap() - (0.2 - max_apy)).abs() < 1e-12);
+
+        // Compounding once a year is already past it
+        let (target, shortfall) = res.with_target_apy(0.01, 10_000.0, 1.0);
+        assert_eq!(shortfall, None);
+        assert_eq!(target.optimal_frequency, 1);
+    }
+
+    #[test]
+    fn test_round_up_to_epochs() {
+        let epoch = IntervalRounding::Epoch;
+        assert_eq!(epoch.round(5_000.0, Some(3_600)), 7_200.0);
+        assert_eq!(epoch.round(7_200.0, Some(3_600)), 7_200.0);
+        assert_eq!(epoch.round(5_000.0, Some(0)), 5_000.0);
+        assert_eq!(epoch.round(5_000.0, None), 5_000.0);
+    }
+
+    #[test]
+    fn test_interval_rounding() {
+        let res = compute_frequency_opt(10_000.0, 0.1, 1.0, None, 1.0, MAX_FREQUENCY).unwrap();
+        let hours = res.hours_between_compounding();
+        let days = res.days_between_compounding();
+
+        assert_eq!(
+            res.interval_secs(IntervalRounding::None, Some(3_600)),
+            res.seconds_between_compunding()
+        );
+        assert_eq!(
+            res.hours_between_compounding_rounded(IntervalRounding::Hour, None),
+            hours.ceil()
+        );
+        assert_eq!(
+            res.days_between_compounding_rounded(IntervalRounding::Day, None),
+            days.ceil()
+        );
+        // What's logged is what the scheduler waits
+        assert_eq!(
+            res.hours_between_compounding_rounded(IntervalRounding::Epoch, Some(7_200)) * 3_600.0,
+            res.interval_secs(IntervalRounding::Epoch, Some(7_200))
+        );
+    }
+
+    #[test]
+    fn test_optimization_cache() {
+        let inputs = OptimizationInputs {
+            principal: 10_000.0,
+            apr: 0.1,
+            fee: 1.0,
+            epochs_per_year: None,
+            horizon_years: 1.0,
+            max_frequency: MAX_FREQUENCY,
+        };
+        let mut cache = OptimizationCache::default();
+        let first = cache.optimize(Optimizer::Search, inputs, 0.001).unwrap();
+
+        // Tamper with the cached result to tell a reuse from a recomputation
+        if let Some((_, Some(result))) = &mut cache.last {
+            result.optimal_frequency = 1;
+        }
+
+        let same = OptimizationInputs {
+            principal: 10_005.0,
+            ..inputs
+        };
+        let reused = cache.optimize(Optimizer::Search, same, 0.001).unwrap();
+        assert_eq!(reused.optimal_frequency, 1);
+
+        let moved = OptimizationInputs {
+            principal: 20_000.0,
+            ..inputs
+        };
+        let recomputed = cache.optimize(Optimizer::Search, moved, 0.001).unwrap();
+        assert_ne!(recomputed.optimal_frequency, 1);
+        assert!(recomputed.optimal_frequency > first.optimal_frequency);
+    }
+
+    #[test]
+    fn test_epoch_duration_caps_compounding() {
+        let (p, apr, fee) = (1_000_000.0, 0.1, 0.01);
+        let hourly_epochs = epochs_per_year(Some(3_600));
+        let two_day_epochs = epochs_per_year(Some(2 * 24 * 3_600));
+        assert_eq!(two_day_epochs, Some(182.5));
+        assert_eq!(epochs_per_year(None), None);
+
+        let hourly = compute_frequency_opt(p, apr, fee, hourly_epochs, 1.0, MAX_FREQUENCY).unwrap();
+        let two_day =
+            compute_frequency_opt(p, apr, fee, two_day_epochs, 1.0, MAX_FREQUENCY).unwrap();
+        assert!(hourly.optimal_frequency > 500);
+        assert!(two_day.optimal_frequency <= 183);
+        assert!(two_day.max_balance < hourly.max_balance);
+
+        // Past one compound per epoch, the extra rounds only cost fees
+        let at_epochs = compound_balance(p, apr, fee, 182, two_day_epochs, 1.0);
+        let faster = compound_balance(p, apr, fee, hourly.optimal_frequency, two_day_epochs, 1.0);
+        assert!(faster < at_epochs);
+
+        let analytic =
+            compute_frequency_analytic(p, apr, fee, two_day_epochs, 1.0, MAX_FREQUENCY).unwrap();
+        assert!(analytic.optimal_frequency <= 183);
+    }
+
+    #[test]
+    fn test_zero_fee() {
+        let (p, apr) = (1_000_000.0, 0.1);
+        let six_hour_epochs = epochs_per_year(Some(6 * 3_600));
+
+        // Free rounds gain nothing past one per epoch, stop there
+        for optimizer in [Optimizer::Search, Optimizer::Analytic] {
+            for fee in [0.0, -1.0] {
+                let result = optimizer
+                    .optimize(
+                        p,
+                        apr,
+                        RoundFee::flat(fee),
+                        six_hour_epochs,
+                        1.0,
+                        MAX_FREQUENCY,
+                    )
+                    .unwrap();
+                assert_eq!(result.optimal_frequency, 1_460);
+                assert!(!result.clamped);
+
+                let daily = optimizer
+                    .optimize(p, apr, RoundFee::flat(fee), None, 1.0, MAX_FREQUENCY)
+                    .unwrap();
+                assert_eq!(daily.optimal_frequency, 365);
+            }
+        }
+
+        // --min-hours-between-compounds still wins when tighter
+        let weekly = compute_frequency_opt(p, apr, 0.0, six_hour_epochs, 1.0, 52.0).unwrap();
+        assert_eq!(weekly.optimal_frequency, 52);
+        assert!(weekly.clamped);
+    }
+
+    #[test]
+    fn test_warm_start() {
+        for (principal, fee) in [(10_000.0, 0.1), (3_000_000.0, 5.0), (500.0, 0.5)] {
+            let problem = CompoundingOptimization {
+                principal,
+                apr: 0.1,
+                fee,
+                epochs_per_year: None,
+                time_in_years: 1.0,
+                max_frequency: MAX_FREQUENCY,
+            };
+            let cold = problem.maximize(None);
+
+            // From the last optimum, as after a round with the same inputs
+            let warm = problem.maximize(Some(cold.frequency));
+            assert_eq!(warm.frequency, cold.frequency);
+            assert!(warm.evaluations < cold.evaluations);
+
+            // A stale warm start still gets there
+            let stale = problem.maximize(Some(cold.frequency * 3));
+            assert_eq!(stale.frequency, cold.frequency);
+        }
     }
 }
@@ -0,0 +1,87 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// How often the loop checks whether it was resumed while paused.
+pub const PAUSE_POLL: Duration = Duration::from_secs(10);
+
+/// Whether compounding is paused, by `--pause-file` existing or SIGUSR1,
+/// which toggles. The loop keeps running and its state in memory while
+/// paused, only skipping rounds.
+#[derive(Debug, Clone, Default)]
+pub struct Pause {
+    file: Option<PathBuf>,
+    toggled: Arc<AtomicBool>,
+}
+
+impl Pause {
+    /// Only `file`, without listening for SIGUSR1.
+    pub fn new(file: Option<PathBuf>) -> Self {
+        Self {
+            file,
+            toggled: Arc::default(),
+        }
+    }
+
+    pub fn listen(file: Option<PathBuf>) -> Self {
+        let pause = Self::new(file);
+
+        tokio::spawn(toggle_on_signal(pause.clone()));
+
+        pause
+    }
+
+    /// Flip the SIGUSR1 pause, returning whether it's now paused.
+    pub fn toggle(&self) -> bool {
+        !self.toggled.fetch_xor(true, Ordering::SeqCst)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.toggled.load(Ordering::SeqCst) || self.file.as_ref().is_some_and(|file| file.exists())
+    }
+}
+
+#[cfg(unix)]
+async fn toggle_on_signal(pause: Pause) {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut toggles = signal(SignalKind::user_defined1()).expect("Can't listen for SIGUSR1");
+    while toggles.recv().await.is_some() {
+        let paused = pause.toggle();
+        tracing::info!(
+            "{} compounding on SIGUSR1",
+            if paused { "Pausing" } else { "Resuming" }
+        );
+    }
+}
+
+#[cfg(not(unix))]
+async fn toggle_on_signal(_pause: Pause) {}
+
+#[cfg(test)]
+mod test {
+    use super::Pause;
+
+    #[test]
+    fn test_pause() {
+        let file = std::env::temp_dir().join(format!("autocompound-pause-{}", std::process::id()));
+        let pause = Pause::new(Some(file.clone()));
+        assert!(!pause.is_paused());
+
+        std::fs::write(&file, "").unwrap();
+        assert!(pause.is_paused());
+        std::fs::remove_file(&file).unwrap();
+        assert!(!pause.is_paused());
+
+        // SIGUSR1 toggles, on its own or along the file
+        assert!(pause.toggle());
+        assert!(pause.is_paused());
+        assert!(!pause.clone().toggle());
+        assert!(!pause.is_paused());
+    }
+}
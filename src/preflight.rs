@@ -0,0 +1,289 @@
+use std::fmt::{self, Display};
+
+use namada_sdk::token;
+
+use crate::{
+    accounts::{self, Account},
+    compound,
+    config::AppConfig,
+    denom,
+    namada::{NamadaRpc, NamadaSdk},
+    opt::RoundFee,
+    rpc_pool::RpcPool,
+    utils,
+};
+
+/// One step of the `preflight` checklist.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Check {
+    pub name: String,
+    /// Failing it fails the preflight, otherwise it's only a warning
+    pub critical: bool,
+    /// What was found, or why it failed
+    pub outcome: Result<String, String>,
+}
+
+/// The checks run so far, in order.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Checklist {
+    pub checks: Vec<Check>,
+}
+
+impl Checklist {
+    /// Record `outcome` under `name`, returning whether it passed.
+    pub fn check(
+        &mut self,
+        name: impl Into<String>,
+        critical: bool,
+        outcome: anyhow::Result<String>,
+    ) -> bool {
+        let passed = outcome.is_ok();
+        self.checks.push(Check {
+            name: name.into(),
+            critical,
+            outcome: outcome.map_err(|err| format!("{:#}", err)),
+        });
+        passed
+    }
+
+    /// No critical check failed.
+    pub fn passed(&self) -> bool {
+        self.checks
+            .iter()
+            .all(|check| check.outcome.is_ok() || !check.critical)
+    }
+}
+
+impl Display for Checklist {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for check in &self.checks {
+            let (mark, detail) = match &check.outcome {
+                Ok(detail) => ("PASS", detail),
+                Err(reason) if check.critical => ("FAIL", reason),
+                Err(reason) => ("WARN", reason),
+            };
+            writeln!(f, "[{}] {}: {}", mark, check.name, detail)?;
+        }
+        write!(
+            f,
+            "{}",
+            if self.passed() {
+                "Ready to compound"
+            } else {
+                "Not ready to compound"
+            }
+        )
+    }
+}
+
+/// Go over the setup the way the loop would use it, without submitting
+/// anything. Checks needing an earlier one that failed, such as those of the
+/// chain without an RPC connection, are left out.
+pub async fn preflight(config: &AppConfig) -> Checklist {
+    let mut checklist = Checklist::default();
+
+    let accounts = accounts::load_accounts(config).and_then(|accounts| {
+        if let Some(expected_address) = &config.expected_address {
+            anyhow::ensure!(
+                &accounts[0].address == expected_address,
+                "The secret key controls {}, not the expected address {}",
+                accounts[0].address,
+                expected_address
+            );
+        }
+        Ok(accounts)
+    });
+    let accounts = match accounts {
+        Ok(accounts) => {
+            for account in &accounts {
+                checklist.check(
+                    format!("Delegator key of {}", account.alias),
+                    true,
+                    Ok(account.address.to_string()),
+                );
+            }
+            accounts
+        }
+        Err(err) => {
+            checklist.check("Delegator key", true, Err(err));
+            return checklist;
+        }
+    };
+
+    checklist.check(
+        "Wallet directory",
+        true,
+        utils::ensure_writable_dir(&config.wallet_dir)
+            .map(|()| format!("{} is writable", config.wallet_dir.display())),
+    );
+
+    let rpc_pool = match RpcPool::new(&config.namada_rpc) {
+        Ok(rpc_pool) => rpc_pool,
+        Err(err) => {
+            checklist.check("RPC connection", true, Err(err));
+            return checklist;
+        }
+    };
+    let chain_id = match &config.chain_id {
+        Some(chain_id) => rpc_pool
+            .check_chain_id(chain_id)
+            .await
+            .map(|()| chain_id.to_string()),
+        None => Err(anyhow::anyhow!("--chain-id isn't set, not checked")),
+    };
+    checklist.check("Chain id", config.chain_id.is_some(), chain_id);
+
+    let rpc = NamadaSdk::from_config(rpc_pool, config);
+    rpc.select_endpoint().await;
+    let epoch = match rpc.get_current_epoch().await {
+        Ok(epoch) => epoch,
+        Err(err) => {
+            checklist.check("RPC connection", true, Err(err.into()));
+            return checklist;
+        }
+    };
+    checklist.check("RPC connection", true, Ok(format!("at epoch {}", epoch)));
+
+    for account in &accounts {
+        check_account(&rpc, config, account, epoch, &mut checklist).await;
+    }
+
+    checklist
+}
+
+/// The delegations of `account`, and whether its txs build and it can pay
+/// for a round.
+async fn check_account<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    account: &Account,
+    epoch: u64,
+    checklist: &mut Checklist,
+) {
+    let validators = compound::delegations(rpc, config, &account.address, epoch)
+        .await
+        .and_then(|validators| {
+            anyhow::ensure!(!validators.is_empty(), "No active delegations to compound");
+            Ok(validators)
+        });
+    let validators = match validators {
+        Ok(validators) => {
+            checklist.check(
+                format!("Delegations of {}", account.alias),
+                true,
+                Ok(format!("{} validator(s)", validators.len())),
+            );
+            validators
+        }
+        Err(err) => {
+            checklist.check(format!("Delegations of {}", account.alias), true, Err(err));
+            return;
+        }
+    };
+
+    checklist.check(
+        format!("Pending rewards of {}", account.alias),
+        false,
+        rpc.query_pos_rewards(&validators, &account.address)
+            .await
+            .map(denom::display_nam)
+            .map_err(anyhow::Error::from),
+    );
+
+    let distribution = compound::validation_distribution(config, &validators);
+    checklist.check(
+        format!("Claim and bond txs of {}", account.alias),
+        true,
+        rpc.validate_txs(
+            &account.address,
+            &validators,
+            &distribution,
+            &account.secret_key,
+        )
+        .await
+        .map(|()| "build and sign, not submitted".to_string())
+        .map_err(anyhow::Error::from),
+    );
+
+    let fees = async {
+        let native_token_address = rpc.query_native_token().await?;
+        let fee = RoundFee::new(
+            compound::txs_per_round(validators.len(), config.batch_claims),
+            compound::estimate_fee_per_tx(rpc, config, &native_token_address).await,
+        )
+        .total();
+        let fee_token = config.fee_token.as_ref().unwrap_or(&native_token_address);
+        let decimals = rpc.query_denomination(fee_token).await?;
+        let balance = rpc.query_balance(&account.address, fee_token).await?;
+        let balance = token::DenominatedAmount::new(balance, token::Denomination(decimals));
+        anyhow::ensure!(
+            balance.amount() >= denom::amount_from_whole(fee, decimals),
+            "{} of {} can't pay the estimated fees of a round ({})",
+            balance,
+            fee_token,
+            fee
+        );
+        Ok(format!(
+            "{} of {} covers the estimated fees of a round ({})",
+            balance, fee_token, fee
+        ))
+    }
+    .await;
+    checklist.check(format!("Fee balance of {}", account.alias), true, fees);
+}
+
+#[cfg(test)]
+mod test {
+    use namada_sdk::{address::EstablishedAddressGen, token};
+
+    use super::{check_account, Checklist};
+    use crate::{
+        accounts::Account,
+        compound::test::{secret_key, MockNamadaRpc},
+        config::test::config_with,
+    };
+
+    #[test]
+    fn test_checklist() {
+        let mut checklist = Checklist::default();
+        assert!(checklist.check("Wallet directory", true, Ok("writable".to_string())));
+        assert!(!checklist.check("Chain id", false, Err(anyhow::anyhow!("not set"))));
+        assert!(checklist.passed());
+        assert_eq!(
+            checklist.to_string(),
+            "[PASS] Wallet directory: writable\n[WARN] Chain id: not set\nReady to compound"
+        );
+
+        assert!(!checklist.check("RPC connection", true, Err(anyhow::anyhow!("unreachable"))));
+        assert!(!checklist.passed());
+        assert!(checklist
+            .to_string()
+            .ends_with("[FAIL] RPC connection: unreachable\nNot ready to compound"));
+    }
+
+    #[tokio::test]
+    async fn test_fee_balance() {
+        let fee_token = EstablishedAddressGen::new("token")
+            .generate_address("fee")
+            .to_string();
+        // 4 txs of 0.5 each, of a token with 8 decimals
+        let config = &config_with(&["--fee-token", &fee_token, "--base-fee-nam", "0.5"]);
+        let account = &Account::new("main".to_string(), secret_key(), None);
+        let fee_balance = |balance: u64| async move {
+            let mut rpc = MockNamadaRpc::new(vec![token::Amount::from_u64(balance)]);
+            rpc.decimals = 8;
+            let mut checklist = Checklist::default();
+            check_account(&rpc, config, account, 100, &mut checklist).await;
+            checklist
+                .checks
+                .into_iter()
+                .find(|check| check.name == "Fee balance of main")
+                .unwrap()
+                .outcome
+        };
+
+        assert!(fee_balance(300_000_000).await.is_ok());
+        // Enough at the 6 decimals of NAM, not at the token's
+        assert!(fee_balance(100_000_000).await.is_err());
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::{self, Display},
+    path::Path,
+};
+
+use namada_sdk::{address::Address, key::common::SecretKey, token};
+
+use crate::{
+    compound,
+    config::{AppConfig, RebalanceArgs, RebalanceTarget},
+    denom,
+    namada::NamadaRpc,
+    state::State,
+};
+
+/// A move of bonded stake between two validators of the delegator.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Redelegation {
+    pub src: Address,
+    pub dest: Address,
+    pub amount: token::Amount,
+}
+
+impl Display for Redelegation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} from {} to {}",
+            denom::display_nam(self.amount),
+            self.src,
+            self.dest
+        )
+    }
+}
+
+/// Bond each validator should hold for `target`, splitting the total of
+/// `bonds`. `commissions` are by validator and must cover all of them for
+/// [`RebalanceTarget::Commission`].
+pub fn target_bonds(
+    target: RebalanceTarget,
+    bonds: &HashMap<Address, f64>,
+    commissions: &HashMap<Address, f64>,
+) -> HashMap<Address, f64> {
+    let weights = bonds
+        .keys()
+        .map(|validator| {
+            let weight = match target {
+                RebalanceTarget::Even => 1.0,
+                RebalanceTarget::Commission => commissions
+                    .get(validator)
+                    .map_or(0.0, |commission| (1.0 - commission).max(0.0)),
+            };
+            (validator, weight)
+        })
+        .collect::<Vec<_>>();
+
+    let total_bond = bonds.values().sum::<f64>();
+    let total_weight = weights.iter().map(|(_, weight)| weight).sum::<f64>();
+    if total_weight <= 0.0 {
+        return bonds.clone();
+    }
+
+    weights
+        .into_iter()
+        .map(|(validator, weight)| (validator.clone(), total_bond * weight / total_weight))
+        .collect()
+}
+
+/// Redelegations moving `bonds` towards `targets`, the biggest surplus into
+/// the biggest shortfall first. Validators in `cooldown` only receive, those
+/// within `margin` of their target, as a share of it, are left as they are,
+/// and moves below `min_move` NAM are left out.
+pub fn plan_redelegations(
+    bonds: &HashMap<Address, f64>,
+    targets: &HashMap<Address, f64>,
+    cooldown: &BTreeSet<Address>,
+    min_move: f64,
+    margin: f64,
+) -> Vec<Redelegation> {
+    let gaps = |surplus: bool| {
+        let mut gaps = bonds
+            .iter()
+            .filter_map(|(validator, bond)| {
+                let target = targets.get(validator).copied().unwrap_or(*bond);
+                let gap = if surplus {
+                    bond - target
+                } else {
+                    target - bond
+                };
+                (gap > 0.0 && gap > target * margin).then(|| (validator.clone(), gap))
+            })
+            .filter(|(validator, _)| !surplus || !cooldown.contains(validator))
+            .collect::<Vec<_>>();
+        gaps.sort_by(|(a, a_gap), (b, b_gap)| b_gap.total_cmp(a_gap).then_with(|| a.cmp(b)));
+        gaps
+    };
+    let (mut surpluses, mut shortfalls) = (gaps(true), gaps(false));
+
+    let mut redelegations = vec![];
+    let (mut src, mut dest) = (0, 0);
+    while src < surpluses.len() && dest < shortfalls.len() {
+        let amount = surpluses[src].1.min(shortfalls[dest].1);
+        if amount >= min_move {
+            redelegations.push(Redelegation {
+                src: surpluses[src].0.clone(),
+                dest: shortfalls[dest].0.clone(),
+                amount: denom::amount_from_nam(amount),
+            });
+        }
+
+        surpluses[src].1 -= amount;
+        shortfalls[dest].1 -= amount;
+        if surpluses[src].1 <= 0.0 {
+            src += 1;
+        }
+        if shortfalls[dest].1 <= 0.0 {
+            dest += 1;
+        }
+    }
+
+    redelegations
+}
+
+/// Print the bonds against the `--target` of `args` and the redelegations
+/// getting there, then submit them with `--execute`. The validators the
+/// rounds persisted to `state_file` chose within `--target-cooldown-epochs`
+/// keep their stake, and `--target-switch-margin` off their target is close
+/// enough.
+pub async fn rebalance<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    secret_key: &SecretKey,
+    state_file: Option<&Path>,
+    args: &RebalanceArgs,
+) -> anyhow::Result<()> {
+    let delegator_address = Address::from(&secret_key.to_public());
+    let current_epoch = rpc.get_current_epoch().await?;
+
+    let validators = compound::delegations(rpc, config, &delegator_address, current_epoch).await?;
+    anyhow::ensure!(
+        validators.len() > 1,
+        "Nothing to rebalance with {} validator(s)",
+        validators.len()
+    );
+
+    // Left out validators would count as an empty bond to fill
+    let bonds = rpc
+        .query_bonds(&validators, &delegator_address, current_epoch, true)
+        .await?;
+    let commissions = match args.target {
+        RebalanceTarget::Even => HashMap::new(),
+        RebalanceTarget::Commission => {
+            rpc.query_validators_commissions(&validators, current_epoch, true)
+                .await?
+        }
+    };
+    let targets = target_bonds(args.target, &bonds, &commissions);
+
+    let chosen = match state_file {
+        Some(path) => {
+            State::load(path)?.chosen_validators(current_epoch, config.target_cooldown_epochs)
+        }
+        None => BTreeSet::new(),
+    };
+    let mut cooldowns = HashMap::new();
+    for validator in &validators {
+        if let Some(cooldown_end) = rpc
+            .redelegation_cooldown(&delegator_address, validator, current_epoch)
+            .await?
+        {
+            cooldowns.insert(validator.clone(), cooldown_end);
+        }
+    }
+
+    println!("Delegator: {}", delegator_address);
+    println!("Epoch: {}", current_epoch);
+    println!();
+    println!("{:<46} {:>18} {:>18}", "Validator", "Bond", "Target");
+    for validator in &validators {
+        let cooldown = match cooldowns.get(validator) {
+            Some(end) => format!(" (redelegated, locked until epoch {})", end),
+            None if chosen.contains(&validator.to_string()) => {
+                " (chosen to bond to, kept)".to_string()
+            }
+            None => String::new(),
+        };
+        println!(
+            "{:<46} {:>18.6} {:>18.6}{}",
+            validator.to_string(),
+            bonds.get(validator).copied().unwrap_or_default(),
+            targets.get(validator).copied().unwrap_or_default(),
+            cooldown
+        );
+    }
+
+    let kept = validators
+        .iter()
+        .filter(|validator| {
+            cooldowns.contains_key(*validator) || chosen.contains(&validator.to_string())
+        })
+        .cloned()
+        .collect();
+    let redelegations = plan_redelegations(
+        &bonds,
+        &targets,
+        &kept,
+        args.min_move,
+        config.target_switch_margin,
+    );
+
+    println!();
+    if redelegations.is_empty() {
+        println!("Nothing to move");
+        return Ok(());
+    }
+    for redelegation in &redelegations {
+        println!("Move {}", redelegation);
+    }
+
+    if !args.execute {
+        return Ok(());
+    }
+    if config.dry_run {
+        tracing::info!("Dry run, not redelegating");
+        return Ok(());
+    }
+
+    let receipts = rpc
+        .redelegate(&delegator_address, &redelegations, secret_key)
+        .await?;
+    println!(
+        "Redelegated: {}",
+        receipts
+            .iter()
+            .map(|receipt| receipt.hash.as_str())
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeSet, HashMap};
+
+    use namada_sdk::address::EstablishedAddressGen;
+
+    use super::{plan_redelegations, target_bonds, Redelegation};
+    use crate::{config::RebalanceTarget, denom};
+
+    #[test]
+    fn test_target_bonds() {
+        let mut address_gen = EstablishedAddressGen::new("rebalance");
+        let [cheap, expensive] = [0, 1].map(|i| address_gen.generate_address(i.to_string()));
+        let bonds = HashMap::from([(cheap.clone(), 100.0), (expensive.clone(), 300.0)]);
+        let commissions = HashMap::from([(cheap.clone(), 0.0), (expensive.clone(), 0.5)]);
+
+        let even = target_bonds(RebalanceTarget::Even, &bonds, &commissions);
+        assert_eq!(even[&cheap], 200.0);
+        assert_eq!(even[&expensive], 200.0);
+
+        // The cheap one leaves twice as much of the rewards
+        let by_commission = target_bonds(RebalanceTarget::Commission, &bonds, &commissions);
+        assert!((by_commission[&cheap] - 800.0 / 3.0).abs() < 1e-9);
+        assert!((by_commission[&expensive] - 400.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_plan_redelegations() {
+        let mut address_gen = EstablishedAddressGen::new("rebalance");
+        let [big, mid, small] = [0, 1, 2].map(|i| address_gen.generate_address(i.to_string()));
+        let bonds = HashMap::from([
+            (big.clone(), 600.0),
+            (mid.clone(), 299.5),
+            (small.clone(), 100.5),
+        ]);
+        let targets = target_bonds(RebalanceTarget::Even, &bonds, &HashMap::new());
+        let third = 1_000.0 / 3.0;
+
+        let redelegations = plan_redelegations(&bonds, &targets, &BTreeSet::new(), 1.0, 0.0);
+        assert_eq!(
+            redelegations,
+            [
+                Redelegation {
+                    src: big.clone(),
+                    dest: small.clone(),
+                    amount: denom::amount_from_nam(third - 100.5),
+                },
+                Redelegation {
+                    src: big.clone(),
+                    dest: mid.clone(),
+                    amount: denom::amount_from_nam(600.0 - third - (third - 100.5)),
+                },
+            ]
+        );
+
+        // Too small to be worth a tx
+        let redelegations = plan_redelegations(&bonds, &targets, &BTreeSet::new(), 50.0, 0.0);
+        assert_eq!(redelegations.len(), 1);
+        assert_eq!(redelegations[0].dest, small);
+
+        // The mid one is within 11% of its target
+        let redelegations = plan_redelegations(&bonds, &targets, &BTreeSet::new(), 1.0, 0.11);
+        assert_eq!(
+            redelegations,
+            [Redelegation {
+                src: big.clone(),
+                dest: small.clone(),
+                amount: denom::amount_from_nam(third - 100.5),
+            }]
+        );
+
+        // Stake redelegated to the big one can't move yet
+        assert!(plan_redelegations(&bonds, &targets, &BTreeSet::from([big]), 1.0, 0.0).is_empty());
+    }
+}
@@ -0,0 +1,218 @@
+use std::{
+    cmp::Reverse,
+    sync::atomic::{AtomicU32, AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use anyhow::Context;
+use futures::future::join_all;
+use namada_sdk::chain::ChainId;
+use tendermint_rpc::{Client, HttpClient};
+
+/// Longest `Retry-After` honored, so a throttled endpoint can't stall a round
+/// for long
+pub const MAX_RETRY_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    client: HttpClient,
+    failures: AtomicU32,
+}
+
+/// A set of RPC endpoints for the same chain. Queries go to the active
+/// endpoint; failures demote it and rotate to the healthiest alternative.
+#[derive(Debug)]
+pub struct RpcPool {
+    endpoints: Vec<Endpoint>,
+    active: AtomicUsize,
+    /// Callers needing consecutive answers from the same node, e.g. to
+    /// compare bonds before and after a tx, which keep failures from
+    /// rotating endpoints
+    pins: AtomicUsize,
+}
+
+impl RpcPool {
+    pub fn new(urls: &[String]) -> anyhow::Result<Self> {
+        anyhow::ensure!(!urls.is_empty(), "At least one RPC endpoint is required");
+
+        let endpoints = urls
+            .iter()
+            .map(|url| {
+                let client = HttpClient::new(url.as_str())
+                    .with_context(|| format!("Invalid http url: {}", url))?;
+                Ok(Endpoint {
+                    url: url.clone(),
+                    client,
+                    failures: AtomicU32::new(0),
+                })
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        Ok(Self {
+            endpoints,
+            active: AtomicUsize::new(0),
+            pins: AtomicUsize::new(0),
+        })
+    }
+
+    /// The active endpoint index (to be handed back to `report_*`) and its client.
+    pub fn client(&self) -> (usize, HttpClient) {
+        let index = self.active.load(Ordering::Relaxed);
+        (index, self.endpoints[index].client.clone())
+    }
+
+    pub fn active_url(&self) -> &str {
+        &self.endpoints[self.active.load(Ordering::Relaxed)].url
+    }
+
+    /// Keep the active endpoint until the matching `pin(false)`, however it
+    /// fails. Pins nest.
+    pub fn pin(&self, pinned: bool) {
+        if pinned {
+            self.pins.fetch_add(1, Ordering::Relaxed);
+        } else {
+            let _ = self
+                .pins
+                .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |pins| {
+                    pins.checked_sub(1)
+                });
+        }
+    }
+
+    pub fn report_success(&self, index: usize) {
+        self.endpoints[index].failures.store(0, Ordering::Relaxed);
+    }
+
+    pub fn report_failure(&self, index: usize) {
+        let failures = self.endpoints[index]
+            .failures
+            .fetch_add(1, Ordering::Relaxed)
+            + 1;
+        if self.pins.load(Ordering::Relaxed) > 0 {
+            return;
+        }
+
+        let Some(next) = self
+            .endpoints
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != index)
+            .min_by_key(|(_, endpoint)| endpoint.failures.load(Ordering::Relaxed))
+            .map(|(i, _)| i)
+        else {
+            return;
+        };
+
+        // Another caller may have already rotated away from the failing endpoint
+        if self
+            .active
+            .compare_exchange(index, next, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            tracing::warn!(
+                "RPC {} failed ({} consecutive failures), switching to {}",
+                self.endpoints[index].url,
+                failures,
+                self.endpoints[next].url
+            );
+        }
+    }
+
+    /// Make the endpoint with the fewest recent failures and, among those,
+    /// the highest block height the active one.
+    pub async fn select_freshest(&self) {
+        let heights = join_all(self.endpoints.iter().map(|endpoint| async move {
+            match endpoint.client.status().await {
+                Ok(status) => Some(status.sync_info.latest_block_height.value()),
+                Err(err) => {
+                    tracing::warn!("RPC {} is unreachable: {}", endpoint.url, err);
+                    endpoint.failures.fetch_add(1, Ordering::Relaxed);
+                    None
+                }
+            }
+        }))
+        .await;
+
+        let best = self
+            .endpoints
+            .iter()
+            .zip(heights)
+            .enumerate()
+            .filter_map(|(index, (endpoint, height))| {
+                height.map(|height| (index, endpoint.failures.load(Ordering::Relaxed), height))
+            })
+            .min_by_key(|(_, failures, height)| (*failures, Reverse(*height)));
+
+        match best {
+            Some((index, _, height)) => {
+                self.active.store(index, Ordering::Relaxed);
+                tracing::debug!(
+                    "Using RPC {} at height {}",
+                    self.endpoints[index].url,
+                    height
+                );
+            }
+            None => tracing::warn!("No RPC endpoint reported its status"),
+        }
+    }
+
+    /// Log the node and app versions of every endpoint, to tell after the
+    /// fact what a failing query was talking to.
+    pub async fn log_versions(&self) {
+        join_all(self.endpoints.iter().map(|endpoint| async move {
+            let node = endpoint.client.status().await;
+            let app = endpoint.client.abci_info().await;
+            match (node, app) {
+                (Ok(status), Ok(info)) => tracing::info!(
+                    "RPC {} runs {} {} on CometBFT {}",
+                    endpoint.url,
+                    info.data,
+                    info.version,
+                    status.node_info.version
+                ),
+                (Err(err), _) | (_, Err(err)) => {
+                    tracing::warn!("Can't tell the version of RPC {}: {}", endpoint.url, err)
+                }
+            }
+        }))
+        .await;
+    }
+
+    /// Fail if any reachable endpoint serves another chain than `chain_id`,
+    /// or if none can be reached to confirm it.
+    pub async fn check_chain_id(&self, chain_id: &ChainId) -> anyhow::Result<()> {
+        let networks = join_all(self.endpoints.iter().map(|endpoint| async move {
+            match endpoint.client.status().await {
+                Ok(status) => Some(status.node_info.network.to_string()),
+                Err(err) => {
+                    tracing::warn!("RPC {} is unreachable: {}", endpoint.url, err);
+                    None
+                }
+            }
+        }))
+        .await;
+
+        let mut confirmed = false;
+        for (endpoint, network) in self.endpoints.iter().zip(networks) {
+            let Some(network) = network else {
+                continue;
+            };
+            anyhow::ensure!(
+                network == chain_id.as_str(),
+                "RPC {} serves chain {}, expected {}",
+                endpoint.url,
+                network,
+                chain_id
+            );
+            confirmed = true;
+        }
+
+        anyhow::ensure!(
+            confirmed,
+            "No RPC endpoint reachable to confirm chain {}",
+            chain_id
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,185 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::config::AppConfig;
+
+/// Slack in seconds after the estimated start of an epoch, so its first block
+/// is in by the time we wake up.
+pub const EPOCH_WAIT_MARGIN: u64 = 30;
+
+/// Where the chain is in its epochs.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct EpochClock {
+    pub current: u64,
+    /// Seconds until the next epoch starts
+    pub next_in: u64,
+    /// Minimum duration of an epoch in seconds
+    pub length: u64,
+}
+
+impl EpochClock {
+    /// Seconds until `epoch` starts, zero if it already has.
+    pub fn until(&self, epoch: u64) -> u64 {
+        match epoch.checked_sub(self.current + 1) {
+            Some(later) => self
+                .next_in
+                .saturating_add(later.saturating_mul(self.length)),
+            None => 0,
+        }
+    }
+}
+
+/// What the next wake-up depends on, as of the unix timestamp `now`.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct WakeInputs {
+    pub now: u64,
+    /// Earliest interval deadline of the accounts
+    pub next_due: Option<u64>,
+    /// Earliest epoch an account may compound at with `--epoch-aligned`
+    pub due_epoch: Option<u64>,
+    /// `None` if it couldn't be queried or isn't needed, see [`needs_epoch`]
+    pub epoch: Option<EpochClock>,
+    /// Whether every account was below the minimum claim in the last round
+    pub below_minimum: bool,
+}
+
+/// Whether [`next_wake`] looks at the [`EpochClock`] of its inputs.
+pub fn needs_epoch(config: &AppConfig, below_minimum: bool) -> bool {
+    (config.wait_for_epoch && below_minimum) || (config.adaptive_sleep && config.epoch_aligned)
+}
+
+/// Unix timestamp to run the next round at:
+/// - with `--wait-for-epoch` and every account below the minimum, the start
+///   of the next epoch, which brings new rewards
+/// - with `--adaptive-sleep`, once the interval deadline is reached and, with
+///   `--epoch-aligned`, the due epoch started, at least `--sleep-for` and at
+///   most `--max-sleep-secs` from now
+/// - `--sleep-for` from now otherwise
+pub fn next_wake(config: &AppConfig, inputs: WakeInputs) -> u64 {
+    let now = inputs.now;
+
+    if config.wait_for_epoch && inputs.below_minimum {
+        return match inputs.epoch {
+            Some(epoch) => now + epoch.next_in + EPOCH_WAIT_MARGIN,
+            None => now + config.sleep_for,
+        };
+    }
+    if !config.adaptive_sleep {
+        return now + config.sleep_for;
+    }
+
+    let due_in = inputs.next_due.map(|due| due.saturating_sub(now));
+    let epoch_in = match (config.epoch_aligned, inputs.due_epoch, inputs.epoch) {
+        (true, Some(due_epoch), Some(epoch)) if due_epoch > epoch.current => {
+            Some(epoch.until(due_epoch) + EPOCH_WAIT_MARGIN)
+        }
+        _ => None,
+    };
+    // Both have to pass before the next compound
+    let wait = due_in
+        .max(epoch_in)
+        .unwrap_or_default()
+        .min(config.max_sleep_secs)
+        .max(config.sleep_for);
+
+    now + wait
+}
+
+/// How long to sleep until [`next_wake`].
+pub fn sleep_until_wake(config: &AppConfig, inputs: WakeInputs) -> Duration {
+    Duration::from_secs(next_wake(config, inputs).saturating_sub(inputs.now))
+}
+
+/// The current unix timestamp, for [`WakeInputs::now`].
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{next_wake, EpochClock, WakeInputs, EPOCH_WAIT_MARGIN};
+    use crate::config::{test::config_with, AppConfig};
+
+    const NOW: u64 = 1_700_000_000;
+
+    fn config(extra: &[&str]) -> AppConfig {
+        config_with(&[&["--sleep-for", "5"], extra].concat())
+    }
+
+    fn due_in(secs: Option<u64>) -> WakeInputs {
+        WakeInputs {
+            now: NOW,
+            next_due: secs.map(|secs| NOW + secs),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_sleep_for() {
+        let config = config(&[]);
+        assert_eq!(next_wake(&config, due_in(Some(600))), NOW + 5);
+
+        let config = self::config(&["--adaptive-sleep"]);
+        assert_eq!(next_wake(&config, due_in(Some(600))), NOW + 600);
+        assert_eq!(next_wake(&config, due_in(Some(86_400))), NOW + 3_600);
+        assert_eq!(next_wake(&config, due_in(Some(1))), NOW + 5);
+        assert_eq!(next_wake(&config, due_in(None)), NOW + 5);
+    }
+
+    #[test]
+    fn test_epoch_aligned() {
+        let config = config(&["--adaptive-sleep", "--epoch-aligned"]);
+        let epoch = EpochClock {
+            current: 10,
+            next_in: 200,
+            length: 600,
+        };
+        let inputs = |due_epoch| WakeInputs {
+            due_epoch: Some(due_epoch),
+            epoch: Some(epoch),
+            ..due_in(Some(100))
+        };
+
+        // The interval deadline comes first, then the start of epoch 12
+        assert_eq!(
+            next_wake(&config, inputs(12)),
+            NOW + 800 + EPOCH_WAIT_MARGIN
+        );
+        // Already there, down to the interval
+        assert_eq!(next_wake(&config, inputs(10)), NOW + 100);
+        assert_eq!(epoch.until(9), 0);
+        assert_eq!(epoch.until(11), 200);
+    }
+
+    #[test]
+    fn test_wait_for_epoch() {
+        let config = config(&["--wait-for-epoch", "--adaptive-sleep"]);
+        let below_minimum = WakeInputs {
+            below_minimum: true,
+            epoch: Some(EpochClock {
+                current: 10,
+                next_in: 7_200,
+                length: 7_200,
+            }),
+            ..due_in(Some(600))
+        };
+
+        // Uncapped, nothing changes before the next epoch
+        assert_eq!(
+            next_wake(&config, below_minimum),
+            NOW + 7_200 + EPOCH_WAIT_MARGIN
+        );
+        let unknown = WakeInputs {
+            epoch: None,
+            ..below_minimum
+        };
+        assert_eq!(next_wake(&config, unknown), NOW + 5);
+        let due = WakeInputs {
+            below_minimum: false,
+            ..below_minimum
+        };
+        assert_eq!(next_wake(&config, due), NOW + 600);
+    }
+}
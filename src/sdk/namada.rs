@@ -0,0 +1,204 @@
+use std::{path::Path, str::FromStr, time::Duration};
+
+use namada_sdk::{
+    address::Address,
+    chain::ChainId,
+    control_flow::install_shutdown_signal,
+    io::{DevNullProgressBar, NullIo},
+    key::common::{PublicKey, SecretKey},
+    masp::{
+        fs::FsShieldedUtils, shielded_wallet::ShieldedApi, DatedKeypair, ExtendedViewingKey,
+        LedgerMaspClient, MaspLocalTaskEnv, ShieldedSyncConfig,
+    },
+    rpc, token,
+    wallet::fs::FsWalletUtils,
+    Namada, NamadaImpl,
+};
+use tendermint_rpc::{Client, HttpClient};
+
+use crate::error::{TaskError, TaskResult};
+
+pub type NamadaContext = NamadaImpl<HttpClient, FsWalletUtils, FsShieldedUtils, NullIo>;
+
+/// SDK context backed by the wallet in `wallet_dir`, holding the delegator
+/// key under `alias` so claim and bond txs can be signed with it.
+///
+/// The wallet may be shared with the CLI: the key is only added in memory,
+/// and an alias already holding it is reused instead.
+pub struct Sdk {
+    pub namada: NamadaContext,
+    alias: String,
+}
+
+impl std::fmt::Debug for Sdk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Sdk")
+            .field("alias", &self.alias)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Sdk {
+    pub async fn new(
+        client: HttpClient,
+        wallet_dir: &Path,
+        secret_key: &SecretKey,
+        alias: &str,
+        overwrite: bool,
+    ) -> TaskResult<Self> {
+        let chain_id = client
+            .status()
+            .await
+            .map_err(TaskError::rpc("Error fetching node status"))?
+            .node_info
+            .network
+            .to_string();
+        let chain_id = ChainId::from_str(&chain_id)
+            .map_err(|err| TaskError::InvalidResponse(format!("Invalid chain id: {}", err)))?;
+
+        let mut wallet = FsWalletUtils::new(wallet_dir.to_path_buf());
+        if wallet_dir.join("wallet.toml").exists() {
+            wallet
+                .load()
+                .map_err(|err| TaskError::InvalidKey(format!("Can't load wallet: {}", err)))?;
+        }
+
+        let shielded_ctx = FsShieldedUtils::new(wallet_dir.join("masp"));
+
+        let namada = NamadaImpl::new(client, wallet, shielded_ctx.into(), NullIo)
+            .await
+            .map_err(|err| TaskError::ShieldedSync(err.to_string()))?
+            .chain_id(chain_id);
+
+        let alias = insert_keypair(&namada, secret_key, alias, overwrite).await?;
+
+        Ok(Self { namada, alias })
+    }
+
+    pub async fn find_secret_key(&self) -> TaskResult<SecretKey> {
+        self.namada
+            .wallet_mut()
+            .await
+            .find_secret_key(&self.alias, None)
+            .map_err(|err| TaskError::InvalidKey(format!("Can't find {}: {}", self.alias, err)))
+    }
+
+    /// Fetch the notes of `viewing_key` since the last sync, then return its
+    /// `token` balance at the current MASP epoch, MASP rewards included. The
+    /// synced notes are kept in the wallet directory.
+    pub async fn shielded_balance(
+        &self,
+        viewing_key: &ExtendedViewingKey,
+        token: &Address,
+    ) -> TaskResult<token::Amount> {
+        let viewing_key = viewing_key.as_viewing_key();
+        let mut shielded = self.namada.shielded_mut().await;
+        // Nothing to load on the first sync
+        let _ = shielded.load().await;
+
+        let config = ShieldedSyncConfig::builder()
+            .client(LedgerMaspClient::new(
+                self.namada.client().clone(),
+                100,
+                Duration::from_millis(5),
+            ))
+            .fetched_tracker(DevNullProgressBar)
+            .scanned_tracker(DevNullProgressBar)
+            .applied_tracker(DevNullProgressBar)
+            .shutdown_signal(install_shutdown_signal(false))
+            .build();
+        let env =
+            MaspLocalTaskEnv::new(500).map_err(|err| TaskError::ShieldedSync(err.to_string()))?;
+        shielded
+            .sync(
+                env,
+                config,
+                None,
+                &[],
+                &[DatedKeypair::new(viewing_key, None)],
+            )
+            .await
+            .map_err(|err| TaskError::ShieldedSync(err.to_string()))?;
+
+        let epoch = rpc::query_masp_epoch(self.namada.client())
+            .await
+            .map_err(TaskError::rpc("Error fetching MASP epoch"))?;
+        let balance = shielded
+            .compute_exchanged_balance(self.namada.client(), &NullIo, &viewing_key, epoch)
+            .await
+            .map_err(|err| TaskError::ShieldedSync(err.to_string()))?;
+        let Some(balance) = balance else {
+            return Ok(token::Amount::zero());
+        };
+
+        let (balance, _) = shielded
+            .decode_combine_sum_to_epoch(self.namada.client(), balance, epoch)
+            .await;
+        let change = balance.get(token);
+        if change.is_negative() {
+            return Err(TaskError::InvalidResponse(format!(
+                "Negative shielded balance {}",
+                change
+            )));
+        }
+        Ok(token::Amount::from_change(change))
+    }
+
+    pub async fn find_public_key(&self) -> TaskResult<PublicKey> {
+        self.namada
+            .wallet()
+            .await
+            .find_public_key(&self.alias)
+            .map_err(|err| TaskError::InvalidKey(format!("Can't find {}: {}", self.alias, err)))
+    }
+}
+
+/// Make `secret_key` available for signing and return its alias: the one it
+/// already has in the wallet if stored unencrypted, otherwise `alias`. Unless
+/// `overwrite`, `alias` can't replace a different key.
+async fn insert_keypair(
+    namada: &NamadaContext,
+    secret_key: &SecretKey,
+    alias: &str,
+    overwrite: bool,
+) -> TaskResult<String> {
+    let public_key = secret_key.to_public();
+    let address = Address::from(&public_key);
+    let mut wallet = namada.wallet_mut().await;
+
+    if !overwrite {
+        let existing = wallet.find_alias(&address).map(ToString::to_string);
+        if let Some(existing) = existing {
+            let unencrypted = wallet
+                .store()
+                .find_secret_key(&existing)
+                .is_some_and(|stored| !stored.is_encrypted());
+            if unencrypted {
+                tracing::debug!("Using the wallet key {}", existing);
+                return Ok(existing);
+            }
+        }
+
+        if let Ok(other) = wallet.find_public_key(alias) {
+            if other != public_key {
+                return Err(TaskError::InvalidKey(format!(
+                    "Wallet alias {} holds another key, pass --overwrite-wallet to replace it",
+                    alias
+                )));
+            }
+        }
+    }
+
+    wallet
+        .insert_keypair(
+            alias.to_string(),
+            true,
+            secret_key.clone(),
+            None,
+            Some(address),
+            None,
+        )
+        .ok_or_else(|| TaskError::InvalidKey(format!("Can't add {} to the wallet", alias)))?;
+
+    Ok(alias.to_string())
+}
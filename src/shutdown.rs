@@ -0,0 +1,71 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use tokio::{sync::Notify, time::sleep};
+
+/// Set once SIGINT/SIGTERM is received. The loop checks it between steps so a
+/// round that already claimed still gets to bond before exiting.
+#[derive(Debug, Clone, Default)]
+pub struct Shutdown {
+    requested: Arc<AtomicBool>,
+    notify: Arc<Notify>,
+}
+
+impl Shutdown {
+    pub fn listen() -> Self {
+        let shutdown = Self::default();
+
+        let handle = shutdown.clone();
+        tokio::spawn(async move {
+            wait_for_signal().await;
+            tracing::info!("Shutdown requested, finishing the current round...");
+            handle.trigger();
+        });
+
+        shutdown
+    }
+
+    pub fn trigger(&self) {
+        self.requested.store(true, Ordering::SeqCst);
+        self.notify.notify_waiters();
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Sleep for `duration`, waking up early if shutdown is requested.
+    pub async fn sleep(&self, duration: Duration) {
+        let notified = self.notify.notified();
+        if self.is_requested() {
+            return;
+        }
+
+        tokio::select! {
+            _ = sleep(duration) => {}
+            _ = notified => {}
+        }
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("Can't listen for SIGTERM");
+
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,134 @@
+use anyhow::Context;
+
+use crate::{
+    config::{AppConfig, BreakEvenArgs, CurveArgs, CurveFormat, SimulateArgs},
+    opt::{self, RoundFee},
+};
+
+/// Offline projection of every compounding round over the horizon.
+pub fn print_simulation(args: &SimulateArgs, config: &AppConfig) -> anyhow::Result<()> {
+    let frequency = match args.frequency {
+        Some(frequency) => frequency,
+        None => {
+            config
+                .optimizer
+                .optimize(
+                    args.principal,
+                    args.apr,
+                    RoundFee::flat(args.fee),
+                    None,
+                    config.horizon_years,
+                    config.max_frequency(),
+                )
+                .context("Failed optimizing frequency")?
+                .optimal_frequency
+        }
+    };
+    anyhow::ensure!(frequency > 0, "--frequency must be at least 1");
+
+    let trajectory = opt::simulate(
+        args.principal,
+        args.apr,
+        args.fee,
+        frequency,
+        config.horizon_years,
+    );
+    let days_between = 365.0 / frequency as f64;
+
+    println!(
+        "Compounding {} times a year (every {:.2} days) for {} year(s)",
+        frequency, days_between, config.horizon_years
+    );
+    println!();
+    println!(
+        "{:>6} {:>10} {:>20} {:>14}",
+        "Round", "Day", "Balance", "Fees paid"
+    );
+
+    for step in &trajectory {
+        println!(
+            "{:>6} {:>10.2} {:>20.6} {:>14.6}",
+            step.round,
+            step.round as f64 * days_between,
+            step.balance,
+            step.fees_paid
+        );
+    }
+
+    match trajectory.last() {
+        Some(last) => {
+            println!();
+            println!("Final balance: {:.6}", last.balance);
+            println!("Total fees: {:.6}", last.fees_paid);
+            println!(
+                "Gain: {:.6} ({:.2}%)",
+                last.balance - args.principal,
+                (last.balance / args.principal - 1.0) * 100.0
+            );
+        }
+        None => println!("The fees drain the balance before the first round completes"),
+    }
+
+    Ok(())
+}
+
+/// `(frequency, balance)` pairs of [`opt::cost_curve`] as JSON objects.
+pub fn curve_json(curve: &[(f64, f64)]) -> serde_json::Value {
+    curve
+        .iter()
+        .map(|(frequency, balance)| {
+            serde_json::json!({ "frequency": frequency, "balance": balance })
+        })
+        .collect()
+}
+
+/// Offline final balance at frequencies across the optimizer's range, to
+/// check the optimum is a real peak and how flat it is.
+pub fn print_cost_curve(args: &CurveArgs, config: &AppConfig) -> anyhow::Result<()> {
+    let curve = opt::cost_curve(
+        args.principal,
+        args.apr,
+        args.fee,
+        config.horizon_years,
+        args.samples,
+    );
+
+    match args.format {
+        CurveFormat::Csv => {
+            println!("frequency,balance");
+            for (frequency, balance) in &curve {
+                println!("{},{:.6}", frequency, balance);
+            }
+        }
+        CurveFormat::Json => println!("{}", curve_json(&curve)),
+    }
+
+    Ok(())
+}
+
+/// Offline APR below which compounding `args.frequency` times a year loses
+/// money to the fees.
+pub fn print_break_even(args: &BreakEvenArgs, config: &AppConfig) -> anyhow::Result<()> {
+    anyhow::ensure!(args.frequency > 0, "--frequency must be at least 1");
+
+    let apr = opt::break_even_apr(
+        args.principal,
+        args.fee,
+        args.frequency,
+        config.horizon_years,
+    );
+    if apr.is_finite() {
+        println!(
+            "Compounding {} times a year breaks even at {:.4}% APR",
+            args.frequency,
+            apr * 100.0
+        );
+    } else {
+        println!(
+            "Compounding {} times a year never covers its fees",
+            args.frequency
+        );
+    }
+
+    Ok(())
+}
@@ -1,43 +1,943 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
-#[derive(Debug, Clone)]
+use anyhow::Context;
+use namada_sdk::{address::Address, token};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    denom,
+    opt::{OptimizationCache, SECONDS_PER_YEAR},
+};
+
+/// Failures in a row after which a claim isn't retried on its own anymore,
+/// its rewards going with the next scheduled compound instead
+pub const MAX_CLAIM_ATTEMPTS: u32 = 3;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct State {
     pub last_claimed_timestamp: u64,
     pub claimed_first_time: bool,
+    #[serde(default)]
+    pub last_claimed_epoch: u64,
+    /// Last claim per validator address, for `--per-validator-schedule`
+    #[serde(default)]
+    pub validators: BTreeMap<String, u64>,
+    /// Validator addresses delegated to as of the last round
+    #[serde(default)]
+    pub delegations: BTreeSet<String>,
+    /// Exponential moving average of the net APR, see `--apr-ema-alpha`
+    #[serde(default)]
+    pub apr_ema: Option<f64>,
+    /// Last epoch folded into `apr_ema`
+    #[serde(default)]
+    pub apr_ema_epoch: u64,
+    /// Optimal seconds between two compounds, as of the last one
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    /// When the next compound is due
+    #[serde(default)]
+    pub next_due_timestamp: Option<u64>,
+    /// Average fee of the txs sent so far, in place of the estimate once
+    /// known
+    #[serde(default)]
+    pub avg_fee_per_tx: Option<f64>,
+    /// Txs counted in `avg_fee_per_tx`
+    #[serde(default)]
+    pub fee_samples: u64,
+    /// Uptime of each validator address by epoch, for
+    /// `--uptime-window-epochs`
+    #[serde(default)]
+    pub uptime: BTreeMap<String, BTreeMap<u64, f64>>,
+    /// Rewards claimed by a round that didn't get to bond them
+    #[serde(default)]
+    pub pending_bond: Option<PendingBond>,
+    /// Rewards a `--round-order bond-then-claim` round bonded ahead, but
+    /// didn't get to claim
+    #[serde(default)]
+    pub pending_claim: Option<PendingClaim>,
+    /// Rewards left liquid until they reach `--accumulate-until`
+    #[serde(default)]
+    pub accumulated: token::Amount,
+    /// Validators whose claim failed, retried once per epoch
+    #[serde(default)]
+    pub failed_claims: BTreeMap<String, FailedClaim>,
+    /// Validator `--bond-target best-apr` went to last, for
+    /// `--target-cooldown-epochs`
+    #[serde(default)]
+    pub chosen_target: Option<ChosenTarget>,
+    /// Validators `--auto-select-validators` bonded to last, for
+    /// `--target-cooldown-epochs`
+    #[serde(default)]
+    pub auto_selected: Option<ChosenValidators>,
+    /// Gains, net of fees, projected for the compounds so far
+    #[serde(default)]
+    pub projected_gain_total: f64,
+    /// What those compounds actually gained
+    #[serde(default)]
+    pub realized_gain_total: f64,
+    /// Total bonded as of the last round, for `--anomaly-threshold-pct`
+    #[serde(default)]
+    pub last_bonded_amount: Option<f64>,
+    /// Why compounding is paused, until `--clear-anomaly`
+    #[serde(default)]
+    pub anomaly: Option<String>,
+    /// Rewards bonded back so far, until `--reset-stats`
+    #[serde(default)]
+    pub compounded_total: f64,
+    /// Fees paid by those compounds, from the gas they used
+    #[serde(default)]
+    pub fees_paid_total: f64,
+    /// Where the state was loaded from, for [`State::checkpoint`]
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    /// Recomputed on restart
+    #[serde(skip)]
+    pub optimization: OptimizationCache,
+    #[serde(skip)]
+    pub last_round: Option<LastRound>,
+}
+
+/// What the last round saw and optimized for, served by `GET /status`
+/// without querying the chain again.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct LastRound {
+    pub epoch: u64,
+    pub bonded_amount: f64,
+    pub net_apr: f64,
+    pub optimal_frequency_hours: f64,
+}
+
+/// The validator bonded to since `epoch`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChosenTarget {
+    pub validator: String,
+    pub epoch: u64,
+}
+
+/// The validators bonded to since `epoch`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChosenValidators {
+    pub validators: BTreeSet<String>,
+    pub epoch: u64,
+}
+
+/// Rewards bonded ahead of claiming them, kept until claimed so the next
+/// round only claims them instead of bonding them again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingClaim {
+    /// Validator addresses still to claim from
+    pub validators: BTreeSet<String>,
+    /// What was bonded ahead, zero once a round recorded it
+    pub bond: PendingBond,
+}
+
+/// A claim that failed, and why.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FailedClaim {
+    pub reason: String,
+    /// Failures in a row, up to [`MAX_CLAIM_ATTEMPTS`]
+    pub attempts: u32,
+    /// Of the last attempt
+    pub epoch: u64,
+}
+
+/// A claim whose rewards are still to be bonded, kept across restarts so the
+/// next round bonds them instead of claiming again.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PendingBond {
+    pub epoch: u64,
+    pub rewards: token::Amount,
+    /// Share of `rewards` to bond, per `--compound-ratio`
+    pub amount: token::Amount,
+    /// `None` if not queried, see `--claimed-amount`
+    #[serde(default)]
+    pub balance_pre: Option<token::Amount>,
+    #[serde(default)]
+    pub balance_post: Option<token::Amount>,
+    /// Gain, net of fees, the round expected, `None` for the first compound
+    #[serde(default)]
+    pub projected_gain: Option<f64>,
 }
 
 impl State {
     pub fn init() -> Self {
         Self {
-            last_claimed_timestamp: SystemTime::now()
-                .duration_since(UNIX_EPOCH)
-                .unwrap()
-                .as_secs(),
+            last_claimed_timestamp: now(),
             claimed_first_time: false,
+            last_claimed_epoch: 0,
+            validators: BTreeMap::new(),
+            delegations: BTreeSet::new(),
+            apr_ema: None,
+            apr_ema_epoch: 0,
+            interval_secs: None,
+            next_due_timestamp: None,
+            avg_fee_per_tx: None,
+            fee_samples: 0,
+            uptime: BTreeMap::new(),
+            pending_bond: None,
+            pending_claim: None,
+            accumulated: token::Amount::zero(),
+            failed_claims: BTreeMap::new(),
+            chosen_target: None,
+            auto_selected: None,
+            projected_gain_total: 0.0,
+            realized_gain_total: 0.0,
+            last_bonded_amount: None,
+            anomaly: None,
+            compounded_total: 0.0,
+            fees_paid_total: 0.0,
+            path: None,
+            optimization: OptimizationCache::default(),
+            last_round: None,
+        }
+    }
+
+    /// Load the state persisted at `path`, or start fresh if there is none yet.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut state = if path.exists() {
+            let raw = std::fs::read_to_string(path)
+                .with_context(|| format!("Can't read state file {}", path.display()))?;
+            serde_json::from_str::<Self>(&raw)
+                .with_context(|| format!("Can't parse state file {}", path.display()))?
+        } else {
+            Self::init()
+        };
+
+        state.path = Some(path.to_path_buf());
+        Ok(state)
+    }
+
+    /// Write the state to `path` through a temporary file so a crash can't
+    /// leave it half-written.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("tmp");
+        let raw = serde_json::to_string_pretty(self).context("Can't serialize state")?;
+
+        std::fs::write(&tmp_path, raw)
+            .and_then(|_| std::fs::rename(&tmp_path, path))
+            .with_context(|| format!("Can't write state file {}", path.display()))
+    }
+
+    /// Save the state mid-round to where it was loaded from, if anywhere, so
+    /// a crash doesn't lose what the round already did on chain.
+    pub fn checkpoint(&self) -> anyhow::Result<()> {
+        match &self.path {
+            Some(path) => self.save(path),
+            None => Ok(()),
+        }
+    }
+
+    /// `interval` is the number of seconds between two compounding rounds.
+    pub fn should_reclaim(&self, interval: u64) -> bool {
+        self.should_reclaim_at(interval, now())
+    }
+
+    /// [`State::should_reclaim`] as of the unix timestamp `now`.
+    pub fn should_reclaim_at(&self, interval: u64, now: u64) -> bool {
+        !self.claimed_first_time || now.saturating_sub(self.last_claimed_timestamp) >= interval
+    }
+
+    /// Seconds left until the next compounding round is due.
+    pub fn next_reclaim_in(&self, interval: u64) -> u64 {
+        self.next_reclaim_in_at(interval, now())
+    }
+
+    /// [`State::next_reclaim_in`] as of the unix timestamp `now`.
+    pub fn next_reclaim_in_at(&self, interval: u64, now: u64) -> u64 {
+        interval.saturating_sub(now.saturating_sub(self.last_claimed_timestamp))
+    }
+
+    /// Seconds left until the scheduled compound, if any.
+    pub fn next_due_in(&self) -> Option<u64> {
+        self.next_due_timestamp.map(|due| due.saturating_sub(now()))
+    }
+
+    /// Like [`State::should_reclaim`] for a single validator, due right away
+    /// if it was never claimed from.
+    pub fn should_reclaim_validator(&self, validator: &Address, interval: u64) -> bool {
+        match self.validators.get(&validator.to_string()) {
+            Some(last_claimed) => now().saturating_sub(*last_claimed) >= interval,
+            None => true,
+        }
+    }
+
+    pub fn next_validator_reclaim_in(&self, validator: &Address, interval: u64) -> u64 {
+        match self.validators.get(&validator.to_string()) {
+            Some(last_claimed) => interval.saturating_sub(now().saturating_sub(*last_claimed)),
+            None => 0,
+        }
+    }
+
+    /// First epoch [`State::should_reclaim_epoch`] holds at, `None` before
+    /// the first claim.
+    pub fn next_due_epoch(&self, epochs: u64) -> Option<u64> {
+        self.claimed_first_time
+            .then(|| self.last_claimed_epoch.saturating_add(epochs))
+    }
+
+    /// Whether at least `epochs` epochs went by since the last claim.
+    pub fn should_reclaim_epoch(&self, current_epoch: u64, epochs: u64) -> bool {
+        !self.claimed_first_time || current_epoch.saturating_sub(self.last_claimed_epoch) >= epochs
+    }
+
+    /// Record a round that claimed from `validators` at `epoch`.
+    pub fn update_validators<'a>(
+        &mut self,
+        validators: impl IntoIterator<Item = &'a Address>,
+        epoch: u64,
+    ) {
+        self.update();
+        self.last_claimed_epoch = epoch;
+        for validator in validators {
+            self.validators
+                .insert(validator.to_string(), self.last_claimed_timestamp);
+        }
+    }
+
+    /// Whether the failed claim from `validator` is to be retried at
+    /// `epoch`, at most once per epoch.
+    pub fn should_retry_claim(&self, validator: &Address, epoch: u64) -> bool {
+        self.failed_claims
+            .get(&validator.to_string())
+            .is_some_and(|claim| claim.epoch < epoch)
+    }
+
+    /// Record the claims from `attempted` at `epoch`, of which those in
+    /// `failed` failed, with why. Returns the validators given up on after
+    /// [`MAX_CLAIM_ATTEMPTS`] failures.
+    pub fn record_claims<'a>(
+        &mut self,
+        attempted: impl IntoIterator<Item = &'a Address>,
+        failed: &BTreeMap<String, String>,
+        epoch: u64,
+    ) -> Vec<String> {
+        let mut given_up = vec![];
+        for validator in attempted {
+            let validator = validator.to_string();
+            let attempts = self
+                .failed_claims
+                .remove(&validator)
+                .map_or(0, |claim| claim.attempts)
+                + 1;
+            let Some(reason) = failed.get(&validator) else {
+                continue;
+            };
+            if attempts >= MAX_CLAIM_ATTEMPTS {
+                given_up.push(validator);
+                continue;
+            }
+            self.failed_claims.insert(
+                validator,
+                FailedClaim {
+                    reason: reason.clone(),
+                    attempts,
+                    epoch,
+                },
+            );
+        }
+        given_up
+    }
+
+    /// Record a round that only retried the failed claims of the last one,
+    /// from `validators`, leaving the schedule of the others as is.
+    pub fn update_retried<'a>(&mut self, validators: impl IntoIterator<Item = &'a Address>) {
+        let now = now();
+        for validator in validators {
+            self.validators.insert(validator.to_string(), now);
+        }
+    }
+
+    /// Replace the delegations of the last round by `validators`, forgetting
+    /// the schedule and uptime of the validators no longer delegated to.
+    /// Returns the added and removed validators, none on the first round.
+    pub fn reconcile_delegations<'a>(
+        &mut self,
+        validators: impl IntoIterator<Item = &'a Address>,
+    ) -> (Vec<String>, Vec<String>) {
+        let validators = validators
+            .into_iter()
+            .map(Address::to_string)
+            .collect::<BTreeSet<_>>();
+        let previous = std::mem::replace(&mut self.delegations, validators);
+        if previous.is_empty() {
+            return (vec![], vec![]);
+        }
+
+        let added = self
+            .delegations
+            .difference(&previous)
+            .cloned()
+            .collect::<Vec<_>>();
+        let removed = previous
+            .difference(&self.delegations)
+            .cloned()
+            .collect::<Vec<_>>();
+        for validator in &removed {
+            self.validators.remove(validator);
+            self.uptime.remove(validator);
+        }
+
+        (added, removed)
+    }
+
+    /// Rewards `bonds` should have earned at `apr` since each validator was
+    /// last claimed from, `None` before the first compound.
+    pub fn projected_rewards<'a>(
+        &self,
+        bonds: impl IntoIterator<Item = (&'a Address, f64)>,
+        apr: f64,
+    ) -> Option<f64> {
+        if !self.claimed_first_time {
+            return None;
+        }
+
+        let now = now();
+        let rewards = bonds
+            .into_iter()
+            .map(|(validator, bond)| {
+                let last_claimed = self
+                    .validators
+                    .get(&validator.to_string())
+                    .copied()
+                    .unwrap_or(self.last_claimed_timestamp);
+                bond * apr * now.saturating_sub(last_claimed) as f64 / SECONDS_PER_YEAR
+            })
+            .sum();
+        Some(rewards)
+    }
+
+    /// Fold a compound that gained `realized` where `projected` was expected
+    /// into the running totals, and return the ratio of the totals.
+    pub fn record_projection(&mut self, projected: f64, realized: f64) -> Option<f64> {
+        if !projected.is_finite() || !realized.is_finite() {
+            return None;
+        }
+
+        self.projected_gain_total += projected;
+        self.realized_gain_total += realized;
+        (self.projected_gain_total > 0.0)
+            .then(|| self.realized_gain_total / self.projected_gain_total)
+    }
+
+    /// Fold the `apr` observed at `epoch` into the moving average and return
+    /// the smoothed value. The APR only changes with epochs, so each epoch is
+    /// counted once no matter how many rounds run during it.
+    pub fn smooth_apr(&mut self, apr: f64, epoch: u64, alpha: f64) -> f64 {
+        let ema = match self.apr_ema {
+            Some(ema) if epoch <= self.apr_ema_epoch => ema,
+            Some(ema) => alpha * apr + (1.0 - alpha) * ema,
+            None => apr,
+        };
+
+        self.apr_ema = Some(ema);
+        self.apr_ema_epoch = self.apr_ema_epoch.max(epoch);
+        ema
+    }
+
+    /// Record that the next compound is due `interval` seconds after the
+    /// last claim.
+    pub fn schedule(&mut self, interval: u64) {
+        self.interval_secs = Some(interval);
+        self.next_due_timestamp = Some(self.last_claimed_timestamp + interval);
+    }
+
+    /// Like [`State::schedule`] with each validator on its own interval: the
+    /// next compound is due when the first of them is.
+    pub fn schedule_validators<'a>(
+        &mut self,
+        intervals: impl IntoIterator<Item = (&'a Address, u64)>,
+    ) {
+        let next = intervals
+            .into_iter()
+            .map(|(validator, interval)| {
+                let due = self
+                    .validators
+                    .get(&validator.to_string())
+                    .map_or(now(), |last_claimed| last_claimed + interval);
+                (due, interval)
+            })
+            .min();
+
+        self.interval_secs = next.map(|(_, interval)| interval);
+        self.next_due_timestamp = next.map(|(due, _)| due);
+    }
+
+    /// Record the `bonded` amount and return by how many percent it dropped
+    /// since the last round, if it did.
+    pub fn record_bonded(&mut self, bonded: f64) -> Option<f64> {
+        let previous = self.last_bonded_amount.replace(bonded)?;
+        (previous > 0.0 && bonded < previous).then(|| (previous - bonded) / previous * 100.0)
+    }
+
+    /// Validators `--bond-target best-apr` or `--auto-select-validators`
+    /// chose within `cooldown_epochs` of `epoch`, to keep bonded to.
+    pub fn chosen_validators(&self, epoch: u64, cooldown_epochs: u64) -> BTreeSet<String> {
+        let within = |chosen_epoch: u64| epoch < chosen_epoch.saturating_add(cooldown_epochs);
+        let target = self
+            .chosen_target
+            .iter()
+            .filter(|chosen| within(chosen.epoch))
+            .map(|chosen| chosen.validator.clone());
+        let selected = self
+            .auto_selected
+            .iter()
+            .filter(|chosen| within(chosen.epoch))
+            .flat_map(|chosen| chosen.validators.iter().cloned());
+        target.chain(selected).collect()
+    }
+
+    /// Resume compounding after an anomaly, comparing the next rounds to the
+    /// bonded amount from then on.
+    pub fn clear_anomaly(&mut self) {
+        self.anomaly = None;
+        self.last_bonded_amount = None;
+    }
+
+    /// Add a compound that bonded `compounded` for `fee_paid`, both in NAM.
+    pub fn record_compound(&mut self, compounded: f64, fee_paid: f64) {
+        if compounded.is_finite() && fee_paid.is_finite() {
+            self.compounded_total += compounded;
+            self.fees_paid_total += fee_paid;
+        }
+    }
+
+    /// See [`efficiency`].
+    pub fn efficiency(&self) -> Option<f64> {
+        efficiency(self.compounded_total, self.fees_paid_total)
+    }
+
+    /// Add claimed `rewards` to those accumulated until bonded at once. What
+    /// was bonded of them comes off with [`State::bonded_accumulated`].
+    pub fn accumulate(&mut self, rewards: token::Amount) {
+        self.accumulated = self
+            .accumulated
+            .checked_add(rewards)
+            .unwrap_or(self.accumulated);
+    }
+
+    /// `bonded` of the accumulated rewards went through.
+    pub fn bonded_accumulated(&mut self, bonded: token::Amount) {
+        self.accumulated = self.accumulated.checked_sub(bonded).unwrap_or_default();
+    }
+
+    /// Lifetime tally for the log.
+    pub fn stats_summary(&self) -> String {
+        let mut summary = format!(
+            "Compounded {} for {} of fees so far, net {}",
+            denom::display_nam_f64(self.compounded_total),
+            denom::display_nam_f64(self.fees_paid_total),
+            denom::display_nam_f64(self.compounded_total - self.fees_paid_total)
+        );
+        if let Some(efficiency) = self.efficiency() {
+            summary.push_str(&format!(" ({:.1}% efficient)", efficiency * 100.0));
+        }
+        if !self.accumulated.is_zero() {
+            summary.push_str(&format!(
+                ", {} accumulated to bond",
+                denom::display_nam(self.accumulated)
+            ));
         }
+        summary
+    }
+
+    /// Start the tally over, for `--reset-stats`.
+    pub fn reset_stats(&mut self) {
+        self.compounded_total = 0.0;
+        self.fees_paid_total = 0.0;
     }
 
-    pub fn should_reclaim(&self, compunding_frequency: u64) -> bool {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        !self.claimed_first_time || now - self.last_claimed_timestamp >= compunding_frequency
+    /// Fold `fee` paid over `txs` txs into the average fee per tx.
+    pub fn record_fees(&mut self, txs: u64, fee: f64) {
+        if txs == 0 || !fee.is_finite() {
+            return;
+        }
+
+        let samples = self.fee_samples + txs;
+        let total = self.avg_fee_per_tx.unwrap_or_default() * self.fee_samples as f64 + fee;
+        self.avg_fee_per_tx = Some(total / samples as f64);
+        self.fee_samples = samples;
     }
 
-    pub fn next_reclaim_in(&self, compunding_frequency: u64) -> u64 {
-        let now = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
-        (compunding_frequency * 60 * 60) - (now - self.last_claimed_timestamp)
+    /// Record the `uptime` of `validator` at `epoch` and return its average
+    /// over the last `window` epochs, dropping older samples.
+    pub fn average_uptime(
+        &mut self,
+        validator: &Address,
+        uptime: f64,
+        epoch: u64,
+        window: u64,
+    ) -> f64 {
+        let samples = self.uptime.entry(validator.to_string()).or_default();
+        samples.insert(epoch, uptime);
+        samples.retain(|sample_epoch, _| sample_epoch + window > epoch);
+
+        samples.values().sum::<f64>() / samples.len() as f64
     }
 
+    /// Record a claim made now.
     pub fn update(&mut self) {
+        self.update_at(now());
+    }
+
+    /// Record a claim made at the unix timestamp `now`.
+    pub fn update_at(&mut self, now: u64) {
         self.claimed_first_time = true;
-        self.last_claimed_timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs();
+        self.last_claimed_timestamp = now;
+    }
+}
+
+/// Share of what the compounds cost, compounded plus fees, that was
+/// compounded. `None` before any compound.
+pub fn efficiency(compounded: f64, fees_paid: f64) -> Option<f64> {
+    let total = compounded + fees_paid;
+    (total > 0.0).then(|| compounded / total)
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::{BTreeMap, BTreeSet};
+
+    use namada_sdk::{address::EstablishedAddressGen, token};
+
+    use super::{ChosenTarget, ChosenValidators, PendingBond, State};
+
+    #[test]
+    fn test_first_round_is_due() {
+        let state = State::init();
+        assert!(state.should_reclaim(60 * 60));
+    }
+
+    #[test]
+    fn test_not_due_after_update() {
+        let interval = 24 * 60 * 60;
+        let mut state = State::init();
+        state.update();
+
+        assert!(!state.should_reclaim(interval));
+
+        let next = state.next_reclaim_in(interval);
+        assert!(next <= interval);
+        assert!(next >= interval - 1);
+    }
+
+    #[test]
+    fn test_reclaim_boundary() {
+        let claimed = 1_700_000_000;
+        let interval = 6 * 60 * 60;
+        let mut state = State::init();
+        assert!(state.should_reclaim_at(interval, claimed));
+
+        state.update_at(claimed);
+        assert_eq!(state.last_claimed_timestamp, claimed);
+        assert!(!state.should_reclaim_at(interval, claimed));
+        assert_eq!(state.next_reclaim_in_at(interval, claimed), interval);
+
+        // Due on the second the interval is up, not one before
+        assert!(!state.should_reclaim_at(interval, claimed + interval - 1));
+        assert_eq!(
+            state.next_reclaim_in_at(interval, claimed + interval - 1),
+            1
+        );
+        assert!(state.should_reclaim_at(interval, claimed + interval));
+        assert_eq!(state.next_reclaim_in_at(interval, claimed + interval), 0);
+        assert_eq!(
+            state.next_reclaim_in_at(interval, claimed + 2 * interval),
+            0
+        );
+
+        // A clock going backwards doesn't make it due
+        assert!(!state.should_reclaim_at(interval, claimed - 60));
+        assert_eq!(state.next_reclaim_in_at(interval, claimed - 60), interval);
+    }
+
+    #[test]
+    fn test_smooth_apr() {
+        let mut state = State::init();
+
+        assert_eq!(state.smooth_apr(0.1, 10, 0.25), 0.1);
+        // Same epoch, the dip isn't counted
+        assert_eq!(state.smooth_apr(0.02, 10, 0.25), 0.1);
+        assert!((state.smooth_apr(0.02, 11, 0.25) - 0.08).abs() < 1e-12);
+        assert_eq!(state.apr_ema_epoch, 11);
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = std::env::temp_dir().join("autocompound-state-test.json");
+        let mut state = State::init();
+        state.update();
+        state.save(&path).unwrap();
+
+        let loaded = State::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(loaded.claimed_first_time);
+        assert_eq!(loaded.last_claimed_timestamp, state.last_claimed_timestamp);
+    }
+
+    #[test]
+    fn test_checkpoint_pending_bond() {
+        let path = std::env::temp_dir().join("autocompound-checkpoint-test.json");
+        let mut state = State::load(&path).unwrap();
+        state.pending_bond = Some(PendingBond {
+            epoch: 100,
+            rewards: token::Amount::native_whole(5),
+            amount: token::Amount::native_whole(4),
+            balance_pre: Some(token::Amount::native_whole(100)),
+            balance_post: Some(token::Amount::native_whole(105)),
+            projected_gain: Some(4.5),
+        });
+        state.checkpoint().unwrap();
+
+        let loaded = State::load(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(loaded.pending_bond, state.pending_bond);
+        assert_eq!(loaded.path, Some(path));
+    }
+
+    #[test]
+    fn test_per_validator_schedule() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let validator = address_gen.generate_address("0");
+        let other = address_gen.generate_address("1");
+        let mut state = State::init();
+
+        assert!(state.should_reclaim_validator(&validator, 60));
+
+        state.update_validators([&validator], 100);
+
+        assert!(!state.should_reclaim_validator(&validator, 60));
+        assert!(state.should_reclaim_validator(&validator, 0));
+        assert!(state.should_reclaim_validator(&other, 60));
+        assert!(state.next_validator_reclaim_in(&validator, 60) <= 60);
+        assert!(state.claimed_first_time);
+    }
+
+    #[test]
+    fn test_reconcile_delegations() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let kept = address_gen.generate_address("0");
+        let unbonded = address_gen.generate_address("1");
+        let new = address_gen.generate_address("2");
+        let mut state = State::init();
+
+        let (added, removed) = state.reconcile_delegations([&kept, &unbonded]);
+        assert!(added.is_empty() && removed.is_empty());
+        state.update_validators([&kept, &unbonded], 100);
+        state.average_uptime(&unbonded, 1.0, 100, 10);
+
+        let (added, removed) = state.reconcile_delegations([&kept, &new]);
+        assert_eq!(added, [new.to_string()]);
+        assert_eq!(removed, [unbonded.to_string()]);
+        // The one left has no schedule nor uptime anymore, the new one is due
+        assert!(state.should_reclaim_validator(&unbonded, 60));
+        assert!(!state.uptime.contains_key(&unbonded.to_string()));
+        assert!(!state.should_reclaim_validator(&kept, 60));
+        assert!(state.should_reclaim_validator(&new, 60));
+    }
+
+    #[test]
+    fn test_epoch_aligned() {
+        let mut state = State::init();
+        assert!(state.should_reclaim_epoch(100, 2));
+
+        state.update_validators([], 100);
+
+        assert!(!state.should_reclaim_epoch(100, 2));
+        assert!(!state.should_reclaim_epoch(101, 2));
+        assert!(state.should_reclaim_epoch(102, 2));
+    }
+
+    #[test]
+    fn test_schedule() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let validator = address_gen.generate_address("0");
+        let other = address_gen.generate_address("1");
+        let mut state = State::init();
+        state.update_validators([&validator, &other], 100);
+
+        state.schedule(3_600);
+        assert_eq!(state.interval_secs, Some(3_600));
+        assert_eq!(
+            state.next_due_timestamp,
+            Some(state.last_claimed_timestamp + 3_600)
+        );
+
+        state.schedule_validators([(&validator, 7_200), (&other, 600)]);
+        assert_eq!(state.interval_secs, Some(600));
+        assert_eq!(
+            state.next_due_timestamp,
+            Some(state.last_claimed_timestamp + 600)
+        );
+    }
+
+    #[test]
+    fn test_average_uptime() {
+        let validator = EstablishedAddressGen::new("validators").generate_address("0");
+        let mut state = State::init();
+
+        assert_eq!(state.average_uptime(&validator, 1.0, 10, 2), 1.0);
+        assert_eq!(state.average_uptime(&validator, 0.5, 11, 2), 0.75);
+        // Epoch 10 left the window
+        assert_eq!(state.average_uptime(&validator, 0.7, 12, 2), 0.6);
+        assert_eq!(state.uptime[&validator.to_string()].len(), 2);
+    }
+
+    #[test]
+    fn test_projection() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let validator = address_gen.generate_address("0");
+        let mut state = State::init();
+        assert_eq!(state.projected_rewards([(&validator, 1_000.0)], 0.1), None);
+
+        state.update_validators([&validator], 100);
+        state.validators.insert(
+            validator.to_string(),
+            state.last_claimed_timestamp - 365 * 24 * 60 * 60,
+        );
+        let projected = state
+            .projected_rewards([(&validator, 1_000.0)], 0.1)
+            .unwrap();
+        assert!((projected - 100.0).abs() < 0.01);
+
+        assert_eq!(state.record_projection(0.0, 1.0), None);
+        assert_eq!(state.record_projection(4.0, 2.0), Some(0.75));
+        assert_eq!(state.record_projection(f64::NAN, 2.0), None);
+    }
+
+    #[test]
+    fn test_record_fees() {
+        let mut state = State::init();
+        state.record_fees(0, 1.0);
+        assert_eq!(state.avg_fee_per_tx, None);
+
+        state.record_fees(2, 0.4);
+        assert_eq!(state.avg_fee_per_tx, Some(0.2));
+
+        state.record_fees(1, 0.5);
+        assert!((state.avg_fee_per_tx.unwrap() - 0.3).abs() < 1e-12);
+        assert_eq!(state.fee_samples, 3);
+    }
+
+    #[test]
+    fn test_compound_stats() {
+        let mut state = State::init();
+        assert_eq!(state.efficiency(), None);
+
+        state.record_compound(9.0, 0.5);
+        state.record_compound(10.5, 0.5);
+        state.record_compound(f64::NAN, 0.5);
+        assert_eq!(state.compounded_total, 19.5);
+        assert_eq!(state.fees_paid_total, 1.0);
+        assert_eq!(state.efficiency(), Some(19.5 / 20.5));
+        assert_eq!(
+            state.stats_summary(),
+            "Compounded 19.500000 NAM for 1.000000 NAM of fees so far, net 18.500000 NAM (95.1% efficient)"
+        );
+
+        state.reset_stats();
+        assert_eq!(state.efficiency(), None);
+    }
+
+    #[test]
+    fn test_record_bonded() {
+        let mut state = State::init();
+        assert_eq!(state.record_bonded(1_000.0), None);
+        assert_eq!(state.record_bonded(1_010.0), None);
+        assert_eq!(state.record_bonded(909.0), Some(10.0));
+
+        state.anomaly = Some("slashed".to_string());
+        state.clear_anomaly();
+        assert_eq!(state.anomaly, None);
+        assert_eq!(state.record_bonded(500.0), None);
+    }
+
+    #[test]
+    fn test_record_claims() {
+        let mut address_gen = EstablishedAddressGen::new("validators");
+        let [failing, claimed] = [0, 1].map(|i| address_gen.generate_address(i.to_string()));
+        let failed = BTreeMap::from([(failing.to_string(), "rejected".to_string())]);
+        let mut state = State::init();
+
+        let given_up = state.record_claims([&failing, &claimed], &failed, 100);
+        assert!(given_up.is_empty());
+        assert_eq!(
+            state.failed_claims.keys().collect::<Vec<_>>(),
+            [&failing.to_string()]
+        );
+
+        // Once per epoch
+        assert!(!state.should_retry_claim(&failing, 100));
+        assert!(state.should_retry_claim(&failing, 101));
+        assert!(!state.should_retry_claim(&claimed, 101));
+
+        state.record_claims([&failing], &failed, 101);
+        assert_eq!(state.failed_claims[&failing.to_string()].attempts, 2);
+        let given_up = state.record_claims([&failing], &failed, 102);
+        assert_eq!(given_up, [failing.to_string()]);
+        assert!(state.failed_claims.is_empty());
+
+        // Going through clears it
+        state.record_claims([&failing], &failed, 103);
+        state.record_claims([&failing], &BTreeMap::new(), 104);
+        assert!(state.failed_claims.is_empty());
+    }
+
+    #[test]
+    fn test_accumulate() {
+        let mut state = State::init();
+
+        state.accumulate(token::Amount::native_whole(4));
+        state.accumulate(token::Amount::native_whole(4));
+        assert_eq!(state.accumulated, token::Amount::native_whole(8));
+        assert!(state
+            .stats_summary()
+            .ends_with(", 8.000000 NAM accumulated to bond"));
+
+        state.accumulate(token::Amount::native_whole(4));
+        assert_eq!(state.accumulated, token::Amount::native_whole(12));
+
+        // Only part of it fit in the bonds
+        state.bonded_accumulated(token::Amount::native_whole(9));
+        assert_eq!(state.accumulated, token::Amount::native_whole(3));
+        state.bonded_accumulated(token::Amount::native_whole(3));
+        assert!(state.accumulated.is_zero());
+    }
+
+    #[test]
+    fn test_chosen_validators() {
+        let mut state = State::init();
+        assert!(state.chosen_validators(100, 10).is_empty());
+
+        state.chosen_target = Some(ChosenTarget {
+            validator: "target".to_string(),
+            epoch: 95,
+        });
+        state.auto_selected = Some(ChosenValidators {
+            validators: BTreeSet::from(["selected".to_string()]),
+            epoch: 100,
+        });
+        assert_eq!(
+            state.chosen_validators(104, 10),
+            BTreeSet::from(["selected".to_string(), "target".to_string()])
+        );
+        // The target's cooldown is over
+        assert_eq!(
+            state.chosen_validators(105, 10),
+            BTreeSet::from(["selected".to_string()])
+        );
+        assert!(state.chosen_validators(100, 0).is_empty());
     }
 }
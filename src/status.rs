@@ -0,0 +1,148 @@
+use std::collections::{BTreeSet, HashMap};
+
+use anyhow::Context;
+use namada_sdk::address::Address;
+
+use crate::{
+    compound,
+    config::AppConfig,
+    denom,
+    namada::NamadaRpc,
+    opt::{self, RoundFee},
+    utils,
+};
+
+/// Read-only report of the delegation: never builds or submits a tx.
+pub async fn print_status<R: NamadaRpc>(
+    rpc: &R,
+    config: &AppConfig,
+    delegator_address: &Address,
+) -> anyhow::Result<()> {
+    let current_epoch = rpc.get_current_epoch().await?;
+    let pos_inflation = compound::inflation_rate(rpc, config).await?;
+
+    let validators = rpc
+        .get_delegators_validators(delegator_address, current_epoch)
+        .await?;
+    let validators = utils::filter_validators(
+        validators,
+        &config.only_validators,
+        &config.exclude_validators,
+    )?;
+
+    println!("Delegator: {}", delegator_address);
+    println!("Epoch: {}", current_epoch);
+    println!("Inflation rate: {:.2}%", pos_inflation * 100.0);
+    let epoch_secs = match rpc.epoch_duration().await {
+        Ok(duration) => {
+            println!(
+                "Epoch duration: {:.2} hours",
+                duration.as_secs() as f64 / 3600.0
+            );
+            Some(duration.as_secs())
+        }
+        Err(err) => {
+            tracing::warn!("Can't query the epoch duration: {:#}", err);
+            None
+        }
+    };
+
+    if validators.is_empty() {
+        println!("No active delegations");
+        return Ok(());
+    }
+
+    // Informative only, the status still shows without it
+    let uptimes = rpc
+        .query_uptime()
+        .await
+        .inspect_err(|err| tracing::warn!("Can't query validator uptime: {:#}", err))
+        .unwrap_or_default();
+
+    println!();
+    println!(
+        "{:<46} {:>18} {:>11} {:>9} {:>8} {:>18}",
+        "Validator", "Bond", "Commission", "Net APR", "Uptime", "Pending rewards"
+    );
+
+    let mut commissions = HashMap::with_capacity(validators.len());
+    let mut bonds = HashMap::with_capacity(validators.len());
+    let mut total_rewards = 0.0;
+
+    for validator in &validators {
+        let bond = rpc
+            .query_bond(validator, delegator_address, current_epoch)
+            .await?;
+        let commission = rpc
+            .query_validator_commissions(validator, current_epoch)
+            .await?;
+        let rewards = rpc
+            .query_pos_rewards(&BTreeSet::from([validator.clone()]), delegator_address)
+            .await?;
+        let rewards = R::amount_to_f64(rewards)?;
+
+        let uptime = uptimes
+            .get(validator)
+            .map_or("-".to_string(), |uptime| format!("{:.2}%", uptime * 100.0));
+
+        println!(
+            "{:<46} {:>18.6} {:>10.2}% {:>8.2}% {:>8} {:>18.6}",
+            validator.to_string(),
+            bond,
+            commission * 100.0,
+            pos_inflation * (1.0 - commission) * 100.0,
+            uptime,
+            rewards
+        );
+
+        commissions.insert(validator.clone(), commission);
+        bonds.insert(validator.clone(), bond);
+        total_rewards += rewards;
+    }
+
+    let bonded_amount = bonds.values().sum::<f64>();
+    let commission = config
+        .commission_aggregation
+        .aggregate(&commissions, &bonds)
+        .context("Can't compute validator commissions")?;
+    let net_apr = pos_inflation - (pos_inflation * commission);
+
+    let native_token_address = rpc.query_native_token().await?;
+    let fee_per_tx = compound::estimate_fee_per_tx(rpc, config, &native_token_address).await;
+
+    let optimization_result = config
+        .optimizer
+        .optimize(
+            bonded_amount,
+            net_apr,
+            RoundFee::new(
+                compound::txs_per_round(validators.len(), config.batch_claims),
+                fee_per_tx,
+            ),
+            opt::epochs_per_year(epoch_secs),
+            config.horizon_years,
+            config.max_frequency(),
+        )
+        .context("Failed optimizing frequency")?;
+
+    println!();
+    println!("Total bonded: {}", denom::display_nam_f64(bonded_amount));
+    println!(
+        "Total pending rewards: {}",
+        denom::display_nam_f64(total_rewards)
+    );
+    println!("Net APR: {:.2}%", net_apr * 100.0);
+    println!(
+        "Optimal frequency: {} compounds/year (every {:.2} hours)",
+        optimization_result.optimal_frequency,
+        optimization_result.hours_between_compounding()
+    );
+    if optimization_result.clamped {
+        println!(
+            "  (capped by --min-hours-between-compounds {})",
+            config.min_hours_between_compounds
+        );
+    }
+
+    Ok(())
+}
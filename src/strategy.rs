@@ -0,0 +1,151 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use namada_sdk::token;
+
+use crate::denom;
+
+/// When to compound, see `--strategy`.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Strategy {
+    /// At the interval the optimizer picks
+    Optimizer,
+    /// Every so many hours, whatever the optimizer says
+    FixedInterval(f64),
+    /// As soon as the pending rewards reach this many NAM, ignoring the clock
+    Threshold(f64),
+}
+
+/// What the strategies know of time.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Clock {
+    pub now: u64,
+    /// `None` before the first compound
+    pub last_claimed: Option<u64>,
+}
+
+impl Strategy {
+    /// Seconds between two compounds given the `optimal` one, `None` when the
+    /// clock doesn't matter.
+    pub fn interval(&self, optimal: u64) -> Option<u64> {
+        match self {
+            Self::Optimizer => Some(optimal),
+            Self::FixedInterval(hours) => Some((hours * 3600.0) as u64),
+            Self::Threshold(_) => None,
+        }
+    }
+
+    /// Whether enough time went by since the last compound. The first one is
+    /// always due.
+    pub fn is_due(&self, clock: Clock, optimal: u64) -> bool {
+        match (self.interval(optimal), clock.last_claimed) {
+            (Some(interval), Some(last_claimed)) => {
+                clock.now.saturating_sub(last_claimed) >= interval
+            }
+            _ => true,
+        }
+    }
+
+    /// Whether `pending_rewards` are worth a compound. Only the threshold
+    /// looks at them, the others leave it to `--min-claim-nam`.
+    pub fn enough_rewards(&self, pending_rewards: token::Amount) -> bool {
+        match self {
+            Self::Optimizer | Self::FixedInterval(_) => true,
+            Self::Threshold(threshold) => pending_rewards >= denom::amount_from_nam(*threshold),
+        }
+    }
+}
+
+impl FromStr for Strategy {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        let expected = "expected optimizer, fixed-interval:<hours> or threshold:<NAM>";
+
+        match value.split_once(':') {
+            None if value == "optimizer" => Ok(Self::Optimizer),
+            Some(("fixed-interval", hours)) => match hours.parse::<f64>() {
+                Ok(hours) if hours.is_finite() && hours > 0.0 => Ok(Self::FixedInterval(hours)),
+                _ => Err(format!("{} is not a positive number of hours", hours)),
+            },
+            Some(("threshold", amount)) => denom::parse_nam(amount).map(Self::Threshold),
+            _ => Err(format!("{}, got {}", expected, value)),
+        }
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Optimizer => f.write_str("optimizer"),
+            Self::FixedInterval(hours) => write!(f, "fixed-interval:{}", hours),
+            Self::Threshold(amount) => write!(f, "threshold:{}", amount),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::str::FromStr;
+
+    use namada_sdk::token;
+
+    use super::{Clock, Strategy};
+
+    const HOUR: u64 = 3600;
+
+    fn clock(hours_since_claim: Option<u64>) -> Clock {
+        let now = 1_700_000_000;
+        Clock {
+            now,
+            last_claimed: hours_since_claim.map(|hours| now - hours * HOUR),
+        }
+    }
+
+    #[test]
+    fn test_optimizer() {
+        let strategy = Strategy::Optimizer;
+
+        assert!(strategy.is_due(clock(None), 24 * HOUR));
+        assert!(!strategy.is_due(clock(Some(23)), 24 * HOUR));
+        assert!(strategy.is_due(clock(Some(24)), 24 * HOUR));
+        assert!(strategy.enough_rewards(token::Amount::zero()));
+    }
+
+    #[test]
+    fn test_fixed_interval() {
+        let strategy = Strategy::FixedInterval(6.0);
+
+        // Whatever the optimizer says
+        assert!(strategy.is_due(clock(Some(6)), 24 * HOUR));
+        assert!(!strategy.is_due(clock(Some(5)), HOUR));
+        assert_eq!(strategy.interval(24 * HOUR), Some(6 * HOUR));
+    }
+
+    #[test]
+    fn test_threshold() {
+        let strategy = Strategy::Threshold(10.0);
+
+        // The clock doesn't matter, only the rewards
+        assert!(strategy.is_due(clock(Some(0)), 24 * HOUR));
+        assert_eq!(strategy.interval(24 * HOUR), None);
+        assert!(!strategy.enough_rewards(token::Amount::from_u64(9_999_999)));
+        assert!(strategy.enough_rewards(token::Amount::native_whole(10)));
+    }
+
+    #[test]
+    fn test_parse() {
+        for strategy in [
+            Strategy::Optimizer,
+            Strategy::FixedInterval(12.5),
+            Strategy::Threshold(10.0),
+        ] {
+            assert_eq!(Strategy::from_str(&strategy.to_string()), Ok(strategy));
+        }
+        assert!(Strategy::from_str("fixed-interval:0").is_err());
+        assert!(Strategy::from_str("threshold").is_err());
+        assert!(Strategy::from_str("sometimes").is_err());
+    }
+}
@@ -1,3 +1,16 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    future::Future,
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::Context;
+use namada_sdk::address::Address;
+use rand::Rng;
+use tokio::time::sleep;
+
 pub fn mean(vec: &[f64]) -> Option<f64> {
     if vec.is_empty() {
         return None;
@@ -5,3 +18,223 @@ pub fn mean(vec: &[f64]) -> Option<f64> {
     let sum: f64 = vec.iter().sum();
     Some(sum / vec.len() as f64)
 }
+
+pub fn median(vec: &[f64]) -> Option<f64> {
+    if vec.is_empty() {
+        return None;
+    }
+    let mut sorted = vec.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let mid = sorted.len() / 2;
+    if sorted.len() % 2 == 1 {
+        Some(sorted[mid])
+    } else {
+        Some((sorted[mid - 1] + sorted[mid]) / 2.0)
+    }
+}
+
+/// Mean of `values` weighted by `weights`. Falls back to the plain mean when
+/// all weights are zero.
+pub fn weighted_mean(values: &[f64], weights: &[f64]) -> Option<f64> {
+    if values.is_empty() || values.len() != weights.len() {
+        return None;
+    }
+    let total_weight: f64 = weights.iter().sum();
+    if total_weight == 0.0 {
+        return mean(values);
+    }
+    let weighted_sum: f64 = values.iter().zip(weights).map(|(v, w)| v * w).sum();
+    Some(weighted_sum / total_weight)
+}
+
+/// Create `dir` if needed and make sure we can write into it.
+pub fn ensure_writable_dir(dir: &Path) -> anyhow::Result<()> {
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Can't create directory {}", dir.display()))?;
+
+    let probe = dir.join(".write-test");
+    std::fs::write(&probe, b"")
+        .and_then(|_| std::fs::remove_file(&probe))
+        .with_context(|| format!("Directory {} is not writable", dir.display()))
+}
+
+/// Overwrite `path` with the current unix timestamp. Only logs failures: a
+/// missed heartbeat shouldn't stop the compounding.
+pub fn touch_heartbeat(path: &Path) {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+
+    if let Err(err) = std::fs::write(path, format!("{}\n", now)) {
+        tracing::warn!("Can't write heartbeat file {}: {}", path.display(), err);
+    }
+}
+
+/// Restrict the delegation set to `only` (when non-empty) and drop anything
+/// in `exclude`.
+pub fn filter_validators(
+    validators: BTreeSet<Address>,
+    only: &[Address],
+    exclude: &[Address],
+) -> anyhow::Result<BTreeSet<Address>> {
+    if let Some(missing) = only.iter().find(|address| !validators.contains(address)) {
+        anyhow::bail!("Validator {} is not part of the delegation set", missing);
+    }
+
+    Ok(validators
+        .into_iter()
+        .filter(|address| only.is_empty() || only.contains(address))
+        .filter(|address| !exclude.contains(address))
+        .collect())
+}
+
+/// Errors that may go away by trying again.
+pub trait Retryable {
+    fn is_retryable(&self) -> bool;
+
+    /// How long the other end asked to wait before retrying, in place of the
+    /// backoff.
+    fn retry_after(&self) -> Option<Duration> {
+        None
+    }
+}
+
+impl Retryable for anyhow::Error {
+    fn is_retryable(&self) -> bool {
+        true
+    }
+}
+
+/// Run `f` up to `attempts` times, sleeping with a jittered exponential
+/// backoff (starting from `base_delay`) between failed attempts. Errors that
+/// aren't [`Retryable`] are returned right away.
+pub async fn retry_with_backoff<F, Fut, T, E>(
+    attempts: u32,
+    base_delay: Duration,
+    mut f: F,
+) -> Result<T, E>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    E: Retryable + Display,
+{
+    let attempts = attempts.max(1);
+    let mut attempt = 1;
+
+    loop {
+        match f().await {
+            Ok(value) => return Ok(value),
+            Err(err) if attempt < attempts && err.is_retryable() => {
+                let delay = err
+                    .retry_after()
+                    .unwrap_or_else(|| backoff_delay(base_delay, attempt));
+                tracing::warn!(
+                    "Attempt {}/{} failed: {:#}. Retrying in {}ms...",
+                    attempt,
+                    attempts,
+                    err,
+                    delay.as_millis()
+                );
+                sleep(delay).await;
+                attempt += 1;
+            }
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+fn backoff_delay(base_delay: Duration, attempt: u32) -> Duration {
+    let delay = base_delay.saturating_mul(2_u32.saturating_pow(attempt - 1));
+    let max_jitter = delay.as_millis() as u64 / 2;
+    let jitter = rand::thread_rng().gen_range(0..=max_jitter);
+    delay + Duration::from_millis(jitter)
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::{median, retry_with_backoff, touch_heartbeat, weighted_mean};
+    use crate::error::TaskError;
+
+    #[test]
+    fn test_median() {
+        assert_eq!(median(&[]), None);
+        assert_eq!(median(&[0.1, 0.5, 0.05]), Some(0.1));
+        assert_eq!(median(&[0.1, 0.2, 0.3, 0.4]), Some(0.25));
+    }
+
+    #[test]
+    fn test_weighted_mean() {
+        assert_eq!(weighted_mean(&[], &[]), None);
+        assert_eq!(weighted_mean(&[0.1, 0.2], &[1.0]), None);
+        assert_eq!(weighted_mean(&[0.05, 1.0], &[1000.0, 0.0]), Some(0.05));
+        assert_eq!(weighted_mean(&[0.25, 0.75], &[3.0, 1.0]), Some(0.375));
+    }
+
+    #[test]
+    fn test_weighted_mean_zero_weights() {
+        assert_eq!(weighted_mean(&[0.1, 0.3], &[0.0, 0.0]), Some(0.2));
+    }
+
+    #[test]
+    fn test_touch_heartbeat() {
+        let path =
+            std::env::temp_dir().join(format!("autocompound-heartbeat-{}", std::process::id()));
+
+        touch_heartbeat(&path);
+        let first = std::fs::read_to_string(&path).unwrap();
+        touch_heartbeat(&path);
+        let second = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert!(first.trim().parse::<u64>().unwrap() <= second.trim().parse::<u64>().unwrap());
+        // Doesn't panic when the file can't be written
+        touch_heartbeat(&std::env::temp_dir().join("missing-dir/heartbeat"));
+    }
+
+    #[tokio::test]
+    async fn test_retry_until_success() {
+        let mut calls = 0;
+        let res = retry_with_backoff(3, Duration::from_millis(1), || {
+            calls += 1;
+            let current = calls;
+            async move {
+                if current < 3 {
+                    anyhow::bail!("transient")
+                }
+                Ok(current)
+            }
+        })
+        .await;
+
+        assert_eq!(res.unwrap(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_retry_gives_up() {
+        let mut calls = 0;
+        let res: anyhow::Result<()> = retry_with_backoff(2, Duration::from_millis(1), || {
+            calls += 1;
+            async { anyhow::bail!("down") }
+        })
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(calls, 2);
+    }
+
+    #[tokio::test]
+    async fn test_retry_stops_on_permanent_error() {
+        let mut calls = 0;
+        let res: Result<(), TaskError> = retry_with_backoff(3, Duration::from_millis(1), || {
+            calls += 1;
+            async { Err(TaskError::InvalidKey("bad".to_string())) }
+        })
+        .await;
+
+        assert!(res.is_err());
+        assert_eq!(calls, 1);
+    }
+}
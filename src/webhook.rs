@@ -0,0 +1,114 @@
+use std::{
+    collections::BTreeMap,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::Url;
+use serde::Serialize;
+
+use crate::{history::CompoundRecord, state::FailedClaim};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    Compounded,
+    /// Compounded, but some claims failed and are retried in a later epoch
+    ClaimsFailed,
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookEvent {
+    pub event: EventKind,
+    pub timestamp: u64,
+    pub rewards: Option<String>,
+    pub bonded: Option<String>,
+    pub error: Option<String>,
+}
+
+impl WebhookEvent {
+    pub fn compounded(record: &CompoundRecord) -> Self {
+        Self {
+            event: EventKind::Compounded,
+            timestamp: record.timestamp,
+            rewards: Some(record.rewards.to_string_native()),
+            bonded: Some(record.bonded.to_string_native()),
+            error: None,
+        }
+    }
+
+    /// The claims of a round that failed, by validator with why.
+    pub fn claims_failed(failed: &BTreeMap<String, FailedClaim>) -> Self {
+        let failed = failed
+            .iter()
+            .map(|(validator, claim)| {
+                format!(
+                    "{} ({}, {} attempt(s))",
+                    validator, claim.reason, claim.attempts
+                )
+            })
+            .collect::<Vec<_>>()
+            .join(", ");
+        Self {
+            event: EventKind::ClaimsFailed,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            rewards: None,
+            bonded: None,
+            error: Some(format!("Claims failed: {}", failed)),
+        }
+    }
+
+    pub fn error(err: &anyhow::Error) -> Self {
+        Self {
+            event: EventKind::Error,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs(),
+            rewards: None,
+            bonded: None,
+            error: Some(format!("{:#}", err)),
+        }
+    }
+}
+
+/// Best-effort notifications: failures are logged and never interrupt the
+/// compounding loop.
+#[derive(Debug, Clone)]
+pub struct Webhook {
+    client: reqwest::Client,
+    url: Option<Url>,
+}
+
+impl Webhook {
+    pub fn new(url: Option<Url>) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url,
+        }
+    }
+
+    pub async fn notify(&self, event: WebhookEvent) {
+        let Some(url) = &self.url else {
+            return;
+        };
+
+        let res = self
+            .client
+            .post(url.clone())
+            .timeout(TIMEOUT)
+            .json(&event)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status());
+
+        if let Err(err) = res {
+            tracing::warn!("Webhook notification failed: {}", err);
+        }
+    }
+}